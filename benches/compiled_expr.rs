@@ -0,0 +1,62 @@
+//! Compares evaluating a rule expression through a `CompiledExpr` against parsing it with
+//! `Interpreter::run_source` on every evaluation, single-threaded and from several threads.
+//!
+//! Run with `cargo bench --bench compiled_expr`.
+
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use astra::{Interpreter, Options, Value};
+
+const EVALUATIONS: u32 = 100_000;
+const THREADS: u32 = 4;
+const RULE: &str = "price * qty > 100 and discount(price, qty) < 500";
+const PRELUDE: &str = "fn discount(price, qty) [\n    if (qty > 10) return price * qty / 10\n    return 0\n]\n";
+
+fn row(i: u32) -> HashMap<String, Value> {
+    HashMap::from([
+        ("price".to_string(), Value::Integer((i % 50).into())),
+        ("qty".to_string(), Value::Integer((i % 17).into())),
+    ])
+}
+
+fn report(label: &str, elapsed: Duration, evaluations: u32) -> Duration {
+    let per_eval = elapsed / evaluations;
+    println!("{:<16} {:>10.3?} per evaluation", label, per_eval);
+    per_eval
+}
+
+fn main() {
+    let mut interpreter = Interpreter::new(Options::default());
+    interpreter.run_source(PRELUDE).expect("Prelude failed");
+
+    // Naive: bind the row as globals and re-parse the rule every time
+    let start = Instant::now();
+    for i in 0..EVALUATIONS {
+        let source = format!("price = {}\nqty = {}\n{}", i % 50, i % 17, RULE);
+        interpreter.run_source(&source).expect("Rule failed");
+    }
+    let naive = report("run_source", start.elapsed(), EVALUATIONS);
+
+    let compiled = interpreter.compile_expr(RULE).expect("Rule does not compile");
+    let start = Instant::now();
+    for i in 0..EVALUATIONS {
+        compiled.eval(&row(i)).expect("Rule failed");
+    }
+    let single = report("compiled", start.elapsed(), EVALUATIONS);
+
+    let start = Instant::now();
+    thread::scope(|scope| {
+        for t in 0..THREADS {
+            let compiled = &compiled;
+            scope.spawn(move || {
+                for i in (t..EVALUATIONS).step_by(THREADS as usize) {
+                    compiled.eval(&row(i)).expect("Rule failed");
+                }
+            });
+        }
+    });
+    report(&format!("compiled x{THREADS}"), start.elapsed(), EVALUATIONS);
+    println!("speedup          {:>9.1}x", naive.as_secs_f64() / single.as_secs_f64());
+}
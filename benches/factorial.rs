@@ -0,0 +1,39 @@
+//! Times factorial(20000) computed by a loop of Integer multiplications, once inside a function
+//! and once at the top level, where every statement used to render its value for the runlog.
+//!
+//! Run with `cargo bench --bench factorial`.
+
+use std::env;
+use std::fs;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+const IN_FUNCTION: &str = "fn factorial(n) [\n  result = 1\n  for (i in range(1, n + 1)) [ result = result * i ]\n  return result\n]\nprint(factorial(20000) % 1000007)\n";
+const TOP_LEVEL: &str = "result = 1\nfor (i in range(1, 20001)) [ result = result * i ]\nprint(result % 1000007)\n";
+// 20000! mod 1000007
+const EXPECTED: &str = "928493";
+
+fn run(label: &str, source: &str) -> Duration {
+    let dir = env::temp_dir().join("astra_factorial_bench");
+    fs::create_dir_all(&dir).expect("Failed to create bench directory");
+    let script = dir.join(format!("{}.as", label.replace(' ', "_")));
+    fs::write(&script, source).expect("Failed to write bench script");
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_astra"));
+    // Run inside the temp dir so the runlog doesn't land in the repo, and keep debug logging out of the timing
+    cmd.arg(&script).current_dir(&dir).env("RUST_LOG", "off");
+
+    let start = Instant::now();
+    let output = cmd.output().expect("Failed to run astra");
+    let elapsed = start.elapsed();
+
+    assert!(output.status.success(), "{} run failed: {}", label, String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), EXPECTED);
+    println!("{:<10} {:>10.3?}", label, elapsed);
+    elapsed
+}
+
+fn main() {
+    run("function", IN_FUNCTION);
+    run("top level", TOP_LEVEL);
+}
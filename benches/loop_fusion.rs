@@ -0,0 +1,39 @@
+//! Compares the fused numeric loop against the general interpreter path on a 10M-iteration sum.
+//!
+//! Run with `cargo bench --bench loop_fusion`.
+
+use std::env;
+use std::fs;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+const SCRIPT: &str = "total = 0\nfor (i in range(10000000)) [ total += i ]\nprint(total)\n";
+
+fn run(label: &str, disable_fusion: bool) -> Duration {
+    let dir = env::temp_dir().join("astra_loop_fusion_bench");
+    fs::create_dir_all(&dir).expect("Failed to create bench directory");
+    let script = dir.join("sum.as");
+    fs::write(&script, SCRIPT).expect("Failed to write bench script");
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_astra"));
+    // Run inside the temp dir so the runlog doesn't land in the repo, and keep debug logging out of the timing
+    if disable_fusion {
+        cmd.arg("--no-loop-fusion");
+    }
+    cmd.arg(&script).current_dir(&dir).env("RUST_LOG", "off");
+
+    let start = Instant::now();
+    let output = cmd.output().expect("Failed to run astra");
+    let elapsed = start.elapsed();
+
+    assert!(output.status.success(), "{} run failed: {}", label, String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "49999995000000");
+    println!("{:<8} {:>10.3?}", label, elapsed);
+    elapsed
+}
+
+fn main() {
+    let fused = run("fused", false);
+    let general = run("general", true);
+    println!("speedup  {:>9.1}x", general.as_secs_f64() / fused.as_secs_f64());
+}
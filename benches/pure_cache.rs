@@ -0,0 +1,34 @@
+//! Compares a script that repeatedly deduplicates the same array with and without
+//! the cache of pure builtin results.
+//!
+//! Run with `cargo bench --bench pure_cache`.
+
+use std::time::{Duration, Instant};
+
+use astra::{Interpreter, Options, Value};
+
+const SCRIPT: &str = "
+fn reading(i) [ return (i % 50) * 1.5 ]
+readings = map(reading, range(400))
+total = 0
+for (i in range(2000)) [
+    total += length(unique(readings))
+]
+total
+";
+
+fn run(label: &str, pure_cache_size: Option<usize>) -> Duration {
+    let mut interpreter = Interpreter::new(Options { pure_cache_size, ..Options::default() });
+    let start = Instant::now();
+    let value = interpreter.run_source(SCRIPT).expect("Script failed");
+    let elapsed = start.elapsed();
+    assert_eq!(value, Value::Integer((2000 * 50).into()));
+    println!("{:<9} {:>10.3?}", label, elapsed);
+    elapsed
+}
+
+fn main() {
+    let uncached = run("uncached", None);
+    let cached = run("cached", Some(64));
+    println!("speedup   {:>9.1}x", uncached.as_secs_f64() / cached.as_secs_f64());
+}
@@ -0,0 +1,42 @@
+//! Compares creating interpreters that parse and run a prelude each time against
+//! creating them from a shared `PreparedRuntime`.
+//!
+//! Run with `cargo bench --bench snapshot_startup`.
+
+use std::time::{Duration, Instant};
+
+use astra::{Interpreter, Options, PreparedRuntime, Value};
+
+const INSTANCES: u32 = 1000;
+
+fn prelude() -> String {
+    let mut source = String::from("pi = 3.14159\n");
+    for i in 0..50 {
+        source.push_str(&format!("fn helper{i}(x, y) [\n    if (x > y) return x - y\n    return (x + y) * {i}\n]\n"));
+    }
+    source
+}
+
+fn run(label: &str, mut make: impl FnMut() -> Interpreter) -> Duration {
+    let start = Instant::now();
+    for _ in 0..INSTANCES {
+        let mut interpreter = make();
+        let value = interpreter.run_source("helper7(2, 3)").expect("Script failed");
+        assert_eq!(value, Value::Integer(35.into()));
+    }
+    let per_instance = start.elapsed() / INSTANCES;
+    println!("{:<9} {:>10.3?} per instance", label, per_instance);
+    per_instance
+}
+
+fn main() {
+    let source = prelude();
+    let cold = run("cold", || {
+        let mut interpreter = Interpreter::new(Options::default());
+        interpreter.run_source(&source).expect("Prelude failed");
+        interpreter
+    });
+    let snapshot = PreparedRuntime::new(&source, Options::default()).expect("Prelude failed");
+    let warm = run("snapshot", || Interpreter::from_snapshot(&snapshot));
+    println!("speedup   {:>9.1}x", cold.as_secs_f64() / warm.as_secs_f64());
+}
@@ -0,0 +1,148 @@
+//! gzip and zip builtins, compiled with the "archives" feature. Binary data goes in and out as
+//! an Array of byte values; reading and writing files needs --allow-read and --allow-write.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use num_traits::ToPrimitive;
+use zip::ZipArchive;
+
+use crate::{Environment, Runtime, Value, byte_values};
+
+fn bytes_value(bytes: Vec<u8>) -> Value {
+    Value::Array(bytes.into_iter().map(|byte| Value::Integer(byte.into())).collect())
+}
+
+pub(crate) fn native_gzip_compress(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    let data = match args.as_slice() {
+        [Value::String(s)] => s.as_bytes().to_vec(),
+        [Value::Array(items)] => byte_values(fn_name, items)?,
+        [other] => return Err(message!("Argument to '{}' must be a String or an Array of bytes, found {:?}", fn_name, other)),
+        _ => return Err(message!("'{}' expects 1 argument (string or bytes), found {}", fn_name, args.len())),
+    };
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&data).and_then(|()| encoder.finish()).map(bytes_value).map_err(|e| message!("'{}' failed: {}", fn_name, e))
+}
+
+/// Bytes gzip_decompress returns at most unless given another limit: each becomes a Value, so a
+/// small gzip bomb would otherwise exhaust memory.
+const DEFAULT_MAX_DECOMPRESSED_BYTES: u64 = 64 << 20;
+
+/// The optional byte limit argument of `fn_name`, or `default` without one.
+fn byte_limit(fn_name: &str, limit: Option<&Value>, default: u64) -> Result<u64, String> {
+    match limit {
+        None => Ok(default),
+        Some(Value::Integer(n)) => n.to_u64().ok_or_else(|| message!("The byte limit of '{}' must be a non-negative Integer, found {}", fn_name, n)),
+        Some(other) => Err(message!("The byte limit of '{}' must be an Integer, found {:?}", fn_name, other)),
+    }
+}
+
+pub(crate) fn native_gzip_decompress(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    let (items, format, limit) = match args.as_slice() {
+        [Value::Array(items)] => (items, None, None),
+        [Value::Array(items), format] => (items, Some(format), None),
+        [Value::Array(items), format, limit] => (items, Some(format), Some(limit)),
+        [other, ..] if args.len() <= 3 => return Err(message!("'{}' expects an Array of bytes, found {:?}", fn_name, other)),
+        _ => return Err(message!("'{}' expects 1 to 3 arguments (bytes, format, max_bytes), found {}", fn_name, args.len())),
+    };
+    let as_text = match format {
+        None => false,
+        Some(Value::String(format)) if format == "text" || format == "bytes" => format == "text",
+        Some(other) => return Err(message!("The second argument to '{}' must be \"text\" or \"bytes\", found {:?}", fn_name, other)),
+    };
+    let max_bytes = byte_limit(fn_name, limit, DEFAULT_MAX_DECOMPRESSED_BYTES)?;
+    let compressed = byte_values(fn_name, items)?;
+    let mut data = Vec::new();
+    GzDecoder::new(compressed.as_slice())
+        .take(max_bytes.saturating_add(1))
+        .read_to_end(&mut data)
+        .map_err(|e| message!("'{}' could not decompress the data: {}", fn_name, e))?;
+    if data.len() as u64 > max_bytes {
+        return Err(message!("'{}' stopped decompressing: the data takes more than the limit of {} bytes", fn_name, max_bytes));
+    }
+    if as_text {
+        return String::from_utf8(data)
+            .map(Value::String)
+            .map_err(|_| message!("'{}' found data that is not UTF-8 text; leave out \"text\" to get the bytes", fn_name));
+    }
+    Ok(bytes_value(data))
+}
+
+fn open_zip(fn_name: &str, path: &str) -> Result<ZipArchive<File>, String> {
+    let file = File::open(path).map_err(|e| message!("'{}' could not open {}: {}", fn_name, path, e))?;
+    ZipArchive::new(file).map_err(|e| message!("'{}' could not read {} as a zip file: {}", fn_name, path, e))
+}
+
+pub(crate) fn native_zip_list(fn_name: &str, _env: &mut Environment, runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    let path = match args.as_slice() {
+        [Value::String(path)] => path,
+        [other] => return Err(message!("Argument to '{}' must be a String path, found {:?}", fn_name, other)),
+        _ => return Err(message!("'{}' expects 1 argument (path), found {}", fn_name, args.len())),
+    };
+    runtime.require_read(fn_name)?;
+    let archive = open_zip(fn_name, path)?;
+    Ok(Value::Array(archive.file_names().map(|name| Value::String(name.to_string())).collect()))
+}
+
+/// Bytes zip_extract writes at most unless given another limit, so that a small archive can't fill
+/// the disk.
+const DEFAULT_MAX_EXTRACT_BYTES: u64 = 1 << 30;
+
+/// Entries are only written below `dest`: if any name is absolute or climbs out with '..', or the
+/// files would take more than `max_bytes`, nothing is extracted.
+pub(crate) fn native_zip_extract(fn_name: &str, _env: &mut Environment, runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    let (path, dest, limit) = match args.as_slice() {
+        [Value::String(path), Value::String(dest)] => (path, Path::new(dest), None),
+        [Value::String(path), Value::String(dest), limit] => (path, Path::new(dest), Some(limit)),
+        [a, b] | [a, b, _] => return Err(message!("'{}' expects two String paths (path, dest), found {:?} and {:?}", fn_name, a, b)),
+        _ => return Err(message!("'{}' expects 2 or 3 arguments (path, dest, max_bytes), found {}", fn_name, args.len())),
+    };
+    let max_bytes = byte_limit(fn_name, limit, DEFAULT_MAX_EXTRACT_BYTES)?;
+    runtime.require_read(fn_name)?;
+    runtime.require_write(fn_name)?;
+    let mut archive = open_zip(fn_name, path)?;
+    let read_error = |i: usize, e: zip::result::ZipError| message!("'{}' could not read entry {} of {}: {}", fn_name, i, path, e);
+    let write_error = |target: &Path, e: io::Error| message!("'{}' could not write {}: {}", fn_name, target.display(), e);
+
+    // Every name and the total size are checked before anything is written
+    let mut entries = Vec::with_capacity(archive.len());
+    let mut total: u64 = 0;
+    for i in 0..archive.len() {
+        let entry = archive.by_index(i).map_err(|e| read_error(i, e))?;
+        let Some(name) = entry.enclosed_name() else {
+            return Err(message!("'{}' refuses to extract '{}' from {}: the path leaves the destination directory", fn_name, entry.name(), path));
+        };
+        total = total.saturating_add(entry.size());
+        entries.push((name, entry.is_dir()));
+    }
+    if total > max_bytes {
+        return Err(message!("'{}' refuses to extract {}: its files take {} bytes, more than the limit of {}", fn_name, path, total, max_bytes));
+    }
+
+    let mut remaining = max_bytes;
+    let mut written = Vec::new();
+    for (i, (name, is_dir)) in entries.into_iter().enumerate() {
+        let target = dest.join(name);
+        if is_dir {
+            fs::create_dir_all(&target).map_err(|e| write_error(&target, e))?;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| write_error(parent, e))?;
+        }
+        let entry = archive.by_index(i).map_err(|e| read_error(i, e))?;
+        let mut file = File::create(&target).map_err(|e| write_error(&target, e))?;
+        // The sizes an archive declares may be forged, so the limit also applies to what is written
+        let copied = io::copy(&mut entry.take(remaining + 1), &mut file).map_err(|e| write_error(&target, e))?;
+        if copied > remaining {
+            return Err(message!("'{}' stopped extracting {}: its files take more than the limit of {} bytes", fn_name, path, max_bytes));
+        }
+        remaining -= copied;
+        written.push(Value::String(target.to_string_lossy().into_owned()));
+    }
+    Ok(Value::Array(written))
+}
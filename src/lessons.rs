@@ -0,0 +1,113 @@
+//! The lessons of 'astra learn'. Each one is a short explanation and a task, plus Astra programs
+//! that set the scene, answer the task, and check a learner's attempt with `assert`.
+
+use crate::{Interpreter, Options, Parser};
+
+pub struct Lesson {
+    pub title: &'static str,
+    pub explanation: &'static str,
+    pub task: &'static str,
+    // Runs before the learner types anything, e.g. to define the variables the task uses
+    pub setup: &'static str,
+    // An answer that passes; shown on request
+    pub solution: &'static str,
+    // Runs after each attempt, against a copy of the learner's variables and functions
+    pub check: &'static str,
+    // What the learner's latest input must print, one entry per print
+    pub expected_output: &'static [&'static str],
+}
+
+pub const LESSONS: &[Lesson] = &[
+    Lesson {
+        title: "Values and variables",
+        explanation: "Astra works with numbers, text in double quotes, true and false. '=' stores a value in a\nvariable, and arithmetic uses + - * / as usual. Type an expression and its value is echoed.",
+        task: "Store 6 times 7 in a variable called 'answer'.",
+        setup: "",
+        solution: "answer = 6 * 7",
+        check: "assert(answer == 42, \"'answer' should hold 6 times 7\")",
+        expected_output: &[],
+    },
+    Lesson {
+        title: "Printing",
+        explanation: "print(...) writes a line of output. Text goes between double quotes.",
+        task: "Print the text: Hello, Astra!",
+        setup: "",
+        solution: "print(\"Hello, Astra!\")",
+        check: "",
+        expected_output: &["Hello, Astra!"],
+    },
+    Lesson {
+        title: "Format strings",
+        explanation: "In print, each {} is replaced by the next argument: print(\"{} + {}\", 1, 2) prints 1 + 2.",
+        task: "The variable 'name' holds \"Ada\". Print Hi, Ada! using a {} placeholder.",
+        setup: "name = \"Ada\"",
+        solution: "print(\"Hi, {}!\", name)",
+        check: "",
+        expected_output: &["Hi, Ada!"],
+    },
+    Lesson {
+        title: "Arrays",
+        explanation: "Square brackets make an array: [1, 2, 3]. xs[0] is the first item, length(xs) counts\nthe items and sum(xs) adds them up.",
+        task: "'prices' holds [3, 1, 4, 1, 5]. Store their total in 'total' and the first price in 'first'.",
+        setup: "prices = [3, 1, 4, 1, 5]",
+        solution: "total = sum(prices)\nfirst = prices[0]",
+        check: "assert(total == 14, \"'total' should be the sum of prices\")\nassert(first == 3, \"'first' should be prices[0]\")",
+        expected_output: &[],
+    },
+    Lesson {
+        title: "Decisions",
+        explanation: "if (condition) [ ... ] else [ ... ] runs one of two blocks. Compare with == != < > <= >=,\nand combine conditions with 'and' and 'or'.",
+        task: "'n' holds 12. Set 'size' to \"big\" if n is more than 10, and to \"small\" otherwise.",
+        setup: "n = 12",
+        solution: "if (n > 10) [\n    size = \"big\"\n] else [\n    size = \"small\"\n]",
+        check: "assert(size == \"big\", \"'size' should be \\\"big\\\" because n is 12\")",
+        expected_output: &[],
+    },
+    Lesson {
+        title: "Loops",
+        explanation: "for (item in items) [ ... ] runs the block once for every item of an array or range.",
+        task: "'names' holds [\"Ada\", \"Grace\", \"Linus\"]. Print each name on a line of its own.",
+        setup: "names = [\"Ada\", \"Grace\", \"Linus\"]",
+        solution: "for (name in names) [\n    print(name)\n]",
+        check: "",
+        expected_output: &["Ada", "Grace", "Linus"],
+    },
+    Lesson {
+        title: "Functions",
+        explanation: "fn name(parameters) [ ... ] defines a function; 'return' gives back its result.\nA function sees only its parameters, not the variables outside it.",
+        task: "Define a function square(x) that returns x times x.",
+        setup: "",
+        solution: "fn square(x) [\n    return x * x\n]",
+        check: "assert(square(4) == 16, \"square(4) should be 16\")\nassert(square(-3) == 9, \"square(-3) should be 9\")",
+        expected_output: &[],
+    },
+];
+
+impl Lesson {
+    /// A fresh interpreter for the lesson, with its setup run and print output captured.
+    pub fn start(&self) -> Result<Interpreter, String> {
+        let mut interpreter = Interpreter::new(Options::default());
+        interpreter.capture_output();
+        interpreter.run_source(self.setup)?;
+        Ok(interpreter)
+    }
+
+    /// Checks an attempt: `output` is what the learner's latest input printed. The check runs in
+    /// a copy of the interpreter, so it can't change the learner's variables. The error explains
+    /// what is still missing.
+    pub fn check(&self, interpreter: &Interpreter, output: &[String]) -> Result<(), String> {
+        if !self.expected_output.is_empty() && output != self.expected_output {
+            return Err(match output {
+                [] => message!("expected the output {:?}, but nothing was printed", self.expected_output.join("\n")),
+                _ => message!("expected the output {:?}, but got {:?}", self.expected_output.join("\n"), output.join("\n")),
+            });
+        }
+        let mut checker = Interpreter::from_snapshot(&interpreter.snapshot());
+        checker.capture_output();
+        let statements = Parser::new(self.check).parse().map_err(|e| message!("Parsing Error: {}", e))?;
+        for stmt in &statements {
+            checker.run_statement(stmt)?;
+        }
+        Ok(())
+    }
+}
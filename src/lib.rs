@@ -0,0 +1,5310 @@
+//! The Astra language: lexer, parser, formatter and tree-walking interpreter.
+//!
+//! The `astra` binary is a thin command-line wrapper; embedders create an [`Interpreter`],
+//! optionally from a [`PreparedRuntime`] that parses and runs a shared prelude only once.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::cmp::Ordering;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+use log::{debug, info};
+
+// --- Big Integer Imports ---
+use num_bigint::BigInt;
+// Imported traits to enable methods like is_positive (Signed), to_u32, and to_f64 (ToPrimitive)
+use num_traits::{Zero, One, Signed, ToPrimitive}; 
+// ---------------------------
+
+// --- Messages ---
+
+/// Supplies translations for user-visible strings (error messages, diagnostics) and the
+/// locale's number formatting. Install one with [`Interpreter::set_messages`].
+///
+/// Translations are looked up by the English template, e.g. `"Division by zero"` or
+/// `"Undefined function: {}"`, and refer to the template's arguments as `{0}`, `{1}`, ...
+/// (or `{}` to take them in order).
+pub trait Messages: Send + Sync {
+    /// The translation of `template`, or None to keep the English text.
+    fn translate(&self, template: &str) -> Option<String>;
+
+    /// Decimal separator used when printing floats.
+    fn decimal_separator(&self) -> char {
+        '.'
+    }
+}
+
+/// A fixed message catalog, e.g. loaded from a translation file by the embedder.
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    translations: HashMap<String, String>,
+    decimal_separator: char,
+}
+
+impl Catalog {
+    pub fn new() -> Catalog {
+        Catalog { translations: HashMap::new(), decimal_separator: '.' }
+    }
+
+    /// Adds the translation of one English template.
+    pub fn with(mut self, template: &str, translation: &str) -> Catalog {
+        self.translations.insert(template.to_string(), translation.to_string());
+        self
+    }
+
+    pub fn with_decimal_separator(mut self, separator: char) -> Catalog {
+        self.decimal_separator = separator;
+        self
+    }
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        Catalog::new()
+    }
+}
+
+impl Messages for Catalog {
+    fn translate(&self, template: &str) -> Option<String> {
+        self.translations.get(template).cloned()
+    }
+
+    fn decimal_separator(&self) -> char {
+        self.decimal_separator
+    }
+}
+
+/// Texts of the values marked with `mark_secret`, shared with the thread running the interpreter.
+type Secrets = Arc<Mutex<Vec<String>>>;
+
+/// Shortest printed form `mark_secret` accepts.
+const MIN_SECRET_LENGTH: usize = 6;
+
+thread_local! {
+    // Provider of the interpreter call currently running on this thread, if it has one
+    static ACTIVE_MESSAGES: RefCell<Option<Arc<dyn Messages>>> = const { RefCell::new(None) };
+    // Secrets of that call, masked in everything it writes to logs and the runlog
+    static ACTIVE_SECRETS: RefCell<Option<Secrets>> = const { RefCell::new(None) };
+}
+
+/// Makes an interpreter's message provider and secrets active for the rest of a call,
+/// restoring the previous ones on drop.
+struct ActiveScope(Option<Arc<dyn Messages>>, Option<Secrets>);
+
+impl ActiveScope {
+    fn enter(messages: Option<&Arc<dyn Messages>>, secrets: &Secrets) -> ActiveScope {
+        ActiveScope(
+            ACTIVE_MESSAGES.with(|active| active.replace(messages.cloned())),
+            ACTIVE_SECRETS.with(|active| active.replace(Some(Arc::clone(secrets)))),
+        )
+    }
+}
+
+impl Drop for ActiveScope {
+    fn drop(&mut self) {
+        ACTIVE_MESSAGES.with(|active| *active.borrow_mut() = self.0.take());
+        ACTIVE_SECRETS.with(|active| *active.borrow_mut() = self.1.take());
+    }
+}
+
+/// Masks the secrets of the interpreter call running on this thread as "***". Log formatters
+/// use this so that traces written during a call don't leak secret values.
+pub fn redact(text: &str) -> String {
+    ACTIVE_SECRETS.with(|active| match active.borrow().as_ref() {
+        Some(secrets) => mask_secrets(text, secrets),
+        None => text.to_string(),
+    })
+}
+
+fn mask_secrets(text: &str, secrets: &Secrets) -> String {
+    let mut masked = text.to_string();
+    for secret in secrets.lock().unwrap().iter() {
+        masked = masked.replace(secret.as_str(), "***");
+    }
+    masked
+}
+
+fn decimal_separator() -> char {
+    ACTIVE_MESSAGES.with(|active| active.borrow().as_ref().map_or('.', |m| m.decimal_separator()))
+}
+
+/// Builds a user-visible message like `format!`, then replaces it with the active provider's
+/// translation of the same template, if there is one. Placeholders are `{}` or `{:?}`; format
+/// an argument that needs anything else (e.g. hex) before passing it.
+macro_rules! message {
+    ($template:literal $(, $arg:expr)* $(,)?) => {{
+        let texts = ::std::cell::RefCell::new(Vec::new());
+        let english = format!($template $(, $crate::MessageArg { value: &$arg, texts: &texts })*);
+        $crate::localize($template, english, texts.into_inner())
+    }};
+}
+
+pub mod lessons;
+pub mod numeric;
+pub mod schedule;
+pub mod text;
+#[cfg(feature = "archives")]
+mod archives;
+#[cfg(feature = "mail")]
+mod mail;
+#[cfg(feature = "markup")]
+mod markup;
+
+pub use numeric::{IntDivision, RoundingMode};
+pub use schedule::ScheduledJob;
+
+/// An argument of `message!`. It formats like the value it wraps and keeps a copy of the text,
+/// so a translation is filled with exactly what the English message shows.
+struct MessageArg<'a, T: ?Sized> {
+    value: &'a T,
+    texts: &'a RefCell<Vec<String>>,
+}
+
+impl<T: fmt::Display + ?Sized> fmt::Display for MessageArg<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = self.value.to_string();
+        f.write_str(&text)?;
+        self.texts.borrow_mut().push(text);
+        Ok(())
+    }
+}
+
+impl<T: fmt::Debug + ?Sized> fmt::Debug for MessageArg<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = format!("{:?}", self.value);
+        f.write_str(&text)?;
+        self.texts.borrow_mut().push(text);
+        Ok(())
+    }
+}
+
+/// The active provider's translation of `template` filled with `args` (the texts of its
+/// placeholders, in order), or the English message if there is none.
+fn localize(template: &str, english: String, args: Vec<String>) -> String {
+    let translation = ACTIVE_MESSAGES.with(|active| active.borrow().as_ref().and_then(|m| m.translate(template)));
+    match translation {
+        Some(translation) => fill_translation(&translation, &args),
+        None => english,
+    }
+}
+
+/// Substitutes `{0}`, `{1}`, ... (or `{}` in order) in a translation.
+fn fill_translation(translation: &str, args: &[String]) -> String {
+    let mut out = String::new();
+    let mut next = 0;
+    let mut rest = translation;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        let index = &rest[start + 1..start + len];
+        let arg = if index.is_empty() {
+            next += 1;
+            args.get(next - 1)
+        } else {
+            index.parse::<usize>().ok().and_then(|i| args.get(i))
+        };
+        match arg {
+            Some(arg) => out.push_str(arg),
+            None => out.push_str(&rest[start..=start + len]),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// --- Value and AST Definitions ---
+
+#[derive(Debug, Clone, PartialEq)] 
+pub enum Value {
+    // Changed i64 to BigInt to support arbitrary precision arithmetic
+    Integer(BigInt), 
+    Float(f64),
+    String(String),
+    Boolean(bool), 
+    Array(Vec<Value>), 
+    // Reference to a named function, produced when a function name is used as a value (e.g., group_by(f, list))
+    Function(String),
+    // Lazy sequence (range, take, drop, ...) that is only materialized item by item when consumed
+    Sequence(Box<LazySeq>),
+    // Syntax captured by 'quote [ ... ]', taken apart with ast_kind / ast_children and run by eval_ast
+    Ast(Arc<Ast>),
+    Void,
+}
+
+/// Lazy iterator values. Nothing is allocated up front, so range(1, 10^9) is as cheap as range(1, 10).
+#[derive(Debug, Clone, PartialEq)]
+pub enum LazySeq {
+    // start, end (exclusive), step (non-zero)
+    Range(BigInt, BigInt, BigInt),
+    // First n items of the inner iterable
+    Take(Value, BigInt),
+    // Inner iterable without its first n items
+    Drop(Value, BigInt),
+    // Every n-th item of the inner iterable, starting with the first
+    Step(Value, BigInt),
+    // [index, item] pairs of the inner iterable
+    Enumerate(Value),
+}
+
+impl Value {
+    /// Helper to check if a value is numeric (Integer or Float)
+    fn is_number(&self) -> bool {
+        matches!(self, Value::Integer(_) | Value::Float(_))
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Integer(n) => write!(f, "{}", n),
+            Value::Float(n) => write!(f, "{}", n),
+            // Note: Display of Value::String includes quotes
+            Value::String(s) => write!(f, "\"{}\"", s), 
+            // Corrected: Outputs 'true' or 'false' without quotes
+            Value::Boolean(b) => write!(f, "{}", if *b { "true" } else { "false" }), 
+            Value::Void => write!(f, "void"),
+            Value::Function(name) => write!(f, "<fn {}>", name),
+            Value::Sequence(seq) => write!(f, "{}", seq),
+            Value::Ast(ast) => write!(f, "<ast {}>", ast),
+            // MODIFIED: Display for Array
+            Value::Array(v) => {
+                write!(f, "[")?;
+                for (i, val) in v.iter().enumerate() {
+                    // Array elements are displayed without quotes for strings here, 
+                    // which is a stylistic choice for compact output.
+                    match val {
+                        Value::String(s) => write!(f, "{}", s)?,
+                        _ => write!(f, "{}", val)?,
+                    }
+                    
+                    if i < v.len() - 1 {
+                        write!(f, ", ")?;
+                    }
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+impl fmt::Display for LazySeq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LazySeq::Range(start, end, step) if step.is_one() => write!(f, "range({}, {})", start, end),
+            LazySeq::Range(start, end, step) => write!(f, "range({}, {}, {})", start, end, step),
+            LazySeq::Take(inner, n) => write!(f, "take({}, {})", inner, n),
+            LazySeq::Drop(inner, n) => write!(f, "drop({}, {})", inner, n),
+            LazySeq::Step(inner, n) => write!(f, "step({}, {})", inner, n),
+            LazySeq::Enumerate(inner) => write!(f, "enumerate({})", inner),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Var(String),
+    Num(String), // Stores raw number string to preserve type distinction (e.g., "1" vs "1.0")
+    Str(Arc<str>), // Shared with identical literals through the parser's constant pool
+    Bool(bool), // Boolean literal (true or false)
+    Prefix(char, Box<Expr>),
+    Infix(Box<Expr>, char, Box<Expr>),
+    Cmp(Box<Expr>, String, Box<Expr>), 
+    Logic(Box<Expr>, String, Box<Expr>),
+    Array(Vec<Expr>), 
+    // Slice variant for both indexing (arr[i]) and slicing (arr[i:j])
+    Slice(Box<Expr>, Option<Box<Expr>>, Option<Box<Expr>>), // (array_expr, start_expr_opt, end_expr_opt)
+    Call(String, Vec<Expr>),
+    // Spread argument (f(...list)): the list's items become positional arguments. Only valid in calls.
+    Spread(Box<Expr>),
+    // Source region that failed to parse (only produced by Parser::parse_lenient)
+    Error(Span),
+    // quote [ ... ]: the block as a syntax value (Value::Ast) instead of running it
+    Quote(Vec<Statement>),
+    // Use of a macro whose template is a single expression
+    Expand(Box<Expansion<Expr>>),
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Var(id) => write!(f, "{}", id),
+            Expr::Num(s) => write!(f, "{}", s), 
+            Expr::Str(s) => write!(f, "\"{}\"", s),
+            Expr::Bool(b) => write!(f, "{}", if *b { "true" } else { "false" }), 
+            Expr::Prefix(op, expr) => write!(f, "({} {})", op, expr),
+            Expr::Infix(lhs, op, rhs) => write!(f, "({} {} {})", lhs, op, rhs),
+            Expr::Cmp(lhs, op, rhs) => write!(f, "({} {} {})", lhs, op, rhs), 
+            Expr::Logic(lhs, op, rhs) => write!(f, "({} {} {})", lhs, op, rhs),
+            // MODIFIED: Array display
+            Expr::Array(elements) => {
+                write!(f, "[")?;
+                for (i, expr) in elements.iter().enumerate() {
+                    write!(f, "{}", expr)?;
+                    if i < elements.len() - 1 {
+                        write!(f, ", ")?;
+                    }
+                }
+                write!(f, "]")
+            }
+            // MODIFIED: Slice/Index display
+            Expr::Slice(array, start, end) => {
+                write!(f, "{}[", array)?;
+                if let Some(s) = start {
+                    write!(f, "{}", s)?;
+                }
+                if start.is_some() || end.is_some() {
+                    write!(f, ":")?;
+                }
+                if let Some(e) = end {
+                    write!(f, "{}", e)?;
+                }
+                write!(f, "]")
+            }
+            Expr::Call(name, args) => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    write!(f, "{}", arg)?;
+                    if i < args.len() - 1 {
+                        write!(f, ", ")?;
+                    }
+                }
+                write!(f, ")")
+            }
+            Expr::Spread(inner) => write!(f, "...{}", inner),
+            Expr::Error(span) => write!(f, "<error {}>", span),
+            Expr::Quote(_) | Expr::Expand(_) => write!(f, "{}", format_expr(self, Layout::PLAIN)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)] // Added Clone to Statement for use in the interpreter
+pub enum Statement {
+    Expr(Expr),
+    Print(Option<String>, Vec<Expr>),
+    // Function body now Vec<Statement>; the last field lists '@decorator' names, outermost first
+    Def(String, Vec<String>, Vec<Statement>, Vec<String>, Contract),
+    Return(Option<Expr>),
+    // If and Else bodies now Vec<Statement>
+    If(Expr, Vec<Statement>, Option<Vec<Statement>>),
+    // for (<var> in <iterable>) [ body ]
+    For(String, Expr, Vec<Statement>),
+    // timed("label") [ body ]; without a label one is derived from the body
+    Timed(Option<String>, Vec<Statement>),
+    // Source region that failed to parse (only produced by Parser::parse_lenient)
+    Error(Span),
+    // macro <name>(<params>) [ quote [ template ] ]; only the template is kept
+    Macro(String, Vec<String>, Vec<Statement>),
+    // Use of a macro whose template is a block of statements
+    Expand(Expansion<Vec<Statement>>),
+}
+
+/// A macro use as written, with the code it expanded to at parse time. The code is what runs;
+/// the use is what the formatter writes back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expansion<T> {
+    pub name: String,
+    pub args: Vec<Expr>,
+    // Trailing block passed as the last argument: unless (x > 0) [ ... ]
+    pub block: Option<Vec<Statement>>,
+    pub code: T,
+}
+
+/// Syntax as a value, produced by 'quote [ ... ]'. A quoted block holding a single statement is
+/// that statement, and an expression statement is its expression: `quote [ x + 1 ]` is an Infix node.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ast {
+    Expr(Expr),
+    Statement(Statement),
+    Block(Vec<Statement>),
+}
+
+impl Ast {
+    fn of_block(body: &[Statement]) -> Ast {
+        match body {
+            [stmt] => Ast::of_statement(stmt),
+            _ => Ast::Block(body.to_vec()),
+        }
+    }
+
+    fn of_statement(stmt: &Statement) -> Ast {
+        match stmt {
+            Statement::Expr(expr) => Ast::Expr(expr.clone()),
+            _ => Ast::Statement(stmt.clone()),
+        }
+    }
+
+    /// The node's variant name, as in the output of 'astra ast' ("Infix", "Call", "If", ...),
+    /// or "Block" for a quoted block of several statements.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Ast::Expr(expr) => match expr {
+                Expr::Var(_) => "Var",
+                Expr::Num(_) => "Num",
+                Expr::Str(_) => "Str",
+                Expr::Bool(_) => "Bool",
+                Expr::Prefix(..) => "Prefix",
+                Expr::Infix(..) => "Infix",
+                Expr::Cmp(..) => "Cmp",
+                Expr::Logic(..) => "Logic",
+                Expr::Array(_) => "Array",
+                Expr::Slice(..) => "Slice",
+                Expr::Call(..) => "Call",
+                Expr::Spread(_) => "Spread",
+                Expr::Error(_) => "Error",
+                Expr::Quote(_) => "Quote",
+                Expr::Expand(_) => "Expand",
+            },
+            Ast::Statement(stmt) => match stmt {
+                Statement::Expr(_) => "Expr",
+                Statement::Print(..) => "Print",
+                Statement::Def(..) => "Def",
+                Statement::Return(_) => "Return",
+                Statement::If(..) => "If",
+                Statement::For(..) => "For",
+                Statement::Timed(..) => "Timed",
+                Statement::Error(_) => "Error",
+                Statement::Macro(..) => "Macro",
+                Statement::Expand(_) => "Expand",
+            },
+            Ast::Block(_) => "Block",
+        }
+    }
+}
+
+impl fmt::Display for Ast {
+    /// Canonical source, as written by 'astra fmt'.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut text = String::new();
+        match self {
+            Ast::Expr(expr) => text = format_expr(expr, Layout::PLAIN),
+            Ast::Statement(stmt) => format_statement(stmt, 0, BlockStyle::Brackets, "", &mut text),
+            Ast::Block(body) => {
+                for stmt in body {
+                    format_statement(stmt, 0, BlockStyle::Brackets, "", &mut text);
+                }
+            }
+        }
+        write!(f, "{}", text.trim_end())
+    }
+}
+
+/// A region of the source as byte offsets, end exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// Conditions declared after a function's parameter list:
+///     fn sqrt_int(n) requires (n >= 0) ensures (result >= 0) [ ... ]
+/// They are only checked when running with --contracts.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Contract {
+    pub requires: Vec<Expr>,
+    // Evaluated after the body with the return value bound to 'result'
+    pub ensures: Vec<Expr>,
+}
+
+// --- Lexer and Token Definitions ---
+
+/// Decodes a script read from disk. A UTF-8 byte order mark is removed; invalid UTF-8 is
+/// reported with the byte offset of the first bad sequence instead of being replaced.
+pub fn decode_source(bytes: Vec<u8>) -> Result<String, String> {
+    let (bom, bytes) = match bytes.strip_prefix(b"\xEF\xBB\xBF") {
+        Some(rest) => (3, rest.to_vec()),
+        None => (0, bytes),
+    };
+    String::from_utf8(bytes).map_err(|e| {
+        let offset = e.utf8_error().valid_up_to();
+        let byte = e.as_bytes()[offset];
+        // Offsets count from the start of the file, mark included
+        message!("source is not valid UTF-8: unexpected byte 0x{} at offset {}", format!("{:02X}", byte), bom + offset)
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Ident(String),
+    Keyword(String),
+    Number(String), 
+    StringLiteral(String),
+    Op(char),
+    Cmp(String), 
+    Spread, // '...'
+    Eof,
+}
+
+pub struct Lexer {
+    input: Vec<char>,
+    // Byte offset in the original source of each char of `input`, plus one for the end
+    offsets: Vec<usize>,
+    pos: usize,
+    // Index in `input` where the last token returned by next_token starts
+    token_start: usize,
+    // Text after '; astra:' in each directive comment seen so far, with its line number
+    directives: Vec<(usize, String)>,
+    // Index in `input` of the ';' of each comment seen so far
+    comments: Vec<usize>,
+}
+
+impl Lexer {
+    pub fn new(input: &str) -> Lexer {
+        // Editors on Windows may start the file with a byte order mark and end lines with CRLF;
+        // drop the mark and read CRLF as '\n' so string literals and comments see plain newlines
+        let bom = if input.starts_with('\u{feff}') { '\u{feff}'.len_utf8() } else { 0 };
+        let mut input_chars: Vec<char> = Vec::with_capacity(input.len());
+        let mut offsets = Vec::with_capacity(input.len() + 1);
+        let mut chars = input[bom..].char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            if c == '\r' && chars.peek().is_some_and(|&(_, next)| next == '\n') {
+                continue;
+            }
+            input_chars.push(c);
+            offsets.push(bom + i);
+        }
+        offsets.push(input.len());
+        Lexer { input: input_chars, offsets, pos: 0, token_start: 0, directives: Vec::new(), comments: Vec::new() }
+    }
+
+    fn peek_char(&self) -> Option<char> {
+        self.input.get(self.pos).cloned()
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let ch = self.input.get(self.pos).cloned();
+        if ch.is_some() {
+            self.pos += 1;
+        }
+        ch
+    }
+
+    pub fn next_token(&mut self) -> Token {
+        self.skip_whitespace();
+        self.token_start = self.pos;
+        let Some(ch) = self.next_char() else {
+            return Token::Eof;
+        };
+        
+        if ch.is_ascii_digit() {
+            let mut num = ch.to_string();
+            
+            while let Some(next_ch) = self.peek_char() {
+                if next_ch.is_ascii_digit() {
+                    num.push(self.next_char().unwrap());
+                } else {
+                    break;
+                }
+            }
+            if self.peek_char() == Some('.') {
+                num.push(self.next_char().unwrap());
+                while let Some(next_ch) = self.peek_char() {
+                    if next_ch.is_ascii_digit() {
+                        num.push(self.next_char().unwrap());
+                    } else {
+                        break;
+                    }
+                }
+            }
+            // The token holds the original string representation ("1" or "1.0")
+            Token::Number(num)
+        } 
+        else if ch == '"' || ch == '\'' {
+            let delimiter = ch;
+            let mut s = String::new();
+            while let Some(next_ch) = self.next_char() {
+                if next_ch == delimiter {
+                    return Token::StringLiteral(s);
+                }
+                // Handle escape sequences
+                if next_ch == '\\' {
+                    if let Some(escaped_ch) = self.next_char() {
+                        match escaped_ch {
+                            'n' => s.push('\n'),
+                            't' => s.push('\t'),
+                            '\\' => s.push('\\'),
+                            '"' => s.push('"'),
+                            '\'' => s.push('\''),
+                            c => s.push(c),
+                        }
+                    } else {
+                        break; 
+                    }
+                } else {
+                    s.push(next_ch);
+                }
+            }
+            Token::StringLiteral(s)
+        } 
+        // '$name' stands for a macro argument; the parser only accepts it inside macro templates
+        else if ch.is_alphabetic() || ch == '_' || (ch == '$' && self.peek_char().is_some_and(|c| c.is_alphabetic() || c == '_')) {
+            let mut ident = ch.to_string();
+            while let Some(next_ch) = self.peek_char() {
+                if next_ch.is_alphanumeric() || next_ch == '_' {
+                    ident.push(self.next_char().unwrap());
+                } else {
+                    break;
+                }
+            }
+            // MODIFIED: Added 'and', 'or', 'true', and 'false' as keywords
+            if ident == "print" || ident == "def" || ident == "fn" || ident == "return" || ident == "if" || ident == "else" || ident == "and" || ident == "or" || ident == "true" || ident == "false" || ident == "for" || ident == "in" || ident == "timed" || ident == "quote" || ident == "not" || ident == "macro" {
+                Token::Keyword(ident)
+            } else {
+                Token::Ident(ident)
+            }
+        } 
+        // Compound Assignment and Single Arithmetic Operators (+, -, *, /, %, ^)
+        else if "+-*/%^".contains(ch) {
+            if self.peek_char() == Some('=') {
+                self.next_char(); // consume '='
+                // Use Cmp for compound assignment tokens to carry the string value
+                return Token::Cmp(format!("{}{}", ch, '=')); 
+            }
+            Token::Op(ch) // Single arithmetic operator
+        }
+        // Comparison and Simple Assignment (=)
+        else if ch == '=' {
+            if self.peek_char() == Some('=') {
+                self.next_char(); 
+                if self.peek_char() == Some('=') {
+                    self.next_char();
+                    return Token::Cmp("===".to_string());
+                }
+                return Token::Cmp("==".to_string());
+            }
+            Token::Op(ch) // Simple assignment '='
+        } else if ch == '!' {
+            if self.peek_char() == Some('=') {
+                self.next_char();
+                if self.peek_char() == Some('=') {
+                    self.next_char();
+                    return Token::Cmp("!==".to_string());
+                }
+                return Token::Cmp("!=".to_string());
+            }
+            Token::Op(ch) // Logical NOT operator '!'
+        } else if ch == '<' {
+            if self.peek_char() == Some('=') {
+                self.next_char();
+                return Token::Cmp("<=".to_string());
+            }
+            Token::Cmp("<".to_string())
+        } else if ch == '>' {
+            if self.peek_char() == Some('=') {
+                self.next_char();
+                return Token::Cmp(">=".to_string());
+            }
+            Token::Cmp(">".to_string())
+        }
+        else if ch == '.' && self.peek_char() == Some('.') && self.input.get(self.pos + 1) == Some(&'.') {
+            self.pos += 2; // consume the remaining '..'
+            Token::Spread
+        }
+        else {
+            Token::Op(ch)
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            if self.peek_char().is_some_and(|c| c.is_whitespace()) {
+                self.pos += 1;
+                continue;
+            }
+            
+            // Handle comments (';' until newline)
+            if self.peek_char() == Some(';') {
+                self.comments.push(self.pos);
+                self.pos += 1; 
+                let start = self.pos;
+                
+                while self.peek_char().is_some_and(|c| c != '\n') {
+                    self.pos += 1;
+                }
+                let comment: String = self.input[start..self.pos].iter().collect();
+                if let Some(directive) = comment.trim_start().strip_prefix("astra:") {
+                    let line = self.input[..start].iter().filter(|&&c| c == '\n').count() + 1;
+                    self.directives.push((line, directive.trim().to_string()));
+                }
+                continue; 
+            }
+
+            break;
+        }
+    }
+}
+
+// --- Directives ---
+
+/// Lints that `allow(...)` directives can silence.
+pub const LINTS: &[&str] = &["implicit-return"];
+
+/// Per-file settings written as comments, so a script carries its own configuration:
+///     ; astra: allow(implicit-return)
+///     ; astra: max-steps=1e6, max-output-bytes=4096
+/// Command-line flags take precedence over the limits set here.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Directives {
+    // Lints silenced for this file
+    pub allow: Vec<String>,
+    pub max_steps: Option<u64>,
+    pub max_output_bytes: Option<u64>,
+    // Problems that don't stop the script, e.g. allow(...) naming a lint this version lacks
+    pub warnings: Vec<String>,
+}
+
+impl Directives {
+    /// Parses the text after '; astra:' of each directive comment, with its line number.
+    fn parse(comments: &[(usize, String)]) -> Result<Directives, String> {
+        let mut directives = Directives::default();
+        for (line, text) in comments {
+            for item in split_directive_items(text) {
+                directives.apply(*line, item).map_err(|e| message!("Invalid directive on line {}: {}", line, e))?;
+            }
+        }
+        Ok(directives)
+    }
+
+    fn apply(&mut self, line: usize, item: &str) -> Result<(), String> {
+        if let Some(lints) = item.strip_prefix("allow(").and_then(|rest| rest.strip_suffix(')')) {
+            for lint in lints.split(',').map(str::trim) {
+                // Allowing a lint that doesn't exist changes nothing, so the script still runs
+                if !LINTS.contains(&lint) {
+                    self.warnings.push(message!("unknown lint '{}' in the directive on line {} (known lints: {})", lint, line, LINTS.join(", ")));
+                    continue;
+                }
+                self.allow.push(lint.to_string());
+            }
+            return Ok(());
+        }
+        let (name, value) = item.split_once('=').ok_or_else(|| message!("expected 'allow(...)' or 'name=value', found '{}'", item))?;
+        let (name, value) = (name.trim(), value.trim());
+        let limit = match name {
+            "max-steps" => &mut self.max_steps,
+            "max-output-bytes" => &mut self.max_output_bytes,
+            _ => return Err(message!("unknown setting '{}' (known settings: max-steps, max-output-bytes)", name)),
+        };
+        *limit = Some(parse_directive_count(value).ok_or_else(|| message!("'{}' expects a whole number, found '{}'", name, value))?);
+        Ok(())
+    }
+
+    pub fn allows(&self, lint: &str) -> bool {
+        self.allow.iter().any(|allowed| allowed == lint)
+    }
+
+    /// Fills in the limits that the options (i.e., the command line) leave unset.
+    pub fn apply_to(&self, options: &mut Options) {
+        options.max_steps = options.max_steps.or(self.max_steps);
+        options.max_output_bytes = options.max_output_bytes.or(self.max_output_bytes);
+    }
+}
+
+/// Splits a directive at the commas that are not inside parentheses: "allow(a, b), max-steps=5".
+fn split_directive_items(text: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(text[start..].trim());
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+/// A count written as an integer or in exponent form ("1e6").
+fn parse_directive_count(value: &str) -> Option<u64> {
+    if let Ok(n) = value.parse::<u64>() {
+        return Some(n);
+    }
+    let f = value.parse::<f64>().ok()?;
+    (f >= 0.0 && f.fract() == 0.0 && f < u64::MAX as f64).then_some(f as u64)
+}
+
+// --- Parser ---
+
+pub struct Parser {
+    lexer: Lexer,
+    current: Token,
+    // Constant pool: identical string literals share one allocation
+    string_pool: HashSet<Arc<str>>,
+    // Current syntax tree depth, bounded by MAX_NESTING_DEPTH
+    depth: usize,
+    // Lexer positions (char indices) where the current token starts and the previous one ended
+    current_start: usize,
+    previous_end: usize,
+    // Set by parse_lenient: syntax errors become Error nodes and are collected here
+    lenient: bool,
+    errors: Vec<(Span, String)>,
+    // Macros defined so far; a macro can be used from its definition to the end of the input
+    macros: HashMap<String, MacroDef>,
+    // Parameters of the macro whose template is being parsed, the only place '$name' is valid
+    template_params: Option<Vec<String>>,
+    // Macro uses expanded so far, numbering the variables each expansion renames
+    expansions: usize,
+    // Set by parse_legacy: older syntax is accepted, and each reinterpretation is noted here
+    legacy: bool,
+    migrations: Vec<(Span, String)>,
+    // Whether the expression expr_bp returned last ended with an operator outside parentheses
+    folded: bool,
+}
+
+/// A macro as defined by 'macro name(params) [ quote [ template ] ]'.
+#[derive(Debug, Clone)]
+struct MacroDef {
+    params: Vec<String>,
+    template: Vec<Statement>,
+}
+
+impl MacroDef {
+    /// A template holding a single expression can be used inside expressions; any other only as a statement.
+    fn is_expression(&self) -> bool {
+        matches!(self.template.as_slice(), [Statement::Expr(_)])
+    }
+}
+
+/// The syntax passed for one macro parameter.
+enum MacroArg<'a> {
+    Expr(&'a Expr),
+    Block(&'a [Statement]),
+}
+
+/// Rewrites a macro template into the code of one use. '$param' is replaced by the argument's
+/// syntax; a '$param' standing alone as a statement is replaced by the statements of a block
+/// argument. For hygiene, variables the template assigns or loops over get a name of their own
+/// ('tmp' becomes 'tmp#3' in the third expansion), so they can't clash with the caller's
+/// variables or those of other expansions. Arguments are inserted as written, and other names
+/// (functions, variables the template only reads) are resolved where the macro is used.
+struct Expander<'a> {
+    args: HashMap<&'a str, MacroArg<'a>>,
+    renamed: HashMap<String, String>,
+}
+
+impl<'a> Expander<'a> {
+    fn new(def: &'a MacroDef, args: &'a [Expr], block: Option<&'a [Statement]>, expansion: usize) -> Expander<'a> {
+        let mut bound = HashSet::new();
+        bound_in_block(&def.template, &mut bound);
+        let renamed = bound
+            .into_iter()
+            .filter(|name| !name.starts_with('$'))
+            .map(|name| {
+                let fresh = format!("{}#{}", name, expansion);
+                (name, fresh)
+            })
+            .collect();
+        let passed = args.iter().map(MacroArg::Expr).chain(block.map(MacroArg::Block));
+        let args = def.params.iter().map(|p| p.as_str()).zip(passed).collect();
+        Expander { args, renamed }
+    }
+
+    fn block(&self, statements: &[Statement]) -> Result<Vec<Statement>, String> {
+        let mut code = Vec::with_capacity(statements.len());
+        for stmt in statements {
+            match stmt {
+                Statement::Expr(Expr::Var(id)) if let Some(MacroArg::Block(block)) = self.placeholder(id) => {
+                    code.extend_from_slice(block);
+                }
+                _ => code.push(self.statement(stmt)?),
+            }
+        }
+        Ok(code)
+    }
+
+    fn statement(&self, stmt: &Statement) -> Result<Statement, String> {
+        let optional_block = |body: &Option<Vec<Statement>>| body.as_deref().map(|body| self.block(body)).transpose();
+        Ok(match stmt {
+            Statement::Expr(expr) => Statement::Expr(self.expr(expr)?),
+            Statement::Print(format_string, args) => Statement::Print(format_string.clone(), self.exprs(args)?),
+            Statement::Return(value) => Statement::Return(value.as_ref().map(|value| self.expr(value)).transpose()?),
+            Statement::If(cond, then, otherwise) => Statement::If(self.expr(cond)?, self.block(then)?, optional_block(otherwise)?),
+            Statement::For(var, iterable, body) => Statement::For(self.variable(var)?, self.expr(iterable)?, self.block(body)?),
+            Statement::Timed(label, body) => Statement::Timed(label.clone(), self.block(body)?),
+            Statement::Expand(expansion) => Statement::Expand(Expansion {
+                name: expansion.name.clone(),
+                args: self.exprs(&expansion.args)?,
+                block: optional_block(&expansion.block)?,
+                code: self.block(&expansion.code)?,
+            }),
+            // Definitions only occur at the top level, never in a template
+            Statement::Def(..) | Statement::Macro(..) | Statement::Error(_) => stmt.clone(),
+        })
+    }
+
+    fn exprs(&self, exprs: &[Expr]) -> Result<Vec<Expr>, String> {
+        exprs.iter().map(|expr| self.expr(expr)).collect()
+    }
+
+    fn expr(&self, expr: &Expr) -> Result<Expr, String> {
+        let boxed = |e: &Expr| self.expr(e).map(Box::new);
+        let optional = |e: &Option<Box<Expr>>| e.as_deref().map(boxed).transpose();
+        Ok(match expr {
+            Expr::Var(id) => match self.placeholder(id) {
+                Some(MacroArg::Expr(arg)) => (*arg).clone(),
+                Some(MacroArg::Block(_)) => {
+                    return Err(message!("The argument for '{}' is a block, so '{}' can only stand alone as a statement in the template", id, id));
+                }
+                None => Expr::Var(self.renamed.get(id).unwrap_or(id).clone()),
+            },
+            Expr::Num(_) | Expr::Str(_) | Expr::Bool(_) | Expr::Error(_) => expr.clone(),
+            Expr::Prefix(op, rhs) => Expr::Prefix(*op, boxed(rhs)?),
+            Expr::Infix(lhs, op, rhs) => Expr::Infix(boxed(lhs)?, *op, boxed(rhs)?),
+            Expr::Cmp(lhs, op, rhs) => Expr::Cmp(boxed(lhs)?, op.clone(), boxed(rhs)?),
+            Expr::Logic(lhs, op, rhs) => Expr::Logic(boxed(lhs)?, op.clone(), boxed(rhs)?),
+            Expr::Array(items) => Expr::Array(self.exprs(items)?),
+            Expr::Slice(array, start, end) => Expr::Slice(boxed(array)?, optional(start)?, optional(end)?),
+            Expr::Call(name, args) => Expr::Call(name.clone(), self.exprs(args)?),
+            Expr::Spread(inner) => Expr::Spread(boxed(inner)?),
+            Expr::Quote(body) => Expr::Quote(self.block(body)?),
+            Expr::Expand(expansion) => Expr::Expand(Box::new(Expansion {
+                name: expansion.name.clone(),
+                args: self.exprs(&expansion.args)?,
+                block: expansion.block.as_deref().map(|body| self.block(body)).transpose()?,
+                code: self.expr(&expansion.code)?,
+            })),
+        })
+    }
+
+    /// A loop variable: a '$param' must be given a variable name.
+    fn variable(&self, name: &str) -> Result<String, String> {
+        match self.placeholder(name) {
+            Some(MacroArg::Expr(Expr::Var(id))) => Ok(id.clone()),
+            Some(_) => Err(message!("The argument for '{}' must be a variable name, as it is used as a loop variable", name)),
+            None => Ok(self.renamed.get(name).map_or(name, |fresh| fresh.as_str()).to_string()),
+        }
+    }
+
+    fn placeholder(&self, id: &str) -> Option<&MacroArg<'a>> {
+        self.args.get(id.strip_prefix('$')?)
+    }
+}
+
+/// Collects the variables assigned or looped over anywhere in `statements`.
+fn bound_in_block(statements: &[Statement], bound: &mut HashSet<String>) {
+    for stmt in statements {
+        match stmt {
+            Statement::Expr(expr) => bound_in_expr(expr, bound),
+            Statement::Print(_, args) => args.iter().for_each(|arg| bound_in_expr(arg, bound)),
+            Statement::Return(value) => value.iter().for_each(|value| bound_in_expr(value, bound)),
+            Statement::If(cond, then, otherwise) => {
+                bound_in_expr(cond, bound);
+                bound_in_block(then, bound);
+                otherwise.iter().for_each(|body| bound_in_block(body, bound));
+            }
+            Statement::For(var, iterable, body) => {
+                bound.insert(var.clone());
+                bound_in_expr(iterable, bound);
+                bound_in_block(body, bound);
+            }
+            Statement::Timed(_, body) => bound_in_block(body, bound),
+            // Only the code runs; the arguments are part of it where the template used them
+            Statement::Expand(expansion) => bound_in_block(&expansion.code, bound),
+            Statement::Def(..) | Statement::Macro(..) | Statement::Error(_) => {}
+        }
+    }
+}
+
+fn bound_in_expr(expr: &Expr, bound: &mut HashSet<String>) {
+    match expr {
+        Expr::Infix(lhs, op, rhs) => {
+            if let (Expr::Var(id), '=') = (&**lhs, op) {
+                bound.insert(id.clone());
+            }
+            bound_in_expr(lhs, bound);
+            bound_in_expr(rhs, bound);
+        }
+        Expr::Cmp(lhs, _, rhs) | Expr::Logic(lhs, _, rhs) => {
+            bound_in_expr(lhs, bound);
+            bound_in_expr(rhs, bound);
+        }
+        Expr::Prefix(_, inner) | Expr::Spread(inner) => bound_in_expr(inner, bound),
+        Expr::Array(items) | Expr::Call(_, items) => items.iter().for_each(|item| bound_in_expr(item, bound)),
+        Expr::Slice(array, start, end) => {
+            bound_in_expr(array, bound);
+            start.iter().chain(end).for_each(|e| bound_in_expr(e, bound));
+        }
+        Expr::Quote(body) => bound_in_block(body, bound),
+        Expr::Expand(expansion) => bound_in_expr(&expansion.code, bound),
+        Expr::Var(_) | Expr::Num(_) | Expr::Str(_) | Expr::Bool(_) | Expr::Error(_) => {}
+    }
+}
+
+/// Deepest syntax tree the parser builds. Evaluation, formatting and dropping the tree all recurse
+/// once per level, so the limit keeps adversarial input (e.g., 100k nested parentheses) from
+/// overflowing the stack.
+const MAX_NESTING_DEPTH: usize = 256;
+
+/// Keywords that can only start a statement; lenient parsing resynchronizes on them.
+const STATEMENT_KEYWORDS: &[&str] = &["print", "fn", "return", "if", "for", "timed", "macro"];
+
+impl Parser {
+    pub fn new(input: &str) -> Parser {
+        let mut lexer = Lexer::new(input);
+        let current = lexer.next_token();
+        let current_start = lexer.token_start;
+        Parser {
+            lexer,
+            current,
+            string_pool: HashSet::new(),
+            depth: 0,
+            current_start,
+            previous_end: 0,
+            lenient: false,
+            errors: Vec::new(),
+            macros: HashMap::new(),
+            template_params: None,
+            expansions: 0,
+            legacy: false,
+            migrations: Vec::new(),
+            folded: false,
+        }
+    }
+
+    /// Returns the pooled copy of a string literal, adding it to the pool on first use.
+    fn intern(&mut self, s: String) -> Arc<str> {
+        if let Some(existing) = self.string_pool.get(s.as_str()) {
+            return existing.clone();
+        }
+        let interned: Arc<str> = Arc::from(s);
+        self.string_pool.insert(interned.clone());
+        interned
+    }
+
+    /// Consumes a run of adjacent string literals ("foo" "bar") and returns them concatenated.
+    /// The caller must ensure self.current is a string literal.
+    fn parse_string_literals(&mut self) -> String {
+        let mut combined = String::new();
+        while let Token::StringLiteral(s) = &self.current {
+            combined.push_str(s);
+            self.advance();
+        }
+        combined
+    }
+
+    /// Goes one level deeper into the syntax tree, failing cleanly past MAX_NESTING_DEPTH.
+    fn nest(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING_DEPTH {
+            return Err(message!(
+                "Expression or block is nested more than {} levels deep (each operator of a chain like 1 + 2 + 3 counts as a level); split it into smaller parts",
+                MAX_NESTING_DEPTH
+            ));
+        }
+        Ok(())
+    }
+
+    /// Runs `parse` one level deeper, restoring the depth afterwards (also on errors).
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Parser) -> Result<T, String>) -> Result<T, String> {
+        let saved = self.depth;
+        let result = self.nest().and_then(|()| parse(self));
+        self.depth = saved;
+        result
+    }
+
+    fn advance(&mut self) {
+        self.previous_end = self.lexer.pos;
+        self.current = self.lexer.next_token();
+        self.current_start = self.lexer.token_start;
+        //debug!("Advanced to token {:?}", self.current);
+    }
+
+    pub fn parse(&mut self) -> Result<Vec<Statement>, String> {
+        let mut statements = Vec::new();
+        while self.current != Token::Eof {
+            //debug!("Parsing statement, current token: {:?}", self.current);
+            let stmt = self.statement_or_error(false, |parser| match parser.current.clone() {
+                Token::Keyword(k) if k == "print" => parser.parse_print_statement(),
+                Token::Keyword(k) if k == "fn" => parser.parse_fn_statement(),
+                Token::Op('@') => parser.parse_decorated_fn_statement(),
+                Token::Keyword(k) if k == "return" => parser.parse_return_statement(),
+                Token::Keyword(k) if k == "if" => parser.parse_if_statement(),
+                Token::Keyword(k) if k == "for" => parser.parse_for_statement(),
+                Token::Keyword(k) if k == "timed" => parser.parse_timed_statement(),
+                Token::Keyword(k) if k == "macro" => parser.parse_macro_statement(),
+                // Defensive check: The assignment operator cannot start a statement.
+                Token::Op('=') => {
+                    Err(message!("The assignment operator '=' cannot start a statement. Assignment must follow a variable (e.g., x = 10)."))
+                }
+                Token::Keyword(k) if k == "def" && parser.legacy => {
+                    let (start, index) = (parser.current_start, parser.migrations.len());
+                    let def = parser.parse_fn_statement()?;
+                    // Ahead of the notes on the body
+                    parser.migrations.insert(index, (parser.span_from(start), message!("'def' is now 'fn'")));
+                    Ok(def)
+                }
+                Token::Keyword(k) if k == "def" => Err(message!("The 'def' keyword is deprecated. Please use 'fn' for function definitions (e.g., fn name(...) [...])")),
+                Token::Keyword(k) if k == "else" => Err(message!("The 'else' keyword must immediately follow the body of an 'if'.")),
+                _ => parser.parse_expression_statement(),
+            })?;
+            statements.push(stmt);
+        }
+        Ok(statements)
+    }
+
+    /// Parses the whole input without stopping at syntax errors, for tools that work on broken
+    /// files: each statement that fails to parse becomes a Statement::Error, and a bad call
+    /// argument or array element an Expr::Error, covering the skipped source. The messages are
+    /// available from `errors`.
+    pub fn parse_lenient(&mut self) -> Vec<Statement> {
+        self.lenient = true;
+        // Every statement error is recovered from in lenient mode
+        self.parse().unwrap_or_default()
+    }
+
+    /// Parses a script written for an older version of the language, for 'astra migrate': 'def'
+    /// is accepted for 'fn', and a function body may be a string of source code ('def f(x) "return x"').
+    /// Formatting the result writes it in the current syntax. As with parse_lenient, statements
+    /// that still don't parse become Statement::Error; `migrations` lists what was reinterpreted,
+    /// and comparisons next to 'and' / 'or' without parentheses, which older scripts may have
+    /// meant to group differently.
+    pub fn parse_legacy(&mut self) -> Vec<Statement> {
+        self.legacy = true;
+        self.parse_lenient()
+    }
+
+    /// What parse_legacy read differently from the current syntax, or could not be sure of, in
+    /// source order.
+    pub fn migrations(&self) -> &[(Span, String)] {
+        &self.migrations
+    }
+
+    /// Byte offset of each comment's ';' in the input. Call after parsing; the formatter drops
+    /// comments, so tools that rewrite a file can warn about them.
+    pub fn comments(&self) -> Vec<usize> {
+        let mut offsets: Vec<usize> = self.lexer.comments.iter().map(|&i| self.lexer.offsets[i]).collect();
+        // Statements skipped in lenient mode are lexed again
+        offsets.sort_unstable();
+        offsets.dedup();
+        offsets
+    }
+
+    /// The '; astra:' directives of the input. Call after parsing, once the lexer has seen every comment.
+    pub fn directives(&self) -> Result<Directives, String> {
+        Directives::parse(&self.lexer.directives)
+    }
+
+    /// Syntax errors collected by `parse_lenient`, in source order, with the region each one replaced.
+    pub fn errors(&self) -> &[(Span, String)] {
+        &self.errors
+    }
+
+    /// Source span from the token starting at char index `start` to the end of the last consumed token.
+    fn span_from(&self, start: usize) -> Span {
+        let start_byte = self.lexer.offsets[start];
+        // Measured from the last char rather than the next one, which may follow a dropped '\r'
+        let end_byte = match self.previous_end.checked_sub(1) {
+            Some(last) if self.previous_end > start => self.lexer.offsets[last] + self.lexer.input[last].len_utf8(),
+            _ => start_byte,
+        };
+        Span { start: start_byte, end: end_byte }
+    }
+
+    /// Restarts lexing at char index `start`.
+    fn rewind(&mut self, start: usize) {
+        self.lexer.pos = start;
+        self.advance();
+    }
+
+    /// Runs `parse` for one statement. In lenient mode a failure is recorded and the statement's
+    /// tokens are skipped (see skip_statement), leaving a Statement::Error in its place.
+    fn statement_or_error(
+        &mut self,
+        in_block: bool,
+        parse: impl FnOnce(&mut Parser) -> Result<Statement, String>,
+    ) -> Result<Statement, String> {
+        let (start, recorded, migrated) = (self.current_start, self.errors.len(), self.migrations.len());
+        match parse(self) {
+            Err(e) if self.lenient => {
+                let span = self.skip_statement(start, in_block);
+                // Errors recovered inside the failed statement are covered by this one, and it is kept as it was
+                self.errors.truncate(recorded);
+                self.migrations.truncate(migrated);
+                self.errors.push((span, e));
+                Ok(Statement::Error(span))
+            }
+            result => result,
+        }
+    }
+
+    /// Skips a broken statement starting at char index `start`: everything up to the next token
+    /// that begins a line outside any brackets, or, inside a block, up to the block's closer.
+    /// A statement keyword at the start of a line also ends it, so an unclosed bracket doesn't
+    /// swallow the rest of the file. At least one token is always consumed.
+    fn skip_statement(&mut self, start: usize, in_block: bool) -> Span {
+        self.rewind(start);
+        let mut depth = 0usize;
+        let mut consumed = false;
+        loop {
+            let starts_line = self.lexer.input[self.previous_end..self.current_start].contains(&'\n');
+            match self.current {
+                Token::Eof => break,
+                _ if consumed && depth == 0 && starts_line => break,
+                Token::Keyword(ref k) if consumed && starts_line && STATEMENT_KEYWORDS.contains(&k.as_str()) => break,
+                Token::Op(']' | '}') if consumed && depth == 0 && in_block => break,
+                Token::Op('(' | '[' | '{') => depth += 1,
+                Token::Op(')' | ']' | '}') => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            self.advance();
+            consumed = true;
+        }
+        self.span_from(start)
+    }
+
+    /// Parses one element of a list closed by `closer` (call arguments, array literals). In lenient
+    /// mode an element that fails to parse, or is not followed by ',' or `closer`, is skipped and
+    /// becomes an Expr::Error; if the list itself is unterminated the error is passed on.
+    fn list_element(
+        &mut self,
+        closer: char,
+        what: &str,
+        parse: impl FnOnce(&mut Parser) -> Result<Expr, String>,
+    ) -> Result<Expr, String> {
+        if !self.lenient {
+            return parse(self);
+        }
+        let (start, recorded) = (self.current_start, self.errors.len());
+        let error = match parse(self) {
+            Ok(expr) if matches!(self.current, Token::Op(c) if c == ',' || c == closer) => return Ok(expr),
+            Ok(_) => message!("Expected ',' or '{}' in {}, found {:?}", closer, what, self.current),
+            Err(e) => e,
+        };
+        self.rewind(start);
+        let mut depth = 0usize;
+        loop {
+            match self.current {
+                Token::Op(c) if depth == 0 && (c == ',' || c == closer) => break,
+                Token::Eof | Token::Op(')' | ']' | '}') if depth == 0 => return Err(error),
+                Token::Op('(' | '[' | '{') => depth += 1,
+                Token::Op(')' | ']' | '}') => depth -= 1,
+                _ => {}
+            }
+            self.advance();
+        }
+        let span = self.span_from(start);
+        self.errors.truncate(recorded);
+        self.errors.push((span, error));
+        Ok(Expr::Error(span))
+    }
+
+    /// Parses a block delimited by either '[ ... ]' or '{ ... }'. Braces avoid the visual clash
+    /// with array literals and indexing; the closing delimiter must match the opening one.
+    /// `what` names the construct in diagnostics (e.g., "if body").
+    fn parse_block(&mut self, what: &str) -> Result<Vec<Statement>, String> {
+        let closer = match self.current {
+            Token::Op('[') => ']',
+            Token::Op('{') => '}',
+            _ => return Err(message!("Expected '[' or '{{' to start {}, found {:?}", what, self.current)),
+        };
+        self.advance(); // Consume the opening delimiter
+        self.parse_block_body(closer)
+    }
+
+    // CHANGE: parse_block_body now returns Vec<Statement> and directly parses tokens
+    fn parse_block_body(&mut self, closer: char) -> Result<Vec<Statement>, String> {
+        // The calling function (parse_block) must ensure self.current is the token *after* the opener
+        let mut statements = Vec::new();
+
+        // Loop until the closing delimiter or EOF
+        while self.current != Token::Op(closer) && self.current != Token::Eof {
+            let stmt = self.statement_or_error(true, |parser| match parser.current {
+                Token::Op(other @ (']' | '}')) => {
+                    Err(message!("Mismatched block delimiter: expected '{}' to close the block, found '{}'", closer, other))
+                }
+                _ => parser.parse_block_statement(),
+            })?;
+            statements.push(stmt);
+        }
+        
+        if self.current != Token::Op(closer) {
+            return Err(message!("Unclosed block body. Expected matching '{}', found {:?}", closer, self.current));
+        }
+
+        self.advance(); // consume the closing delimiter
+        
+        Ok(statements)
+    }
+
+    /// Parses one statement inside a block (or a bracketless if/else body).
+    fn parse_block_statement(&mut self) -> Result<Statement, String> {
+        self.nested(Parser::parse_block_statement_at_depth)
+    }
+
+    fn parse_block_statement_at_depth(&mut self) -> Result<Statement, String> {
+        match self.current.clone() {
+            // Include all recognized statement types (except 'fn', which should only be top-level)
+            Token::Keyword(k) if k == "print" => self.parse_print_statement(),
+            Token::Keyword(k) if k == "return" => self.parse_return_statement(),
+            Token::Keyword(k) if k == "if" => self.parse_if_statement(),
+            Token::Keyword(k) if k == "for" => self.parse_for_statement(),
+            Token::Keyword(k) if k == "timed" => self.parse_timed_statement(),
+            // Ensure proper error handling for deprecated/misplaced keywords
+            Token::Keyword(k) if k == "def" => Err(message!("The 'def' keyword is deprecated.")),
+            Token::Keyword(k) if k == "else" => Err(message!("The 'else' keyword must immediately follow the body of an 'if'.")),
+            Token::Keyword(k) if k == "fn" => Err(message!("Function definitions are only allowed at the top level.")),
+            Token::Keyword(k) if k == "macro" => Err(message!("Macro definitions are only allowed at the top level.")),
+            Token::Op('@') => Err(message!("Decorated function definitions are only allowed at the top level.")),
+            Token::Op('=') => Err(message!("The assignment operator '=' cannot start a statement.")),
+            // Default: parse as an expression statement
+            _ => self.parse_expression_statement(),
+        }
+    }
+
+    /// Parses an expression statement, or the use of a macro whose template is a block of statements.
+    fn parse_expression_statement(&mut self) -> Result<Statement, String> {
+        if let Token::Ident(name) = &self.current
+            && self.macros.get(name).is_some_and(|def| !def.is_expression())
+        {
+            let name = name.clone();
+            self.advance(); // consume the macro name
+            return Ok(Statement::Expand(self.parse_macro_use(name)?));
+        }
+        Ok(Statement::Expr(self.expr_bp(0)?))
+    }
+
+    /// Parses an if/else body: either a block or a single statement,
+    /// which allows terse guard clauses such as: if (x < 0) return -1
+    fn parse_if_body(&mut self) -> Result<Vec<Statement>, String> {
+        if self.current == Token::Op('[') || self.current == Token::Op('{') {
+            return self.parse_block("if body");
+        }
+        if self.current == Token::Eof {
+            return Err(message!("Expected a block or a statement for the if body, found end of input"));
+        }
+        Ok(vec![self.parse_block_statement()?])
+    }
+
+    fn parse_if_statement(&mut self) -> Result<Statement, String> {
+        //debug!("Parsing if statement");
+        self.advance(); // consume 'if'
+
+        if self.current != Token::Op('(') {
+            return Err(message!("Expected '(' after 'if', found {:?}", self.current));
+        }
+        self.advance(); // consume '('
+
+        let condition = self.expr_bp(0)?;
+
+        if self.current != Token::Op(')') {
+            return Err(message!("Expected ')' after if condition, found {:?}", self.current));
+        }
+        self.advance(); // consume ')'
+
+        // CHANGE: if_body is now Vec<Statement>
+        let if_body_statements = self.parse_if_body()?;
+
+        let mut else_body_statements: Option<Vec<Statement>> = None;
+
+        if let Token::Keyword(k) = self.current.clone() && k == "else" {
+            //debug!("Found 'else' keyword");
+            self.advance(); // consume 'else'
+            
+            // CHANGE: else_body is now Vec<Statement>
+            else_body_statements = Some(self.parse_if_body()?);
+        }
+        
+        debug!("Parsed if statement with condition {:?}, if body {:?}, and else body {:?}", condition, if_body_statements, else_body_statements);
+        // CHANGE: Store the Vec<Statement>
+        Ok(Statement::If(condition, if_body_statements, else_body_statements))
+    }
+
+    fn parse_for_statement(&mut self) -> Result<Statement, String> {
+        self.advance(); // consume 'for'
+
+        // The header is parenthesized like 'if', so a '[' body is not mistaken for indexing
+        if self.current != Token::Op('(') {
+            return Err(message!("Expected '(' after 'for', found {:?}", self.current));
+        }
+        self.advance(); // consume '('
+
+        let var_name = match self.current.clone() {
+            Token::Ident(id) => {
+                self.check_placeholder(&id)?;
+                self.advance();
+                id
+            }
+            _ => return Err(message!("Expected loop variable name after 'for', found {:?}", self.current)),
+        };
+
+        if self.current != Token::Keyword("in".to_string()) {
+            return Err(message!("Expected 'in' after loop variable '{}', found {:?}", var_name, self.current));
+        }
+        self.advance(); // consume 'in'
+
+        let iterable = self.expr_bp(0)?;
+
+        if self.current != Token::Op(')') {
+            return Err(message!("Expected ')' after for header, found {:?}", self.current));
+        }
+        self.advance(); // consume ')'
+
+        let body_statements = self.parse_block("for body")?;
+
+        debug!("Parsed for statement: for {} in {:?} [{:?}]", var_name, iterable, body_statements);
+        Ok(Statement::For(var_name, iterable, body_statements))
+    }
+
+    fn parse_timed_statement(&mut self) -> Result<Statement, String> {
+        self.advance(); // consume 'timed'
+
+        let label = if self.current == Token::Op('(') {
+            self.advance(); // consume '('
+            let label = match self.current.clone() {
+                Token::StringLiteral(s) => {
+                    self.advance();
+                    s
+                }
+                _ => return Err(message!("Expected a string label in timed(...), found {:?}", self.current)),
+            };
+            if self.current != Token::Op(')') {
+                return Err(message!("Expected ')' after timed label, found {:?}", self.current));
+            }
+            self.advance(); // consume ')'
+            Some(label)
+        } else {
+            None
+        };
+
+        let body_statements = self.parse_block("timed body (e.g., timed(\"label\") [body])")?;
+        Ok(Statement::Timed(label, body_statements))
+    }
+
+    fn parse_return_statement(&mut self) -> Result<Statement, String> {
+        debug!("Parsing return statement");
+        self.advance(); // consume 'return' keyword
+
+        // FIX E0408: Split the match arms to prevent the compiler error about unbound variables.
+        let has_expr = match self.current.clone() {
+            // All expression starters that don't need a custom guard
+            Token::Number(_) | Token::StringLiteral(_) | Token::Op('(') | Token::Op('[') | Token::Ident(_) | Token::Op('+') | Token::Op('-') | Token::Op('!') => true, // <--- MODIFIED: Added Token::Op('!')
+            
+            // The Keyword case, which requires checking the inner string
+            Token::Keyword(k) if k == "true" || k == "false" || k == "quote" || k == "not" => true,
+            
+            _ => false,
+        };
+
+        let return_expr = if has_expr {
+            let expr = self.expr_bp(0)?;
+            Some(expr)
+        } else {
+            None
+        };
+
+        debug!("Parsed return statement: Return({:?})", return_expr);
+        Ok(Statement::Return(return_expr))
+    }
+
+    fn parse_print_statement(&mut self) -> Result<Statement, String> {
+        //debug!("Parsing print statement");
+        self.advance(); // Consume 'print'
+        if self.current != Token::Op('(') {
+            return Err(message!("Expected '(' after 'print', found {:?}", self.current));
+        }
+        self.advance(); // Consume '('
+
+        let mut format_string: Option<String> = None;
+        let mut expressions = Vec::new();
+
+        if let Token::StringLiteral(_) = self.current {
+            let template = self.parse_string_literals();
+            parse_format_string(&template)?;
+            format_string = Some(template);
+
+            while self.current == Token::Op(',') {
+                self.advance();
+                // Tolerate a trailing comma, as in function calls
+                if self.current == Token::Op(')') {
+                    break;
+                }
+                //debug!("Parsing print argument (formatted), current token: {:?}", self.current);
+                let expr = self.expr_bp(0)?;
+                expressions.push(expr);
+            }
+
+        } else if self.current != Token::Op(')') {
+            //debug!("Parsing print argument (simple), current token: {:?}", self.current);
+            let expr = self.expr_bp(0)?;
+            expressions.push(expr);
+
+            // A trailing comma is tolerated here too: print(x,)
+            if self.current == Token::Op(',') {
+                self.advance();
+                if self.current != Token::Op(')') {
+                    return Err(message!("When using 'print(expr)' format (without a format string), only a single expression is allowed. Found ',' after argument: {:?}", expressions[0]));
+                }
+            }
+        }
+        
+        if self.current != Token::Op(')') {
+            return Err(message!("Expected closing ')' after print arguments, found {:?}", self.current));
+        }
+        self.advance(); // Consume ')'
+        debug!("Parsed print statement: Print({:?}, {:?})", format_string, expressions);
+        Ok(Statement::Print(format_string, expressions))
+    }
+
+    fn parse_fn_statement(&mut self) -> Result<Statement, String> {
+        //debug!("Parsing fn statement");
+        self.advance();
+        let fn_name = match self.current.clone() {
+            Token::Ident(id) => {
+                self.advance();
+                id
+            }
+            _ => return Err(message!("Expected function name (identifier) after 'fn', found {:?}", self.current)),
+        };
+        if self.current != Token::Op('(') {
+            return Err(message!(
+                "Expected '(' to start parameter list in function definition, found {:?}. Syntax must be: fn {}() [...]", 
+                self.current, fn_name
+            ));
+        }
+        self.advance();
+        let params = self.parse_parameters("function definition")?;
+        let contract = self.parse_contract(&fn_name)?;
+        let body_statements = match self.current {
+            Token::StringLiteral(_) if self.legacy => self.parse_string_body(&fn_name)?,
+            // CHANGE: raw_body is now a Vec<Statement>
+            _ => self.parse_block(&format!("function body (e.g., fn {}() [body])", fn_name))?,
+        };
+        
+        debug!("Parsed fn {}({:?}) [{:?}]", fn_name, params, body_statements);
+        // CHANGE: Store the Vec<Statement>
+        Ok(Statement::Def(fn_name, params, body_statements, Vec::new(), contract))
+    }
+
+    /// A legacy function body given as a string of source code, parsed as a block.
+    fn parse_string_body(&mut self, fn_name: &str) -> Result<Vec<Statement>, String> {
+        let start = self.current_start;
+        let source = self.parse_string_literals();
+        let mut body = Parser::new(&source);
+        body.legacy = true;
+        let statements = body.parse().map_err(|e| message!("The string body of '{}' could not be translated: {}", fn_name, e))?;
+        let span = self.span_from(start);
+        self.migrations.push((span, message!("The string body of '{}' is now a block", fn_name)));
+        // Positions inside the string don't map to the file, so notes point at the whole string
+        self.migrations.extend(body.migrations.into_iter().map(|(_, note)| (span, note)));
+        Ok(statements)
+    }
+
+    /// Parses any 'requires (cond)' / 'ensures (cond)' clauses between the parameter list and the body.
+    /// Both words are only special in this position, so they remain usable as names elsewhere.
+    fn parse_contract(&mut self, fn_name: &str) -> Result<Contract, String> {
+        let mut contract = Contract::default();
+        while let Token::Ident(word) = self.current.clone() {
+            if word != "requires" && word != "ensures" {
+                break;
+            }
+            self.advance();
+            if self.current != Token::Op('(') {
+                return Err(message!("Expected '(' after '{}' in function '{}', found {:?}", word, fn_name, self.current));
+            }
+            self.advance();
+            let condition = self.expr_bp(0)?;
+            if self.current != Token::Op(')') {
+                return Err(message!("Expected ')' to close '{}' condition in function '{}', found {:?}", word, fn_name, self.current));
+            }
+            self.advance();
+            if word == "requires" {
+                contract.requires.push(condition);
+            } else {
+                contract.ensures.push(condition);
+            }
+        }
+        Ok(contract)
+    }
+
+    /// Parses a parameter list up to and including its ')'; `what` names the definition in diagnostics.
+    fn parse_parameters(&mut self, what: &str) -> Result<Vec<String>, String> {
+        let mut params = Vec::new();
+        while self.current != Token::Op(')') {
+            let param_name = match self.current.clone() {
+                Token::Ident(id) if !id.starts_with('$') => {
+                    self.advance();
+                    params.push(id.clone());
+                    id
+                }
+                Token::Eof => return Err(message!("Unclosed parameter list in {}. Expected ')'", what)),
+                _ => return Err(message!("Expected parameter name or ')' in {}, found {:?}", what, self.current)),
+            };
+            // A trailing comma is allowed: fn f(a, b,) [...]
+            if self.current == Token::Op(',') {
+                self.advance();
+            } else if self.current != Token::Op(')') {
+                return Err(message!("Expected ',' or ')' after parameter {}, found {:?}", param_name, self.current));
+            }
+        }
+        self.advance();
+        Ok(params)
+    }
+
+    /// Parses a macro definition. The body is a quoted template in which '$param' stands for the
+    /// syntax passed as that argument:
+    ///     macro unless(cond, body) [ quote [ if (not $cond) [ $body ] ] ]
+    /// Later uses are expanded as they are parsed (see parse_macro_use).
+    fn parse_macro_statement(&mut self) -> Result<Statement, String> {
+        self.advance(); // consume 'macro'
+        let name = match self.current.clone() {
+            Token::Ident(id) if !id.starts_with('$') => {
+                self.advance();
+                id
+            }
+            _ => return Err(message!("Expected macro name (identifier) after 'macro', found {:?}", self.current)),
+        };
+        if self.current != Token::Op('(') {
+            return Err(message!("Expected '(' to start parameter list in macro definition, found {:?}. Syntax must be: macro {}() [ quote [...] ]", self.current, name));
+        }
+        self.advance();
+        let params = self.parse_parameters("macro definition")?;
+
+        let outer = self.template_params.replace(params.clone());
+        let body = self.parse_block(&format!("macro body (e.g., macro {}() [ quote [...] ])", name));
+        self.template_params = outer;
+        let mut body = body?;
+        let template = match body.pop() {
+            Some(Statement::Expr(Expr::Quote(template))) if body.is_empty() => template,
+            _ => return Err(message!("The body of macro '{}' must be a single quoted template: macro {}(...) [ quote [...] ]", name, name)),
+        };
+
+        debug!("Parsed macro {}({:?}) [{:?}]", name, params, template);
+        self.macros.insert(name.clone(), MacroDef { params: params.clone(), template: template.clone() });
+        Ok(Statement::Macro(name, params, template))
+    }
+
+    /// Parses the arguments of a use of macro `name`, whose name has been consumed, and expands it.
+    /// Arguments are parsed as expressions; when the list is one short of the parameters, a block
+    /// after it is passed as the last one: unless (done) [ step() ].
+    fn parse_macro_use(&mut self, name: String) -> Result<Expansion<Vec<Statement>>, String> {
+        let def = self.macros[&name].clone();
+        if self.current != Token::Op('(') {
+            return Err(message!("Expected '(' after macro '{}', found {:?}. Syntax must be: {}({})", name, self.current, name, def.params.join(", ")));
+        }
+        self.advance(); // consume '('
+        let args = self.parse_arguments()?;
+        let block = if args.len() + 1 == def.params.len() && matches!(self.current, Token::Op('[' | '{')) {
+            Some(self.parse_block(&format!("block argument of macro '{}'", name))?)
+        } else {
+            None
+        };
+        let found = args.len() + usize::from(block.is_some());
+        if found != def.params.len() {
+            return Err(message!("Macro '{}' expects {} arguments ({}), found {}", name, def.params.len(), def.params.join(", "), found));
+        }
+
+        self.expansions += 1;
+        let code = Expander::new(&def, &args, block.as_deref(), self.expansions).block(&def.template)?;
+        debug!("Expanded macro {} to {:?}", name, code);
+        Ok(Expansion { name, args, block, code })
+    }
+
+    /// '$name' is only valid inside the template of a macro that has a parameter 'name'.
+    fn check_placeholder(&self, id: &str) -> Result<(), String> {
+        let Some(param) = id.strip_prefix('$') else {
+            return Ok(());
+        };
+        match &self.template_params {
+            Some(params) if params.iter().any(|p| p == param) => Ok(()),
+            Some(_) => Err(message!("'{}' does not name a parameter of the macro being defined", id)),
+            None => Err(message!("'{}' is only allowed in a macro template, where it stands for an argument", id)),
+        }
+    }
+
+    /// Parses one or more '@name' lines followed by a function definition:
+    ///     @memoize
+    ///     fn fib(n) [ ... ]
+    fn parse_decorated_fn_statement(&mut self) -> Result<Statement, String> {
+        let mut decorators = Vec::new();
+        while self.current == Token::Op('@') {
+            self.advance(); // consume '@'
+            match self.current.clone() {
+                // 'timed' is also the timed-block keyword
+                Token::Ident(name) | Token::Keyword(name) if name != "fn" => {
+                    self.advance();
+                    decorators.push(name);
+                }
+                _ => return Err(message!("Expected decorator name after '@', found {:?}", self.current)),
+            }
+        }
+        match self.current.clone() {
+            Token::Keyword(k) if k == "fn" => {}
+            _ => return Err(message!("Expected 'fn' after decorator '@{}', found {:?}", decorators.join(" @"), self.current)),
+        }
+        match self.parse_fn_statement()? {
+            Statement::Def(name, params, body, _, contract) => Ok(Statement::Def(name, params, body, decorators, contract)),
+            _ => unreachable!("parse_fn_statement always returns Statement::Def"),
+        }
+    }
+
+    fn parse_arguments(&mut self) -> Result<Vec<Expr>, String> {
+        let mut args = Vec::new();
+        if self.current == Token::Op(')') {
+            self.advance();
+            return Ok(args);
+        }
+        loop {
+            debug!("Parsing argument, current token: {:?}", self.current);
+            let arg_expr = self.list_element(')', "function call arguments", |parser| {
+                if parser.current == Token::Spread {
+                    parser.advance(); // consume '...'
+                    Ok(Expr::Spread(Box::new(parser.expr_bp(0)?)))
+                } else {
+                    parser.expr_bp(0)
+                }
+            })?;
+            args.push(arg_expr);
+            if self.current == Token::Op(')') {
+                self.advance();
+                break;
+            } else if self.current == Token::Op(',') {
+                self.advance();
+                // A trailing comma before ')' is allowed: f(1, 2,)
+                if self.current == Token::Op(')') {
+                    self.advance();
+                    break;
+                }
+            } else {
+                return Err(message!("Expected ',' or ')' in function call arguments, found {:?}", self.current));
+            }
+        }
+        Ok(args)
+    }
+
+    fn expr_bp(&mut self, min_bp: u8) -> Result<Expr, String> {
+        self.nested(|parser| parser.expr_bp_at_depth(min_bp))
+    }
+
+    // The prefix and postfix forms below are kept out of expr_bp_at_depth, which is on the stack
+    // once per nesting level: its frame stays small even in debug builds, where every local of a
+    // function gets a slot of its own.
+
+    /// A variable, a call, or the use of an expression macro.
+    fn parse_identifier(&mut self, id: String) -> Result<Expr, String> {
+        self.check_placeholder(&id)?;
+        self.advance();
+        if self.macros.contains_key(&id) {
+            let Expansion { name, args, block, mut code } = self.parse_macro_use(id)?;
+            match code.pop() {
+                Some(Statement::Expr(expr)) if code.is_empty() => Ok(Expr::Expand(Box::new(Expansion { name, args, block, code: expr }))),
+                _ => Err(message!("Macro '{}' expands to statements, so it can only be used as a statement", name)),
+            }
+        } else if self.current == Token::Op('(') {
+            self.advance();
+            let args = self.parse_arguments()?;
+            Ok(Expr::Call(id, args))
+        } else {
+            Ok(Expr::Var(id))
+        }
+    }
+
+    fn parse_array_literal(&mut self) -> Result<Expr, String> {
+        self.advance(); // consume '['
+        let mut elements = Vec::new();
+
+        if self.current == Token::Op(']') {
+            // Empty array: no early return, so operators still apply (e.g., [] == xs)
+            self.advance(); // consume ']'
+        } else {
+            loop {
+                let expr = self.list_element(']', "array literal", |parser| parser.expr_bp(0))?;
+                elements.push(expr);
+
+                if self.current == Token::Op(']') {
+                    self.advance(); // consume ']'
+                    break;
+                } else if self.current == Token::Op(',') {
+                    self.advance(); // consume ','
+                } else {
+                    return Err(message!("Expected ',' or ']' in array literal, found {:?}", self.current));
+                }
+            }
+        }
+        Ok(Expr::Array(elements))
+    }
+
+    /// Indexing (arr[i]) or slicing (arr[start:end]) of `lhs`; the current token is the '['.
+    fn parse_index(&mut self, lhs: Expr) -> Result<Expr, String> {
+        self.advance(); // consume '['
+
+        // Parse the start expression (optional: [expr:...)
+        let mut start_expr: Option<Expr> = None;
+        if self.current != Token::Op(':') && self.current != Token::Op(']') {
+            start_expr = Some(self.expr_bp(0)?);
+        }
+
+        if self.current == Token::Op(':') {
+            // Slicing: arr[start:end] or arr[:end] or arr[start:]
+            self.advance(); // consume ':'
+
+            // Parse the end expression (optional: ...:expr])
+            let mut end_expr: Option<Expr> = None;
+            if self.current != Token::Op(']') {
+                end_expr = Some(self.expr_bp(0)?);
+            }
+
+            if self.current != Token::Op(']') {
+                return Err(message!("Expected ']' after slice expression, found {:?}", self.current));
+            }
+            self.advance(); // consume ']'
+
+            // The Slice expression (arr[start:end])
+            Ok(Expr::Slice(Box::new(lhs), start_expr.map(Box::new), end_expr.map(Box::new)))
+        } else if self.current == Token::Op(']') {
+            // Indexing: arr[index] (where index is the sole expression)
+            self.advance(); // consume ']'
+
+            let index_expr = start_expr
+                .ok_or_else(|| message!("Array index expression missing for simple indexing"))?;
+
+            // Simple indexing is represented as a slice with only the start expression set
+            Ok(Expr::Slice(Box::new(lhs), Some(Box::new(index_expr)), None))
+        } else {
+            Err(message!("Expected ':' or ']' inside array access, found {:?}", self.current))
+        }
+    }
+
+    /// Desugars `lhs op= rhs` into `lhs = lhs op rhs`; the current token is the compound operator.
+    fn parse_compound_assignment(&mut self, lhs: Expr, op_str: &str) -> Result<Expr, String> {
+        let actual_op = op_str.chars().next().unwrap(); // e.g., '+' or '-'
+        self.advance(); // consume the compound operator token (e.g., +=)
+
+        // The right hand side of the assignment
+        let rhs = self.expr_bp(1)?; // Right binding power of assignment is 1
+
+        // Left-hand side must be a variable OR a slice/index expression
+        let assign_target = match &lhs {
+            Expr::Var(id) => Expr::Var(id.clone()), // Clone the Var(id) for both LHS and RHS of new Infix
+            Expr::Slice(arr, start, end) => Expr::Slice(arr.clone(), start.clone(), end.clone()),
+            _ => return Err(message!("Left-hand side of compound assignment '{}' must be a variable or array index", op_str)),
+        };
+
+        // Desugar: x += 5  -->  x = (x + 5)
+        // 1a. Create the arithmetic expression: (x + 5)
+        let arithmetic_expr = Expr::Infix(Box::new(assign_target.clone()), actual_op, Box::new(rhs));
+
+        // 1b. The full assignment: x = (x + 5)
+        // Use '=' as the operator for the final AST node
+        Ok(Expr::Infix(Box::new(assign_target), '=', Box::new(arithmetic_expr)))
+    }
+
+    /// Body of `expr_bp`. Besides the call itself, every operator folded into `lhs` deepens the
+    /// tree by one level (a long chain like 1 + 1 + ... + 1 is built in a loop but evaluated
+    /// recursively), so each fold is counted against the nesting limit too.
+    fn expr_bp_at_depth(&mut self, min_bp: u8) -> Result<Expr, String> {
+        //debug!("Parsing expression with min_bp {}, current token: {:?}", min_bp, self.current);
+        let mut lhs = match self.current.clone() {
+            // Store the raw number string
+            Token::Number(num_str) => {
+                self.advance();
+                Expr::Num(num_str) 
+            }
+            Token::Ident(id) => self.parse_identifier(id)?,
+            Token::StringLiteral(_) => {
+                let s = self.parse_string_literals();
+                Expr::Str(self.intern(s))
+            }
+            Token::Keyword(k) if k == "true" => { // Boolean literal true
+                self.advance();
+                Expr::Bool(true)
+            }
+            Token::Keyword(k) if k == "false" => { // Boolean literal false
+                self.advance();
+                Expr::Bool(false)
+            }
+            // 'not' is '!' with the precedence of 'and' and 'or': not x > 0 is !(x > 0)
+            Token::Keyword(k) if k == "not" => {
+                self.advance();
+                Expr::Prefix('!', Box::new(self.expr_bp(NOT_BINDING_POWER)?))
+            }
+            Token::Keyword(k) if k == "quote" => {
+                self.advance(); // consume 'quote'
+                Expr::Quote(self.parse_block("quoted block (e.g., quote [ x + 1 ])")?)
+            }
+            Token::Op('(') => {
+                self.advance();
+                let expr = self.expr_bp(0)?;
+                if self.current != Token::Op(')') {
+                    return Err(message!("Expected ')', found {:?}", self.current));
+                }
+                self.advance();
+                expr
+            }
+            // Array Literal parsing integrated as a prefix expression
+            Token::Op('[') => self.parse_array_literal()?,
+            
+            // MODIFIED: Added '!' for Logical NOT
+            Token::Op(op) if op == '+' || op == '-' || op == '!' => {
+                self.advance();
+                let (_, r_bp) = prefix_binding_power(op);
+                let rhs = self.expr_bp(r_bp)?;
+                Expr::Prefix(op, Box::new(rhs))
+            }
+            Token::Spread => return Err(message!("The spread operator '...' is only allowed in function call arguments (e.g., f(...list))")),
+            t => return Err(message!("Bad token in prefix: {:?} (Expected expression start or operator)", t)),
+        };
+        
+        // Whether `lhs` is now the result of an operator, rather than a single operand
+        let mut folded = false;
+        loop {
+            let op_token = self.current.clone();
+            
+            // MODIFIED: Check for Array Indexing and Slicing (highest precedence, 15/16)
+            if op_token == Token::Op('[') {
+                if 15 < min_bp {
+                    break;
+                }
+                lhs = self.parse_index(lhs)?;
+                self.nest()?;
+                continue;
+            }
+            // END MODIFIED
+            
+            // Check for logical keywords as operators
+            let is_logic_op = matches!(op_token, Token::Keyword(ref k) if k == "and" || k == "or");
+
+            let op_str = if is_logic_op {
+                match op_token {
+                    Token::Keyword(k) => k,
+                    _ => unreachable!(),
+                }
+            } else {
+                match op_token {
+                    Token::Op(op) => op.to_string(),
+                    Token::Cmp(op) => op,
+                    Token::Eof => break,
+                    _ => break,
+                }
+            };
+
+            // 1. Check for Compound Assignment (e.g., +=, -=) - MUST be desugared here
+            if op_str.len() == 2 && op_str.ends_with('=') && "+-*/%^".contains(op_str.chars().next().unwrap()) {
+                // Compound assignment (A += B) has the same precedence (2) as simple assignment (A = B)
+                if 2 < min_bp {
+                    break;
+                }
+                lhs = self.parse_compound_assignment(lhs, &op_str)?;
+                self.nest()?;
+                continue;
+            }
+
+            // 2. Check for simple assignment, comparison, standard infix operators OR LOGIC OPS
+            if let Some((l_bp, r_bp, is_cmp)) = binding_power(op_str.as_str()) {
+                if l_bp < min_bp {
+                    break;
+                }
+                let op_start = self.current_start;
+                self.advance();
+                //debug!("Parsing infix/cmp/logic op {}, right expr with bp {}", op_str, r_bp);
+                let rhs = self.expr_bp(r_bp)?;
+                // Only a comparison built by operators (not one in parentheses) is ambiguous
+                let ambiguous = (folded && matches!(lhs, Expr::Cmp(..))) || (self.folded && matches!(rhs, Expr::Cmp(..)));
+                if self.legacy && is_logic_op && ambiguous {
+                    let span = self.span_from(op_start);
+                    self.migrations.push((span, message!(
+                        "check: comparisons next to '{}' are read as in '(a < b) {} c'; scripts from before comparisons bound tighter than 'and' / 'or' may have meant otherwise",
+                        op_str, op_str
+                    )));
+                }
+                folded = true;
+                
+                lhs = if is_cmp {
+                    // Cmp covers ==, !=, <, >, <=, >=, ===, !==
+                    Expr::Cmp(Box::new(lhs), op_str, Box::new(rhs))
+                } else if is_logic_op {
+                    // NEW: Logic covers "and" and "or"
+                    Expr::Logic(Box::new(lhs), op_str, Box::new(rhs))
+                }
+                 else {
+                    // Infix covers simple assignment (=) and standard arithmetic (+, -, *, /, %, ^)
+                    let single_char_op = op_str.chars().next().unwrap(); 
+                    Expr::Infix(Box::new(lhs), single_char_op, Box::new(rhs))
+                };
+                self.nest()?;
+                continue;
+            }
+            break;
+        }
+        //debug!("Parsed expression: {:?}", lhs);
+        self.folded = folded;
+        Ok(lhs)
+    }
+}
+
+// MODIFIED: Added binding power for '!'
+fn prefix_binding_power(op: char) -> ((), u8) {
+    match op {
+        '+' | '-' => ((), 10),
+        '!' => ((), 16), // High precedence for NOT
+        _ => ((), 0),
+    }
+}
+
+// Right binding power of 'not': above 'and', below the comparisons
+const NOT_BINDING_POWER: u8 = 6;
+
+// MODIFIED binding_power to introduce 'or' and 'and', and raise precedence of Cmp
+fn binding_power(op: &str) -> Option<(u8, u8, bool)> { // (l_bp, r_bp, is_comparison)
+    match op {
+        "=" => Some((2, 1, false)), // Simple Assignment
+        "or" => Some((3, 4, false)), // Logical OR (Lowest precedence)
+        "and" => Some((5, 6, false)), // Logical AND
+        // Comparison (Raised to 7/8 to be higher than AND/OR)
+        "==" | "!=" | "<" | ">" | "<=" | ">=" | "===" | "!==" => Some((7, 8, true)), 
+        "+" | "-" => Some((9, 10, false)), // Addition/Subtraction
+        "*" | "/" | "%" => Some((11, 12, false)), // Multiplication/Division/Modulo
+        "^" => Some((13, 14, false)), // Exponentiation (Highest precedence)
+        _ => None,
+    }
+}
+
+// --- Formatter ---
+
+const INDENT: &str = "    ";
+
+/// Delimiters the formatter writes around blocks. Both are accepted by the parser;
+/// `--braces` rewrites a script from '[ ]' blocks to '{ }' blocks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockStyle {
+    Brackets,
+    Braces,
+}
+
+impl BlockStyle {
+    fn delimiters(self) -> (char, char) {
+        match self {
+            BlockStyle::Brackets => ('[', ']'),
+            BlockStyle::Braces => ('{', '}'),
+        }
+    }
+}
+
+/// Binding powers (l_bp, r_bp) of a binary expression's operator, or None for atoms and prefix expressions.
+fn expr_binding_power(expr: &Expr) -> Option<(u8, u8)> {
+    let op = match expr {
+        Expr::Infix(_, op, _) => op.to_string(),
+        Expr::Cmp(_, op, _) | Expr::Logic(_, op, _) => op.clone(),
+        _ => return None,
+    };
+    binding_power(&op).map(|(l_bp, r_bp, _)| (l_bp, r_bp))
+}
+
+fn escape_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Where an expression is written: the source of Error nodes, and the style and statement
+/// depth that blocks inside it (quote [ ... ]) are laid out with.
+#[derive(Clone, Copy)]
+struct Layout<'a> {
+    source: &'a str,
+    style: BlockStyle,
+    depth: usize,
+}
+
+impl Layout<'_> {
+    // For expressions shown in diagnostics
+    const PLAIN: Layout<'static> = Layout { source: "", style: BlockStyle::Brackets, depth: 0 };
+}
+
+/// Comma-separated expressions. Trailing commas are accepted by the parser but never written.
+fn format_list(exprs: &[Expr], layout: Layout) -> String {
+    exprs.iter().map(|expr| format_expr(expr, layout)).collect::<Vec<_>>().join(", ")
+}
+
+/// Formats the left operand of a binary operator with left binding power `parent_l_bp`, adding
+/// parentheses only where the operand would otherwise capture the operator that follows it.
+fn format_left_operand(expr: &Expr, parent_l_bp: u8, layout: Layout) -> String {
+    let needs_parens = match expr {
+        Expr::Prefix(op, _) => parent_l_bp >= prefix_binding_power(*op).1,
+        _ => expr_binding_power(expr).is_some_and(|(_, r_bp)| r_bp <= parent_l_bp),
+    };
+    if needs_parens { format!("({})", format_expr(expr, layout)) } else { format_expr(expr, layout) }
+}
+
+/// Formats an operand parsed with expr_bp(parent_r_bp): the right side of a binary operator
+/// or the operand of a prefix operator.
+fn format_right_operand(expr: &Expr, parent_r_bp: u8, layout: Layout) -> String {
+    let needs_parens = match expr {
+        // A prefix operator binds looser than '*', '/', '%' and '^', so it would capture
+        // an operator following the parent expression
+        Expr::Prefix(op, _) => parent_r_bp > prefix_binding_power(*op).1,
+        _ => expr_binding_power(expr).is_some_and(|(l_bp, _)| l_bp < parent_r_bp),
+    };
+    if needs_parens { format!("({})", format_expr(expr, layout)) } else { format_expr(expr, layout) }
+}
+
+/// Formats an expression in canonical source form (the inverse of Parser::expr_bp).
+fn format_expr(expr: &Expr, layout: Layout) -> String {
+    match expr {
+        Expr::Var(id) => id.clone(),
+        Expr::Num(s) => s.clone(),
+        Expr::Str(s) => escape_string(s),
+        Expr::Bool(b) => (if *b { "true" } else { "false" }).to_string(),
+        Expr::Prefix(op, rhs) => {
+            let (_, r_bp) = prefix_binding_power(*op);
+            let operand = match &**rhs {
+                // Indexing binds at 15, so it only stays inside a weaker prefix operator
+                Expr::Slice(..) if r_bp > 15 => format!("({})", format_expr(rhs, layout)),
+                _ => format_right_operand(rhs, r_bp, layout),
+            };
+            format!("{}{}", op, operand)
+        }
+        Expr::Infix(lhs, _, rhs) | Expr::Cmp(lhs, _, rhs) | Expr::Logic(lhs, _, rhs) => {
+            let op = match expr {
+                Expr::Infix(_, op, _) => op.to_string(),
+                Expr::Cmp(_, op, _) | Expr::Logic(_, op, _) => op.clone(),
+                _ => unreachable!(),
+            };
+            let (l_bp, r_bp) = expr_binding_power(expr).unwrap_or((0, 0));
+            format!("{} {} {}", format_left_operand(lhs, l_bp, layout), op, format_right_operand(rhs, r_bp, layout))
+        }
+        Expr::Array(elements) => format!("[{}]", format_list(elements, layout)),
+        Expr::Slice(array, start, end) => {
+            let base = match &**array {
+                Expr::Var(_) | Expr::Call(..) | Expr::Array(_) | Expr::Slice(..) | Expr::Str(_) => format_expr(array, layout),
+                _ => format!("({})", format_expr(array, layout)),
+            };
+            let start = start.as_deref().map(|start| format_expr(start, layout)).unwrap_or_default();
+            match end {
+                Some(end) => format!("{}[{}:{}]", base, start, format_expr(end, layout)),
+                None => format!("{}[{}]", base, start),
+            }
+        }
+        Expr::Call(name, args) => format!("{}({})", name, format_list(args, layout)),
+        Expr::Spread(inner) => format!("...{}", format_expr(inner, layout)),
+        Expr::Error(span) => source_text(*span, layout.source).to_string(),
+        Expr::Quote(body) => {
+            let mut out = "quote ".to_string();
+            format_block(body, layout.depth, layout.style, layout.source, &mut out);
+            out
+        }
+        Expr::Expand(expansion) => format_macro_use(expansion, layout),
+    }
+}
+
+/// A macro use as written: name(args), followed by its block argument if it has one.
+fn format_macro_use<T>(expansion: &Expansion<T>, layout: Layout) -> String {
+    let mut out = format!("{}({})", expansion.name, format_list(&expansion.args, layout));
+    if let Some(block) = &expansion.block {
+        out.push(' ');
+        format_block(block, layout.depth, layout.style, layout.source, &mut out);
+    }
+    out
+}
+
+fn format_block(statements: &[Statement], depth: usize, style: BlockStyle, source: &str, out: &mut String) {
+    let (open, close) = style.delimiters();
+    out.push(open);
+    if statements.is_empty() {
+        out.push(close);
+        return;
+    }
+    out.push('\n');
+    for stmt in statements {
+        format_statement(stmt, depth + 1, style, source, out);
+    }
+    out.push_str(&INDENT.repeat(depth));
+    out.push(close);
+}
+
+fn format_statement(stmt: &Statement, depth: usize, style: BlockStyle, source: &str, out: &mut String) {
+    out.push_str(&INDENT.repeat(depth));
+    let layout = Layout { source, style, depth };
+    match stmt {
+        Statement::Expr(expr) => out.push_str(&format_expr(expr, layout)),
+        Statement::Print(format_string, exprs) => {
+            let mut args: Vec<String> = format_string.iter().map(|s| escape_string(s)).collect();
+            args.extend(exprs.iter().map(|expr| format_expr(expr, layout)));
+            // Without a format string, an argument starting with a string literal would be read as one
+            if format_string.is_none() && args.first().is_some_and(|arg| arg.starts_with('"')) {
+                args[0] = format!("({})", args[0]);
+            }
+            out.push_str(&format!("print({})", args.join(", ")));
+        }
+        Statement::Def(name, params, body, decorators, contract) => {
+            for decorator in decorators {
+                out.push_str(&format!("@{}\n{}", decorator, INDENT.repeat(depth)));
+            }
+            out.push_str(&format!("fn {}({}) ", name, params.join(", ")));
+            for condition in &contract.requires {
+                out.push_str(&format!("requires ({}) ", format_expr(condition, layout)));
+            }
+            for condition in &contract.ensures {
+                out.push_str(&format!("ensures ({}) ", format_expr(condition, layout)));
+            }
+            format_block(body, depth, style, source, out);
+        }
+        Statement::Return(None) => out.push_str("return"),
+        Statement::Return(Some(expr)) => out.push_str(&format!("return {}", format_expr(expr, layout))),
+        // A lone 'return x' with no else is printed as a guard clause: if (cond) return x.
+        // A bare 'return' keeps its block, or it would take the next line as its value.
+        Statement::If(cond, body, None) if matches!(body.as_slice(), [Statement::Return(Some(_))]) => {
+            out.push_str(&format!("if ({}) ", format_expr(cond, layout)));
+            // Formatted at this depth, so a quoted block in the value is indented like the if
+            let mut guard = String::new();
+            format_statement(&body[0], depth, style, source, &mut guard);
+            out.push_str(guard.trim());
+        }
+        Statement::If(cond, body, else_body) => {
+            out.push_str(&format!("if ({}) ", format_expr(cond, layout)));
+            format_block(body, depth, style, source, out);
+            match else_body.as_deref() {
+                // Chains print as 'else if (...) [...]' rather than nesting another block
+                Some([nested @ Statement::If(..)]) => {
+                    let mut chained = String::new();
+                    format_statement(nested, depth, style, source, &mut chained);
+                    out.push_str(" else ");
+                    out.push_str(chained.trim());
+                }
+                Some(else_body) => {
+                    out.push_str(" else ");
+                    format_block(else_body, depth, style, source, out);
+                }
+                None => {}
+            }
+        }
+        Statement::For(var_name, iterable, body) => {
+            out.push_str(&format!("for ({} in {}) ", var_name, format_expr(iterable, layout)));
+            format_block(body, depth, style, source, out);
+        }
+        Statement::Timed(label, body) => {
+            match label {
+                Some(label) => out.push_str(&format!("timed({}) ", escape_string(label))),
+                None => out.push_str("timed "),
+            }
+            format_block(body, depth, style, source, out);
+        }
+        Statement::Error(span) => out.push_str(source_text(*span, source)),
+        Statement::Macro(name, params, template) => {
+            out.push_str(&format!("macro {}({}) ", name, params.join(", ")));
+            format_block(&[Statement::Expr(Expr::Quote(template.clone()))], depth, style, source, out);
+        }
+        Statement::Expand(expansion) => out.push_str(&format_macro_use(expansion, layout)),
+    }
+    out.push('\n');
+}
+
+/// The source text of a region that failed to parse, written back unchanged.
+fn source_text(span: Span, source: &str) -> &str {
+    source.get(span.start..span.end).unwrap_or_default()
+}
+
+/// Formats a parsed program in canonical style: four-space indentation, one statement per line,
+/// minimal parentheses, and guard clauses for single-return if statements.
+pub fn format_program(statements: &[Statement], style: BlockStyle) -> String {
+    format_program_with_source(statements, style, "")
+}
+
+/// Formats a program from Parser::parse_lenient: like format_program, but the regions that failed
+/// to parse (Error nodes) are copied unchanged from `source`, the text that was parsed.
+pub fn format_program_with_source(statements: &[Statement], style: BlockStyle, source: &str) -> String {
+    let mut out = String::new();
+    for stmt in statements {
+        format_statement(stmt, 0, style, source, &mut out);
+    }
+    out
+}
+
+// --- Interpreter ---
+
+type Environment = HashMap<String, Value>;
+// CHANGE: Function definition now stores Vec<Statement>
+type FuncDefs = HashMap<String, FunctionDef>;
+
+/// A user-defined function as registered at definition time.
+#[derive(Debug, Clone)]
+struct FunctionDef {
+    params: Vec<String>,
+    body: Vec<Statement>,
+    contract: Contract,
+    // Builtin call-time wrappers ('memoize', 'timed'), innermost first
+    wrappers: Vec<String>,
+    // Set when a user decorator returned a different function: calls are forwarded to it
+    rebound: Option<String>,
+}
+
+/// Decorators implemented by the interpreter itself; they wrap every call of the function.
+const BUILTIN_DECORATORS: &[&str] = &["memoize", "timed"];
+
+/// Behavior switches selected on the command line.
+#[derive(Debug, Clone)]
+pub struct Options {
+    // Functions without 'return' yield their last statement's value (disabled by --no-implicit-return)
+    pub implicit_return: bool,
+    // Check 'requires' / 'ensures' clauses on every call (--contracts)
+    pub contracts: bool,
+    // Total bytes of print output allowed per interpreter (--max-output-bytes)
+    pub max_output_bytes: Option<u64>,
+    // Past the limit, drop output after a marker line instead of failing (--truncate-output)
+    pub truncate_output: bool,
+    // Cache up to this many results of pure builtin calls (--pure-cache)
+    pub pure_cache_size: Option<usize>,
+    // Statements an interpreter may execute before failing (--max-steps)
+    pub max_steps: Option<u64>,
+    // Run qualifying integer 'for' loops on the fused fast path (off with --no-loop-fusion)
+    pub loop_fusion: bool,
+    // Rounding of '/' and '%' on two Integers (--int-div)
+    pub int_division: IntDivision,
+    // Builtins may read the file system, e.g. glob (--allow-read); without it scripts see no files
+    pub allow_read: bool,
+    // Builtins may create and write files, e.g. zip_extract (--allow-write)
+    pub allow_write: bool,
+    // Builtins may connect to other hosts, e.g. send_mail (--allow-net)
+    pub allow_net: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            implicit_return: true,
+            contracts: false,
+            max_output_bytes: None,
+            truncate_output: false,
+            pure_cache_size: None,
+            max_steps: None,
+            loop_fusion: true,
+            int_division: IntDivision::default(),
+            allow_read: false,
+            allow_write: false,
+            allow_net: false,
+        }
+    }
+}
+
+/// Bounded cache of call results (pure builtins, '@memoize' functions); once full, the oldest
+/// entry makes room for the newest. `N` names what was called.
+#[derive(Default)]
+struct CallCache<N> {
+    // Keyed by a hash of the name and arguments, which are kept to tell collisions apart
+    entries: HashMap<u64, (N, Vec<Value>, Value)>,
+    order: VecDeque<u64>,
+}
+
+impl<N: Hash + PartialEq> CallCache<N> {
+    fn key(name: &N, args: &[Value]) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        name.hash(&mut hasher);
+        for arg in args {
+            hash_value(arg, &mut hasher);
+        }
+        hasher.finish()
+    }
+
+    fn get(&self, key: u64, name: &N, args: &[Value]) -> Option<&Value> {
+        match self.entries.get(&key) {
+            Some((cached_name, cached_args, value)) if cached_name == name && cached_args == args => Some(value),
+            _ => None,
+        }
+    }
+
+    fn insert(&mut self, key: u64, name: N, args: Vec<Value>, value: Value, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+        // A colliding call replaces the entry in place and keeps its position
+        if self.entries.insert(key, (name, args, value)).is_some() {
+            return;
+        }
+        if self.entries.len() > capacity && let Some(oldest) = self.order.pop_front() {
+            self.entries.remove(&oldest);
+        }
+        self.order.push_back(key);
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+/// Results each interpreter keeps of '@memoize' functions.
+const MEMO_CACHE_SIZE: usize = 10_000;
+
+/// Counters of what an interpreter has done so far, for hosts that monitor scripts in production.
+/// With the `metrics` feature the same counts are also reported through the `metrics` crate facade
+/// as `astra.statements_executed`, `astra.functions_called`, `astra.errors`, `astra.allocations`
+/// and `astra.pure_cache_hits`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics {
+    pub statements_executed: u64,
+    // Builtin and user-defined calls, including calls made by decorators and higher-order builtins
+    pub functions_called: u64,
+    // Errors returned by the Interpreter API (parse errors included)
+    pub errors: u64,
+    // Estimate: one per string or array value produced by an expression
+    pub allocations_estimate: u64,
+    // Pure builtin calls answered from the --pure-cache
+    pub pure_cache_hits: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Counter {
+    Statements,
+    Calls,
+    Errors,
+    Allocations,
+    PureCacheHits,
+}
+
+/// Stops a running evaluation from another thread: the interpreter returns a "Cancelled" error at the
+/// next statement boundary. Stays triggered until [`CancelHandle::reset`], so later calls fail too.
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.0.store(true, AtomicOrdering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(AtomicOrdering::Relaxed)
+    }
+
+    pub fn reset(&self) {
+        self.0.store(false, AtomicOrdering::Relaxed);
+    }
+}
+
+/// State shared by every statement of a run: the defined functions and the active options.
+struct Runtime {
+    func_defs: FuncDefs,
+    // Functions of a prepared prelude, shared read-only between interpreters
+    prelude: Arc<FuncDefs>,
+    options: Options,
+    // Results of '@memoize' functions, keyed by function, wrapper layer and arguments
+    memo_cache: RefCell<CallCache<(String, usize)>>,
+    // Results of pure builtin calls, keyed by builtin and arguments
+    pure_cache: RefCell<CallCache<&'static str>>,
+    // Every measurement taken by timed blocks and '@timed' functions, in order
+    timings: RefCell<Vec<(String, Duration)>>,
+    metrics: RefCell<Metrics>,
+    cancel: CancelHandle,
+    secrets: Secrets,
+    // Print output written so far, counted against Options::max_output_bytes
+    output_bytes: Cell<u64>,
+    output_truncated: Cell<bool>,
+    // Request-scoped values of the running call_with_env, readable but not assignable inside it
+    bindings: Environment,
+    // Jobs registered with 'every', in order, for 'astra schedule'
+    jobs: RefCell<Vec<ScheduledJob>>,
+    // Print output held back for the host instead of going to stdout, once capture is on
+    captured: RefCell<Option<Vec<String>>>,
+}
+
+impl Runtime {
+    fn new(options: Options, prelude: Arc<FuncDefs>) -> Self {
+        Runtime {
+            func_defs: HashMap::new(),
+            prelude,
+            options,
+            memo_cache: RefCell::new(CallCache::default()),
+            pure_cache: RefCell::new(CallCache::default()),
+            timings: RefCell::new(Vec::new()),
+            metrics: RefCell::new(Metrics::default()),
+            cancel: CancelHandle::default(),
+            secrets: Secrets::default(),
+            output_bytes: Cell::new(0),
+            output_truncated: Cell::new(false),
+            bindings: Environment::new(),
+            jobs: RefCell::new(Vec::new()),
+            captured: RefCell::new(None),
+        }
+    }
+
+    /// Looks up a user-defined function; the script's own definitions shadow the prelude's.
+    fn function(&self, name: &str) -> Option<&FunctionDef> {
+        self.func_defs.get(name).or_else(|| self.prelude.get(name))
+    }
+
+    /// Fails unless the host lets scripts read the file system (--allow-read).
+    fn require_read(&self, fn_name: &str) -> Result<(), String> {
+        if !self.options.allow_read {
+            return Err(message!("'{}' reads the file system, which this script is not allowed to do (run with --allow-read)", fn_name));
+        }
+        Ok(())
+    }
+
+    /// Fails unless the host lets scripts write to the file system (--allow-write).
+    #[cfg_attr(not(feature = "archives"), allow(dead_code))]
+    fn require_write(&self, fn_name: &str) -> Result<(), String> {
+        if !self.options.allow_write {
+            return Err(message!("'{}' writes to the file system, which this script is not allowed to do (run with --allow-write)", fn_name));
+        }
+        Ok(())
+    }
+
+    /// Fails unless the host lets scripts open network connections (--allow-net).
+    #[cfg_attr(not(feature = "mail"), allow(dead_code))]
+    fn require_net(&self, fn_name: &str) -> Result<(), String> {
+        if !self.options.allow_net {
+            return Err(message!("'{}' connects to the network, which this script is not allowed to do (run with --allow-net)", fn_name));
+        }
+        Ok(())
+    }
+
+    /// Names of the user-defined functions, including the prelude's.
+    fn function_names(&self) -> impl Iterator<Item = &str> {
+        self.func_defs.keys().chain(self.prelude.keys()).map(String::as_str)
+    }
+
+    /// Fails if `name` is a host binding that the current scope has not shadowed.
+    fn check_assignable(&self, name: &str, env: &Environment) -> Result<(), String> {
+        if self.bindings.contains_key(name) && !env.contains_key(name) {
+            return Err(message!("Cannot assign to '{}': it is a read-only binding provided by the host", name));
+        }
+        Ok(())
+    }
+
+    /// Registers the printed forms of a value (and of its elements) for masking. Texts shorter
+    /// than MIN_SECRET_LENGTH would mask unrelated output (every "1" for a secret 1), so they're
+    /// rejected when `strict`, and left unmasked otherwise.
+    fn mark_secret(&self, value: &Value, strict: bool) -> Result<(), String> {
+        let mut texts = Vec::new();
+        let mut pending = vec![value];
+        while let Some(value) = pending.pop() {
+            if let Value::Array(items) = value {
+                pending.extend(items);
+            }
+            let text = print_repr(value);
+            if text.chars().count() < MIN_SECRET_LENGTH {
+                if strict {
+                    return Err(message!("A secret must print as at least {} characters, so that masking it doesn't hide unrelated text; this one prints as {}", MIN_SECRET_LENGTH, text.chars().count()));
+                }
+                continue;
+            }
+            // Traces show strings in their escaped Debug form
+            let escaped = format!("{:?}", text).trim_matches('"').to_string();
+            texts.extend([text, escaped]);
+        }
+        let mut secrets = self.secrets.lock().unwrap();
+        for text in texts {
+            if !secrets.contains(&text) {
+                secrets.push(text);
+            }
+        }
+        // Longest first, so that a secret containing another one is masked as a whole
+        secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+        Ok(())
+    }
+
+    /// Whether a builtin called with these arguments computes its result from a secret, e.g.
+    /// title_case(token): such results are masked as well.
+    fn derives_from_secret(&self, args: &[Value]) -> bool {
+        args.iter().any(|arg| matches!(arg, Value::String(text) if self.contains_secret(text)))
+    }
+
+    fn contains_secret(&self, text: &str) -> bool {
+        self.secrets.lock().unwrap().iter().any(|secret| text.contains(secret.as_str()))
+    }
+
+    /// Called at every statement boundary.
+    fn check_cancelled(&self) -> Result<(), String> {
+        if self.cancel.is_cancelled() {
+            return Err(message!("Cancelled"));
+        }
+        Ok(())
+    }
+
+    /// Counts a statement about to run against --max-steps.
+    fn step(&self) -> Result<(), String> {
+        self.count(Counter::Statements);
+        if let Some(limit) = self.options.max_steps
+            && self.metrics.borrow().statements_executed > limit
+        {
+            return Err(message!("Step limit of {} statements exceeded (max-steps)", limit));
+        }
+        Ok(())
+    }
+
+    fn count(&self, counter: Counter) {
+        self.count_many(counter, 1);
+    }
+
+    fn count_many(&self, counter: Counter, n: u64) {
+        let mut counters = self.metrics.borrow_mut();
+        let (value, _name) = match counter {
+            Counter::Statements => (&mut counters.statements_executed, "astra.statements_executed"),
+            Counter::Calls => (&mut counters.functions_called, "astra.functions_called"),
+            Counter::Errors => (&mut counters.errors, "astra.errors"),
+            Counter::Allocations => (&mut counters.allocations_estimate, "astra.allocations"),
+            Counter::PureCacheHits => (&mut counters.pure_cache_hits, "astra.pure_cache_hits"),
+        };
+        *value += n;
+        #[cfg(feature = "metrics")]
+        metrics::counter!(_name).increment(n);
+    }
+}
+
+enum FunctionControlFlow {
+    Continue(Value), 
+    Return(Value),   
+    Print(String),   
+}
+
+fn eval(expr: &Expr, env: &mut Environment, runtime: &Runtime) -> Result<Value, String> {
+    let value = eval_expr(expr, env, runtime)?;
+    if matches!(value, Value::String(_) | Value::Array(_)) {
+        runtime.count(Counter::Allocations);
+    }
+    Ok(value)
+}
+
+fn eval_expr(expr: &Expr, env: &mut Environment, runtime: &Runtime) -> Result<Value, String> {
+    //debug!("Evaluating expr: {:?}", expr);
+    match expr {
+        // ... (Expr::Num, Expr::Str, Expr::Var remain the same)
+        Expr::Num(s) => number_literal(s),
+        Expr::Str(s) => Ok(Value::String(s.to_string())),
+        Expr::Bool(b) => Ok(Value::Boolean(*b)), // Handle Boolean literal
+        Expr::Quote(body) => Ok(Value::Ast(Arc::new(Ast::of_block(body)))),
+        Expr::Expand(expansion) => eval(&expansion.code, env, runtime),
+        Expr::Var(id) => match env.get(id).or_else(|| runtime.bindings.get(id)) {
+            Some(val) => Ok(val.clone()),
+            // A bare function name evaluates to a function reference so it can be passed to higher-order builtins
+            None if runtime.function(id).is_some() || get_native_function(id).is_some() => Ok(Value::Function(id.clone())),
+            None => {
+                let known = env.keys().chain(runtime.bindings.keys()).map(String::as_str);
+                Err(match text::suggestion(id, known) {
+                    Some(name) => message!("Cannot evaluate uninitialized variable: {} (did you mean '{}'?)", id, name),
+                    None => message!("Cannot evaluate uninitialized variable: {}", id),
+                })
+            }
+        },
+        
+        // MODIFIED: Unary Prefix (e.g., -x, !x)
+        Expr::Prefix(op, rhs) => {
+            let val = eval_operand(rhs, env, runtime)?;
+            match (*op, val) {
+                // Arithmetic
+                ('-', Value::Integer(n)) => Ok(Value::Integer(-n)),
+                ('+', Value::Integer(n)) => Ok(Value::Integer(n)),
+                ('-', Value::Float(n)) => Ok(Value::Float(-n)),
+                ('+', Value::Float(n)) => Ok(Value::Float(n)),
+                // Logical NOT (!)
+                ('!', Value::Boolean(b)) => Ok(Value::Boolean(!b)),
+                // Error cases
+                ('!', v) => Err(message!("Unary operator '!' only supports booleans. Found {:?}", v)),
+                (_, v) => Err(message!("Unary operator '{}' only supports numbers. Found {:?}", op, v)),
+            }
+        }
+        
+        // MODIFIED: Array Literal Evaluation
+        Expr::Array(elements) => {
+            let evaluated_elements: Result<Vec<Value>, String> = elements
+                .iter()
+                .map(|e| eval(e, env, runtime))
+                .collect();
+            Ok(Value::Array(evaluated_elements?))
+        }
+
+        // MODIFIED: Array Slicing/Indexing Evaluation (R-value)
+        Expr::Slice(array_expr, start_opt, end_opt) => {
+            // Note: This block is for R-value evaluation (reading from array) and doesn't need a mutable borrow of the environment for the array itself.
+            let array_val = eval(array_expr, env, runtime)?;
+
+            let elements = match array_val {
+                Value::Array(v) => v,
+                _ => return Err(message!("Attempted to index/slice a non-array value: {:?}", array_val)),
+            };
+
+            // Determine array length for bounds and defaults
+            let len = elements.len() as isize;
+
+            // 1. Calculate start index (default 0)
+            let start_index = if let Some(start_expr) = start_opt {
+                let start_val = eval(start_expr, env, runtime)?;
+                let index = match start_val {
+                    Value::Integer(n) => n.to_isize().ok_or_else(|| message!("Array index too large or too small"))?,
+                    _ => return Err(message!("Array index must be an Integer, found {:?}", start_val)),
+                };
+                // Handle negative indexing, defaulting to 0 if out of bounds on the low end
+                let calculated_start = if index < 0 { len + index } else { index };
+                (calculated_start.max(0).min(len)) as usize
+            } else if end_opt.is_some() {
+                 0 // Default start index for slicing (e.g., arr[:end])
+            } else {
+                // If it is an L-value assignment (arr[i] = x), the L-value block handles validation.
+                // If it is an R-value index read (arr[i]), start_opt will be Some and this branch isn't reached.
+                // This branch should only be reached if the slice is empty, e.g. arr[] which is a parser error.
+                return Err(message!("Internal Error: Array index expression missing in R-value evaluation"));
+            };
+
+            // 2. Calculate end index (default array length or start+1 for simple index)
+            let end_index = if let Some(end_expr) = end_opt {
+                let end_val = eval(end_expr, env, runtime)?;
+                let index = match end_val {
+                    Value::Integer(n) => n.to_isize().ok_or_else(|| message!("Array index too large or too small"))?,
+                    _ => return Err(message!("Array index must be an Integer, found {:?}", end_val)),
+                };
+                // Handle negative indexing, defaulting to len if out of bounds on the high end
+                let calculated_end = if index < 0 { len + index } else { index };
+                (calculated_end.max(0).min(len)) as usize
+            } else if end_opt.is_some() || (start_opt.is_some() && end_opt.is_some()) {
+                // If it's a slice (arr[start:] or arr[start:end]), default end is full length
+                len as usize
+            } else {
+                // If it's simple indexing (arr[index]), the end is start + 1
+                start_index + 1
+            };
+
+            // 3. Bounds and Order checks
+            if start_index > end_index || start_index > len as usize || end_index > len as usize {
+                return Err(message!(
+                    "Array slice index error: start index {} must be <= end index {} (size {})", 
+                    start_index, end_index, len
+                ));
+            }
+
+            // 4. Perform slice/index extraction
+            let result_elements: Vec<Value> = elements[start_index..end_index].to_vec();
+
+            // If the result is a single element slice (simple indexing), return the element directly, otherwise return a new Array
+            // If end_opt is Some, it's a slice (arr[:end] or arr[start:end]), so return Value::Array regardless of length.
+            if result_elements.len() == 1 && end_opt.is_none() && start_opt.is_some() {
+                Ok(result_elements.into_iter().next().unwrap())
+            } else {
+                Ok(Value::Array(result_elements))
+            }
+        }
+        
+        // Assignment (=)
+        Expr::Infix(lhs, op, rhs) if *op == '=' => {
+            if let Expr::Var(id) = &**lhs
+                && let Some(val) = assign_in_place(id, rhs, env, runtime)
+            {
+                return Ok(val);
+            }
+            // Evaluate the RHS expression first, before any mutable borrow of the environment
+            let val = eval(rhs, env, runtime)?;
+            
+            match &**lhs {
+                Expr::Var(id) => {
+                    runtime.check_assignable(id, env)?;
+                    env.insert(id.clone(), val.clone());
+                    Ok(val)
+                }
+                // MODIFIED: Index Assignment (arr[3] = 10)
+                Expr::Slice(array_expr, start_opt, end_opt) => {
+                    
+                    // Assignment to slice (arr[i:j] = ...) is not supported, only single index assignment.
+                    if end_opt.is_some() {
+                        return Err(message!("Assignment to array slice (arr[start:end] = ...) is not supported. Only assignment to a single index (arr[index] = ...) is allowed."));
+                    }
+                    let index_expr = start_opt.as_ref().ok_or_else(|| message!("Array index expression missing for assignment"))?;
+
+                    // --- FIX FOR E0499: Evaluate index before mutable borrow ---
+                    let index = match eval(index_expr, env, runtime)? {
+                        Value::Integer(n) => n.to_isize().ok_or_else(|| message!("Array index too large or too small"))?,
+                        v => return Err(message!("Array index must be an Integer, found {:?}", v)),
+                    };
+                    // --- END FIX ---
+
+                    // Target of assignment (the array variable) must be Expr::Var
+                    let array_var_name = match &**array_expr {
+                        Expr::Var(id) => id,
+                        _ => return Err(message!("Left-hand side array must be a simple variable (e.g., arr[i] = 5, not (fn())[i] = 5)")),
+                    };
+                    
+                    runtime.check_assignable(array_var_name, env)?;
+                    // Get the mutable array value from the environment (First mutable borrow)
+                    let array_val_ref = env
+                        .get_mut(array_var_name)
+                        .ok_or_else(|| message!("Cannot assign to uninitialized array variable: {}", array_var_name))?;
+
+                    // Now that index is calculated and we have the mutable ref, proceed.
+                    
+                    let elements = match array_val_ref {
+                        Value::Array(v) => v,
+                        _ => return Err(message!("Variable is not an array and cannot be indexed for assignment")),
+                    };
+
+                    let len = elements.len() as isize;
+                    let actual_index = if index < 0 { len + index } else { index };
+
+                    // Check bounds and perform assignment (mutability)
+                    if actual_index < 0 || actual_index as usize >= elements.len() {
+                        return Err(message!("Array index out of bounds for assignment: {} (size {})", actual_index, len));
+                    }
+
+                    // Perform the mutable update
+                    elements[actual_index as usize] = val.clone();
+
+                    // Assignment returns the assigned value
+                    Ok(val)
+                }
+                _ => Err(message!("Assignment target must be a variable or an index expression")),
+            }
+        }
+        
+        // Arithmetic (+, -, *, /, %, ^) - CONSOLIDATED LOGIC
+        Expr::Infix(lhs, op, rhs) => {
+            if let Some(result) = arithmetic_by_reference(lhs, *op, rhs, env, runtime) {
+                return result;
+            }
+            let left_val = eval_operand(lhs, env, runtime)?;
+            let right_val = eval_operand(rhs, env, runtime)?;
+
+            // Use a single match to cover all type combinations, preventing move errors.
+            match (left_val, right_val) {
+                
+                // 1. Numbers, promoted by the numeric tower (Integer stays exact, a Float promotes both)
+                (l, r) if l.is_number() && r.is_number() => numeric::arithmetic(*op, numeric::coerce_pair(l, r)?, runtime.options.int_division),
+
+                // 2. String Concatenation (+) - only works if both are strings
+                (Value::String(mut l), Value::String(r)) if *op == '+' => {
+                    l.push_str(&r);
+                    Ok(Value::String(l))
+                }
+                
+                // MODIFIED: Array Concatenation (+)
+                (Value::Array(mut l), Value::Array(r)) if *op == '+' => {
+                    l.extend(r); // Append elements from the right array
+                    Ok(Value::Array(l))
+                }
+                
+                // 3. Incompatible Types (Error)
+                (l, r) => Err(message!("Incompatible types for operator '{}': {:?} and {:?}", op, l, r)),
+            }
+        }
+
+        // ... Expr::Cmp and Expr::Logic remain the same ...
+        Expr::Cmp(lhs, op, rhs) => {
+            // Equality is defined for Void (see values_equal); ordering is not
+            let (left_val, right_val) = if matches!(op.as_str(), "<" | ">" | "<=" | ">=") {
+                (eval_operand(lhs, env, runtime)?, eval_operand(rhs, env, runtime)?)
+            } else {
+                (eval(lhs, env, runtime)?, eval(rhs, env, runtime)?)
+            };
+            
+            let result = match op.as_str() {
+                // STRICT Equality/Inequality (value AND type must match exactly)
+                "===" => values_equal(&left_val, &right_val, true),
+                "!==" => !values_equal(&left_val, &right_val, true),
+                
+                // NON-STRICT Equality/Inequality (value must match, type coercion between Int/Float)
+                "==" => values_equal(&left_val, &right_val, false),
+                "!=" => !values_equal(&left_val, &right_val, false),
+                
+                // Ordering Comparisons: numbers (Integer and Float mixed) or two strings.
+                // Any comparison involving NaN is false.
+                "<" | ">" | "<=" | ">=" => {
+                    match partial_compare(&left_val, &right_val) {
+                        Ok(Some(ord)) => match op.as_str() {
+                            "<" => ord.is_lt(), ">" => ord.is_gt(), "<=" => ord.is_le(), ">=" => ord.is_ge(), _ => unreachable!(),
+                        },
+                        Ok(None) => false,
+                        Err(_) => return Err(message!(
+                            "Incompatible types for ordering operator '{}': {:?} and {:?}", op, left_val, right_val
+                        )),
+                    }
+                },
+                _ => return Err(message!("Unknown comparison operator: {}", op)),
+            };
+            
+            Ok(Value::Boolean(result))
+        }
+
+        // NEW: Logical Operators (AND, OR)
+        Expr::Logic(lhs, op, rhs) => {
+            let left_val = eval_operand(lhs, env, runtime)?;
+
+            // Short-circuit evaluation
+            let short_circuit_val = match (op.as_str(), &left_val) {
+                // False AND anything is False
+                ("and", Value::Boolean(false)) => Some(Value::Boolean(false)), 
+                // True OR anything is True
+                ("or", Value::Boolean(true)) => Some(Value::Boolean(true)),   
+                _ => None,
+            };
+
+            if let Some(val) = short_circuit_val {
+                return Ok(val);
+            }
+            
+            // If not short-circuited, evaluate RHS
+            let right_val = eval_operand(rhs, env, runtime)?;
+
+            match (op.as_str(), left_val, right_val) {
+                // Since we passed short-circuiting, the left must be a Boolean as well
+                ("and", Value::Boolean(l_b), Value::Boolean(r_b)) => Ok(Value::Boolean(l_b && r_b)),
+                ("or", Value::Boolean(l_b), Value::Boolean(r_b)) => Ok(Value::Boolean(l_b || r_b)),
+                
+                // Error on incompatible types (if one wasn't a boolean, or if the left was a boolean but the right wasn't)
+                (op_str, l, r) => {
+                    Err(message!("Logical operator '{}' only works on Booleans. Found {:?} and {:?}", op_str, l, r))
+                }
+            }
+        }
+        Expr::Call(name, args) => execute_function(name, args, env, runtime),
+        Expr::Spread(_) => Err(message!("The spread operator '...' can only be used on a function call argument")),
+        Expr::Error(span) => Err(unparsed_code(*span)),
+    }
+}
+
+fn number_literal(s: &str) -> Result<Value, String> {
+    if s.contains('.') {
+        let f = s.parse::<f64>().map_err(|e| message!("Invalid float: {}", e))?;
+        Ok(Value::Float(f))
+    } else {
+        // Parse directly into BigInt
+        let i = s.parse::<BigInt>().map_err(|e| message!("Invalid integer: {}", e))?;
+        Ok(Value::Integer(i))
+    }
+}
+
+/// A variable's number, read where it is stored, or a number literal. None for anything else,
+/// including variables the general path reports on (void, uninitialized, function references).
+fn number_operand<'a>(expr: &Expr, env: &'a Environment, runtime: &'a Runtime) -> Option<Cow<'a, Value>> {
+    let value = match expr {
+        Expr::Var(id) => Cow::Borrowed(env.get(id).or_else(|| runtime.bindings.get(id))?),
+        Expr::Num(s) => Cow::Owned(number_literal(s).ok()?),
+        _ => return None,
+    };
+    value.is_number().then_some(value)
+}
+
+/// `a op b` on variables and literals (e.g., `total * i`) without copying a large Integer out of
+/// the environment first. None leaves the expression to the general path.
+fn arithmetic_by_reference(lhs: &Expr, op: char, rhs: &Expr, env: &Environment, runtime: &Runtime) -> Option<Result<Value, String>> {
+    let l = number_operand(lhs, env, runtime)?;
+    let r = number_operand(rhs, env, runtime)?;
+    Some(numeric::arithmetic_ref(op, &l, &r, runtime.options.int_division))
+}
+
+/// `x = x + y`, `x = x - y` and `x = x * y` (also written `x += y`, ...) on Integers, with `y` a
+/// variable or literal: updates x where it is stored instead of building a new value and
+/// replacing it. None leaves the assignment to the general path.
+fn assign_in_place(id: &str, rhs: &Expr, env: &mut Environment, runtime: &Runtime) -> Option<Value> {
+    let Expr::Infix(target, op @ ('+' | '-' | '*'), operand) = rhs else { return None };
+    if !matches!(&**target, Expr::Var(name) if name == id) {
+        return None;
+    }
+    // Copied first: the operand may be x itself (x *= x)
+    let Value::Integer(operand) = number_operand(operand, env, runtime)?.into_owned() else { return None };
+    // A variable in env is assignable even if it shadows a host binding
+    let value = env.get_mut(id)?;
+    numeric::update_in_place(*op, value, &operand).then(|| value.clone())
+}
+
+/// Evaluates an operand of an arithmetic, logical or ordering operator. A void result gets a dedicated
+/// diagnostic naming its source, instead of surfacing later as an "Incompatible types" error.
+fn eval_operand(expr: &Expr, env: &mut Environment, runtime: &Runtime) -> Result<Value, String> {
+    let val = eval(expr, env, runtime)?;
+    if val != Value::Void {
+        return Ok(val);
+    }
+    Err(match expr {
+        Expr::Call(name, _) => message!("function '{}' returned void; its result cannot be used in an expression", name),
+        Expr::Var(id) => message!("variable '{}' is void; it cannot be used in an expression", id),
+        other => message!("expression '{}' is void; it cannot be used in an expression", other),
+    })
+}
+
+// NEW: Native function definitions
+type NativeFunction = fn(&str, &mut Environment, &Runtime, Vec<Value>) -> Result<Value, String>;
+
+/// A builtin function with the metadata shown by help() and exported to editor tooling.
+pub struct Builtin {
+    pub name: &'static str,
+    // Call shape, e.g. "take(sequence, n)"; alternatives are separated by " / "
+    pub signature: &'static str,
+    pub category: &'static str,
+    pub description: &'static str,
+    // Result depends only on the arguments (no output, no calls back into script functions) and
+    // costs more than hashing them, so it may be served from the pure-call cache
+    pub pure: bool,
+    function: NativeFunction,
+}
+
+/// All builtin functions, in registration order, indexed by name.
+pub struct BuiltinRegistry {
+    builtins: Vec<Builtin>,
+    by_name: HashMap<&'static str, usize>,
+}
+
+impl BuiltinRegistry {
+    fn register(&mut self, name: &'static str, signature: &'static str, category: &'static str, description: &'static str, function: NativeFunction) {
+        self.by_name.insert(name, self.builtins.len());
+        self.builtins.push(Builtin { name, signature, category, description, pure: false, function });
+    }
+
+    fn mark_pure(&mut self, names: &[&str]) {
+        for name in names {
+            let index = self.by_name[name];
+            self.builtins[index].pure = true;
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Builtin> {
+        self.by_name.get(name).map(|&i| &self.builtins[i])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Builtin> {
+        self.builtins.iter()
+    }
+
+    /// Category names in the order they were first registered.
+    pub fn categories(&self) -> Vec<&'static str> {
+        let mut categories: Vec<&'static str> = Vec::new();
+        for builtin in &self.builtins {
+            if !categories.contains(&builtin.category) {
+                categories.push(builtin.category);
+            }
+        }
+        categories
+    }
+}
+
+static BUILTINS: LazyLock<BuiltinRegistry> = LazyLock::new(|| {
+    let mut r = BuiltinRegistry { builtins: Vec::new(), by_name: HashMap::new() };
+    // Array helpers; other array manipulation (slicing, mutability) is handled by Expr::Slice and Expr::Infix
+    r.register("length", "length(array)", "arrays", "Number of elements in an array.", native_length);
+    r.register("binary_search", "binary_search(sorted_array, value)", "arrays", "Index of value in a sorted array, or -1 if absent.", native_binary_search);
+    r.register("unique", "unique(array)", "arrays", "Array without duplicates, keeping first occurrences.", native_unique);
+    r.register("group_by", "group_by(function, array)", "arrays", "Pairs [key, items] grouping elements by function(element).", native_group_by);
+    r.register("zip", "zip(array, array)", "arrays", "Pairs [a, b] of elements at the same index, up to the shorter array.", native_zip);
+    // Builtin forms of == and ===
+    r.register("equals", "equals(a, b)", "comparison", "Same as a == b (numbers compare across Integer and Float).", native_equals);
+    r.register("strict_equals", "strict_equals(a, b)", "comparison", "Same as a === b (no numeric coercion).", native_equals);
+    // Lazy sequences and their consumers
+    r.register("range", "range(end) / range(start, end) / range(start, end, step)", "sequences", "Lazy sequence of integers from start (default 0) up to, not including, end.", native_range);
+    r.register("take", "take(sequence, n)", "sequences", "Lazy sequence of the first n items.", native_take);
+    r.register("drop", "drop(sequence, n)", "sequences", "Lazy sequence without the first n items.", native_drop);
+    r.register("step", "step(sequence, n)", "sequences", "Lazy sequence of every n-th item, starting with the first.", native_step);
+    r.register("enumerate", "enumerate(sequence)", "sequences", "Lazy sequence of [index, item] pairs.", native_enumerate);
+    r.register("map", "map(function, sequence)", "sequences", "Array of function(item) for every item.", native_map);
+    r.register("sum", "sum(sequence)", "sequences", "Sum of all items (0 for an empty sequence).", native_sum);
+    r.register("divmod", "divmod(a, b)", "numbers", "Pair [quotient, remainder] with quotient * b + remainder == a, rounded like '/' and '%' under --int-div.", native_divmod);
+    // Exact rounding; the optional last argument is a mode: half_even, half_up, half_down, up, down, ceiling or floor
+    r.register("round", "round(x) / round(x, digits) / round(x, digits, mode)", "numbers", "x rounded to digits decimal places (default 0; negative rounds to tens, hundreds, ...), keeping its type. Mode defaults to half_even.", native_round);
+    r.register("trunc", "trunc(x) / trunc(x, mode)", "numbers", "x as an Integer, rounded toward zero unless another mode is given.", native_trunc);
+    r.register("floor_div", "floor_div(a, b) / floor_div(a, b, mode)", "numbers", "The exact quotient a / b as an Integer, rounded down unless another mode is given.", native_floor_div);
+    r.register("ceil_div", "ceil_div(a, b) / ceil_div(a, b, mode)", "numbers", "The exact quotient a / b as an Integer, rounded up unless another mode is given.", native_ceil_div);
+    r.register("to_fixed", "to_fixed(x, n) / to_fixed(x, n, mode)", "numbers", "String of x with exactly n digits after the decimal point. Mode defaults to half_even.", native_to_fixed);
+    // Unicode-aware text transforms; case conversion splits at separators and at case changes (fooBar, HTTPServer)
+    r.register("title_case", "title_case(string)", "strings", "Each word with its first letter uppercase and the rest lowercase; spacing and punctuation are kept.", native_title_case);
+    r.register("snake_case", "snake_case(string)", "strings", "Lowercase words joined by '_', e.g. \"parseHTTPResponse\" -> \"parse_http_response\".", native_snake_case);
+    r.register("camel_case", "camel_case(string)", "strings", "Words joined with each one capitalized except the first, e.g. \"user_id\" -> \"userId\".", native_camel_case);
+    r.register("normalize_nfc", "normalize_nfc(string)", "strings", "Unicode canonical composition (NFC): accented letters as single characters.", native_normalize_nfc);
+    r.register("normalize_nfd", "normalize_nfd(string)", "strings", "Unicode canonical decomposition (NFD): accented letters as a base letter and combining marks.", native_normalize_nfd);
+    r.register("strip_accents", "strip_accents(string)", "strings", "The string without accents and other combining marks, e.g. \"café\" -> \"cafe\".", native_strip_accents);
+    // Fuzzy matching by edit distance, as used for "did you mean" hints in errors
+    r.register("edit_distance", "edit_distance(a, b)", "strings", "Fewest single-character insertions, deletions and substitutions turning string a into b (Levenshtein distance).", native_edit_distance);
+    r.register("similarity", "similarity(a, b)", "strings", "Edit distance as a score from 0.0 (nothing in common) to 1.0 (equal strings).", native_similarity);
+    r.register("fuzzy_best", "fuzzy_best(query, strings)", "strings", "The string in the array closest to query by edit distance (the first on ties), or void if it is empty.", native_fuzzy_best);
+    // Shell-style patterns: '*' and '?' stay within one path component, '**' spans directories
+    r.register("glob", "glob(pattern)", "files", "Paths matching the pattern, e.g. glob(\"logs/**/*.txt\"), in sorted order. Needs --allow-read.", native_glob);
+    r.register("glob_match", "glob_match(pattern, path)", "files", "Whether the path matches the pattern, without touching the file system.", native_glob_match);
+    #[cfg(feature = "archives")]
+    {
+        // Binary data is an Array of byte values, as for hexdump
+        r.register("gzip_compress", "gzip_compress(string_or_bytes)", "archives", "The data compressed in gzip format, as bytes.", archives::native_gzip_compress);
+        r.register("gzip_decompress", "gzip_decompress(bytes) / gzip_decompress(bytes, \"text\") / gzip_decompress(bytes, format, max_bytes)", "archives", "The decompressed data of gzip bytes, as bytes or, with \"text\", as a UTF-8 String; fails past max_bytes (default 64 MiB).", archives::native_gzip_decompress);
+        r.register("zip_list", "zip_list(path)", "archives", "Names of the entries in a zip file; directories end with '/'. Needs --allow-read.", archives::native_zip_list);
+        r.register("zip_extract", "zip_extract(path, dest) / zip_extract(path, dest, max_bytes)", "archives", "Extracts a zip file into directory dest and returns the paths of the files written; fails before writing anything if a path leaves dest or the files take more than max_bytes (default 1 GiB). Needs --allow-read and --allow-write.", archives::native_zip_extract);
+        r.mark_pure(&["gzip_compress", "gzip_decompress"]);
+    }
+    #[cfg(feature = "markup")]
+    {
+        r.register("xml_parse", "xml_parse(text)", "markup", "The root element of an XML document as [name, attributes, children]; attributes are [name, value] pairs, children are elements or text.", markup::native_xml_parse);
+        r.register("html_select", "html_select(html, selector) / html_select(html, selector, attribute)", "markup", "Text of the elements matching a CSS selector, or the value of an attribute for those that have it.", markup::native_html_select);
+        r.mark_pure(&["xml_parse", "html_select"]);
+    }
+    #[cfg(feature = "mail")]
+    r.register("send_mail", "send_mail(smtp_config, to, subject, body)", "mail", "Sends a plain text email over SMTP; smtp_config is [key, value] pairs with \"host\" and \"from\" (also \"port\", \"username\", \"password\", \"security\"). Needs --allow-net.", mail::native_send_mail);
+    r.register("help", "help() / help(name)", "introspection", "Lists builtin categories, or describes one function.", native_help);
+    r.register("every", "every(interval, fn_name)", "scheduling", "Registers a zero-argument function to run every interval (e.g. \"5m\", \"1h30m\") under 'astra schedule'.", native_every);
+    r.register("mark_secret", "mark_secret(value)", "security", "Returns value unchanged, masking it (and text containing it or computed from it) as *** in the runlog, traces and errors. The value must print as at least 6 characters.", native_mark_secret);
+    // Debugging aids; both print their report
+    r.register("inspect", "inspect(value)", "debugging", "Prints the type, size and structure of value, and returns it unchanged.", native_inspect);
+    r.register("assert", "assert(condition) / assert(condition, message)", "debugging", "Fails with \"Assertion failed\" and the message unless condition is true.", native_assert);
+    // Quoted syntax (quote [ ... ])
+    r.register("ast_kind", "ast_kind(ast)", "syntax", "Node type of a quoted syntax value, e.g. \"Infix\", \"Call\" or \"If\".", native_ast_kind);
+    r.register("ast_children", "ast_children(ast)", "syntax", "Parts of a quoted syntax value: sub-expressions and statements as syntax values, names, operators and literals as plain values.", native_ast_children);
+    r.register("eval_ast", "eval_ast(ast) / eval_ast(ast, bindings)", "syntax", "Runs quoted syntax like a function body, with only the given [name, value] pairs as variables; returns its value.", native_eval_ast);
+    r.register("hexdump", "hexdump(string_or_bytes)", "debugging", "Prints the UTF-8 bytes of a string, or an array of byte values, as offset, hex and ASCII columns.", native_hexdump);
+    // Cheap builtins (length, range, round, ...) are left out: looking them up costs as much as calling them
+    r.mark_pure(&["unique", "sum", "ast_children"]);
+    r.mark_pure(&["normalize_nfc", "normalize_nfd", "strip_accents"]);
+    r.mark_pure(&["edit_distance", "similarity", "fuzzy_best", "glob_match"]);
+    r
+});
+
+/// The builtin functions available to every script.
+pub fn builtins() -> &'static BuiltinRegistry {
+    &BUILTINS
+}
+
+fn get_native_function(name: &str) -> Option<NativeFunction> {
+    BUILTINS.get(name).map(|builtin| builtin.function)
+}
+
+/// Only values from this point on are masked: a secret written as a literal in the script still
+/// shows up in the parse traces, so hosts should pass secrets in through bindings instead.
+fn native_mark_secret(fn_name: &str, _env: &mut Environment, runtime: &Runtime, mut args: Vec<Value>) -> Result<Value, String> {
+    if args.len() != 1 {
+        return Err(message!("'{}' expects 1 argument (value), found {}", fn_name, args.len()));
+    }
+    let value = args.remove(0);
+    runtime.mark_secret(&value, true)?;
+    Ok(value)
+}
+
+/// Only records the job: 'astra schedule' runs it once the script's top level has finished, and
+/// 'astra run' doesn't run it at all.
+fn native_every(fn_name: &str, _env: &mut Environment, runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    let (interval, function) = match args.as_slice() {
+        [Value::String(interval), Value::String(function)] => (interval, function),
+        [a, b] => return Err(message!("'{}' expects an interval String and a function name, found {:?} and {:?}", fn_name, a, b)),
+        _ => return Err(message!("'{}' expects 2 arguments (interval, fn_name), found {}", fn_name, args.len())),
+    };
+    let interval = schedule::parse_interval(interval)?;
+    match runtime.function(function) {
+        Some(def) if def.params.is_empty() => {}
+        Some(def) => return Err(message!("'{}' can only schedule functions without parameters, but '{}' takes {}", fn_name, function, def.params.len())),
+        None => return Err(message!("'{}' can only schedule functions defined earlier in the script, and '{}' is not", fn_name, function)),
+    }
+    runtime.jobs.borrow_mut().push(ScheduledJob { function: function.clone(), interval });
+    Ok(Value::Void)
+}
+
+fn native_help(fn_name: &str, _env: &mut Environment, runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    let text = match args.as_slice() {
+        [] => {
+            let mut lines = vec!["Builtin functions by category (help(\"name\") for details):".to_string()];
+            for category in BUILTINS.categories() {
+                let names: Vec<&str> = BUILTINS.iter().filter(|b| b.category == category).map(|b| b.name).collect();
+                lines.push(format!("  {}: {}", category, names.join(", ")));
+            }
+            lines.join("\n")
+        }
+        [Value::String(name)] | [Value::Function(name)] => {
+            if let Some(builtin) = BUILTINS.get(name) {
+                format!("{}\n  {}", builtin.signature, builtin.description)
+            } else if let Some(def) = runtime.function(name) {
+                format!("{}({})\n  User-defined function.", name, def.params.join(", "))
+            } else {
+                return Err(message!("No help available for '{}': it is not a builtin or defined function", name));
+            }
+        }
+        [other] => return Err(message!("Argument to '{}' must be a function name, found {:?}", fn_name, other)),
+        _ => return Err(message!("'{}' expects 0 or 1 arguments, found {}", fn_name, args.len())),
+    };
+    write_output(&text, "Block Output", runtime)?;
+    Ok(Value::Void)
+}
+
+// --- Array Helper Functions ---
+
+fn native_length(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, mut args: Vec<Value>) -> Result<Value, String> {
+    if args.len() != 1 {
+        return Err(message!("'{}' expects 1 argument (array), found {}", fn_name, args.len()));
+    }
+    match args.remove(0) {
+        Value::Array(a) => Ok(Value::Integer(BigInt::from(a.len()))),
+        v => Err(message!("Argument to '{}' must be an Array, found {:?}", fn_name, v)),
+    }
+}
+
+/// Orders two values of the same comparable type (Integer, Float or String).
+fn compare_values(l: &Value, r: &Value) -> Result<Ordering, String> {
+    partial_compare(l, r)?.ok_or_else(|| message!("Cannot order NaN: {} and {}", l, r))
+}
+
+/// Orders two numbers (Integer and Float may be mixed) or two strings.
+/// Ok(None) means the values are numbers but unordered because one of them is NaN.
+fn partial_compare(l: &Value, r: &Value) -> Result<Option<Ordering>, String> {
+    if let Some(ordering) = numeric::compare(l, r) {
+        return Ok(ordering);
+    }
+    match (l, r) {
+        (Value::String(l), Value::String(r)) => Ok(Some(l.cmp(r))),
+        (l, r) => Err(message!("Cannot order values of different or unordered types: {:?} and {:?}", l, r)),
+    }
+}
+
+/// Equality used by ==, !=, ===, !== and the equals/strict_equals builtins.
+///
+/// | left \ right | Integer             | Float               | String | Boolean | Array        | Void |
+/// |--------------|---------------------|---------------------|--------|---------|--------------|------|
+/// | Integer      | value               | `==`: exact numeric | false  | false   | false        | false|
+/// | Float        | `==`: exact numeric | value (NaN != NaN)  | false  | false   | false        | false|
+/// | String       | false               | false               | value  | false   | false        | false|
+/// | Boolean      | false               | false               | false  | value   | false        | false|
+/// | Array        | false               | false               | false  | false   | element-wise | false|
+/// | Void         | false               | false               | false  | false   | false        | true |
+///
+/// Strict equality never coerces, so `1 === 1.0` is false while `1 == 1.0` is true. Arrays compare
+/// element by element using the same mode. Functions and sequences are equal only to an identical
+/// function reference or sequence. The matrix is symmetric in both modes.
+fn values_equal(l: &Value, r: &Value, strict: bool) -> bool {
+    match (l, r) {
+        (Value::Integer(_), Value::Float(_)) | (Value::Float(_), Value::Integer(_)) if !strict => {
+            numeric::compare(l, r) == Some(Some(Ordering::Equal))
+        }
+        (Value::Array(l), Value::Array(r)) => {
+            l.len() == r.len() && l.iter().zip(r).all(|(a, b)| values_equal(a, b, strict))
+        }
+        (l, r) => l == r,
+    }
+}
+
+/// A value used as a hash key, so collections can be deduplicated and grouped without rendering
+/// their items. Keys are equal when the values are strictly equal (1 and 1.0 stay apart), except
+/// that every NaN is the same key.
+pub(crate) struct ValueKey<'a>(pub(crate) &'a Value);
+
+// 0.0 and -0.0 are equal, and all NaNs are one key
+fn float_key(f: f64) -> u64 {
+    if f.is_nan() {
+        f64::NAN.to_bits()
+    } else if f == 0.0 {
+        0
+    } else {
+        f.to_bits()
+    }
+}
+
+fn same_key(l: &Value, r: &Value) -> bool {
+    match (l, r) {
+        (Value::Float(l), Value::Float(r)) => float_key(*l) == float_key(*r),
+        (Value::Array(l), Value::Array(r)) => l.len() == r.len() && l.iter().zip(r).all(|(a, b)| same_key(a, b)),
+        (l, r) => l == r,
+    }
+}
+
+fn hash_value<H: Hasher>(value: &Value, state: &mut H) {
+    std::mem::discriminant(value).hash(state);
+    match value {
+        Value::Integer(n) => n.hash(state),
+        Value::Float(f) => float_key(*f).hash(state),
+        Value::String(s) | Value::Function(s) => s.hash(state),
+        Value::Boolean(b) => b.hash(state),
+        Value::Array(items) => {
+            items.len().hash(state);
+            for item in items {
+                hash_value(item, state);
+            }
+        }
+        // Sequences are hashed by their recipe, e.g. sum(range(1, 10^6)) for the pure-call cache
+        Value::Sequence(seq) => {
+            std::mem::discriminant(seq.as_ref()).hash(state);
+            match seq.as_ref() {
+                LazySeq::Range(start, end, step) => (start, end, step).hash(state),
+                LazySeq::Take(inner, n) | LazySeq::Drop(inner, n) | LazySeq::Step(inner, n) => {
+                    hash_value(inner, state);
+                    n.hash(state);
+                }
+                LazySeq::Enumerate(inner) => hash_value(inner, state),
+            }
+        }
+        // Rare as keys; same_key compares them in full
+        Value::Ast(_) | Value::Void => {}
+    }
+}
+
+impl PartialEq for ValueKey<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        same_key(self.0, other.0)
+    }
+}
+
+impl Eq for ValueKey<'_> {}
+
+impl Hash for ValueKey<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_value(self.0, state);
+    }
+}
+
+fn native_equals(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    match args.as_slice() {
+        [a, b] => Ok(Value::Boolean(values_equal(a, b, fn_name == "strict_equals"))),
+        _ => Err(message!("'{}' expects 2 arguments, found {}", fn_name, args.len())),
+    }
+}
+
+fn native_binary_search(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, mut args: Vec<Value>) -> Result<Value, String> {
+    if args.len() != 2 {
+        return Err(message!("'{}' expects 2 arguments (sorted array, value), found {}", fn_name, args.len()));
+    }
+    let target = args.remove(1);
+    let elements = match args.remove(0) {
+        Value::Array(a) => a,
+        v => return Err(message!("First argument to '{}' must be an Array, found {:?}", fn_name, v)),
+    };
+
+    // Returns the index of a matching element, or -1 when the value is absent
+    let (mut low, mut high) = (0, elements.len());
+    while low < high {
+        let mid = low + (high - low) / 2;
+        match compare_values(&elements[mid], &target)? {
+            Ordering::Less => low = mid + 1,
+            Ordering::Greater => high = mid,
+            Ordering::Equal => return Ok(Value::Integer(BigInt::from(mid))),
+        }
+    }
+    Ok(Value::Integer(BigInt::from(-1)))
+}
+
+fn native_unique(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, mut args: Vec<Value>) -> Result<Value, String> {
+    if args.len() != 1 {
+        return Err(message!("'{}' expects 1 argument (array), found {}", fn_name, args.len()));
+    }
+    let elements = match args.remove(0) {
+        Value::Array(a) => a,
+        v => return Err(message!("Argument to '{}' must be an Array, found {:?}", fn_name, v)),
+    };
+
+    // Keeps the first occurrence of each value; 1 and 1.0 stay distinct
+    let mut seen = HashSet::new();
+    let first: Vec<bool> = elements.iter().map(|v| seen.insert(ValueKey(v))).collect();
+    let result = elements.into_iter().zip(first).filter_map(|(v, first)| first.then_some(v)).collect();
+    Ok(Value::Array(result))
+}
+
+fn native_group_by(fn_name: &str, env: &mut Environment, runtime: &Runtime, mut args: Vec<Value>) -> Result<Value, String> {
+    if args.len() != 2 {
+        return Err(message!("'{}' expects 2 arguments (function, array), found {}", fn_name, args.len()));
+    }
+    let elements = match args.remove(1) {
+        Value::Array(a) => a,
+        v => return Err(message!("Second argument to '{}' must be an Array, found {:?}", fn_name, v)),
+    };
+    let key_fn = match args.remove(0) {
+        Value::Function(name) => name,
+        v => return Err(message!("First argument to '{}' must be a function, found {:?}", fn_name, v)),
+    };
+
+    let keys = elements
+        .iter()
+        .map(|element| call_function(&key_fn, vec![element.clone()], env, runtime))
+        .collect::<Result<Vec<Value>, String>>()?;
+    // Groups are returned as [key, [items...]] pairs in order of first appearance
+    let mut index_by_key: HashMap<ValueKey, usize> = HashMap::new();
+    let mut groups: Vec<(&Value, Vec<Value>)> = Vec::new();
+    for (key, element) in keys.iter().zip(elements) {
+        let slot = *index_by_key.entry(ValueKey(key)).or_insert_with(|| {
+            groups.push((key, Vec::new()));
+            groups.len() - 1
+        });
+        groups[slot].1.push(element);
+    }
+    Ok(Value::Array(
+        groups
+            .into_iter()
+            .map(|(key, items)| Value::Array(vec![key.clone(), Value::Array(items)]))
+            .collect(),
+    ))
+}
+
+fn native_zip(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, mut args: Vec<Value>) -> Result<Value, String> {
+    if args.len() != 2 {
+        return Err(message!("'{}' expects 2 arguments (array, array), found {}", fn_name, args.len()));
+    }
+    match (args.remove(0), args.remove(0)) {
+        // Pairs are truncated to the shorter of the two arrays
+        (Value::Array(a), Value::Array(b)) => Ok(Value::Array(
+            a.into_iter().zip(b).map(|(x, y)| Value::Array(vec![x, y])).collect(),
+        )),
+        (a, b) => Err(message!("Arguments to '{}' must be Arrays, found {:?} and {:?}", fn_name, a, b)),
+    }
+}
+
+
+// --- Lazy Sequence Helpers ---
+
+struct RangeIter {
+    current: BigInt,
+    end: BigInt,
+    step: BigInt,
+}
+
+impl Iterator for RangeIter {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        let in_bounds = if self.step.is_positive() { self.current < self.end } else { self.current > self.end };
+        if !in_bounds {
+            return None;
+        }
+        let item = self.current.clone();
+        self.current += &self.step;
+        Some(Value::Integer(item))
+    }
+}
+
+/// Number of items produced by range(start, end, step).
+fn range_len(start: &BigInt, end: &BigInt, step: &BigInt) -> BigInt {
+    let span = if step.is_positive() { end - start } else { start - end };
+    if !span.is_positive() {
+        return BigInt::zero();
+    }
+    let step_abs = step.abs();
+    (span + &step_abs - BigInt::one()) / step_abs
+}
+
+/// Converts a count argument to usize, saturating for counts larger than any in-memory sequence.
+fn count_to_usize(n: &BigInt) -> usize {
+    n.to_usize().unwrap_or(usize::MAX)
+}
+
+/// Produces the items of an Array or lazy Sequence one at a time without materializing the sequence.
+fn iterate(value: &Value) -> Result<Box<dyn Iterator<Item = Value> + '_>, String> {
+    match value {
+        Value::Array(elements) => Ok(Box::new(elements.iter().cloned())),
+        Value::Sequence(seq) => match &**seq {
+            LazySeq::Range(start, end, step) => Ok(Box::new(RangeIter {
+                current: start.clone(),
+                end: end.clone(),
+                step: step.clone(),
+            })),
+            LazySeq::Take(inner, n) => Ok(Box::new(iterate(inner)?.take(count_to_usize(n)))),
+            LazySeq::Drop(inner, n) => Ok(Box::new(iterate(inner)?.skip(count_to_usize(n)))),
+            LazySeq::Step(inner, n) => Ok(Box::new(iterate(inner)?.step_by(count_to_usize(n)))),
+            LazySeq::Enumerate(inner) => Ok(Box::new(
+                iterate(inner)?
+                    .enumerate()
+                    .map(|(i, v)| Value::Array(vec![Value::Integer(BigInt::from(i)), v])),
+            )),
+        },
+        v => Err(message!("Value is not iterable (expected an Array or sequence): {:?}", v)),
+    }
+}
+
+fn expect_integer(fn_name: &str, value: Value) -> Result<BigInt, String> {
+    match value {
+        Value::Integer(n) => Ok(n),
+        v => Err(message!("'{}' expects Integer arguments, found {:?}", fn_name, v)),
+    }
+}
+
+fn expect_iterable(fn_name: &str, value: Value) -> Result<Value, String> {
+    match value {
+        Value::Array(_) | Value::Sequence(_) => Ok(value),
+        v => Err(message!("'{}' expects an Array or sequence, found {:?}", fn_name, v)),
+    }
+}
+
+fn native_range(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    let mut bounds = args
+        .into_iter()
+        .map(|v| expect_integer(fn_name, v))
+        .collect::<Result<Vec<BigInt>, String>>()?;
+    let (start, end, step) = match bounds.len() {
+        1 => (BigInt::zero(), bounds.remove(0), BigInt::one()),
+        2 => (bounds.remove(0), bounds.remove(0), BigInt::one()),
+        3 => (bounds.remove(0), bounds.remove(0), bounds.remove(0)),
+        n => return Err(message!("'{}' expects 1 to 3 arguments (start, end, step), found {}", fn_name, n)),
+    };
+    if step.is_zero() {
+        return Err(message!("'{}' step must not be zero", fn_name));
+    }
+    Ok(Value::Sequence(Box::new(LazySeq::Range(start, end, step))))
+}
+
+/// Shared argument handling for take/drop/step: (iterable, non-negative count).
+fn sequence_and_count(fn_name: &str, mut args: Vec<Value>) -> Result<(Value, BigInt), String> {
+    if args.len() != 2 {
+        return Err(message!("'{}' expects 2 arguments (sequence, count), found {}", fn_name, args.len()));
+    }
+    let n = expect_integer(fn_name, args.remove(1))?;
+    if n.is_negative() {
+        return Err(message!("'{}' count must not be negative, found {}", fn_name, n));
+    }
+    Ok((expect_iterable(fn_name, args.remove(0))?, n))
+}
+
+fn native_take(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    let (inner, n) = sequence_and_count(fn_name, args)?;
+    // Taking from a range stays a range, so the bound is adjusted arithmetically
+    if let Value::Sequence(seq) = &inner && let LazySeq::Range(start, end, step) = &**seq {
+        let len = range_len(start, end, step);
+        let new_end = start + step * n.min(len);
+        return Ok(Value::Sequence(Box::new(LazySeq::Range(start.clone(), new_end, step.clone()))));
+    }
+    Ok(Value::Sequence(Box::new(LazySeq::Take(inner, n))))
+}
+
+fn native_drop(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    let (inner, n) = sequence_and_count(fn_name, args)?;
+    if let Value::Sequence(seq) = &inner && let LazySeq::Range(start, end, step) = &**seq {
+        let len = range_len(start, end, step);
+        let new_start = start + step * n.min(len);
+        return Ok(Value::Sequence(Box::new(LazySeq::Range(new_start, end.clone(), step.clone()))));
+    }
+    Ok(Value::Sequence(Box::new(LazySeq::Drop(inner, n))))
+}
+
+fn native_step(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    let (inner, n) = sequence_and_count(fn_name, args)?;
+    if n.is_zero() {
+        return Err(message!("'{}' step must be positive", fn_name));
+    }
+    if let Value::Sequence(seq) = &inner && let LazySeq::Range(start, end, step) = &**seq {
+        return Ok(Value::Sequence(Box::new(LazySeq::Range(start.clone(), end.clone(), step * n))));
+    }
+    Ok(Value::Sequence(Box::new(LazySeq::Step(inner, n))))
+}
+
+fn native_enumerate(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, mut args: Vec<Value>) -> Result<Value, String> {
+    if args.len() != 1 {
+        return Err(message!("'{}' expects 1 argument (sequence), found {}", fn_name, args.len()));
+    }
+    let inner = expect_iterable(fn_name, args.remove(0))?;
+    Ok(Value::Sequence(Box::new(LazySeq::Enumerate(inner))))
+}
+
+fn native_map(fn_name: &str, env: &mut Environment, runtime: &Runtime, mut args: Vec<Value>) -> Result<Value, String> {
+    if args.len() != 2 {
+        return Err(message!("'{}' expects 2 arguments (function, sequence), found {}", fn_name, args.len()));
+    }
+    let source = expect_iterable(fn_name, args.remove(1))?;
+    let map_fn = match args.remove(0) {
+        Value::Function(name) => name,
+        v => return Err(message!("First argument to '{}' must be a function, found {:?}", fn_name, v)),
+    };
+    let mapped = iterate(&source)?
+        .map(|item| call_function(&map_fn, vec![item], env, runtime))
+        .collect::<Result<Vec<Value>, String>>()?;
+    Ok(Value::Array(mapped))
+}
+
+/// In promote mode '/' gives a Float, but divmod still returns a whole quotient, rounded down.
+fn native_divmod(fn_name: &str, _env: &mut Environment, runtime: &Runtime, mut args: Vec<Value>) -> Result<Value, String> {
+    if args.len() != 2 {
+        return Err(message!("'{}' expects 2 arguments (a, b), found {}", fn_name, args.len()));
+    }
+    let (a, b) = (args.remove(0), args.remove(0));
+    if !a.is_number() || !b.is_number() {
+        return Err(message!("Arguments to '{}' must be numbers, found {:?} and {:?}", fn_name, a, b));
+    }
+    let (quotient, remainder) = numeric::divmod(numeric::coerce_pair(a, b)?, runtime.options.int_division)?;
+    Ok(Value::Array(vec![quotient, remainder]))
+}
+
+// Bounds the power of ten a rounding builtin computes
+const MAX_ROUNDING_DIGITS: u32 = 10_000;
+
+/// The optional rounding mode argument of the rounding builtins.
+fn rounding_mode(fn_name: &str, mode: Option<&Value>, default: RoundingMode) -> Result<RoundingMode, String> {
+    match mode {
+        None => Ok(default),
+        Some(Value::String(name)) => name.parse(),
+        Some(v) => Err(message!("'{}' expects a rounding mode name (e.g., \"half_up\"), found {:?}", fn_name, v)),
+    }
+}
+
+fn rounding_digits(fn_name: &str, value: &Value) -> Result<i64, String> {
+    let digits = expect_integer(fn_name, value.clone())?;
+    digits
+        .to_i64()
+        .filter(|d| d.unsigned_abs() <= u64::from(MAX_ROUNDING_DIGITS))
+        .ok_or_else(|| message!("'{}' digits must be between -{} and {}, found {}", fn_name, MAX_ROUNDING_DIGITS, MAX_ROUNDING_DIGITS, digits))
+}
+
+fn fraction_arg(fn_name: &str, value: &Value) -> Result<(BigInt, BigInt), String> {
+    if !value.is_number() {
+        return Err(message!("'{}' expects a number, found {:?}", fn_name, value));
+    }
+    numeric::to_fraction(value)
+}
+
+/// Rounds the exact value of x, so round(2.675, 2) is 2.67: the nearest f64 to 2.675 is just below it.
+fn native_round(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    if args.is_empty() || args.len() > 3 {
+        return Err(message!("'{}' expects 1 to 3 arguments (x, digits, mode), found {}", fn_name, args.len()));
+    }
+    let digits = match args.get(1) {
+        Some(digits) => rounding_digits(fn_name, digits)?,
+        None => 0,
+    };
+    let mode = rounding_mode(fn_name, args.get(2), RoundingMode::HalfEven)?;
+    let (num, den) = fraction_arg(fn_name, &args[0])?;
+    // units * 10^-digits is the result
+    let scale = BigInt::from(10u32).pow(digits.unsigned_abs() as u32);
+    let units = if digits >= 0 {
+        numeric::round_fraction(&(num * &scale), &den, mode)
+    } else {
+        numeric::round_fraction(&num, &(den * &scale), mode)
+    };
+    match &args[0] {
+        Value::Integer(i) if digits >= 0 => Ok(Value::Integer(i.clone())),
+        Value::Integer(_) => Ok(Value::Integer(units * scale)),
+        // Parsing the decimal gives the f64 nearest to it
+        _ => format!("{}e{}", units, -digits).parse::<f64>().map(Value::Float).map_err(|e| message!("Invalid float: {}", e)),
+    }
+}
+
+fn native_trunc(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(message!("'{}' expects 1 or 2 arguments (x, mode), found {}", fn_name, args.len()));
+    }
+    let mode = rounding_mode(fn_name, args.get(1), RoundingMode::Down)?;
+    let (num, den) = fraction_arg(fn_name, &args[0])?;
+    Ok(Value::Integer(numeric::round_fraction(&num, &den, mode)))
+}
+
+/// Shared by floor_div and ceil_div: the exact quotient of two numbers, rounded to an Integer.
+fn divide_rounded(fn_name: &str, args: Vec<Value>, default: RoundingMode) -> Result<Value, String> {
+    if args.len() < 2 || args.len() > 3 {
+        return Err(message!("'{}' expects 2 or 3 arguments (a, b, mode), found {}", fn_name, args.len()));
+    }
+    let mode = rounding_mode(fn_name, args.get(2), default)?;
+    let (a_num, a_den) = fraction_arg(fn_name, &args[0])?;
+    let (b_num, b_den) = fraction_arg(fn_name, &args[1])?;
+    if b_num.is_zero() {
+        return Err(message!("Division by zero"));
+    }
+    Ok(Value::Integer(numeric::round_fraction(&(a_num * b_den), &(a_den * b_num), mode)))
+}
+
+fn native_floor_div(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    divide_rounded(fn_name, args, RoundingMode::Floor)
+}
+
+fn native_ceil_div(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    divide_rounded(fn_name, args, RoundingMode::Ceiling)
+}
+
+fn native_to_fixed(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    if args.len() < 2 || args.len() > 3 {
+        return Err(message!("'{}' expects 2 or 3 arguments (x, n, mode), found {}", fn_name, args.len()));
+    }
+    let decimals = rounding_digits(fn_name, &args[1])?;
+    if decimals < 0 {
+        return Err(message!("'{}' digits must not be negative, found {}", fn_name, decimals));
+    }
+    let mode = rounding_mode(fn_name, args.get(2), RoundingMode::HalfEven)?;
+    let (num, den) = fraction_arg(fn_name, &args[0])?;
+    let units = numeric::round_fraction(&(num * BigInt::from(10u32).pow(decimals as u32)), &den, mode);
+    Ok(Value::String(numeric::fixed_point(&units, decimals as usize)))
+}
+
+fn native_sum(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, mut args: Vec<Value>) -> Result<Value, String> {
+    if args.len() != 1 {
+        return Err(message!("'{}' expects 1 argument (sequence), found {}", fn_name, args.len()));
+    }
+    let source = expect_iterable(fn_name, args.remove(0))?;
+
+    // Closed form for ranges: n * start + step * n * (n - 1) / 2
+    if let Value::Sequence(seq) = &source && let LazySeq::Range(start, end, step) = &**seq {
+        let n = range_len(start, end, step);
+        let total = &n * start + step * &n * (&n - BigInt::one()) / BigInt::from(2);
+        return Ok(Value::Integer(total));
+    }
+
+    // The first Float promotes the running total
+    let mut total = Value::Integer(BigInt::zero());
+    for item in iterate(&source)? {
+        if !item.is_number() {
+            return Err(message!("'{}' only supports numbers, found {:?}", fn_name, item));
+        }
+        // Integers are added into the running total's allocation
+        if let Value::Integer(n) = &item && numeric::update_in_place('+', &mut total, n) {
+            continue;
+        }
+        total = numeric::arithmetic_ref('+', &total, &item, IntDivision::Trunc)?;
+    }
+    Ok(total)
+}
+
+// --- Debugging Helpers ---
+
+fn native_assert(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    match args.as_slice() {
+        [Value::Boolean(true)] | [Value::Boolean(true), Value::String(_)] => Ok(Value::Void),
+        [Value::Boolean(false)] => Err(message!("Assertion failed")),
+        [Value::Boolean(false), Value::String(text)] => Err(message!("Assertion failed: {}", text)),
+        [Value::Boolean(_), other] => Err(message!("The message of '{}' must be a String, found {:?}", fn_name, other)),
+        [other, ..] if args.len() <= 2 => Err(message!("The condition of '{}' must be a Boolean, found {:?}", fn_name, other)),
+        _ => Err(message!("'{}' expects 1 or 2 arguments (condition, message), found {}", fn_name, args.len())),
+    }
+}
+
+/// Array items that inspect lists before summarizing the rest.
+const INSPECT_MAX_ITEMS: usize = 10;
+
+fn native_inspect(fn_name: &str, _env: &mut Environment, runtime: &Runtime, mut args: Vec<Value>) -> Result<Value, String> {
+    if args.len() != 1 {
+        return Err(message!("'{}' expects 1 argument (value), found {}", fn_name, args.len()));
+    }
+    let value = args.remove(0);
+    let mut lines = Vec::new();
+    describe_value(&value, "", 0, runtime, &mut lines);
+    write_output(&lines.join("\n"), "Block Output", runtime)?;
+    Ok(value)
+}
+
+/// Appends one line for `value` (and one per item for arrays) at the given indentation.
+fn describe_value(value: &Value, prefix: &str, depth: usize, runtime: &Runtime, lines: &mut Vec<String>) {
+    let indent = "  ".repeat(depth);
+    let summary = match value {
+        Value::Integer(n) => format!("Integer, {} bits: {}", n.bits(), n),
+        Value::Float(f) => format!("Float: {}", print_repr(&Value::Float(*f))),
+        Value::String(s) => format!("String, {} chars, {} bytes: {:?}", s.chars().count(), s.len(), s),
+        Value::Boolean(b) => format!("Boolean: {}", b),
+        Value::Function(name) => match runtime.function(name) {
+            Some(def) => format!("Function: {}({}), user-defined", name, def.params.join(", ")),
+            None => format!("Function: {}, builtin", name),
+        },
+        Value::Sequence(seq) => format!("Sequence (lazy): {}", seq),
+        Value::Ast(ast) => format!("Ast, {}: {}", ast.kind(), ast),
+        Value::Void => "Void".to_string(),
+        Value::Array(items) => {
+            lines.push(format!("{}{}Array, {} items", indent, prefix, items.len()));
+            for (i, item) in items.iter().take(INSPECT_MAX_ITEMS).enumerate() {
+                describe_value(item, &format!("[{}] ", i), depth + 1, runtime, lines);
+            }
+            if items.len() > INSPECT_MAX_ITEMS {
+                lines.push(format!("{}  ... {} more", indent, items.len() - INSPECT_MAX_ITEMS));
+            }
+            return;
+        }
+    };
+    lines.push(format!("{}{}{}", indent, prefix, summary));
+}
+
+fn expect_ast(fn_name: &str, value: &Value) -> Result<Arc<Ast>, String> {
+    match value {
+        Value::Ast(ast) => Ok(Arc::clone(ast)),
+        v => Err(message!("'{}' expects quoted syntax (quote [ ... ]), found {:?}", fn_name, v)),
+    }
+}
+
+fn native_ast_kind(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    if args.len() != 1 {
+        return Err(message!("'{}' expects 1 argument (ast), found {}", fn_name, args.len()));
+    }
+    Ok(Value::String(expect_ast(fn_name, &args[0])?.kind().to_string()))
+}
+
+/// Children in source order. Absent optional parts (an 'if' without 'else', a bare 'return', a
+/// print without a format string) are void, so each kind always has the same number of children.
+/// A macro use has its name, arguments, block argument and the code it expanded to.
+fn native_ast_children(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    if args.len() != 1 {
+        return Err(message!("'{}' expects 1 argument (ast), found {}", fn_name, args.len()));
+    }
+    let expr = |e: &Expr| Value::Ast(Arc::new(Ast::Expr(e.clone())));
+    let block = |body: &[Statement]| Value::Ast(Arc::new(Ast::Block(body.to_vec())));
+    let text = |s: &str| Value::String(s.to_string());
+    let optional = |e: Option<&Expr>| e.map_or(Value::Void, expr);
+    let children = match &*expect_ast(fn_name, &args[0])? {
+        Ast::Expr(e) => match e {
+            Expr::Var(id) => vec![text(id)],
+            Expr::Num(n) => vec![number_literal(n)?],
+            Expr::Str(s) => vec![text(s)],
+            Expr::Bool(b) => vec![Value::Boolean(*b)],
+            Expr::Prefix(op, rhs) => vec![text(&op.to_string()), expr(rhs)],
+            Expr::Infix(lhs, op, rhs) => vec![expr(lhs), text(&op.to_string()), expr(rhs)],
+            Expr::Cmp(lhs, op, rhs) | Expr::Logic(lhs, op, rhs) => vec![expr(lhs), text(op), expr(rhs)],
+            Expr::Array(items) => items.iter().map(expr).collect(),
+            Expr::Slice(array, start, end) => vec![expr(array), optional(start.as_deref()), optional(end.as_deref())],
+            Expr::Call(name, args) => std::iter::once(text(name)).chain(args.iter().map(expr)).collect(),
+            Expr::Spread(inner) => vec![expr(inner)],
+            Expr::Error(_) => Vec::new(),
+            Expr::Quote(body) => vec![block(body)],
+            Expr::Expand(expansion) => {
+                let given = Value::Array(expansion.args.iter().map(expr).collect());
+                vec![text(&expansion.name), given, expansion.block.as_deref().map_or(Value::Void, block), expr(&expansion.code)]
+            }
+        },
+        Ast::Statement(stmt) => match stmt {
+            Statement::Expr(e) => vec![expr(e)],
+            Statement::Print(template, args) => {
+                std::iter::once(template.as_deref().map_or(Value::Void, text)).chain(args.iter().map(expr)).collect()
+            }
+            Statement::Def(name, params, body, ..) => {
+                vec![text(name), Value::Array(params.iter().map(|p| text(p)).collect()), block(body)]
+            }
+            Statement::Return(value) => vec![optional(value.as_ref())],
+            Statement::If(cond, then, otherwise) => vec![expr(cond), block(then), otherwise.as_deref().map_or(Value::Void, block)],
+            Statement::For(var, iterable, body) => vec![text(var), expr(iterable), block(body)],
+            Statement::Timed(label, body) => vec![label.as_deref().map_or(Value::Void, text), block(body)],
+            Statement::Error(_) => Vec::new(),
+            Statement::Macro(name, params, template) => {
+                vec![text(name), Value::Array(params.iter().map(|p| text(p)).collect()), block(template)]
+            }
+            Statement::Expand(expansion) => {
+                let given = Value::Array(expansion.args.iter().map(expr).collect());
+                vec![text(&expansion.name), given, expansion.block.as_deref().map_or(Value::Void, block), block(&expansion.code)]
+            }
+        },
+        Ast::Block(body) => body.iter().map(|stmt| Value::Ast(Arc::new(Ast::of_statement(stmt)))).collect(),
+    };
+    Ok(Value::Array(children))
+}
+
+/// Quoted syntax runs in an environment of its own, like a function body: the caller's variables
+/// are not visible, so everything it reads must be passed in `bindings`.
+fn native_eval_ast(fn_name: &str, _env: &mut Environment, runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(message!("'{}' expects 1 or 2 arguments (ast, bindings), found {}", fn_name, args.len()));
+    }
+    let ast = expect_ast(fn_name, &args[0])?;
+    let mut local_env = Environment::new();
+    if let Some(bindings) = args.get(1) {
+        for binding in iterate(bindings)? {
+            let pair = match binding {
+                Value::Array(items) => <[Value; 2]>::try_from(items).map_err(Value::Array),
+                other => Err(other),
+            };
+            match pair {
+                Ok([Value::String(name), value]) => {
+                    local_env.insert(name, value);
+                }
+                Ok(pair) => return Err(message!("'{}' bindings must be [name, value] pairs, found {:?}", fn_name, Value::Array(pair.into()))),
+                Err(other) => return Err(message!("'{}' bindings must be [name, value] pairs, found {:?}", fn_name, other)),
+            }
+        }
+    }
+    match &*ast {
+        Ast::Expr(expr) => eval(expr, &mut local_env, runtime),
+        Ast::Statement(stmt) => run_body_statements(fn_name, std::slice::from_ref(stmt), &mut local_env, runtime),
+        Ast::Block(body) => run_body_statements(fn_name, body, &mut local_env, runtime),
+    }
+}
+
+fn native_hexdump(fn_name: &str, _env: &mut Environment, runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    let bytes = match args.as_slice() {
+        [Value::String(s)] => s.as_bytes().to_vec(),
+        [Value::Array(items)] => byte_values(fn_name, items)?,
+        [other] => return Err(message!("Argument to '{}' must be a String or an Array of bytes, found {:?}", fn_name, other)),
+        _ => return Err(message!("'{}' expects 1 argument (string or bytes), found {}", fn_name, args.len())),
+    };
+    if !bytes.is_empty() {
+        let dump = hexdump(&bytes);
+        // The hex column spells out a secret that masking only finds in the text column
+        if runtime.contains_secret(&String::from_utf8_lossy(&bytes)) {
+            runtime.mark_secret(&Value::String(dump.clone()), false)?;
+        }
+        write_output(&dump, "Block Output", runtime)?;
+    }
+    Ok(Value::Void)
+}
+
+/// Applies a text transform to the single String argument of builtin `fn_name`.
+fn map_string(fn_name: &str, args: &[Value], transform: fn(&str) -> String) -> Result<Value, String> {
+    match args {
+        [Value::String(s)] => Ok(Value::String(transform(s))),
+        [other] => Err(message!("Argument to '{}' must be a String, found {:?}", fn_name, other)),
+        _ => Err(message!("'{}' expects 1 argument (string), found {}", fn_name, args.len())),
+    }
+}
+
+fn native_title_case(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    map_string(fn_name, &args, text::title_case)
+}
+
+fn native_snake_case(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    map_string(fn_name, &args, text::snake_case)
+}
+
+fn native_camel_case(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    map_string(fn_name, &args, text::camel_case)
+}
+
+fn native_normalize_nfc(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    map_string(fn_name, &args, text::normalize_nfc)
+}
+
+fn native_normalize_nfd(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    map_string(fn_name, &args, text::normalize_nfd)
+}
+
+fn native_strip_accents(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    map_string(fn_name, &args, text::strip_accents)
+}
+
+/// The two String arguments of builtin `fn_name`.
+fn string_pair<'a>(fn_name: &str, args: &'a [Value]) -> Result<(&'a str, &'a str), String> {
+    match args {
+        [Value::String(a), Value::String(b)] => Ok((a, b)),
+        [a, b] => Err(message!("'{}' expects two Strings, found {:?} and {:?}", fn_name, a, b)),
+        _ => Err(message!("'{}' expects 2 arguments (a, b), found {}", fn_name, args.len())),
+    }
+}
+
+fn native_edit_distance(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    let (a, b) = string_pair(fn_name, &args)?;
+    Ok(Value::Integer(text::edit_distance(a, b).into()))
+}
+
+fn native_similarity(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    let (a, b) = string_pair(fn_name, &args)?;
+    Ok(Value::Float(text::similarity(a, b)))
+}
+
+fn native_fuzzy_best(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    let [query, candidates] = args.as_slice() else {
+        return Err(message!("'{}' expects 2 arguments (query, strings), found {}", fn_name, args.len()));
+    };
+    let Value::String(query) = query else {
+        return Err(message!("'{}' expects a String query, found {:?}", fn_name, query));
+    };
+    let candidates = iterate(&expect_iterable(fn_name, candidates.clone())?)?
+        .map(|candidate| match candidate {
+            Value::String(s) => Ok(s),
+            other => Err(message!("'{}' expects an array of Strings, found {:?}", fn_name, other)),
+        })
+        .collect::<Result<Vec<String>, String>>()?;
+    Ok(text::closest(query, candidates.iter().map(String::as_str)).map_or(Value::Void, |(best, _)| Value::String(best.to_string())))
+}
+
+/// As in a shell: wildcards don't match '/' or a leading '.', so hidden files are only found by
+/// patterns that spell out the dot.
+const GLOB_OPTIONS: glob::MatchOptions =
+    glob::MatchOptions { case_sensitive: true, require_literal_separator: true, require_literal_leading_dot: true };
+
+fn glob_pattern(fn_name: &str, pattern: &str) -> Result<glob::Pattern, String> {
+    glob::Pattern::new(pattern).map_err(|e| message!("Invalid pattern '{}' for '{}': {}", pattern, fn_name, e))
+}
+
+/// Relative patterns are resolved against the current directory, and the paths keep the
+/// pattern's form: glob("logs/*.txt") gives "logs/a.txt".
+fn native_glob(fn_name: &str, _env: &mut Environment, runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    let pattern = match args.as_slice() {
+        [Value::String(pattern)] => pattern,
+        [other] => return Err(message!("Argument to '{}' must be a String pattern, found {:?}", fn_name, other)),
+        _ => return Err(message!("'{}' expects 1 argument (pattern), found {}", fn_name, args.len())),
+    };
+    runtime.require_read(fn_name)?;
+    let entries = glob::glob_with(pattern, GLOB_OPTIONS).map_err(|e| message!("Invalid pattern '{}' for '{}': {}", pattern, fn_name, e))?;
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| message!("'{}' could not read {}: {}", fn_name, e.path().display(), e.error()))?;
+        paths.push(Value::String(path.to_string_lossy().into_owned()));
+    }
+    Ok(Value::Array(paths))
+}
+
+fn native_glob_match(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    let (pattern, path) = match args.as_slice() {
+        [Value::String(pattern), Value::String(path)] => (pattern, path),
+        [a, b] => return Err(message!("'{}' expects two Strings (pattern, path), found {:?} and {:?}", fn_name, a, b)),
+        _ => return Err(message!("'{}' expects 2 arguments (pattern, path), found {}", fn_name, args.len())),
+    };
+    Ok(Value::Boolean(glob_pattern(fn_name, pattern)?.matches_with(path, GLOB_OPTIONS)))
+}
+
+/// Bytes written as an Array of Integers from 0 to 255, the form builtins take and return binary data in.
+fn byte_values(fn_name: &str, items: &[Value]) -> Result<Vec<u8>, String> {
+    items
+        .iter()
+        .map(|item| match item {
+            Value::Integer(n) => n.to_u8().ok_or_else(|| message!("'{}' expects byte values from 0 to 255, found {}", fn_name, n)),
+            other => Err(message!("'{}' expects byte values from 0 to 255, found {:?}", fn_name, other)),
+        })
+        .collect()
+}
+
+/// Canonical hex+ASCII layout (as 'hexdump -C'): 16 bytes per line in two groups of eight.
+fn hexdump(bytes: &[u8]) -> String {
+    let mut lines = Vec::new();
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        let mut hex = String::new();
+        for j in 0..16 {
+            match chunk.get(j) {
+                Some(byte) => hex.push_str(&format!("{:02x} ", byte)),
+                None => hex.push_str("   "),
+            }
+            if j == 7 {
+                hex.push(' ');
+            }
+        }
+        let ascii: String = chunk.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }).collect();
+        lines.push(format!("{:08x}  {} |{}|", i * 16, hex, ascii));
+    }
+    lines.join("\n")
+}
+
+// --- Numeric Loop Fusion ---
+
+/// Integer-only expression compiled for the fused loop. Variables are resolved to slots up front,
+/// so each iteration runs on machine integers without hashing into the environment.
+enum FusedExpr {
+    Slot(usize),
+    Const(i64),
+    Binary(char, Box<FusedExpr>, Box<FusedExpr>),
+}
+
+impl FusedExpr {
+    /// Returns None when the result leaves the i64 range (or on modulo by zero),
+    /// in which case the iteration is handed back to the general BigInt path.
+    fn eval(&self, slots: &[i64]) -> Option<i64> {
+        match self {
+            FusedExpr::Slot(i) => Some(slots[*i]),
+            FusedExpr::Const(n) => Some(*n),
+            FusedExpr::Binary(op, lhs, rhs) => {
+                let (l, r) = (lhs.eval(slots)?, rhs.eval(slots)?);
+                match op {
+                    '+' => l.checked_add(r),
+                    '-' => l.checked_sub(r),
+                    '*' => l.checked_mul(r),
+                    '%' => l.checked_rem(r),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+/// Loop body made only of integer assignments (e.g., `total += i * i`). Slot 0 is the loop variable.
+struct FusedLoop {
+    // '%' is only fused with truncating division, the semantics of i64::checked_rem
+    division: IntDivision,
+    slots: Vec<String>,
+    // Slots read before the body assigns them, which must be seeded from the environment
+    needs_init: Vec<bool>,
+    assignments: Vec<(usize, FusedExpr)>,
+}
+
+impl FusedLoop {
+    fn slot(&mut self, name: &str) -> usize {
+        match self.slots.iter().position(|s| s == name) {
+            Some(i) => i,
+            None => {
+                self.slots.push(name.to_string());
+                self.needs_init.push(false);
+                self.slots.len() - 1
+            }
+        }
+    }
+
+    fn compile_expr(&mut self, expr: &Expr, written: &[bool]) -> Option<FusedExpr> {
+        match expr {
+            Expr::Num(s) if !s.contains('.') => s.parse::<i64>().ok().map(FusedExpr::Const),
+            Expr::Var(id) => {
+                let slot = self.slot(id);
+                if slot != 0 && !written.get(slot).copied().unwrap_or(false) {
+                    self.needs_init[slot] = true;
+                }
+                Some(FusedExpr::Slot(slot))
+            }
+            Expr::Prefix('+', rhs) => self.compile_expr(rhs, written),
+            Expr::Prefix('-', rhs) => Some(FusedExpr::Binary('-', Box::new(FusedExpr::Const(0)), Box::new(self.compile_expr(rhs, written)?))),
+            Expr::Infix(lhs, op, rhs) if "+-*".contains(*op) || (*op == '%' && self.division == IntDivision::Trunc) => {
+                let l = self.compile_expr(lhs, written)?;
+                let r = self.compile_expr(rhs, written)?;
+                Some(FusedExpr::Binary(*op, Box::new(l), Box::new(r)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Compiles the loop body, or returns None if any statement falls outside the supported pattern.
+    fn compile(var_name: &str, body: &[Statement], division: IntDivision) -> Option<FusedLoop> {
+        let mut fused = FusedLoop { division, slots: vec![var_name.to_string()], needs_init: vec![false], assignments: Vec::new() };
+        let mut written = vec![true];
+        for stmt in body {
+            let Statement::Expr(Expr::Infix(target, '=', value)) = stmt else { return None };
+            let Expr::Var(target) = &**target else { return None };
+            if target == var_name {
+                return None;
+            }
+            let value = fused.compile_expr(value, &written)?;
+            let slot = fused.slot(target);
+            written.resize(fused.slots.len(), false);
+            written[slot] = true;
+            fused.assignments.push((slot, value));
+        }
+        if fused.assignments.is_empty() { None } else { Some(fused) }
+    }
+}
+
+enum FusedOutcome {
+    // The whole range ran on the fast path; carries the value of the last statement executed
+    Finished(Value),
+    // An iteration overflowed i64 or the run was cancelled; the remaining range must run on the general path
+    Resume(Value, Value),
+}
+
+/// Fast path for `for (i in range(...))` loops whose body only does integer arithmetic on variables.
+/// Returns None when the loop doesn't qualify and should run on the general path from the start.
+fn run_fused_range_loop(var_name: &str, iterable: &Value, body: &[Statement], env: &mut Environment, runtime: &Runtime) -> Option<FusedOutcome> {
+    // A step limit counts every statement, which the fused loop doesn't run
+    if !runtime.options.loop_fusion || runtime.options.max_steps.is_some() {
+        return None;
+    }
+    let Value::Sequence(seq) = iterable else { return None };
+    let LazySeq::Range(start, end, step) = &**seq else { return None };
+    let (start, end, step) = (start.to_i64()?, end.to_i64()?, step.to_i64()?);
+    let fused = FusedLoop::compile(var_name, body, runtime.options.int_division)?;
+    // Reading or assigning a host binding needs the checks of the general path
+    if fused.slots.iter().any(|name| runtime.bindings.contains_key(name) && !env.contains_key(name)) {
+        return None;
+    }
+
+    let mut slots = vec![0i64; fused.slots.len()];
+    for (i, name) in fused.slots.iter().enumerate() {
+        if fused.needs_init[i] {
+            slots[i] = match env.get(name) {
+                Some(Value::Integer(n)) => n.to_i64()?,
+                _ => return None,
+            };
+        }
+    }
+    debug!("Running fused loop over range({}, {}, {}) with slots {:?}", start, end, step, fused.slots);
+
+    let mut scratch = slots.clone();
+    let mut current = start;
+    let mut iterations: u64 = 0;
+    let mut interrupted = false;
+    while if step > 0 { current < end } else { current > end } {
+        // Polling the cancel flag every iteration would cost more than the body; the general path
+        // raises the error at the first statement of the remaining range
+        if iterations.is_multiple_of(65536) && runtime.cancel.is_cancelled() {
+            interrupted = true;
+            break;
+        }
+        scratch.copy_from_slice(&slots);
+        scratch[0] = current;
+        for (target, expr) in &fused.assignments {
+            match expr.eval(&scratch) {
+                Some(v) => scratch[*target] = v,
+                None => {
+                    interrupted = true;
+                    break;
+                }
+            }
+        }
+        if interrupted {
+            break;
+        }
+        std::mem::swap(&mut slots, &mut scratch);
+        iterations += 1;
+        // Stepping past i64 also means stepping past `end`, which fits in i64
+        match current.checked_add(step) {
+            Some(next) => current = next,
+            None => break,
+        }
+    }
+
+    // Each completed iteration ran every body statement; an interrupted one is run again by the general path
+    runtime.count_many(Counter::Statements, iterations * body.len() as u64);
+
+    let last_value = if iterations > 0 {
+        // Write back the loop variable and every assigned variable, exactly as the general path leaves them
+        let assigned: HashSet<usize> = fused.assignments.iter().map(|(slot, _)| *slot).collect();
+        for (i, name) in fused.slots.iter().enumerate() {
+            if i == 0 || assigned.contains(&i) {
+                env.insert(name.clone(), Value::Integer(BigInt::from(slots[i])));
+            }
+        }
+        let (last_target, _) = fused.assignments.last()?;
+        Value::Integer(BigInt::from(slots[*last_target]))
+    } else {
+        Value::Void
+    };
+
+    if interrupted {
+        let rest = LazySeq::Range(BigInt::from(current), BigInt::from(end), BigInt::from(step));
+        Some(FusedOutcome::Resume(Value::Sequence(Box::new(rest)), last_value))
+    } else {
+        Some(FusedOutcome::Finished(last_value))
+    }
+}
+
+fn execute_function(fn_name: &str, arg_exprs: &[Expr], caller_env: &mut Environment, runtime: &Runtime) -> Result<Value, String> {
+    debug!("Executing function '{}', args: {:?}", fn_name, arg_exprs);
+    
+    // Evaluate arguments first, expanding spread arguments (f(...list)) into positional values
+    let mut evaluated_args: Vec<Value> = Vec::with_capacity(arg_exprs.len());
+    for e in arg_exprs {
+        match e {
+            Expr::Spread(inner) => {
+                let spread_val = eval(inner, caller_env, runtime)?;
+                let items = iterate(&spread_val)
+                    .map_err(|_| message!("Spread argument to '{}' must be an Array or sequence, found {:?}", fn_name, spread_val))?;
+                evaluated_args.extend(items);
+            }
+            _ => evaluated_args.push(eval(e, caller_env, runtime)?),
+        }
+    }
+
+    call_function(fn_name, evaluated_args, caller_env, runtime)
+}
+
+/// Variables a call may go through to reach the function they refer to (f = g, h = f, ...).
+const MAX_FUNCTION_ALIASES: usize = 16;
+
+/// Invokes a native or user-defined function with already evaluated arguments.
+fn call_function(fn_name: &str, evaluated_args: Vec<Value>, caller_env: &mut Environment, runtime: &Runtime) -> Result<Value, String> {
+    // 0. A variable holding a function reference (e.g., a parameter `f` called as f(x)) shadows global names.
+    // References are followed until one names itself (g = g) or a function with no such variable
+    let mut target = fn_name.to_string();
+    let mut hops = 0;
+    while let Some(Value::Function(next)) = caller_env.get(&target).or_else(|| runtime.bindings.get(&target)) {
+        if *next == target {
+            break;
+        }
+        hops += 1;
+        if hops > MAX_FUNCTION_ALIASES {
+            return Err(message!("'{}' refers to a function through more than {} variables; do they refer to each other in a cycle?", fn_name, MAX_FUNCTION_ALIASES));
+        }
+        target = next.clone();
+    }
+    let fn_name = target.as_str();
+
+    runtime.count(Counter::Calls);
+    // 1. Check for Native Functions
+    if let Some(builtin) = BUILTINS.get(fn_name) {
+        let derived = runtime.derives_from_secret(&evaluated_args);
+        let result = if builtin.pure && runtime.options.pure_cache_size.is_some() {
+            call_pure_cached(builtin, evaluated_args, caller_env, runtime)?
+        } else {
+            (builtin.function)(fn_name, caller_env, runtime, evaluated_args)?
+        };
+        if derived {
+            runtime.mark_secret(&result, false)?;
+        }
+        Ok(result)
+    } 
+    // 2. Check for User-Defined Functions
+    else if let Some(def) = runtime.function(fn_name) {
+        call_decorated(fn_name, def, &def.wrappers, evaluated_args, caller_env, runtime)
+    } 
+    // 3. Undefined Function
+    else {
+        let known = BUILTINS.iter().map(|builtin| builtin.name).chain(runtime.function_names());
+        match text::suggestion(fn_name, known) {
+            Some(name) => Err(message!("Function '{}' is not defined (did you mean '{}'?)", fn_name, name)),
+            None => Err(message!("Function '{}' is not defined", fn_name)),
+        }
+    }
+}
+
+/// Calls a pure builtin through the bounded cache of earlier results.
+fn call_pure_cached(builtin: &Builtin, evaluated_args: Vec<Value>, caller_env: &mut Environment, runtime: &Runtime) -> Result<Value, String> {
+    let key = CallCache::key(&builtin.name, &evaluated_args);
+    if let Some(cached) = runtime.pure_cache.borrow().get(key, &builtin.name, &evaluated_args) {
+        runtime.count(Counter::PureCacheHits);
+        return Ok(cached.clone());
+    }
+    let result = (builtin.function)(builtin.name, caller_env, runtime, evaluated_args.clone())?;
+    let capacity = runtime.options.pure_cache_size.unwrap_or(0);
+    runtime.pure_cache.borrow_mut().insert(key, builtin.name, evaluated_args, result.clone(), capacity);
+    Ok(result)
+}
+
+/// Runs a user function through its builtin wrappers, outermost (last) first.
+fn call_decorated(fn_name: &str, def: &FunctionDef, wrappers: &[String], evaluated_args: Vec<Value>, caller_env: &mut Environment, runtime: &Runtime) -> Result<Value, String> {
+    match wrappers.split_last() {
+        // Host bindings (call_with_env) are input the key doesn't cover, so they bypass the cache
+        Some((wrapper, inner)) if wrapper == "memoize" && !runtime.bindings.is_empty() => {
+            call_decorated(fn_name, def, inner, evaluated_args, caller_env, runtime)
+        }
+        Some((wrapper, inner)) if wrapper == "memoize" => {
+            // Only the returned value is cached; output printed by the body is not replayed
+            let name = (fn_name.to_string(), inner.len());
+            let key = CallCache::key(&name, &evaluated_args);
+            if let Some(cached) = runtime.memo_cache.borrow().get(key, &name, &evaluated_args) {
+                return Ok(cached.clone());
+            }
+            let result = call_decorated(fn_name, def, inner, evaluated_args.clone(), caller_env, runtime)?;
+            runtime.memo_cache.borrow_mut().insert(key, name, evaluated_args, result.clone(), MEMO_CACHE_SIZE);
+            Ok(result)
+        }
+        Some((wrapper, inner)) if wrapper == "timed" => {
+            let start = Instant::now();
+            let result = call_decorated(fn_name, def, inner, evaluated_args, caller_env, runtime);
+            record_timing(fn_name, start.elapsed(), runtime);
+            result
+        }
+        Some((wrapper, _)) => Err(message!("Unknown builtin decorator '@{}' on function '{}'", wrapper, fn_name)),
+        None => match &def.rebound {
+            Some(target) => call_function(target, evaluated_args, caller_env, runtime),
+            None => run_function_body(fn_name, def, evaluated_args, runtime),
+        },
+    }
+}
+
+/// Binds the arguments to a fresh local environment and executes the function body,
+/// checking its contract around the call when --contracts is enabled.
+fn run_function_body(fn_name: &str, def: &FunctionDef, evaluated_args: Vec<Value>, runtime: &Runtime) -> Result<Value, String> {
+    if def.params.len() != evaluated_args.len() {
+        return Err(message!(
+            "Function '{}' expects {} arguments, but received {}",
+            fn_name, def.params.len(), evaluated_args.len()
+        ));
+    }
+    
+    let mut local_env = Environment::new();
+    for (param_name, arg_value) in def.params.iter().zip(evaluated_args) {
+        local_env.insert(param_name.clone(), arg_value);
+    }
+    //debug!("Local env for '{}': {:?}", fn_name, local_env);
+
+    if !runtime.options.contracts {
+        return run_body_statements(fn_name, &def.body, &mut local_env, runtime);
+    }
+    check_contract(fn_name, "requires", &def.contract.requires, &mut local_env.clone(), runtime)?;
+    let result = run_body_statements(fn_name, &def.body, &mut local_env, runtime)?;
+    if !def.contract.ensures.is_empty() {
+        let mut post_env = local_env.clone();
+        post_env.insert("result".to_string(), result.clone());
+        check_contract(fn_name, "ensures", &def.contract.ensures, &mut post_env, runtime)?;
+    }
+    Ok(result)
+}
+
+/// Evaluates each contract condition; the first one that is not true fails the call.
+fn check_contract(fn_name: &str, kind: &str, conditions: &[Expr], env: &mut Environment, runtime: &Runtime) -> Result<(), String> {
+    for condition in conditions {
+        match eval(condition, env, runtime)? {
+            Value::Boolean(true) => {}
+            Value::Boolean(false) => {
+                let what = if kind == "requires" { "Precondition" } else { "Postcondition" };
+                return Err(message!("{} failed for '{}': {} ({})", what, fn_name, kind, format_expr(condition, Layout::PLAIN)));
+            }
+            other => return Err(message!("Contract condition '{}' of '{}' must be a Boolean, found {:?}", format_expr(condition, Layout::PLAIN), fn_name, other)),
+        }
+    }
+    Ok(())
+}
+
+/// Executes the statements of a function body in its local environment.
+fn run_body_statements(fn_name: &str, body_statements: &[Statement], local_env: &mut Environment, runtime: &Runtime) -> Result<Value, String> {
+    let mut last_value = Value::Void;
+
+    // CHANGE: Loop through the pre-parsed statements directly
+    for (i, stmt) in body_statements.iter().enumerate() {
+        match run_statement_in_function(stmt, local_env, runtime) {
+            Ok(flow) => {
+                match flow {
+                    FunctionControlFlow::Return(val) => {
+                        // Explicit return
+                        //debug!("Explicit return triggered from block with value: {:?}", val);
+                        return Ok(val);
+                    }
+                    FunctionControlFlow::Continue(val) => {
+                        last_value = val;
+                    }
+                    FunctionControlFlow::Print(output) => {
+                        write_output(&output, &format!("Block Output (Stmt {})", i + 1), runtime)?;
+                    }
+                }
+            }
+            Err(e) => {
+                return Err(message!("Function '{}' Execution Error (Stmt {}): {}", fn_name, i + 1, e));
+            }
+        }
+    }
+    
+    // Implicit return of the last expression value or Void (always Void under --no-implicit-return)
+    if runtime.options.implicit_return {
+        Ok(last_value)
+    } else {
+        Ok(Value::Void)
+    }
+}
+
+/// Logs one measurement and keeps it for the end-of-run timing report.
+fn record_timing(label: &str, elapsed: Duration, runtime: &Runtime) {
+    info!("timed {}: {:?}", label, elapsed);
+    runtime.timings.borrow_mut().push((label.to_string(), elapsed));
+}
+
+/// Label for a timed block without one: the first line of its first statement.
+fn timed_block_label(body: &[Statement]) -> String {
+    let Some(first) = body.first() else {
+        return "empty block".to_string();
+    };
+    let mut text = String::new();
+    format_statement(first, 0, BlockStyle::Brackets, "", &mut text);
+    let line = text.lines().next().unwrap_or_default().trim_end_matches(['[', ' ']);
+    if line.chars().count() > 40 {
+        format!("{}...", line.chars().take(40).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+/// Summarizes the recorded timings per label (call count and total), in first-seen order.
+fn timing_report(runtime: &Runtime) -> Vec<String> {
+    let mut totals: Vec<(String, usize, Duration)> = Vec::new();
+    for (label, elapsed) in runtime.timings.borrow().iter() {
+        match totals.iter_mut().find(|(l, ..)| l == label) {
+            Some((_, count, total)) => {
+                *count += 1;
+                *total += *elapsed;
+            }
+            None => totals.push((label.clone(), 1, *elapsed)),
+        }
+    }
+    totals
+        .into_iter()
+        .map(|(label, count, total)| format!("{}: {} run(s), total {:?}", label, count, total))
+        .collect()
+}
+
+/// Applies '@decorator' lines to a freshly defined function, innermost (closest to 'fn') first.
+/// Builtin decorators wrap every call; any other function is called once with the function value
+/// and, like `f = d(f)`, a different function it returns replaces the definition.
+fn apply_decorators(name: &str, decorators: &[String], env: &mut Environment, runtime: &mut Runtime) -> Result<(), String> {
+    for decorator in decorators.iter().rev() {
+        if BUILTIN_DECORATORS.contains(&decorator.as_str()) {
+            if let Some(def) = runtime.func_defs.get_mut(name) {
+                def.wrappers.push(decorator.clone());
+            }
+            continue;
+        }
+        if get_native_function(decorator).is_none() && runtime.function(decorator).is_none() {
+            return Err(message!("Unknown decorator '@{}' on function '{}'", decorator, name));
+        }
+        let result = call_function(decorator, vec![Value::Function(name.to_string())], env, runtime)
+            .map_err(|e| message!("Decorator '@{}' on function '{}' failed: {}", decorator, name, e))?;
+        match result {
+            Value::Function(target) if target != name => {
+                if let Some(def) = runtime.func_defs.get_mut(name) {
+                    def.wrappers.clear();
+                    def.rebound = Some(target);
+                }
+            }
+            // Returning the function itself (or nothing) keeps the definition, e.g. registration decorators
+            Value::Function(_) | Value::Void => {}
+            other => return Err(message!("Decorator '@{}' must return a function, found {:?}", decorator, other)),
+        }
+    }
+    Ok(())
+}
+
+// The rest of the `run_statement_in_function`, `run_statement`, and `main` functions
+// remain largely the same, except for incorporating the function call logic into the interpreter.
+
+/// Error for running a region that Parser::parse_lenient could not parse.
+fn unparsed_code(span: Span) -> String {
+    message!("Cannot run code that failed to parse (source bytes {})", span)
+}
+
+fn run_statement_in_function(stmt: &Statement, env: &mut Environment, runtime: &Runtime) -> Result<FunctionControlFlow, String> {
+    debug!("Running statement in function: {:?}", stmt);
+    runtime.check_cancelled()?;
+    runtime.step()?;
+    match stmt {
+        Statement::Expr(expr) => {
+            let result = eval(expr, env, runtime)?;
+            Ok(FunctionControlFlow::Continue(result))
+        }
+        Statement::Print(opt_format_string, expressions) => {
+            let results: Vec<Value> = expressions
+                .iter()
+                .map(|e| eval(e, env, runtime))
+                .collect::<Result<Vec<Value>, String>>()?;
+
+            let output = format_print_output(opt_format_string.as_deref(), &results, env, runtime)?;
+            
+            Ok(FunctionControlFlow::Print(output))
+        }
+        // CHANGE: Uses Vec<Statement> for bodies
+        Statement::If(condition_expr, if_statements, else_opt_statements) => {
+            let condition_val = eval(condition_expr, env, runtime)?;
+
+            let execute_if = match condition_val {
+                Value::Boolean(b) => b,
+                _ => return Err(message!("'if' condition must evaluate to a Boolean, found {:?}", condition_val)),
+            };
+
+            let body_to_execute = if execute_if {
+                Some(if_statements)
+            } else if let Some(else_statements) = else_opt_statements {
+                Some(else_statements)
+            } else {
+                return Ok(FunctionControlFlow::Continue(Value::Void)); 
+            };
+            
+            let mut last_value = Value::Void;
+            
+            // Loop through the statements in the block
+            if let Some(statements) = body_to_execute {
+                for stmt in statements.iter() {
+                    match run_statement_in_function(stmt, env, runtime) {
+                        Ok(flow) => {
+                            match flow {
+                                FunctionControlFlow::Return(val) => {
+                                    // Propagate return flow up the call stack
+                                    return Ok(FunctionControlFlow::Return(val)); 
+                                }
+                                FunctionControlFlow::Continue(val) => {
+                                    last_value = val;
+                                }
+                                FunctionControlFlow::Print(output) => {
+                                    write_output(&output, "Block Output", runtime)?;
+                                }
+                            }
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+            
+            Ok(FunctionControlFlow::Continue(last_value))
+        }
+        Statement::For(var_name, iterable_expr, body_statements) => {
+            let iterable = eval(iterable_expr, env, runtime)?;
+            let mut last_value = Value::Void;
+
+            let iterable = match run_fused_range_loop(var_name, &iterable, body_statements, env, runtime) {
+                Some(FusedOutcome::Finished(val)) => return Ok(FunctionControlFlow::Continue(val)),
+                Some(FusedOutcome::Resume(rest, val)) => {
+                    last_value = val;
+                    rest
+                }
+                None => iterable,
+            };
+
+            runtime.check_assignable(var_name, env)?;
+            for item in iterate(&iterable)? {
+                env.insert(var_name.clone(), item);
+                for stmt in body_statements.iter() {
+                    match run_statement_in_function(stmt, env, runtime)? {
+                        // Propagate return flow up the call stack
+                        FunctionControlFlow::Return(val) => return Ok(FunctionControlFlow::Return(val)),
+                        FunctionControlFlow::Continue(val) => last_value = val,
+                        FunctionControlFlow::Print(output) => write_output(&output, "Block Output", runtime)?,
+                    }
+                }
+            }
+
+            Ok(FunctionControlFlow::Continue(last_value))
+        }
+        Statement::Error(span) => Err(unparsed_code(*span)),
+        Statement::Timed(label, body_statements) => {
+            let start = Instant::now();
+            let mut flow = FunctionControlFlow::Continue(Value::Void);
+            for stmt in body_statements.iter() {
+                match run_statement_in_function(stmt, env, runtime)? {
+                    // An early return still ends the measured block
+                    FunctionControlFlow::Return(val) => {
+                        flow = FunctionControlFlow::Return(val);
+                        break;
+                    }
+                    FunctionControlFlow::Continue(val) => flow = FunctionControlFlow::Continue(val),
+                    FunctionControlFlow::Print(output) => write_output(&output, "Block Output", runtime)?,
+                }
+            }
+            let label = label.clone().unwrap_or_else(|| timed_block_label(body_statements));
+            record_timing(&label, start.elapsed(), runtime);
+            Ok(flow)
+        }
+        Statement::Def(name, ..) => {
+            Err(message!("Function definition '{}' is only allowed at the top level", name))
+        }
+        Statement::Macro(name, ..) => Err(message!("Macro definition '{}' is only allowed at the top level", name)),
+        Statement::Expand(expansion) => {
+            let mut last_value = Value::Void;
+            for stmt in expansion.code.iter() {
+                match run_statement_in_function(stmt, env, runtime)? {
+                    FunctionControlFlow::Return(val) => return Ok(FunctionControlFlow::Return(val)),
+                    FunctionControlFlow::Continue(val) => last_value = val,
+                    FunctionControlFlow::Print(output) => write_output(&output, "Block Output", runtime)?,
+                }
+            }
+            Ok(FunctionControlFlow::Continue(last_value))
+        }
+        Statement::Return(opt_expr) => {
+            let return_val = if let Some(expr) = opt_expr {
+                eval(expr, env, runtime)?
+            } else {
+                Value::Void
+            };
+            Ok(FunctionControlFlow::Return(return_val))
+        }
+    }
+}
+
+/// How a value appears in print output. Strings print without quotes, and Void prints as "void"
+/// so that printing the result of a function with no return value is visible rather than blank.
+/// Floats use the active locale's decimal separator.
+pub fn print_repr(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Boolean(b) => (if *b { "true" } else { "false" }).to_string(),
+        Value::Void => String::from("void"),
+        v => localize_numbers(v, decimal_separator()),
+    }
+}
+
+/// Display form of a value with floats (including array elements) written with `separator`.
+fn localize_numbers(value: &Value, separator: char) -> String {
+    match value {
+        _ if separator == '.' => format!("{}", value),
+        Value::Float(n) => n.to_string().replace('.', &separator.to_string()),
+        Value::Array(items) => {
+            let items: Vec<String> = items
+                .iter()
+                .map(|item| match item {
+                    Value::String(s) => s.clone(),
+                    _ => localize_numbers(item, separator),
+                })
+                .collect();
+            format!("[{}]", items.join(", "))
+        }
+        v => format!("{}", v),
+    }
+}
+
+/// A part of a print format string.
+enum FormatPiece {
+    Text(String),
+    // '{}': the next positional argument
+    Positional,
+    // '{expr}': the placeholder's source text and the 0-based char offset of its '{'
+    Expr(Expr, String, usize),
+}
+
+/// Splits a format string into text and placeholders. '{}' takes the next argument, '{expr}'
+/// evaluates an expression in the current scope, and '{{' / '}}' are literal braces.
+/// Errors give the 1-based character position inside the format string.
+fn parse_format_string(template: &str) -> Result<Vec<FormatPiece>, String> {
+    let chars: Vec<char> = template.chars().collect();
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match (chars[i], chars.get(i + 1)) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                text.push(chars[i]);
+                i += 2;
+                continue;
+            }
+            ('}', _) => {
+                return Err(message!("Format string error at character {}: unmatched '}}' (write '}}}}' for a literal brace)", i + 1));
+            }
+            ('{', _) => {}
+            (c, _) => {
+                text.push(c);
+                i += 1;
+                continue;
+            }
+        }
+        if !text.is_empty() {
+            pieces.push(FormatPiece::Text(std::mem::take(&mut text)));
+        }
+        let close = placeholder_end(&chars, i)
+            .ok_or_else(|| message!("Format string error at character {}: unclosed '{{' (write '{{{{' for a literal brace)", i + 1))?;
+        let source: String = chars[i + 1..close].iter().collect();
+        if source.trim().is_empty() {
+            pieces.push(FormatPiece::Positional);
+        } else {
+            let mut parser = Parser::new(&source);
+            let expr = parser.expr_bp(0).and_then(|expr| match parser.current {
+                Token::Eof => Ok(expr),
+                ref other => Err(message!("Unexpected {:?} after the expression", other)),
+            });
+            match expr {
+                Ok(expr) => pieces.push(FormatPiece::Expr(expr, source, i)),
+                Err(e) => {
+                    // Point at the token the placeholder's parser stopped on
+                    let position = i + 2 + parser.current_start.min(source.chars().count());
+                    return Err(message!("Format string error at character {} in placeholder '{{{}}}': {}", position, source, e));
+                }
+            }
+        }
+        i = close + 1;
+    }
+    if !text.is_empty() {
+        pieces.push(FormatPiece::Text(text));
+    }
+    Ok(pieces)
+}
+
+/// Index of the '}' closing the placeholder that opens at `open`, skipping nested brackets and
+/// string literals inside the expression.
+fn placeholder_end(chars: &[char], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    let mut i = open + 1;
+    while i < chars.len() {
+        let c = chars[i];
+        match quote {
+            Some(_) if c == '\\' => i += 1,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' => depth -= 1,
+                '}' if depth == 0 => return Some(i),
+                '}' => depth -= 1,
+                _ => {}
+            },
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Renders print(...) arguments, substituting them into the format string's {} placeholders if present.
+fn format_print_output(opt_format_string: Option<&str>, results: &[Value], env: &mut Environment, runtime: &Runtime) -> Result<String, String> {
+    let Some(format_string) = opt_format_string else {
+        if results.len() != 1 {
+            return Err(message!("Simple print (without format string) expects exactly one argument"));
+        }
+        return Ok(print_repr(&results[0]));
+    };
+    // Nothing to substitute or unescape
+    if !format_string.contains(['{', '}']) {
+        if !results.is_empty() {
+            return Err(message!("Not enough placeholders ({{}}) in format string: \"{}\"", format_string));
+        }
+        return Ok(format_string.to_string());
+    }
+
+    let mut output = String::with_capacity(format_string.len());
+    let mut arguments = results.iter();
+    for piece in parse_format_string(format_string)? {
+        match piece {
+            FormatPiece::Text(text) => output.push_str(&text),
+            FormatPiece::Positional => match arguments.next() {
+                Some(value) => output.push_str(&print_repr(value)),
+                // A placeholder without an argument is printed as written
+                None => output.push_str("{}"),
+            },
+            FormatPiece::Expr(expr, source, offset) => {
+                let value = eval(&expr, env, runtime).map_err(|e| {
+                    message!("In placeholder '{{{}}}' at character {} of the format string: {}", source, offset + 1, e)
+                })?;
+                output.push_str(&print_repr(&value));
+            }
+        }
+    }
+    if arguments.next().is_some() {
+        return Err(message!("Not enough placeholders ({{}}) in format string: \"{}\"", format_string));
+    }
+    Ok(output)
+}
+
+// --- Output Sink ---
+
+/// The "runlog" file in the current directory. Every line the interpreter and the CLI log goes
+/// through this one handle, so size-based rotation sees all of them.
+struct Runlog {
+    file: Option<File>,
+    // Size of the current file, including what was there before this process opened it
+    written: u64,
+    // Rotate once the file would grow past this many bytes
+    max_bytes: Option<u64>,
+}
+
+static RUNLOG: Mutex<Runlog> = Mutex::new(Runlog { file: None, written: 0, max_bytes: None });
+
+/// Rotated runlogs kept besides the current one: runlog.1 (newest) to runlog.3.
+const RUNLOG_GENERATIONS: usize = 3;
+
+impl Runlog {
+    fn append(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if let Some(max_bytes) = self.max_bytes {
+            if self.file.is_none() {
+                self.written = fs::metadata("runlog").map_or(0, |m| m.len());
+            }
+            if self.written > 0 && self.written + bytes.len() as u64 > max_bytes {
+                self.rotate()?;
+            }
+        }
+        if self.file.is_none() {
+            let file = OpenOptions::new().create(true).append(true).open("runlog")?;
+            self.written = file.metadata()?.len();
+            self.file = Some(file);
+        }
+        self.file.as_mut().unwrap().write_all(bytes)?;
+        self.written += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        for generation in (1..RUNLOG_GENERATIONS).rev() {
+            let older = format!("runlog.{}", generation);
+            if fs::metadata(&older).is_ok() {
+                fs::rename(&older, format!("runlog.{}", generation + 1))?;
+            }
+        }
+        fs::rename("runlog", "runlog.1")?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+/// Rotates the runlog whenever it would grow past `max_bytes` (--max-log-bytes).
+pub fn set_runlog_limit(max_bytes: u64) {
+    RUNLOG.lock().unwrap().max_bytes = Some(max_bytes);
+}
+
+/// Appends one line to the runlog.
+pub fn append_runlog(line: &str) -> io::Result<()> {
+    RUNLOG.lock().unwrap().append(format!("{}\n", line).as_bytes())
+}
+
+/// `io::Write` handle on the runlog for loggers; each write should be a complete record.
+pub struct RunlogWriter;
+
+impl Write for RunlogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        RUNLOG.lock().unwrap().append(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes one line of script output to stdout, or holds it for the host while output is captured,
+/// and mirrors it into the runlog as "<label>: line". Past --max-output-bytes the run fails, or
+/// with --truncate-output the rest of the output is dropped after a marker line.
+fn write_output(output: &str, log_label: &str, runtime: &Runtime) -> Result<(), String> {
+    if let Some(limit) = runtime.options.max_output_bytes {
+        // Shorter lines that would still fit are dropped too once the marker is out
+        if runtime.output_truncated.get() {
+            return Ok(());
+        }
+        let total = runtime.output_bytes.get() + output.len() as u64 + 1;
+        if total > limit {
+            if !runtime.options.truncate_output {
+                return Err(message!("Output limit of {} bytes exceeded (--max-output-bytes)", limit));
+            }
+            runtime.output_truncated.set(true);
+            let marker = message!("[output truncated: limit of {} bytes reached]", limit);
+            return write_line(&marker, log_label, runtime);
+        }
+        runtime.output_bytes.set(total);
+    }
+    write_line(output, log_label, runtime)
+}
+
+fn write_line(output: &str, log_label: &str, runtime: &Runtime) -> Result<(), String> {
+    if let Some(captured) = runtime.captured.borrow_mut().as_mut() {
+        captured.push(output.to_string());
+    } else {
+        writeln!(io::stdout(), "{}", output).map_err(|e| message!("Failed to write to stdout: {}", e))?;
+        io::stdout().flush().map_err(|e| message!("Failed to flush stdout: {}", e))?;
+    }
+    append_runlog(&format!("{}: {}", log_label, redact(output))).map_err(|e| message!("Failed to write to runlog: {}", e))
+}
+
+/// Outcome of a top-level statement.
+pub enum ScriptFlow {
+    // Text for the runlog, plus the statement's value if it was an expression statement
+    Continue(String, Option<Value>),
+    // A top-level 'return' ends the script with this value
+    Return(Value),
+}
+
+fn run_statement(stmt: &Statement, env: &mut Environment, runtime: &mut Runtime) -> Result<ScriptFlow, String> {
+    debug!("Running statement: {:?}", stmt);
+    runtime.check_cancelled()?;
+    runtime.step()?;
+    match stmt {
+        // The runlog text is rendered by Interpreter::run_statement: statements in blocks are
+        // never logged, and rendering a large Integer on every loop iteration dominated the run time
+        Statement::Expr(expr) => Ok(ScriptFlow::Continue(String::new(), Some(eval(expr, env, runtime)?))),
+        Statement::Print(opt_format_string, expressions) => {
+            let results: Vec<Value> = expressions
+                .iter()
+                .map(|e| eval(e, env, runtime))
+                .collect::<Result<Vec<Value>, String>>()?;
+            
+            let output = format_print_output(opt_format_string.as_deref(), &results, env, runtime)?;
+            
+            write_output(&output, "Output", runtime)?;
+            Ok(ScriptFlow::Continue(output, None))
+        }
+        // CHANGE: Store Vec<Statement> directly in FuncDefs
+        Statement::Def(name, params, body_statements, decorators, contract) => {
+            let def = FunctionDef {
+                params: params.clone(),
+                body: body_statements.clone(),
+                contract: contract.clone(),
+                wrappers: Vec::new(),
+                rebound: None,
+            };
+            runtime.func_defs.insert(name.clone(), def);
+            apply_decorators(name, decorators, env, runtime)?;
+            Ok(ScriptFlow::Continue(String::new(), None))
+        }
+        Statement::Return(opt_expr) => {
+            let return_val = if let Some(expr) = opt_expr {
+                eval(expr, env, runtime)?
+            } else {
+                Value::Void
+            };
+            Ok(ScriptFlow::Return(return_val))
+        }
+        // CHANGE: Execute pre-parsed Vec<Statement>
+        Statement::If(condition_expr, if_statements, else_opt_statements) => {
+            let condition_val = eval(condition_expr, env, runtime)?;
+
+            let execute_if = match condition_val {
+                Value::Boolean(b) => b,
+                _ => return Err(message!("'if' condition must evaluate to a Boolean, found {:?}", condition_val)),
+            };
+
+            let body_to_execute = if execute_if {
+                Some(if_statements)
+            } else if let Some(else_statements) = else_opt_statements {
+                Some(else_statements)
+            } else {
+                return Ok(ScriptFlow::Continue(String::new(), None)); 
+            };
+            
+            // Loop through the statements in the block
+            if let Some(statements) = body_to_execute {
+                for stmt in statements.iter() {
+                    match run_statement(stmt, env, runtime)? {
+                        ScriptFlow::Continue(..) => continue,
+                        // Propagate a top-level return out of the block
+                        ScriptFlow::Return(val) => return Ok(ScriptFlow::Return(val)),
+                    }
+                }
+            }
+            
+            Ok(ScriptFlow::Continue(String::new(), None))
+        }
+        Statement::For(var_name, iterable_expr, body_statements) => {
+            let iterable = eval(iterable_expr, env, runtime)?;
+            let iterable = match run_fused_range_loop(var_name, &iterable, body_statements, env, runtime) {
+                Some(FusedOutcome::Finished(_)) => return Ok(ScriptFlow::Continue(String::new(), None)),
+                Some(FusedOutcome::Resume(rest, _)) => rest,
+                None => iterable,
+            };
+            for item in iterate(&iterable)? {
+                env.insert(var_name.clone(), item);
+                for stmt in body_statements.iter() {
+                    if let ScriptFlow::Return(val) = run_statement(stmt, env, runtime)? {
+                        return Ok(ScriptFlow::Return(val));
+                    }
+                }
+            }
+            Ok(ScriptFlow::Continue(String::new(), None))
+        }
+        Statement::Error(span) => Err(unparsed_code(*span)),
+        Statement::Timed(label, body_statements) => {
+            let start = Instant::now();
+            let mut flow = ScriptFlow::Continue(String::new(), None);
+            for stmt in body_statements.iter() {
+                if let ScriptFlow::Return(val) = run_statement(stmt, env, runtime)? {
+                    flow = ScriptFlow::Return(val);
+                    break;
+                }
+            }
+            let label = label.clone().unwrap_or_else(|| timed_block_label(body_statements));
+            record_timing(&label, start.elapsed(), runtime);
+            Ok(flow)
+        }
+        // Expanded where the macro is used; the definition itself does nothing at run time
+        Statement::Macro(..) => Ok(ScriptFlow::Continue(String::new(), None)),
+        Statement::Expand(expansion) => {
+            for stmt in expansion.code.iter() {
+                if let ScriptFlow::Return(val) = run_statement(stmt, env, runtime)? {
+                    return Ok(ScriptFlow::Return(val));
+                }
+            }
+            Ok(ScriptFlow::Continue(String::new(), None))
+        }
+    }
+}
+
+// --- Analysis ---
+
+/// True if running these statements can end on a value that a function would implicitly return.
+fn ends_with_implicit_value(statements: &[Statement]) -> bool {
+    match statements.last() {
+        Some(Statement::Expr(_)) => true,
+        Some(Statement::If(_, if_body, else_body)) => {
+            ends_with_implicit_value(if_body) || else_body.as_deref().is_some_and(ends_with_implicit_value)
+        }
+        Some(Statement::For(_, _, body)) | Some(Statement::Timed(_, body)) => ends_with_implicit_value(body),
+        Some(Statement::Expand(expansion)) => ends_with_implicit_value(&expansion.code),
+        _ => false,
+    }
+}
+
+/// Lists functions whose result changes under --no-implicit-return: those that can finish
+/// on a trailing expression instead of an explicit 'return'. Silenced by allow(implicit-return).
+pub fn implicit_return_warnings(statements: &[Statement], directives: &Directives) -> Vec<String> {
+    if directives.allows("implicit-return") {
+        return Vec::new();
+    }
+    statements
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Statement::Def(name, _, body, ..) if ends_with_implicit_value(body) => Some(format!(
+                "function '{}' can finish on an expression without 'return'; under --no-implicit-return it yields void there",
+                name
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+// --- Embedding ---
+
+/// A prelude that has been parsed and run once, ready to seed any number of interpreters.
+/// Its functions are shared through an `Arc`, so creating an interpreter from it copies
+/// only the prelude's global variables.
+pub struct PreparedRuntime {
+    functions: Arc<FuncDefs>,
+    globals: Environment,
+    options: Options,
+    // Values marked secret so far; each interpreter starts with its own copy
+    secrets: Vec<String>,
+}
+
+impl PreparedRuntime {
+    /// Parses and runs `prelude_source`, keeping the functions and variables it defines.
+    pub fn new(prelude_source: &str, options: Options) -> Result<PreparedRuntime, String> {
+        let mut interpreter = Interpreter::new(options);
+        let statements = Parser::new(prelude_source).parse().map_err(|e| message!("Prelude parsing error: {}", e))?;
+        for (i, stmt) in statements.iter().enumerate() {
+            if let ScriptFlow::Return(_) = interpreter.run_statement(stmt).map_err(|e| message!("Prelude error (Statement {}): {}", i + 1, e))? {
+                break;
+            }
+        }
+        Ok(interpreter.snapshot())
+    }
+}
+
+/// An expression parsed once and evaluated many times against different bindings, e.g. a
+/// spreadsheet formula or a rule condition. It keeps the functions its interpreter had when it
+/// was compiled, and can be shared between threads.
+#[derive(Clone)]
+pub struct CompiledExpr {
+    expr: Expr,
+    functions: Arc<FuncDefs>,
+    options: Options,
+    messages: Option<Arc<dyn Messages>>,
+    secrets: Secrets,
+}
+
+impl CompiledExpr {
+    /// Evaluates the expression with `bindings` as its variables. Assignments inside the
+    /// expression are local to this evaluation.
+    pub fn eval(&self, bindings: &HashMap<String, Value>) -> Result<Value, String> {
+        let mut runtime = Runtime::new(self.options.clone(), Arc::clone(&self.functions));
+        runtime.secrets = Arc::clone(&self.secrets);
+        let _scope = ActiveScope::enter(self.messages.as_ref(), &runtime.secrets);
+        let mut env = bindings.clone();
+        eval(&self.expr, &mut env, &runtime).map_err(|e| mask_secrets(&e, &runtime.secrets))
+    }
+}
+
+/// An interpreter session: global variables plus the functions defined so far.
+pub struct Interpreter {
+    env: Environment,
+    runtime: Runtime,
+    messages: Option<Arc<dyn Messages>>,
+}
+
+impl Interpreter {
+    pub fn new(options: Options) -> Interpreter {
+        Interpreter { env: Environment::new(), runtime: Runtime::new(options, Arc::new(FuncDefs::new())), messages: None }
+    }
+
+    /// Starts from a prepared prelude without parsing or running it again.
+    pub fn from_snapshot(snapshot: &PreparedRuntime) -> Interpreter {
+        let mut runtime = Runtime::new(snapshot.options.clone(), Arc::clone(&snapshot.functions));
+        runtime.secrets = Arc::new(Mutex::new(snapshot.secrets.clone()));
+        Interpreter { env: snapshot.globals.clone(), runtime, messages: None }
+    }
+
+    /// Freezes the functions, global variables and secrets defined so far, so that any number of
+    /// fresh interpreters can start from this point with [`Interpreter::from_snapshot`].
+    pub fn snapshot(&self) -> PreparedRuntime {
+        let mut functions = (*self.runtime.prelude).clone();
+        functions.extend(self.runtime.func_defs.iter().map(|(name, def)| (name.clone(), def.clone())));
+        PreparedRuntime {
+            functions: Arc::new(functions),
+            globals: self.env.clone(),
+            options: self.runtime.options.clone(),
+            secrets: self.runtime.secrets.lock().unwrap().clone(),
+        }
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.runtime.options
+    }
+
+    /// Translates error messages and localizes number output of every later call.
+    pub fn set_messages(&mut self, messages: Arc<dyn Messages>) {
+        self.messages = Some(messages);
+    }
+
+    /// Runs one top-level statement.
+    pub fn run_statement(&mut self, stmt: &Statement) -> Result<ScriptFlow, String> {
+        let _scope = ActiveScope::enter(self.messages.as_ref(), &self.runtime.secrets);
+        let result = run_statement(stmt, &mut self.env, &mut self.runtime).map(|flow| match flow {
+            ScriptFlow::Continue(_, Some(value)) if !matches!(value, Value::Void) => ScriptFlow::Continue(value.to_string(), Some(value)),
+            flow => flow,
+        });
+        self.finish(result)
+    }
+
+    /// Parses and runs a whole script, returning the value of a top-level 'return'
+    /// or else the value of the last expression statement.
+    pub fn run_source(&mut self, source: &str) -> Result<Value, String> {
+        let _scope = ActiveScope::enter(self.messages.as_ref(), &self.runtime.secrets);
+        let statements = self.finish(Parser::new(source).parse().map_err(|e| message!("Parsing Error: {}", e)))?;
+        let mut last_value = Value::Void;
+        for (i, stmt) in statements.iter().enumerate() {
+            // The values of earlier statements are discarded, so they are not rendered to text
+            let result = run_statement(stmt, &mut self.env, &mut self.runtime);
+            match self.finish(result).map_err(|e| message!("Runtime Error (Statement {}): {}", i + 1, e))? {
+                ScriptFlow::Continue(_, Some(value)) => last_value = value,
+                ScriptFlow::Continue(_, None) => {}
+                ScriptFlow::Return(value) => return Ok(value),
+            }
+        }
+        Ok(last_value)
+    }
+
+    /// Re-parses `source` and swaps in the functions it defines, keeping every variable, so a host
+    /// can live-edit script logic. Statements other than 'fn' definitions are ignored. If parsing or
+    /// a decorator fails, no definition is changed. Returns the names of the redefined functions.
+    pub fn redefine_from_source(&mut self, source: &str) -> Result<Vec<String>, String> {
+        let _scope = ActiveScope::enter(self.messages.as_ref(), &self.runtime.secrets);
+        let result = self.redefine(source);
+        self.finish(result)
+    }
+
+    fn redefine(&mut self, source: &str) -> Result<Vec<String>, String> {
+        let statements = Parser::new(source).parse().map_err(|e| message!("Parsing Error: {}", e))?;
+        let previous = self.runtime.func_defs.clone();
+        let mut redefined = Vec::new();
+        for stmt in &statements {
+            let Statement::Def(name, ..) = stmt else {
+                continue;
+            };
+            if let Err(e) = run_statement(stmt, &mut self.env, &mut self.runtime) {
+                self.runtime.func_defs = previous;
+                return Err(message!("Redefinition of '{}' failed: {}", name, e));
+            }
+            redefined.push(name.clone());
+        }
+        // Memoized results may come from the old bodies
+        self.runtime.memo_cache.borrow_mut().clear();
+        Ok(redefined)
+    }
+
+    /// Parses a single expression for repeated evaluation with [`CompiledExpr::eval`].
+    pub fn compile_expr(&self, source: &str) -> Result<CompiledExpr, String> {
+        let _scope = ActiveScope::enter(self.messages.as_ref(), &self.runtime.secrets);
+        let mut statements = Parser::new(source).parse().map_err(|e| message!("Parsing Error: {}", e))?;
+        let expr = match (statements.pop(), statements.is_empty()) {
+            (Some(Statement::Expr(expr)), true) => expr,
+            _ => return Err(message!("Expected a single expression, found: {}", source)),
+        };
+        let mut functions = (*self.runtime.prelude).clone();
+        functions.extend(self.runtime.func_defs.iter().map(|(name, def)| (name.clone(), def.clone())));
+        Ok(CompiledExpr {
+            expr,
+            functions: Arc::new(functions),
+            options: self.runtime.options.clone(),
+            messages: self.messages.clone(),
+            secrets: Arc::clone(&self.runtime.secrets),
+        })
+    }
+
+    /// Calls a builtin or defined function with already evaluated arguments.
+    pub fn call_function(&mut self, name: &str, args: Vec<Value>) -> Result<Value, String> {
+        let _scope = ActiveScope::enter(self.messages.as_ref(), &self.runtime.secrets);
+        let result = call_function(name, args, &mut self.env, &self.runtime);
+        self.finish(result)
+    }
+
+    /// Calls a function with request-scoped `bindings` (user id, row values, ...) that the function
+    /// and everything it calls can read like variables but not assign. Parameters and local
+    /// variables shadow bindings of the same name. The bindings are gone once the call returns.
+    pub fn call_with_env(&mut self, name: &str, args: Vec<Value>, bindings: HashMap<String, Value>) -> Result<Value, String> {
+        let previous = std::mem::replace(&mut self.runtime.bindings, bindings);
+        let result = self.call_function(name, args);
+        self.runtime.bindings = previous;
+        result
+    }
+
+    /// Masks `value` as "***" in the runlog, traces and errors from now on, as the `mark_secret`
+    /// builtin does for values created by the script. Text containing the value is masked too,
+    /// e.g. "Bearer " + token, and so are the results of builtins called with it. Fails for
+    /// values printed with fewer than 6 characters, which would mask unrelated output.
+    pub fn mark_secret(&self, value: &Value) -> Result<(), String> {
+        let _scope = ActiveScope::enter(self.messages.as_ref(), &self.runtime.secrets);
+        self.runtime.mark_secret(value, true)
+    }
+
+    /// Masks this interpreter's secrets in text the host writes itself, e.g. statement results.
+    pub fn redact(&self, text: &str) -> String {
+        mask_secrets(text, &self.runtime.secrets)
+    }
+
+    /// Per-label summary of the timed blocks and '@timed' functions run so far.
+    pub fn timing_report(&self) -> Vec<String> {
+        timing_report(&self.runtime)
+    }
+
+    /// A token that cancels this interpreter's running (and later) evaluations when triggered.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.runtime.cancel.clone()
+    }
+
+    /// Holds back print output from now on instead of writing it to stdout, e.g. to check what a
+    /// learner's code printed. The runlog still records it. Collect it with [`Interpreter::take_output`].
+    pub fn capture_output(&mut self) {
+        self.runtime.captured.borrow_mut().get_or_insert_with(Vec::new);
+    }
+
+    /// What was printed since capture started or the last call, one entry per print, oldest first.
+    pub fn take_output(&self) -> Vec<String> {
+        self.runtime.captured.borrow_mut().as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// The jobs registered with `every` so far, in order.
+    pub fn scheduled_jobs(&self) -> Vec<ScheduledJob> {
+        self.runtime.jobs.borrow().clone()
+    }
+
+    /// Counters accumulated since the interpreter was created.
+    pub fn metrics(&self) -> Metrics {
+        *self.runtime.metrics.borrow()
+    }
+
+    /// Counts and redacts an error on its way out to the host.
+    fn finish<T>(&self, result: Result<T, String>) -> Result<T, String> {
+        result.map_err(|e| {
+            self.runtime.count(Counter::Errors);
+            mask_secrets(&e, &self.runtime.secrets)
+        })
+    }
+}
@@ -0,0 +1,164 @@
+//! The send_mail builtin, compiled with the "mail" feature. There is no map type, so the SMTP
+//! settings are [key, value] pairs; sending needs --allow-net.
+
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+
+use crate::{text, Environment, Runtime, Value};
+
+const CONFIG_KEYS: &[&str] = &["host", "port", "from", "username", "password", "security"];
+
+/// How the connection is protected: "starttls" upgrades a plain connection (port 587 by
+/// default), "tls" connects over TLS (port 465) and "none" sends in the clear (port 25).
+enum Security {
+    StartTls,
+    Tls,
+    None,
+}
+
+struct SmtpConfig {
+    host: String,
+    port: Option<u16>,
+    from: String,
+    credentials: Option<Credentials>,
+    security: Security,
+}
+
+/// The kind of a value, for errors that must not print the value itself: the settings hold
+/// the password, and error text ends up on stderr and in the runlog.
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Integer(_) => "an Integer",
+        Value::Float(_) => "a Float",
+        Value::String(_) => "a String",
+        Value::Boolean(_) => "a Boolean",
+        Value::Array(_) => "an Array",
+        Value::Function(_) => "a Function",
+        Value::Sequence(_) => "a sequence",
+        Value::Ast(_) => "a syntax tree",
+        Value::Void => "void",
+    }
+}
+
+fn config_string(fn_name: &str, key: &str, value: Value) -> Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s),
+        other => Err(message!("'{}' setting \"{}\" must be a String, found {}", fn_name, key, kind(&other))),
+    }
+}
+
+fn smtp_config(fn_name: &str, pairs: &[Value]) -> Result<SmtpConfig, String> {
+    let (mut host, mut port, mut from, mut username, mut password, mut security) = (None, None, None, None, None, Security::StartTls);
+    for pair in pairs {
+        let (key, value) = match pair {
+            Value::Array(items) => match items.as_slice() {
+                [Value::String(key), value] => (key.as_str(), value.clone()),
+                [key, _] => return Err(message!("'{}' setting names must be Strings, found {}", fn_name, kind(key))),
+                _ => return Err(message!("'{}' settings must be [key, value] pairs, found an Array of {} items", fn_name, items.len())),
+            },
+            other => return Err(message!("'{}' settings must be [key, value] pairs, found {}", fn_name, kind(other))),
+        };
+        match key {
+            "host" => host = Some(config_string(fn_name, key, value)?),
+            "from" => from = Some(config_string(fn_name, key, value)?),
+            "username" => username = Some(config_string(fn_name, key, value)?),
+            "password" => password = Some(config_string(fn_name, key, value)?),
+            "port" => match value {
+                Value::Integer(n) => match u16::try_from(&n) {
+                    Ok(n) => port = Some(n),
+                    Err(_) => return Err(message!("'{}' setting \"port\" must be between 0 and 65535, found {}", fn_name, n)),
+                },
+                other => return Err(message!("'{}' setting \"port\" must be an Integer, found {:?}", fn_name, other)),
+            },
+            "security" => {
+                security = match config_string(fn_name, key, value)?.as_str() {
+                    "starttls" => Security::StartTls,
+                    "tls" => Security::Tls,
+                    "none" => Security::None,
+                    other => return Err(message!("'{}' setting \"security\" must be \"starttls\", \"tls\" or \"none\", found \"{}\"", fn_name, other)),
+                }
+            }
+            _ => {
+                return Err(match text::suggestion(key, CONFIG_KEYS.iter().copied()) {
+                    Some(name) => message!("'{}' has no setting \"{}\" (did you mean \"{}\"?)", fn_name, key, name),
+                    None => message!("'{}' has no setting \"{}\" (expected one of {})", fn_name, key, CONFIG_KEYS.join(", ")),
+                });
+            }
+        }
+    }
+    let credentials = match (username, password) {
+        (Some(username), Some(password)) => Some(Credentials::new(username, password)),
+        (None, None) => None,
+        _ => return Err(message!("'{}' needs both \"username\" and \"password\" to log in, or neither", fn_name)),
+    };
+    let Some(host) = host else {
+        return Err(message!("'{}' needs the \"host\" of the SMTP server in its settings", fn_name));
+    };
+    let Some(from) = from else {
+        return Err(message!("'{}' needs a \"from\" address in its settings", fn_name));
+    };
+    Ok(SmtpConfig { host, port, from, credentials, security })
+}
+
+fn mailbox(fn_name: &str, address: &str) -> Result<Mailbox, String> {
+    address.parse().map_err(|e| message!("'{}' cannot send to or from '{}': {}", fn_name, address, e))
+}
+
+/// Recipients are a single address or an Array of them. Addresses may include a display name,
+/// as in "Ops <ops@example.com>".
+pub(crate) fn native_send_mail(fn_name: &str, _env: &mut Environment, runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    let (config, to, subject, body) = match args.as_slice() {
+        [Value::Array(config), to, Value::String(subject), Value::String(body)] => (config, to, subject, body),
+        [config, _, subject, body] => {
+            let (position, expected, found) = match (config, subject) {
+                (Value::Array(_), Value::String(_)) => ("fourth", "a String body", body),
+                (Value::Array(_), _) => ("third", "a String subject", subject),
+                _ => ("first", "the settings as an Array of [key, value] pairs", config),
+            };
+            return Err(message!("'{}' expects {} as its {} argument, found {}", fn_name, expected, position, kind(found)));
+        }
+        _ => return Err(message!("'{}' expects 4 arguments (smtp_config, to, subject, body), found {}", fn_name, args.len())),
+    };
+    let recipients = match to {
+        Value::String(address) => vec![address.as_str()],
+        Value::Array(items) if !items.is_empty() => items
+            .iter()
+            .map(|item| match item {
+                Value::String(address) => Ok(address.as_str()),
+                other => Err(message!("'{}' recipients must be String addresses, found {:?}", fn_name, other)),
+            })
+            .collect::<Result<_, _>>()?,
+        other => return Err(message!("'{}' expects a recipient address or a non-empty Array of them, found {:?}", fn_name, other)),
+    };
+    let config = smtp_config(fn_name, config)?;
+    runtime.require_net(fn_name)?;
+
+    let mut message = Message::builder().from(mailbox(fn_name, &config.from)?).subject(subject.as_str());
+    for recipient in recipients {
+        message = message.to(mailbox(fn_name, recipient)?);
+    }
+    let message = message
+        .header(ContentType::TEXT_PLAIN)
+        .body(body.clone())
+        .map_err(|e| message!("'{}' could not build the message: {}", fn_name, e))?;
+
+    let relay_error = |e| message!("'{}' cannot use SMTP server '{}': {}", fn_name, config.host, e);
+    let mut transport = match config.security {
+        Security::StartTls => SmtpTransport::starttls_relay(&config.host).map_err(relay_error)?,
+        Security::Tls => SmtpTransport::relay(&config.host).map_err(relay_error)?,
+        Security::None => SmtpTransport::builder_dangerous(&config.host),
+    };
+    if let Some(port) = config.port {
+        transport = transport.port(port);
+    }
+    if let Some(credentials) = config.credentials {
+        transport = transport.credentials(credentials);
+    }
+    transport
+        .build()
+        .send(&message)
+        .map_err(|e| message!("'{}' could not send the message through {}: {}", fn_name, config.host, e))?;
+    Ok(Value::Void)
+}
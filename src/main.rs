@@ -1,1589 +1,915 @@
 use std::fmt;
-use std::env;
-use std::collections::HashMap;
 use std::fs::{self, OpenOptions};
-use std::io::{self, Write, BufWriter};
-use log::{debug, LevelFilter};
-use env_logger;
-
-// --- Big Integer Imports ---
-use num_bigint::BigInt;
-// Imported traits to enable methods like is_positive (Signed), to_u32, and to_f64 (ToPrimitive)
-use num_traits::{Zero, One, Signed, ToPrimitive}; 
-// ---------------------------
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::thread;
+use std::time::Instant;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{Args, CommandFactory, FromArgMatches, Subcommand};
+use clap_complete::Shell;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use log::{debug, info, warn, LevelFilter};
+use num_traits::ToPrimitive;
+
+use astra::lessons::LESSONS;
+use astra::schedule::{Interval, Schedule};
+use astra::{builtins, decode_source, format_program_with_source, implicit_return_warnings, print_repr, redact, append_runlog, set_runlog_limit, BlockStyle, Directives, Expr, IntDivision, Interpreter, Lexer, Options, Parser, PreparedRuntime, RunlogWriter, ScriptFlow, Statement, Token, Value};
+
+#[derive(clap::Parser)]
+#[command(
+    name = "astra",
+    version,
+    about = "Runs Astra scripts",
+    subcommand_negates_reqs = true,
+    override_usage = "astra [OPTIONS] <FILENAME>\n       astra [--log <LEVEL>] [--max-log-bytes <BYTES>] <COMMAND>"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    // 'astra <file>' is shorthand for 'astra run <file>'
+    #[command(flatten)]
+    run: RunArgs,
+    /// Runlog verbosity: off, error, warn, info, debug or trace (RUST_LOG overrides it)
+    #[arg(long, global = true, value_name = "LEVEL", default_value = "debug")]
+    log: LevelFilter,
+    /// Rotate the runlog to runlog.1 (keeping up to runlog.3) before it grows past this size
+    #[arg(long, global = true, value_name = "BYTES")]
+    max_log_bytes: Option<u64>,
+}
 
-// --- Value and AST Definitions ---
+#[derive(Args)]
+struct RunArgs {
+    /// Functions without 'return' yield void instead of their last statement's value
+    #[arg(long)]
+    no_implicit_return: bool,
+    /// Check 'requires' / 'ensures' clauses on every call
+    #[arg(long)]
+    contracts: bool,
+    /// Print the script's final value: a top-level 'return' value or the last expression statement
+    #[arg(long)]
+    print_last: bool,
+    /// Fail once the script has printed more than this many bytes (overrides a 'max-output-bytes' directive)
+    #[arg(long, value_name = "BYTES")]
+    max_output_bytes: Option<u64>,
+    /// With --max-output-bytes, drop the rest of the output after a marker line instead of failing
+    #[arg(long, requires = "max_output_bytes")]
+    truncate_output: bool,
+    /// Fail once the script has executed this many statements (overrides a 'max-steps' directive)
+    #[arg(long, value_name = "COUNT")]
+    max_steps: Option<u64>,
+    /// Run every loop on the general path, e.g. to compare it with the fused integer loop
+    #[arg(long)]
+    no_loop_fusion: bool,
+    /// Rounding of '/' and '%' on two Integers: trunc (toward zero), floor (down) or promote ('/' gives a Float); overrides astra.toml
+    #[arg(long, value_name = "MODE")]
+    int_div: Option<IntDivision>,
+    #[command(flatten)]
+    permissions: Permissions,
+    /// Reuse the results of up to this many pure builtin calls (e.g., sum over the same range)
+    #[arg(long, value_name = "ENTRIES")]
+    pure_cache: Option<usize>,
+    /// Refuse to run the script unless this signature file (from 'astra sign') matches it
+    #[arg(long, value_name = "SIGNATURE")]
+    verify: Option<PathBuf>,
+    /// Public key to check --verify signatures against (the '.pub' file written by 'astra sign')
+    #[arg(long, value_name = "KEY", env = "ASTRA_PUBLIC_KEY")]
+    public_key: Option<PathBuf>,
+    /// Script to run
+    #[arg(required = true)]
+    filename: Option<PathBuf>,
+}
 
-#[derive(Debug, Clone, PartialEq)] 
-enum Value {
-    // Changed i64 to BigInt to support arbitrary precision arithmetic
-    Integer(BigInt), 
-    Float(f64),
-    String(String),
-    Boolean(bool), 
-    Array(Vec<Value>), 
-    Void,
+/// What builtins may do outside the interpreter; scripts can do none of it by default.
+#[derive(Args)]
+struct Permissions {
+    /// Let builtins such as glob read the file system
+    #[arg(long)]
+    allow_read: bool,
+    /// Let builtins such as zip_extract create and write files
+    #[arg(long)]
+    allow_write: bool,
+    /// Let builtins such as send_mail connect to other hosts
+    #[arg(long)]
+    allow_net: bool,
 }
 
-impl Value {
-    /// Helper to check if a value is numeric (Integer or Float)
-    fn is_number(&self) -> bool {
-        matches!(self, Value::Integer(_) | Value::Float(_))
+impl Permissions {
+    fn apply_to(&self, options: &mut Options) {
+        options.allow_read = self.allow_read;
+        options.allow_write = self.allow_write;
+        options.allow_net = self.allow_net;
     }
 }
 
-impl fmt::Display for Value {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Value::Integer(n) => write!(f, "{}", n),
-            Value::Float(n) => write!(f, "{}", n),
-            // Note: Display of Value::String includes quotes
-            Value::String(s) => write!(f, "\"{}\"", s), 
-            // Corrected: Outputs 'true' or 'false' without quotes
-            Value::Boolean(b) => write!(f, "{}", if *b { "true" } else { "false" }), 
-            Value::Void => write!(f, "void"),
-            // MODIFIED: Display for Array
-            Value::Array(v) => {
-                write!(f, "[")?;
-                for (i, val) in v.iter().enumerate() {
-                    // Array elements are displayed without quotes for strings here, 
-                    // which is a stylistic choice for compact output.
-                    match val {
-                        Value::String(s) => write!(f, "{}", s)?,
-                        _ => write!(f, "{}", val)?,
-                    }
-                    
-                    if i < v.len() - 1 {
-                        write!(f, ", ")?;
-                    }
-                }
-                write!(f, "]")
-            }
-        }
+#[derive(Subcommand)]
+enum Command {
+    /// Run a script
+    Run(RunArgs),
+    /// Read statements from stdin and run them one at a time
+    Repl,
+    /// Work through the interactive lessons, typing code that is checked as you go
+    Learn {
+        /// Lesson to start at
+        #[arg(long, value_name = "NUMBER", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..=LESSONS.len() as u64))]
+        lesson: u64,
+    },
+    /// Parse a script and report syntax errors without running it
+    Check { filename: PathBuf },
+    /// Print the canonically formatted source of a script
+    Fmt {
+        filename: PathBuf,
+        /// Write '{ }' blocks instead of '[ ]'
+        #[arg(long)]
+        braces: bool,
+    },
+    /// Rewrite a script from older syntax ('def', string function bodies) in the current syntax, flagging ambiguous comparisons
+    Migrate {
+        filename: PathBuf,
+        /// Replace the file instead of printing the result
+        #[arg(long)]
+        write: bool,
+    },
+    /// Run every zero-argument 'test_*' function of the given scripts
+    Test {
+        #[arg(required = true)]
+        filenames: Vec<PathBuf>,
+    },
+    /// Run the jobs a script registers with 'every', each on its interval, until stopped
+    Schedule {
+        filename: PathBuf,
+        #[command(flatten)]
+        permissions: Permissions,
+        /// Run every job once, in order, then exit (nonzero if any failed)
+        #[arg(long)]
+        once: bool,
+    },
+    /// Print the parsed syntax tree of a script
+    Ast { filename: PathBuf },
+    /// Print the tokens of a script, one per line
+    Tokens { filename: PathBuf },
+    /// Sign a script with an ed25519 key for 'astra run --verify'
+    Sign {
+        filename: PathBuf,
+        /// Secret key file; created, with its public key in '<KEY>.pub', if it does not exist
+        #[arg(long, value_name = "KEY")]
+        key: PathBuf,
+        /// Signature file to write (default: '<filename>.sig')
+        #[arg(long, short, value_name = "SIGNATURE")]
+        output: Option<PathBuf>,
+    },
+    /// Print a completion script for the given shell
+    Completions { shell: Shell },
+    /// List the builtin functions
+    Builtins {
+        /// Print name, signature, category and description as a JSON array
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Maps a top-level 'return' value to the process exit code: Void and true exit 0, false exits 1,
+/// an Integer in 0..=255 exits with that code, other Integers exit 1, and any other value exits 0.
+fn exit_code_for(value: &Value) -> ExitCode {
+    match value {
+        Value::Boolean(false) => ExitCode::FAILURE,
+        Value::Integer(n) => n.to_u8().map_or(ExitCode::FAILURE, ExitCode::from),
+        _ => ExitCode::SUCCESS,
     }
 }
 
-#[derive(Debug, Clone)]
-enum Expr {
-    Var(String),
-    Num(String), // Stores raw number string to preserve type distinction (e.g., "1" vs "1.0")
-    Str(String),
-    Bool(bool), // Boolean literal (true or false)
-    Prefix(char, Box<Expr>),
-    Infix(Box<Expr>, char, Box<Expr>),
-    Cmp(Box<Expr>, String, Box<Expr>), 
-    Logic(Box<Expr>, String, Box<Expr>),
-    Array(Vec<Expr>), 
-    // Slice variant for both indexing (arr[i]) and slicing (arr[i:j])
-    Slice(Box<Expr>, Option<Box<Expr>>, Option<Box<Expr>>), // (array_expr, start_expr_opt, end_expr_opt)
-    Call(String, Vec<Expr>),
+/// A failure that ends the command. Commands return it instead of printing and exiting
+/// themselves, so `main` renders every fatal path the same way.
+enum Fatal {
+    // What was being attempted, and the I/O error that stopped it
+    Io(String, io::Error),
+    // The script file is not valid UTF-8
+    Encoding(String),
+    Parse(String),
+    // 1-based top-level statement number and the error message
+    Runtime(usize, String),
+    // A signature could not be created or does not match the script
+    Signature(String),
+    // astra.toml is not valid TOML or has an unknown or invalid setting
+    Config(String),
+    // 'astra schedule' was given a script that registers no jobs
+    Schedule(String),
+    // Already reported to the user (e.g., by the test summary); only the exit code remains
+    Reported,
 }
 
-impl fmt::Display for Expr {
+impl fmt::Display for Fatal {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Expr::Var(id) => write!(f, "{}", id),
-            Expr::Num(s) => write!(f, "{}", s), 
-            Expr::Str(s) => write!(f, "\"{}\"", s),
-            Expr::Bool(b) => write!(f, "{}", if *b { "true" } else { "false" }), 
-            Expr::Prefix(op, expr) => write!(f, "({} {})", op, expr),
-            Expr::Infix(lhs, op, rhs) => write!(f, "({} {} {})", lhs, op, rhs),
-            Expr::Cmp(lhs, op, rhs) => write!(f, "({} {} {})", lhs, op, rhs), 
-            Expr::Logic(lhs, op, rhs) => write!(f, "({} {} {})", lhs, op, rhs),
-            // MODIFIED: Array display
-            Expr::Array(elements) => {
-                write!(f, "[")?;
-                for (i, expr) in elements.iter().enumerate() {
-                    write!(f, "{}", expr)?;
-                    if i < elements.len() - 1 {
-                        write!(f, ", ")?;
-                    }
-                }
-                write!(f, "]")
-            }
-            // MODIFIED: Slice/Index display
-            Expr::Slice(array, start, end) => {
-                write!(f, "{}[", array)?;
-                if let Some(s) = start {
-                    write!(f, "{}", s)?;
-                }
-                if start.is_some() || end.is_some() {
-                    write!(f, ":")?;
-                }
-                if let Some(e) = end {
-                    write!(f, "{}", e)?;
-                }
-                write!(f, "]")
-            }
-            Expr::Call(name, args) => {
-                write!(f, "{}(", name)?;
-                for (i, arg) in args.iter().enumerate() {
-                    write!(f, "{}", arg)?;
-                    if i < args.len() - 1 {
-                        write!(f, ", ")?;
-                    }
-                }
-                write!(f, ")")
-            }
+            Fatal::Io(context, e) => write!(f, "Error: {}: {}", context, e),
+            Fatal::Encoding(e) => write!(f, "Encoding Error: {}", e),
+            Fatal::Parse(e) => write!(f, "Parsing Error: {}", e),
+            Fatal::Runtime(statement, e) => write!(f, "Runtime Error (Statement {}): {}", statement, e),
+            Fatal::Signature(e) => write!(f, "Signature Error: {}", e),
+            Fatal::Config(e) => write!(f, "Configuration Error: {}", e),
+            Fatal::Schedule(e) => write!(f, "Schedule Error: {}", e),
+            Fatal::Reported => Ok(()),
         }
     }
 }
 
-#[derive(Debug, Clone)] // Added Clone to Statement for use in the interpreter
-enum Statement {
-    Expr(Expr),
-    Print(Option<String>, Vec<Expr>),
-    // Function body now Vec<Statement>
-    Def(String, Vec<String>, Vec<Statement>),
-    Return(Option<Expr>),
-    // If and Else bodies now Vec<Statement>
-    If(Expr, Vec<Statement>, Option<Vec<Statement>>),
+/// Builds a `map_err` adapter that records what was being attempted.
+fn io_error(context: impl Into<String>) -> impl FnOnce(io::Error) -> Fatal {
+    let context = context.into();
+    move |e| Fatal::Io(context, e)
 }
 
-// --- Lexer and Token Definitions ---
-
-#[derive(Debug, Clone, PartialEq, Eq)]
-enum Token {
-    Ident(String),
-    Keyword(String),
-    Number(String), 
-    StringLiteral(String),
-    Op(char),
-    Cmp(String), 
-    Eof,
+/// Writes one line of command output to stdout.
+fn emit(text: impl fmt::Display) -> Result<(), Fatal> {
+    writeln!(io::stdout(), "{}", text).map_err(io_error("Failed to write to stdout"))
 }
 
-struct Lexer {
-    input: Vec<char>,
-    pos: usize,
-}
-
-impl Lexer {
-    fn new(input: &str) -> Lexer {
-        let input_chars: Vec<char> = input.chars().collect();
-        Lexer { input: input_chars, pos: 0 }
+fn main() -> ExitCode {
+    let matches = Cli::command().get_matches();
+    // Global flags may come before a subcommand, but the flags of 'astra <file>' may not
+    if let Some((name, _)) = matches.subcommand()
+        && let Some(flag) = RunArgs::augment_args(clap::Command::new("run"))
+            .get_arguments()
+            .find(|arg| matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine))
+            .map(|arg| arg.get_long().map_or_else(|| format!("<{}>", arg.get_id().as_str().to_uppercase()), |long| format!("--{}", long)))
+    {
+        Cli::command()
+            .error(ErrorKind::ArgumentConflict, format!("'{}' cannot be used before the '{}' subcommand", flag, name))
+            .exit();
     }
-
-    fn peek_char(&self) -> Option<char> {
-        self.input.get(self.pos).cloned()
-    }
-
-    fn next_char(&mut self) -> Option<char> {
-        let ch = self.input.get(self.pos).cloned();
-        if ch.is_some() {
-            self.pos += 1;
-        }
-        ch
-    }
-
-    fn next_token(&mut self) -> Token {
-        self.skip_whitespace();
-        let Some(ch) = self.next_char() else {
-            return Token::Eof;
-        };
-        
-        if ch.is_ascii_digit() {
-            let mut num = ch.to_string();
-            
-            while let Some(next_ch) = self.peek_char() {
-                if next_ch.is_ascii_digit() {
-                    num.push(self.next_char().unwrap());
-                } else {
-                    break;
-                }
-            }
-            if self.peek_char() == Some('.') {
-                num.push(self.next_char().unwrap());
-                while let Some(next_ch) = self.peek_char() {
-                    if next_ch.is_ascii_digit() {
-                        num.push(self.next_char().unwrap());
-                    } else {
-                        break;
-                    }
-                }
-            }
-            // The token holds the original string representation ("1" or "1.0")
-            Token::Number(num)
-        } 
-        else if ch == '"' || ch == '\'' {
-            let delimiter = ch;
-            let mut s = String::new();
-            while let Some(next_ch) = self.next_char() {
-                if next_ch == delimiter {
-                    return Token::StringLiteral(s);
-                }
-                // Handle escape sequences
-                if next_ch == '\\' {
-                    if let Some(escaped_ch) = self.next_char() {
-                        match escaped_ch {
-                            'n' => s.push('\n'),
-                            't' => s.push('\t'),
-                            '\\' => s.push('\\'),
-                            '"' => s.push('"'),
-                            '\'' => s.push('\''),
-                            c => s.push(c),
-                        }
-                    } else {
-                        break; 
-                    }
-                } else {
-                    s.push(next_ch);
-                }
-            }
-            Token::StringLiteral(s)
-        } 
-        else if ch.is_alphabetic() || ch == '_' {
-            let mut ident = ch.to_string();
-            while let Some(next_ch) = self.peek_char() {
-                if next_ch.is_alphanumeric() || next_ch == '_' {
-                    ident.push(self.next_char().unwrap());
-                } else {
-                    break;
-                }
-            }
-            // MODIFIED: Added 'and', 'or', 'true', and 'false' as keywords
-            if ident == "print" || ident == "def" || ident == "fn" || ident == "return" || ident == "if" || ident == "else" || ident == "and" || ident == "or" || ident == "true" || ident == "false" {
-                Token::Keyword(ident)
-            } else {
-                Token::Ident(ident)
-            }
-        } 
-        // Compound Assignment and Single Arithmetic Operators (+, -, *, /, %, ^)
-        else if "+-*/%^".contains(ch) {
-            if self.peek_char() == Some('=') {
-                self.next_char(); // consume '='
-                // Use Cmp for compound assignment tokens to carry the string value
-                return Token::Cmp(format!("{}{}", ch, '=')); 
-            }
-            Token::Op(ch) // Single arithmetic operator
-        }
-        // Comparison and Simple Assignment (=)
-        else if ch == '=' {
-            if self.peek_char() == Some('=') {
-                self.next_char(); 
-                if self.peek_char() == Some('=') {
-                    self.next_char();
-                    return Token::Cmp("===".to_string());
-                }
-                return Token::Cmp("==".to_string());
-            }
-            Token::Op(ch) // Simple assignment '='
-        } else if ch == '!' {
-            if self.peek_char() == Some('=') {
-                self.next_char();
-                if self.peek_char() == Some('=') {
-                    self.next_char();
-                    return Token::Cmp("!==".to_string());
-                }
-                return Token::Cmp("!=".to_string());
-            }
-            Token::Op(ch) // Logical NOT operator '!'
-        } else if ch == '<' {
-            if self.peek_char() == Some('=') {
-                self.next_char();
-                return Token::Cmp("<=".to_string());
-            }
-            Token::Cmp("<".to_string())
-        } else if ch == '>' {
-            if self.peek_char() == Some('=') {
-                self.next_char();
-                return Token::Cmp(">=".to_string());
-            }
-            Token::Cmp(">".to_string())
-        }
-        else {
-            Token::Op(ch)
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    match dispatch(cli) {
+        Ok(code) => code,
+        // The reader went away (e.g., output piped into 'head'); there is nobody left to tell
+        Err(Fatal::Io(_, e)) if e.kind() == io::ErrorKind::BrokenPipe => ExitCode::FAILURE,
+        Err(Fatal::Reported) => ExitCode::FAILURE,
+        Err(fatal) => {
+            eprintln!("{}", fatal);
+            ExitCode::FAILURE
         }
     }
+}
 
-    fn skip_whitespace(&mut self) {
-        loop {
-            if self.peek_char().map_or(false, |c| c.is_whitespace()) {
-                self.pos += 1;
-                continue;
-            }
-            
-            // Handle comments (';' until newline)
-            if self.peek_char() == Some(';') {
-                self.pos += 1; 
-                
-                while self.peek_char().map_or(false, |c| c != '\n') {
-                    self.pos += 1;
-                }
-                continue; 
+fn dispatch(cli: Cli) -> Result<ExitCode, Fatal> {
+    match cli.command {
+        Some(Command::Completions { shell }) => {
+            let mut script = Vec::new();
+            clap_complete::generate(shell, &mut Cli::command(), "astra", &mut script);
+            io::stdout().write_all(&script).map_err(io_error("Failed to write to stdout"))?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Builtins { json }) => list_builtins(json),
+        Some(Command::Tokens { filename }) => print_tokens(&filename),
+        Some(Command::Fmt { filename, braces }) => format_script(&filename, braces),
+        Some(Command::Migrate { filename, write }) => migrate_script(&filename, write),
+        Some(Command::Sign { filename, key, output }) => sign_script(&filename, &key, output),
+        command => {
+            init_logging(cli.log, cli.max_log_bytes);
+            let result = match command {
+                Some(Command::Run(args)) => run_script(args),
+                Some(Command::Repl) => repl(),
+                Some(Command::Learn { lesson }) => learn(lesson as usize),
+                Some(Command::Check { filename }) => check_script(&filename),
+                Some(Command::Test { filenames }) => test_scripts(&filenames),
+                Some(Command::Schedule { filename, permissions, once }) => schedule_script(&filename, &permissions, once),
+                Some(Command::Ast { filename }) => print_ast(&filename),
+                _ => run_script(cli.run),
+            };
+            // Written directly rather than through the logger, which --log off silences
+            if let Err(fatal) = &result
+                && !matches!(fatal, Fatal::Reported)
+            {
+                let _ = RunLog.line(fatal);
             }
-
-            break;
+            result
         }
     }
 }
 
-// --- Parser ---
-
-struct Parser {
-    lexer: Lexer,
-    current: Token,
-}
-
-impl Parser {
-    fn new(input: &str) -> Parser {
-        let mut lexer = Lexer::new(input);
-        let current = lexer.next_token();
-        Parser { lexer, current }
-    }
-
-    fn advance(&mut self) {
-        self.current = self.lexer.next_token();
-        //debug!("Advanced to token {:?}", self.current);
-    }
-
-    fn parse(&mut self) -> Result<Vec<Statement>, String> {
-        let mut statements = Vec::new();
-        while self.current != Token::Eof {
-            //debug!("Parsing statement, current token: {:?}", self.current);
-            let stmt = match self.current.clone() {
-                Token::Keyword(k) if k == "print" => self.parse_print_statement(),
-                Token::Keyword(k) if k == "fn" => self.parse_fn_statement(),
-                Token::Keyword(k) if k == "return" => self.parse_return_statement(),
-                Token::Keyword(k) if k == "if" => self.parse_if_statement(),
-                // Defensive check: The assignment operator cannot start a statement.
-                Token::Op(op) if op == '=' => {
-                    return Err("The assignment operator '=' cannot start a statement. Assignment must follow a variable (e.g., x = 10).".to_string());
-                }
-                Token::Keyword(k) if k == "def" => return Err(format!("The 'def' keyword is deprecated. Please use 'fn' for function definitions (e.g., fn name(...) [...])")),
-                Token::Keyword(k) if k == "else" => return Err(format!("The 'else' keyword must immediately follow a closing ']' of an 'if' block.")),
-                _ => {
-                    let expr = self.expr_bp(0)?;
-                    Ok(Statement::Expr(expr))
-                }
-            }?;
-            statements.push(stmt);
-        }
-        Ok(statements)
+/// Sends log records to the runlog file in the current directory, with script secrets masked.
+fn init_logging(level: LevelFilter, max_log_bytes: Option<u64>) {
+    if let Some(max_bytes) = max_log_bytes {
+        set_runlog_limit(max_bytes);
     }
+    env_logger::Builder::new()
+        .filter_level(level)
+        // RUST_LOG (e.g., RUST_LOG=off for benchmarks) overrides --log
+        .parse_default_env()
+        .format(|buf, record| {
+            writeln!(buf, "[{} {} {}] {}", buf.timestamp(), record.level(), record.target(), redact(&record.args().to_string()))
+        })
+        .target(env_logger::Target::Pipe(Box::new(RunlogWriter)))
+        .init();
+}
 
-    // CHANGE: parse_block_body now returns Vec<Statement> and directly parses tokens
-    fn parse_block_body(&mut self) -> Result<Vec<Statement>, String> {
-        // The calling function (parse_fn, parse_if) must ensure self.current is the token *after* '['
-        let mut statements = Vec::new();
-
-        // Loop until ']' or EOF
-        while self.current != Token::Op(']') && self.current != Token::Eof {
-            let stmt = match self.current.clone() {
-                // Include all recognized statement types (except 'fn', which should only be top-level)
-                Token::Keyword(k) if k == "print" => self.parse_print_statement(),
-                Token::Keyword(k) if k == "return" => self.parse_return_statement(),
-                Token::Keyword(k) if k == "if" => self.parse_if_statement(),
-                // Ensure proper error handling for deprecated/misplaced keywords
-                Token::Keyword(k) if k == "def" => return Err(format!("The 'def' keyword is deprecated.")),
-                Token::Keyword(k) if k == "else" => return Err(format!("The 'else' keyword must immediately follow a closing ']' of an 'if' block.")),
-                Token::Op(op) if op == '=' => {
-                    return Err("The assignment operator '=' cannot start a statement.".to_string());
-                }
-                // Default: parse as an expression statement
-                _ => {
-                    let expr = self.expr_bp(0)?;
-                    Ok(Statement::Expr(expr))
-                }
-            }?;
-            statements.push(stmt);
-        }
-        
-        if self.current != Token::Op(']') {
-            return Err(format!("Unclosed block body. Expected matching ']', found {:?}", self.current));
-        }
+/// Script progress, results and errors, appended to the runlog for later inspection.
+struct RunLog;
 
-        self.advance(); // consume the closing ']'
-        
-        Ok(statements)
+impl RunLog {
+    fn line(&mut self, text: impl fmt::Display) -> Result<(), Fatal> {
+        append_runlog(&text.to_string()).map_err(io_error("Failed to write to runlog"))
     }
+}
 
-    fn parse_if_statement(&mut self) -> Result<Statement, String> {
-        //debug!("Parsing if statement");
-        self.advance(); // consume 'if'
-
-        if self.current != Token::Op('(') {
-            return Err(format!("Expected '(' after 'if', found {:?}", self.current));
-        }
-        self.advance(); // consume '('
-
-        let condition = self.expr_bp(0)?;
-
-        if self.current != Token::Op(')') {
-            return Err(format!("Expected ')' after if condition, found {:?}", self.current));
-        }
-        self.advance(); // consume ')'
-
-        if self.current != Token::Op('[') {
-            return Err(format!("Expected '[' to start if body, found {:?}", self.current));
-        }
-        
-        self.advance(); // CRITICAL: Consume the opening '['
-        // CHANGE: if_body is now Vec<Statement>
-        let if_body_statements = self.parse_block_body()?;
+fn read_script(path: &Path) -> Result<String, Fatal> {
+    let bytes = fs::read(path).map_err(io_error(format!("Failed to read file {}", path.display())))?;
+    decode_source(bytes).map_err(|e| Fatal::Encoding(format!("{}: {}", path.display(), e)))
+}
 
-        let mut else_body_statements: Option<Vec<Statement>> = None;
+/// Parses a script along with its '; astra:' directives.
+fn parse_script(path: &Path) -> Result<(Vec<Statement>, Directives), Fatal> {
+    parse_source(&read_script(path)?)
+}
 
-        if let Token::Keyword(k) = self.current.clone() {
-            if k == "else" {
-                //debug!("Found 'else' keyword");
-                self.advance(); // consume 'else'
-                
-                if self.current != Token::Op('[') {
-                    return Err(format!("Expected '[' to start else body, found {:?}", self.current));
-                }
-                
-                self.advance(); // CRITICAL: Consume the opening '['
-                // CHANGE: else_body is now Vec<Statement>
-                else_body_statements = Some(self.parse_block_body()?);
-            }
-        }
-        
-        debug!("Parsed if statement with condition {:?}, if body {:?}, and else body {:?}", condition, if_body_statements, else_body_statements);
-        // CHANGE: Store the Vec<Statement>
-        Ok(Statement::If(condition, if_body_statements, else_body_statements))
+fn parse_source(source: &str) -> Result<(Vec<Statement>, Directives), Fatal> {
+    let mut parser = Parser::new(source);
+    let statements = parser.parse().map_err(Fatal::Parse)?;
+    let directives = parser.directives().map_err(Fatal::Parse)?;
+    for warning in &directives.warnings {
+        eprintln!("Warning: {}", warning);
     }
+    Ok((statements, directives))
+}
 
-    fn parse_return_statement(&mut self) -> Result<Statement, String> {
-        debug!("Parsing return statement");
-        self.advance(); // consume 'return' keyword
+// --- Project configuration ---
 
-        // FIX E0408: Split the match arms to prevent the compiler error about unbound variables.
-        let has_expr = match self.current.clone() {
-            // All expression starters that don't need a custom guard
-            Token::Number(_) | Token::StringLiteral(_) | Token::Op('(') | Token::Op('[') | Token::Ident(_) | Token::Op('+') | Token::Op('-') | Token::Op('!') => true, // <--- MODIFIED: Added Token::Op('!')
-            
-            // The Keyword case, which requires checking the inner string
-            Token::Keyword(k) if k == "true" || k == "false" => true,
-            
-            _ => false,
-        };
+const PROJECT_CONFIG: &str = "astra.toml";
 
-        let return_expr = if has_expr {
-            let expr = self.expr_bp(0)?;
-            Some(expr)
-        } else {
-            None
-        };
+/// Settings shared by every script of a project, from the nearest astra.toml. The matching
+/// command-line flags take precedence.
+#[derive(Default)]
+struct ProjectConfig {
+    // 'int-div = "floor"'
+    int_division: Option<IntDivision>,
+}
 
-        debug!("Parsed return statement: Return({:?})", return_expr);
-        Ok(Statement::Return(return_expr))
+impl ProjectConfig {
+    /// Reads the astra.toml of the script's directory or its closest ancestor that has one.
+    fn for_script(path: &Path) -> Result<ProjectConfig, Fatal> {
+        let script = std::path::absolute(path).map_err(io_error(format!("Failed to resolve {}", path.display())))?;
+        Self::find(script.parent().unwrap_or(&script))
     }
 
-    fn parse_print_statement(&mut self) -> Result<Statement, String> {
-        //debug!("Parsing print statement");
-        self.advance(); // Consume 'print'
-        if self.current != Token::Op('(') {
-            return Err(format!("Expected '(' after 'print', found {:?}", self.current));
-        }
-        self.advance(); // Consume '('
-
-        let mut format_string: Option<String> = None;
-        let mut expressions = Vec::new();
-
-        if let Token::StringLiteral(s) = self.current.clone() {
-            format_string = Some(s);
-            self.advance();
-
-            while self.current == Token::Op(',') {
-                self.advance();
-                //debug!("Parsing print argument (formatted), current token: {:?}", self.current);
-                let expr = self.expr_bp(0)?;
-                expressions.push(expr);
-            }
-
-        } else if self.current != Token::Op(')') {
-            //debug!("Parsing print argument (simple), current token: {:?}", self.current);
-            let expr = self.expr_bp(0)?;
-            expressions.push(expr);
-
-            if self.current == Token::Op(',') {
-                return Err(format!("When using 'print(expr)' format (without a format string), only a single expression is allowed. Found ',' after argument: {:?}", expressions[0]));
-            }
-        }
-        
-        if self.current != Token::Op(')') {
-            return Err(format!("Expected closing ')' after print arguments, found {:?}", self.current));
+    fn find(dir: &Path) -> Result<ProjectConfig, Fatal> {
+        match dir.ancestors().map(|dir| dir.join(PROJECT_CONFIG)).find(|path| path.is_file()) {
+            Some(path) => Self::load(&path),
+            None => Ok(ProjectConfig::default()),
         }
-        self.advance(); // Consume ')'
-        debug!("Parsed print statement: Print({:?}, {:?})", format_string, expressions);
-        Ok(Statement::Print(format_string, expressions))
     }
 
-    fn parse_fn_statement(&mut self) -> Result<Statement, String> {
-        //debug!("Parsing fn statement");
-        self.advance();
-        let fn_name = match self.current.clone() {
-            Token::Ident(id) => {
-                self.advance();
-                id
+    fn load(path: &Path) -> Result<ProjectConfig, Fatal> {
+        let text = fs::read_to_string(path).map_err(io_error(format!("Failed to read {}", path.display())))?;
+        let invalid = |e: String| Fatal::Config(format!("{}: {}", path.display(), e));
+        let table: toml::Table = text.parse().map_err(|e: toml::de::Error| invalid(e.message().to_string()))?;
+        let mut config = ProjectConfig::default();
+        for (key, value) in &table {
+            match (key.as_str(), value) {
+                ("int-div", toml::Value::String(mode)) => config.int_division = Some(mode.parse().map_err(invalid)?),
+                ("int-div", _) => return Err(invalid("'int-div' must be a string".to_string())),
+                _ => return Err(invalid(format!("unknown setting '{}'", key))),
             }
-            _ => return Err(format!("Expected function name (identifier) after 'fn', found {:?}", self.current)),
-        };
-        if self.current != Token::Op('(') {
-            return Err(format!(
-                "Expected '(' to start parameter list in function definition, found {:?}. Syntax must be: fn {}() [...]", 
-                self.current, fn_name
-            ));
-        }
-        self.advance();
-        let mut params = Vec::new();
-        while self.current != Token::Op(')') {
-            let param_name = match self.current.clone() {
-                Token::Ident(id) => {
-                    self.advance();
-                    params.push(id.clone());
-                    id
-                }
-                Token::Eof => return Err("Unclosed parameter list in function definition. Expected ')'".to_string()),
-                _ => return Err(format!("Expected parameter name or ')' in function definition, found {:?}", self.current)),
-            };
-            if self.current == Token::Op(',') {
-                self.advance();
-            } else if self.current != Token::Op(')') {
-                return Err(format!("Expected ',' or ')' after parameter {}, found {:?}", param_name, self.current));
-            }
-        }
-        self.advance();
-        if self.current != Token::Op('[') {
-            return Err(format!("Expected '[' to start function body (e.g., fn {}() [body]), found {:?}", fn_name, self.current));
         }
-        
-        self.advance(); // CRITICAL: Consume the opening '['
-        // CHANGE: raw_body is now a Vec<Statement>
-        let body_statements = self.parse_block_body()?;
-        
-        debug!("Parsed fn {}({:?}) [{:?}]", fn_name, params, body_statements);
-        // CHANGE: Store the Vec<Statement>
-        Ok(Statement::Def(fn_name, params, body_statements))
+        Ok(config)
     }
 
-    fn parse_arguments(&mut self) -> Result<Vec<Expr>, String> {
-        let mut args = Vec::new();
-        if self.current == Token::Op(')') {
-            self.advance();
-            return Ok(args);
-        }
-        loop {
-            debug!("Parsing argument, current token: {:?}", self.current);
-            let arg_expr = self.expr_bp(0)?;
-            args.push(arg_expr);
-            if self.current == Token::Op(')') {
-                self.advance();
-                break;
-            } else if self.current == Token::Op(',') {
-                self.advance();
-            } else {
-                return Err(format!("Expected ',' or ')' in function call arguments, found {:?}", self.current));
-            }
-        }
-        Ok(args)
+    fn apply_to(&self, options: &mut Options) {
+        options.int_division = self.int_division.unwrap_or_default();
     }
+}
 
-    fn expr_bp(&mut self, min_bp: u8) -> Result<Expr, String> {
-        //debug!("Parsing expression with min_bp {}, current token: {:?}", min_bp, self.current);
-        let mut lhs = match self.current.clone() {
-            // Store the raw number string
-            Token::Number(num_str) => {
-                self.advance();
-                Expr::Num(num_str) 
-            }
-            Token::Ident(id) => {
-                self.advance();
-                if self.current == Token::Op('(') {
-                    self.advance();
-                    let args = self.parse_arguments()?;
-                    Expr::Call(id, args)
-                } else {
-                    Expr::Var(id)
-                }
-            }
-            Token::StringLiteral(s) => {
-                self.advance();
-                Expr::Str(s)
-            }
-            Token::Keyword(k) if k == "true" => { // Boolean literal true
-                self.advance();
-                Expr::Bool(true)
-            }
-            Token::Keyword(k) if k == "false" => { // Boolean literal false
-                self.advance();
-                Expr::Bool(false)
-            }
-            Token::Op('(') => {
-                self.advance();
-                let expr = self.expr_bp(0)?;
-                if self.current != Token::Op(')') {
-                    return Err(format!("Expected ')', found {:?}", self.current));
-                }
-                self.advance();
-                expr
-            }
-            // Array Literal parsing integrated as a prefix expression
-            Token::Op('[') => {
-                self.advance(); // consume '['
-                let mut elements = Vec::new();
-
-                if self.current == Token::Op(']') {
-                    self.advance(); // consume ']' for empty array
-                    return Ok(Expr::Array(elements));
-                }
-
-                loop {
-                    let expr = self.expr_bp(0)?;
-                    elements.push(expr);
+// --- Script signing ---
 
-                    if self.current == Token::Op(']') {
-                        self.advance(); // consume ']'
-                        break;
-                    } else if self.current == Token::Op(',') {
-                        self.advance(); // consume ','
-                    } else {
-                        return Err(format!("Expected ',' or ']' in array literal, found {:?}", self.current));
-                    }
-                }
-                Expr::Array(elements)
-            }
-            // END MODIFIED
-            
-            // MODIFIED: Added '!' for Logical NOT
-            Token::Op(op) if op == '+' || op == '-' || op == '!' => {
-                self.advance();
-                let (_, r_bp) = prefix_binding_power(op);
-                let rhs = self.expr_bp(r_bp)?;
-                Expr::Prefix(op, Box::new(rhs))
-            }
-            t => return Err(format!("Bad token in prefix: {:?} (Expected expression start or operator)", t)),
-        };
-        
-        loop {
-            let op_token = self.current.clone();
-            
-            // MODIFIED: Check for Array Indexing and Slicing (highest precedence, 15/16)
-            if op_token == Token::Op('[') {
-                if 15 < min_bp {
-                    break;
-                }
-                self.advance(); // consume '['
-                
-                // Parse the start expression (optional: [expr:...)
-                let mut start_expr: Option<Expr> = None;
-                if self.current != Token::Op(':') && self.current != Token::Op(']') {
-                    start_expr = Some(self.expr_bp(0)?);
-                }
+// Prefixed to the signed bytes so a script signature can't be replayed as any other ed25519 message
+const SIGNATURE_CONTEXT: &[u8] = b"astra script v1\n";
 
-                if self.current == Token::Op(':') {
-                    // Slicing: arr[start:end] or arr[:end] or arr[start:]
-                    self.advance(); // consume ':'
-                    
-                    // Parse the end expression (optional: ...:expr])
-                    let mut end_expr: Option<Expr> = None;
-                    if self.current != Token::Op(']') {
-                        end_expr = Some(self.expr_bp(0)?);
-                    }
-                    
-                    if self.current != Token::Op(']') {
-                        return Err(format!("Expected ']' after slice expression, found {:?}", self.current));
-                    }
-                    self.advance(); // consume ']'
-                    
-                    // Overwrite lhs with the Slice expression (arr[start:end])
-                    lhs = Expr::Slice(Box::new(lhs), start_expr.map(Box::new), end_expr.map(Box::new));
-                    continue;
+/// The text that is signed: the script exactly as read (without its byte order mark), except
+/// that CRLF line endings count as LF so a checkout converting them keeps the signature valid.
+/// Any other change, whitespace included, can change what a script does (e.g. inside a string).
+fn canonical_source(source: &str) -> String {
+    source.replace("\r\n", "\n")
+}
 
-                } else if self.current == Token::Op(']') {
-                    // Indexing: arr[index] (where index is the sole expression)
-                    self.advance(); // consume ']'
-                    
-                    let index_expr = start_expr
-                        .ok_or_else(|| "Array index expression missing for simple indexing".to_string())?;
+fn signed_message(source: &str) -> Vec<u8> {
+    [SIGNATURE_CONTEXT, canonical_source(source).as_bytes()].concat()
+}
 
-                    // Simple indexing is represented as a slice with only the start expression set
-                    lhs = Expr::Slice(Box::new(lhs), Some(Box::new(index_expr)), None); 
-                    continue;
+/// Reads a file holding exactly N hex-encoded bytes (keys and signatures).
+fn read_hex<const N: usize>(path: &Path, what: &str) -> Result<[u8; N], Fatal> {
+    let text = fs::read_to_string(path).map_err(io_error(format!("Failed to read {} {}", what, path.display())))?;
+    let bytes = hex::decode(text.trim()).map_err(|e| Fatal::Signature(format!("{} {} is not valid hex: {}", what, path.display(), e)))?;
+    bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| Fatal::Signature(format!("{} {} has {} bytes, expected {}", what, path.display(), bytes.len(), N)))
+}
 
-                } else {
-                    return Err(format!("Expected ':' or ']' inside array access, found {:?}", self.current));
-                }
-            }
-            // END MODIFIED
-            
-            // Check for logical keywords as operators
-            let is_logic_op = match op_token {
-                Token::Keyword(ref k) if k == "and" || k == "or" => true,
-                _ => false,
-            };
+fn write_new_file(path: &Path, contents: &str, secret: bool) -> Result<(), Fatal> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    if secret {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = secret;
+    let context = || format!("Failed to create {}", path.display());
+    let mut file = options.open(path).map_err(io_error(context()))?;
+    writeln!(file, "{}", contents).map_err(io_error(context()))
+}
 
-            let op_str = if is_logic_op {
-                match op_token {
-                    Token::Keyword(k) => k,
-                    _ => unreachable!(),
-                }
-            } else {
-                match op_token {
-                    Token::Op(op) => op.to_string(),
-                    Token::Cmp(op) => op,
-                    Token::Eof => break,
-                    _ => break,
-                }
-            };
+/// Loads the signing key, or creates a new key pair if the key file does not exist yet.
+fn load_or_create_key(path: &Path) -> Result<SigningKey, Fatal> {
+    if path.exists() {
+        return Ok(SigningKey::from_bytes(&read_hex(path, "secret key")?));
+    }
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).map_err(|e| Fatal::Signature(format!("Failed to generate a key: {}", e)))?;
+    let key = SigningKey::from_bytes(&seed);
+    let public_path = PathBuf::from(format!("{}.pub", path.display()));
+    write_new_file(path, &hex::encode(seed), true)?;
+    write_new_file(&public_path, &hex::encode(key.verifying_key().as_bytes()), false)?;
+    eprintln!("Created key {} (public key {})", path.display(), public_path.display());
+    Ok(key)
+}
 
-            // 1. Check for Compound Assignment (e.g., +=, -=) - MUST be desugared here
-            if op_str.len() == 2 && op_str.ends_with('=') && "+-*/%^".contains(op_str.chars().next().unwrap()) {
-                let actual_op = op_str.chars().next().unwrap(); // e.g., '+' or '-'
-                
-                // Compound assignment (A += B) has the same precedence (2) as simple assignment (A = B)
-                if 2 < min_bp {
-                    break;
-                }
-                
-                self.advance(); // consume the compound operator token (e.g., +=)
-                
-                // The right hand side of the assignment
-                let rhs = self.expr_bp(1)?; // Right binding power of assignment is 1
+fn sign_script(path: &Path, key_path: &Path, output: Option<PathBuf>) -> Result<ExitCode, Fatal> {
+    let source = read_script(path)?;
+    let key = load_or_create_key(key_path)?;
+    let signature = key.sign(&signed_message(&source));
+    let output = output.unwrap_or_else(|| PathBuf::from(format!("{}.sig", path.display())));
+    fs::write(&output, format!("{}\n", hex::encode(signature.to_bytes())))
+        .map_err(io_error(format!("Failed to write {}", output.display())))?;
+    emit(format_args!("{}: signed, signature in {}", path.display(), output.display()))?;
+    Ok(ExitCode::SUCCESS)
+}
 
-                // Left-hand side must be a variable OR a slice/index expression
-                let assign_target = match &lhs {
-                    Expr::Var(id) => Expr::Var(id.clone()), // Clone the Var(id) for both LHS and RHS of new Infix
-                    Expr::Slice(arr, start, end) => Expr::Slice(arr.clone(), start.clone(), end.clone()),
-                    _ => return Err(format!("Left-hand side of compound assignment '{}' must be a variable or array index", op_str)),
-                };
-                
-                // Desugar: x += 5  -->  x = (x + 5)
-                // 1a. Create the arithmetic expression: (x + 5)
-                let arithmetic_expr = Expr::Infix(Box::new(assign_target.clone()), actual_op, Box::new(rhs));
-                
-                // 1b. Overwrite LHS with the full assignment: x = (x + 5)
-                // Use '=' as the operator for the final AST node
-                lhs = Expr::Infix(Box::new(assign_target), '=', Box::new(arithmetic_expr));
-                continue;
-            }
+fn verify_script(source: &str, signature_path: &Path, public_key_path: Option<&Path>) -> Result<(), Fatal> {
+    let Some(public_key_path) = public_key_path else {
+        return Err(Fatal::Signature("--verify needs a public key: pass --public-key or set ASTRA_PUBLIC_KEY".to_string()));
+    };
+    let public_key = VerifyingKey::from_bytes(&read_hex(public_key_path, "public key")?)
+        .map_err(|e| Fatal::Signature(format!("Invalid public key {}: {}", public_key_path.display(), e)))?;
+    let signature = Signature::from_bytes(&read_hex(signature_path, "signature")?);
+    public_key.verify_strict(&signed_message(source), &signature).map_err(|_| {
+        Fatal::Signature(format!("{} does not match the script or was made with another key", signature_path.display()))
+    })
+}
 
-            // 2. Check for simple assignment, comparison, standard infix operators OR LOGIC OPS
-            if let Some((l_bp, r_bp, is_cmp)) = binding_power(op_str.as_str()) {
-                if l_bp < min_bp {
-                    break;
-                }
-                self.advance();
-                //debug!("Parsing infix/cmp/logic op {}, right expr with bp {}", op_str, r_bp);
-                let rhs = self.expr_bp(r_bp)?;
-                
-                lhs = if is_cmp {
-                    // Cmp covers ==, !=, <, >, <=, >=, ===, !==
-                    Expr::Cmp(Box::new(lhs), op_str, Box::new(rhs))
-                } else if is_logic_op {
-                    // NEW: Logic covers "and" and "or"
-                    Expr::Logic(Box::new(lhs), op_str, Box::new(rhs))
-                }
-                 else {
-                    // Infix covers simple assignment (=) and standard arithmetic (+, -, *, /, %, ^)
-                    let single_char_op = op_str.chars().next().unwrap(); 
-                    Expr::Infix(Box::new(lhs), single_char_op, Box::new(rhs))
-                };
-                continue;
-            }
-            break;
+fn list_builtins(json: bool) -> Result<ExitCode, Fatal> {
+    if json {
+        let entries: Vec<serde_json::Value> = builtins()
+            .iter()
+            .map(|b| serde_json::json!({
+                "name": b.name,
+                "signature": b.signature,
+                "category": b.category,
+                "description": b.description,
+                "pure": b.pure,
+            }))
+            .collect();
+        emit(serde_json::Value::Array(entries))?;
+    } else {
+        for b in builtins().iter() {
+            emit(format_args!("{:<16} {}", b.name, b.description))?;
         }
-        //debug!("Parsed expression: {:?}", lhs);
-        Ok(lhs)
     }
+    Ok(ExitCode::SUCCESS)
 }
 
-// MODIFIED: Added binding power for '!'
-fn prefix_binding_power(op: char) -> ((), u8) {
-    match op {
-        '+' | '-' => ((), 10),
-        '!' => ((), 16), // High precedence for NOT
-        _ => ((), 0),
+fn check_script(path: &Path) -> Result<ExitCode, Fatal> {
+    let (statements, directives) = parse_script(path)?;
+    for warning in implicit_return_warnings(&statements, &directives) {
+        eprintln!("Note: {}", warning);
     }
+    emit(format_args!("{}: OK", path.display()))?;
+    Ok(ExitCode::SUCCESS)
 }
 
-// MODIFIED binding_power to introduce 'or' and 'and', and raise precedence of Cmp
-fn binding_power(op: &str) -> Option<(u8, u8, bool)> { // (l_bp, r_bp, is_comparison)
-    match op {
-        "=" => Some((2, 1, false)), // Simple Assignment
-        "or" => Some((3, 4, false)), // Logical OR (Lowest precedence)
-        "and" => Some((5, 6, false)), // Logical AND
-        // Comparison (Raised to 7/8 to be higher than AND/OR)
-        "==" | "!=" | "<" | ">" | "<=" | ">=" | "===" | "!==" => Some((7, 8, true)), 
-        "+" | "-" => Some((9, 10, false)), // Addition/Subtraction
-        "*" | "/" | "%" => Some((11, 12, false)), // Multiplication/Division/Modulo
-        "^" => Some((13, 14, false)), // Exponentiation (Highest precedence)
-        _ => None,
+/// Formats what parses and copies broken statements through unchanged, so a script with syntax
+/// errors can still be formatted; the errors are reported and the exit code is nonzero. The
+/// formatter drops comments, so those are reported the same way ('; astra:' directives are kept
+/// at the top).
+fn format_script(path: &Path, braces: bool) -> Result<ExitCode, Fatal> {
+    let source = read_script(path)?;
+    let mut parser = Parser::new(&source);
+    let statements = parser.parse_lenient();
+    let style = if braces { BlockStyle::Braces } else { BlockStyle::Brackets };
+    let (mut formatted, dropped) = keep_directives(&source, &parser);
+    formatted.push_str(&format_program_with_source(&statements, style, &source));
+    io::stdout().write_all(formatted.as_bytes()).map_err(io_error("Failed to write to stdout"))?;
+    let mut report: Vec<(usize, String)> = parser.errors().iter().map(|(span, e)| (span.start, Fatal::Parse(e.clone()).to_string())).collect();
+    report.extend(dropped.iter().map(|(offset, text)| (*offset, format!("comment not carried over: {}", text))));
+    report.sort_by_key(|&(offset, _)| offset);
+    for (offset, text) in &report {
+        eprintln!("{}:{}: {}", path.display(), source[..*offset].matches('\n').count() + 1, text);
     }
+    if report.is_empty() { Ok(ExitCode::SUCCESS) } else { Err(Fatal::Reported) }
 }
 
-// --- Interpreter ---
+/// The '; astra:' directives among a parsed script's comments, one per line, and the offset and
+/// text of every other comment, which the formatter drops.
+fn keep_directives<'a>(source: &'a str, parser: &Parser) -> (String, Vec<(usize, &'a str)>) {
+    let mut directives = String::new();
+    let mut dropped = Vec::new();
+    for comment in parser.comments() {
+        let text = source[comment..].lines().next().unwrap_or_default().trim_end();
+        if text[1..].trim_start().starts_with("astra:") {
+            directives.push_str(text);
+            directives.push('\n');
+        } else {
+            dropped.push((comment, text));
+        }
+    }
+    (directives, dropped)
+}
 
-type Environment = HashMap<String, Value>;
-// CHANGE: Function definition now stores Vec<Statement>
-type FuncDefs = HashMap<String, (Vec<String>, Vec<Statement>)>;
+/// Translates a legacy script (see Parser::parse_legacy) by formatting what it parses to. Every
+/// change and everything left as it was is reported on stderr with its line: statements that
+/// don't parse are copied unchanged and make the exit code nonzero. The formatter drops comments
+/// except '; astra:' directives, which move to the top, so --write refuses to replace a script
+/// that has any others. A script the current parser accepts is left alone: it has nothing to migrate.
+fn migrate_script(path: &Path, write: bool) -> Result<ExitCode, Fatal> {
+    let source = read_script(path)?;
+    if Parser::new(&source).parse().is_ok() {
+        eprintln!("{}: already valid in the current syntax; nothing to migrate", path.display());
+        if !write {
+            io::stdout().write_all(source.as_bytes()).map_err(io_error("Failed to write to stdout"))?;
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    let mut parser = Parser::new(&source);
+    let statements = parser.parse_legacy();
+    let mut report: Vec<(usize, String)> = parser.migrations().iter().map(|(span, note)| (span.start, note.clone())).collect();
+    report.extend(parser.errors().iter().map(|(span, e)| (span.start, format!("not translated, kept as it was: {}", e))));
+    let (mut migrated, dropped) = keep_directives(&source, &parser);
+    report.extend(dropped.iter().map(|(offset, text)| (*offset, format!("comment not carried over: {}", text))));
+    report.sort_by_key(|&(offset, _)| offset);
+    for (offset, text) in &report {
+        eprintln!("{}:{}: {}", path.display(), source[..*offset].matches('\n').count() + 1, text);
+    }
+    migrated.push_str(&format_program_with_source(&statements, BlockStyle::Brackets, &source));
+    if write && !dropped.is_empty() {
+        eprintln!("{}: not written, since its comments would be lost; run without --write and copy them into the output", path.display());
+        return Err(Fatal::Reported);
+    }
+    if write {
+        fs::write(path, &migrated).map_err(io_error(format!("Failed to write {}", path.display())))?;
+        emit(format_args!("{}: migrated, {} note(s), {} statement(s) not translated", path.display(), parser.migrations().len(), parser.errors().len()))?;
+    } else {
+        io::stdout().write_all(migrated.as_bytes()).map_err(io_error("Failed to write to stdout"))?;
+    }
+    if parser.errors().is_empty() { Ok(ExitCode::SUCCESS) } else { Err(Fatal::Reported) }
+}
 
-enum FunctionControlFlow {
-    Continue(Value), 
-    Return(Value),   
-    Print(String),   
+fn print_ast(path: &Path) -> Result<ExitCode, Fatal> {
+    emit(format_args!("{:#?}", parse_script(path)?.0))?;
+    Ok(ExitCode::SUCCESS)
 }
 
-fn eval(expr: &Expr, env: &mut Environment, func_defs: &FuncDefs) -> Result<Value, String> {
-    //debug!("Evaluating expr: {:?}", expr);
-    match expr {
-        // ... (Expr::Num, Expr::Str, Expr::Var remain the same)
-        Expr::Num(s) => {
-            if s.contains('.') {
-                let f = s.parse::<f64>().map_err(|e| format!("Invalid float: {}", e))?;
-                Ok(Value::Float(f))
-            } else {
-                // Parse directly into BigInt
-                let i = s.parse::<BigInt>().map_err(|e| format!("Invalid integer: {}", e))?;
-                Ok(Value::Integer(i))
-            }
-        },
-        Expr::Str(s) => Ok(Value::String(s.clone())),
-        Expr::Bool(b) => Ok(Value::Boolean(*b)), // Handle Boolean literal
-        Expr::Var(id) => env
-            .get(id)
-            .cloned()
-            .ok_or_else(|| format!("Cannot evaluate uninitialized variable: {}", id)),
-        
-        // MODIFIED: Unary Prefix (e.g., -x, !x)
-        Expr::Prefix(op, rhs) => {
-            let val = eval(rhs, env, func_defs)?;
-            match (*op, val) {
-                // Arithmetic
-                ('-', Value::Integer(n)) => Ok(Value::Integer(-n)),
-                ('+', Value::Integer(n)) => Ok(Value::Integer(n)),
-                ('-', Value::Float(n)) => Ok(Value::Float(-n)),
-                ('+', Value::Float(n)) => Ok(Value::Float(n)),
-                // Logical NOT (!)
-                ('!', Value::Boolean(b)) => Ok(Value::Boolean(!b)),
-                // Error cases
-                ('!', v) => Err(format!("Unary operator '!' only supports booleans. Found {:?}", v)),
-                (_, v) => Err(format!("Unary operator '{}' only supports numbers. Found {:?}", op, v)),
-            }
+fn print_tokens(path: &Path) -> Result<ExitCode, Fatal> {
+    let source = read_script(path)?;
+    let mut lexer = Lexer::new(&source);
+    loop {
+        let token = lexer.next_token();
+        emit(format_args!("{:?}", token))?;
+        if token == Token::Eof {
+            return Ok(ExitCode::SUCCESS);
         }
-        
-        // MODIFIED: Array Literal Evaluation
-        Expr::Array(elements) => {
-            let evaluated_elements: Result<Vec<Value>, String> = elements
-                .iter()
-                .map(|e| eval(e, env, func_defs))
-                .collect();
-            Ok(Value::Array(evaluated_elements?))
-        }
-
-        // MODIFIED: Array Slicing/Indexing Evaluation (R-value)
-        Expr::Slice(array_expr, start_opt, end_opt) => {
-            // Note: This block is for R-value evaluation (reading from array) and doesn't need a mutable borrow of the environment for the array itself.
-            let array_val = eval(array_expr, env, func_defs)?;
-
-            let elements = match array_val {
-                Value::Array(v) => v,
-                _ => return Err(format!("Attempted to index/slice a non-array value: {:?}", array_val)),
-            };
-
-            // Determine array length for bounds and defaults
-            let len = elements.len() as isize;
-
-            // 1. Calculate start index (default 0)
-            let start_index = if let Some(start_expr) = start_opt {
-                let start_val = eval(start_expr, env, func_defs)?;
-                let index = match start_val {
-                    Value::Integer(n) => n.to_isize().ok_or("Array index too large or too small")?,
-                    _ => return Err(format!("Array index must be an Integer, found {:?}", start_val)),
-                };
-                // Handle negative indexing, defaulting to 0 if out of bounds on the low end
-                let calculated_start = if index < 0 { len + index } else { index };
-                (calculated_start.max(0).min(len)) as usize
-            } else if end_opt.is_some() {
-                 0 // Default start index for slicing (e.g., arr[:end])
-            } else {
-                // If it is an L-value assignment (arr[i] = x), the L-value block handles validation.
-                // If it is an R-value index read (arr[i]), start_opt will be Some and this branch isn't reached.
-                // This branch should only be reached if the slice is empty, e.g. arr[] which is a parser error.
-                return Err("Internal Error: Array index expression missing in R-value evaluation".to_string());
-            };
-
-            // 2. Calculate end index (default array length or start+1 for simple index)
-            let end_index = if let Some(end_expr) = end_opt {
-                let end_val = eval(end_expr, env, func_defs)?;
-                let index = match end_val {
-                    Value::Integer(n) => n.to_isize().ok_or("Array index too large or too small")?,
-                    _ => return Err(format!("Array index must be an Integer, found {:?}", end_val)),
-                };
-                // Handle negative indexing, defaulting to len if out of bounds on the high end
-                let calculated_end = if index < 0 { len + index } else { index };
-                (calculated_end.max(0).min(len)) as usize
-            } else if end_opt.is_some() || (start_opt.is_some() && end_opt.is_some()) {
-                // If it's a slice (arr[start:] or arr[start:end]), default end is full length
-                len as usize
-            } else {
-                // If it's simple indexing (arr[index]), the end is start + 1
-                start_index + 1
-            };
+    }
+}
 
-            // 3. Bounds and Order checks
-            if start_index > end_index || start_index > len as usize || end_index > len as usize {
-                return Err(format!(
-                    "Array slice index error: start index {} must be <= end index {} (size {})", 
-                    start_index, end_index, len
-                ));
+/// Runs each script's top level, then calls its zero-argument 'test_*' functions in order.
+/// A test fails if it raises an error or returns false.
+fn test_scripts(paths: &[PathBuf]) -> Result<ExitCode, Fatal> {
+    let (mut passed, mut failed) = (0, 0);
+    for path in paths {
+        let (statements, directives) = match parse_script(path) {
+            Ok(parsed) => parsed,
+            Err(fatal) => {
+                eprintln!("{}: {}", path.display(), fatal);
+                failed += 1;
+                continue;
             }
-
-            // 4. Perform slice/index extraction
-            let result_elements: Vec<Value> = elements[start_index..end_index].to_vec();
-
-            // If the result is a single element slice (simple indexing), return the element directly, otherwise return a new Array
-            // If end_opt is Some, it's a slice (arr[:end] or arr[start:end]), so return Value::Array regardless of length.
-            if result_elements.len() == 1 && end_opt.is_none() && start_opt.is_some() {
-                Ok(result_elements.into_iter().next().unwrap())
-            } else {
-                Ok(Value::Array(result_elements))
+        };
+        let mut options = Options::default();
+        match ProjectConfig::for_script(path) {
+            Ok(config) => config.apply_to(&mut options),
+            Err(fatal) => {
+                eprintln!("{}: {}", path.display(), fatal);
+                failed += 1;
+                continue;
             }
         }
-        
-        // Assignment (=)
-        Expr::Infix(lhs, op, rhs) if *op == '=' => {
-            // Evaluate the RHS expression first, before any mutable borrow of the environment
-            let val = eval(rhs, env, func_defs)?;
-            
-            match &**lhs {
-                Expr::Var(id) => {
-                    env.insert(id.clone(), val.clone());
-                    Ok(val)
-                }
-                // MODIFIED: Index Assignment (arr[3] = 10)
-                Expr::Slice(array_expr, start_opt, end_opt) => {
-                    
-                    // Assignment to slice (arr[i:j] = ...) is not supported, only single index assignment.
-                    if end_opt.is_some() {
-                        return Err("Assignment to array slice (arr[start:end] = ...) is not supported. Only assignment to a single index (arr[index] = ...) is allowed.".to_string());
-                    }
-                    let index_expr = start_opt.as_ref().ok_or("Array index expression missing for assignment")?;
-
-                    // --- FIX FOR E0499: Evaluate index before mutable borrow ---
-                    let index = match eval(index_expr, env, func_defs)? {
-                        Value::Integer(n) => n.to_isize().ok_or("Array index too large or too small")?,
-                        v => return Err(format!("Array index must be an Integer, found {:?}", v)),
-                    };
-                    // --- END FIX ---
-
-                    // Target of assignment (the array variable) must be Expr::Var
-                    let array_var_name = match &**array_expr {
-                        Expr::Var(id) => id,
-                        _ => return Err("Left-hand side array must be a simple variable (e.g., arr[i] = 5, not (fn())[i] = 5)".to_string()),
-                    };
-                    
-                    // Get the mutable array value from the environment (First mutable borrow)
-                    let array_val_ref = env
-                        .get_mut(array_var_name)
-                        .ok_or_else(|| format!("Cannot assign to uninitialized array variable: {}", array_var_name))?;
-
-                    // Now that index is calculated and we have the mutable ref, proceed.
-                    
-                    let elements = match array_val_ref {
-                        Value::Array(v) => v,
-                        _ => return Err("Variable is not an array and cannot be indexed for assignment".to_string()),
-                    };
-
-                    let len = elements.len() as isize;
-                    let actual_index = if index < 0 { len + index } else { index };
-
-                    // Check bounds and perform assignment (mutability)
-                    if actual_index < 0 || actual_index as usize >= elements.len() {
-                        return Err(format!("Array index out of bounds for assignment: {} (size {})", actual_index, len));
-                    }
-
-                    // Perform the mutable update
-                    elements[actual_index as usize] = val.clone();
-
-                    // Assignment returns the assigned value
-                    Ok(val)
-                }
-                _ => return Err("Assignment target must be a variable or an index expression".to_string()),
-            }
+        directives.apply_to(&mut options);
+        let mut interpreter = Interpreter::new(options);
+        if let Some(e) = statements.iter().find_map(|stmt| interpreter.run_statement(stmt).err()) {
+            eprintln!("{}: setup failed: {}", path.display(), e);
+            failed += 1;
+            continue;
         }
-        
-        // Arithmetic (+, -, *, /, %, ^) - CONSOLIDATED LOGIC
-        Expr::Infix(lhs, op, rhs) => {
-            let left_val = eval(lhs, env, func_defs)?;
-            let right_val = eval(rhs, env, func_defs)?;
-
-            // Use a single match to cover all type combinations, preventing move errors.
-            match (left_val, right_val) {
-                
-                // 1. Pure BigInt Arithmetic
-                (Value::Integer(l), Value::Integer(r)) => {
-                    return match op {
-                        '+' => Ok(Value::Integer(l + r)),
-                        '-' => Ok(Value::Integer(l - r)),
-                        '*' => Ok(Value::Integer(l * r)),
-                        '%' => {
-                            if r.is_zero() {
-                                Err("Modulo by zero".to_string())
-                            } else {
-                                Ok(Value::Integer(l % r))
-                            }
-                        }
-                        '/' => {
-                            if r.is_zero() {
-                                // Keep integer division as integer division (no float promotion)
-                                Err("Division by zero".to_string()) 
-                            } else {
-                                Ok(Value::Integer(l / r))
-                            }
-                        }
-                        '^' => {
-                            // Exponentiation: Base is BigInt, exponent must be converted to u32
-                            if r.is_positive() && r <= BigInt::from(u32::MAX) { 
-                                // to_u32 is available due to ToPrimitive trait import
-                                let exp: u32 = r.to_u32().ok_or("Exponent too large to convert to u32")?; 
-                                Ok(Value::Integer(l.pow(exp)))
-                            } else if r.is_zero() {
-                                Ok(Value::Integer(BigInt::one()))
-                            } else {
-                                Err("Integer exponentiation only supports positive exponents up to u32 max".to_string())
-                            }
-                        }
-                        _ => Err(format!("Unknown numeric infix operator: {}", op)),
-                    };
+        let tests = statements.iter().filter_map(|stmt| match stmt {
+            Statement::Def(name, params, ..) if name.starts_with("test_") && params.is_empty() => Some(name),
+            _ => None,
+        });
+        for name in tests {
+            match interpreter.call_function(name, Vec::new()) {
+                Ok(Value::Boolean(false)) => {
+                    emit(format_args!("FAILED {}::{}: returned false", path.display(), name))?;
+                    failed += 1;
                 }
-
-                // 2. String Concatenation (+) - only works if both are strings
-                (Value::String(mut l), Value::String(r)) if *op == '+' => {
-                    l.push_str(&r);
-                    return Ok(Value::String(l));
-                }
-                
-                // MODIFIED: Array Concatenation (+)
-                (Value::Array(mut l), Value::Array(r)) if *op == '+' => {
-                    l.extend(r.into_iter()); // Append elements from the right array
-                    return Ok(Value::Array(l));
+                Ok(_) => {
+                    emit(format_args!("ok     {}::{}", path.display(), name))?;
+                    passed += 1;
                 }
-                
-                // 3. Mixed or Float Arithmetic (Coerce to f64)
-                (l, r) if l.is_number() && r.is_number() => {
-                    // Coercion: l and r are guaranteed to be Int or Float.
-                    // to_f64 is available due to ToPrimitive trait import
-                    let l_f = match l {
-                        Value::Float(f) => f,
-                        Value::Integer(i) => i.to_f64().ok_or("Left BigInt too large for float conversion")?, 
-                        _ => unreachable!(), 
-                    };
-                    let r_f = match r {
-                        Value::Float(f) => f,
-                        Value::Integer(i) => i.to_f64().ok_or("Right BigInt too large for float conversion")?,
-                        _ => unreachable!(), 
-                    };
-
-                    let result_f = match op {
-                        '+' => Ok(l_f + r_f),
-                        '-' => Ok(l_f - r_f),
-                        '*' => Ok(l_f * r_f),
-                        '%' => {
-                            if r_f.abs() < f64::EPSILON {
-                                Err("Modulo by zero in float operation".to_string())
-                            } else {
-                                Ok(l_f % r_f)
-                            }
-                        }
-                        '/' => {
-                            if r_f.abs() < f64::EPSILON {
-                                Err("Division by zero in float operation".to_string())
-                            } else {
-                                Ok(l_f / r_f)
-                            }
-                        }
-                        '^' => Ok(l_f.powf(r_f)),
-                        _ => Err(format!("Unknown numeric infix operator: {}", op)),
-                    }?;
-                    
-                    Ok(Value::Float(result_f))
+                Err(e) => {
+                    emit(format_args!("FAILED {}::{}: {}", path.display(), name, e))?;
+                    failed += 1;
                 }
-
-                // 4. Incompatible Types (Error)
-                (l, r) => Err(format!("Incompatible types for operator '{}': {:?} and {:?}", op, l, r)),
             }
         }
+    }
+    emit(format_args!("{} passed, {} failed", passed, failed))?;
+    if failed == 0 { Ok(ExitCode::SUCCESS) } else { Err(Fatal::Reported) }
+}
 
-        // ... Expr::Cmp and Expr::Logic remain the same ...
-        Expr::Cmp(lhs, op, rhs) => {
-            let left_val = eval(lhs, env, func_defs)?;
-            let right_val = eval(rhs, env, func_defs)?;
-            
-            let result = match op.as_str() {
-                // STRICT Equality/Inequality (value AND type must match exactly)
-                "===" => left_val == right_val,
-                "!==" => left_val != right_val,
-                
-                // NON-STRICT Equality/Inequality (value must match, type coercion between Int/Float)
-                "==" | "!=" => {
-                    let non_strict_equal = match (&left_val, &right_val) {
-                        // Exact match (Value and Type)
-                        (l, r) if l == r => true,
-                        // Non-strict coercion for BigInt/Float
-                        (Value::Integer(l), Value::Float(r)) => {
-                            // to_f64 is available due to ToPrimitive trait import
-                            l.to_f64().map_or(false, |l_f| l_f == *r)
-                        }
-                        (Value::Float(l), Value::Integer(r)) => {
-                            // to_f64 is available due to ToPrimitive trait import
-                            r.to_f64().map_or(false, |r_f| *l == r_f)
-                        }
-                        // All other combinations are false (String/Bool/Void != Int/Float, etc.)
-                        _ => false,
-                    };
-
-                    if op.as_str() == "==" { non_strict_equal } else { !non_strict_equal }
-                },
-                
-                // Ordering Comparisons: require same type for ordering
-                "<" | ">" | "<=" | ">=" => {
-                    match (&left_val, &right_val) {
-                        (Value::Integer(l), Value::Integer(r)) => match op.as_str() {
-                            "<" => l < r, ">" => l > r, "<=" => l <= r, ">=" => l >= r, _ => unreachable!(),
-                        },
-                        (Value::Float(l), Value::Float(r)) => match op.as_str() {
-                            "<" => l < r, ">" => l > r, "<=" => l <= r, ">=" => r >= r, _ => unreachable!(), 
-                        },
-                        (Value::String(l), Value::String(r)) => match op.as_str() {
-                            "<" => l < r, ">" => l > r, "<=" => l <= r, ">=" => l >= r, _ => unreachable!(), 
-                        },
-                        (l, r) => return Err(format!(
-                            "Incompatible types for ordering operator '{}': {:?} and {:?}", op, l, r
-                        )),
-                    }
-                },
-                _ => return Err(format!("Unknown comparison operator: {}", op)),
-            };
-            
-            Ok(Value::Boolean(result))
+/// Runs the script's top level once, to define its functions and register its jobs, then calls
+/// the jobs as they come due. Every run gets an interpreter of its own that starts from the end
+/// of the top level, so nothing one run changes is seen by the next. A failed run is logged and
+/// the job backs off; the scheduler keeps going until it is stopped.
+fn schedule_script(path: &Path, permissions: &Permissions, once: bool) -> Result<ExitCode, Fatal> {
+    let source = read_script(path)?;
+    let mut options = Options::default();
+    ProjectConfig::for_script(path)?.apply_to(&mut options);
+    permissions.apply_to(&mut options);
+    let (statements, directives) = parse_source(&source)?;
+    directives.apply_to(&mut options);
+    let mut log = RunLog;
+    log.line(format_args!("--- Starting scheduler for {} ---", path.display()))?;
+    let mut setup = Interpreter::new(options);
+    for (i, stmt) in statements.iter().enumerate() {
+        if let ScriptFlow::Return(_) = setup.run_statement(stmt).map_err(|e| Fatal::Runtime(i + 1, e))? {
+            break;
         }
+    }
+    let jobs = setup.scheduled_jobs();
+    if jobs.is_empty() {
+        return Err(Fatal::Schedule(format!("{} registers no jobs; call every(interval, fn_name) at its top level", path.display())));
+    }
+    for job in &jobs {
+        info!("Scheduled {} every {}", job.function, Interval(job.interval));
+    }
+    let snapshot = setup.snapshot();
 
-        // NEW: Logical Operators (AND, OR)
-        Expr::Logic(lhs, op, rhs) => {
-            let left_val = eval(lhs, env, func_defs)?;
-
-            // Short-circuit evaluation
-            let short_circuit_val = match (op.as_str(), &left_val) {
-                // False AND anything is False
-                ("and", Value::Boolean(false)) => Some(Value::Boolean(false)), 
-                // True OR anything is True
-                ("or", Value::Boolean(true)) => Some(Value::Boolean(true)),   
-                _ => None,
-            };
-
-            if let Some(val) = short_circuit_val {
-                return Ok(val);
-            }
-            
-            // If not short-circuited, evaluate RHS
-            let right_val = eval(rhs, env, func_defs)?;
-
-            match (op.as_str(), left_val, right_val) {
-                // Since we passed short-circuiting, the left must be a Boolean as well
-                ("and", Value::Boolean(l_b), Value::Boolean(r_b)) => Ok(Value::Boolean(l_b && r_b)),
-                ("or", Value::Boolean(l_b), Value::Boolean(r_b)) => Ok(Value::Boolean(l_b || r_b)),
-                
-                // Error on incompatible types (if one wasn't a boolean, or if the left was a boolean but the right wasn't)
-                (op_str, l, r) => {
-                    Err(format!("Logical operator '{}' only works on Booleans. Found {:?} and {:?}", op_str, l, r))
-                }
+    if once {
+        let mut failed = 0;
+        for job in &jobs {
+            if !run_job(&snapshot, &job.function)? {
+                failed += 1;
             }
         }
-        Expr::Call(name, args) => execute_function(name, args, env, func_defs),
+        return if failed == 0 { Ok(ExitCode::SUCCESS) } else { Err(Fatal::Reported) };
+    }
+    let mut schedule = Schedule::new(jobs, Instant::now());
+    while let Some((index, due)) = schedule.next_due() {
+        thread::sleep(due.saturating_duration_since(Instant::now()));
+        let started = Instant::now();
+        let succeeded = run_job(&snapshot, &schedule.job(index).function)?;
+        let next_run = schedule.record(index, succeeded, started);
+        if !succeeded {
+            let wait = Interval(next_run.saturating_duration_since(started));
+            warn!("{} failed {} time(s) in a row; next attempt {} after this one", schedule.job(index).function, schedule.failures(index), wait);
+        }
     }
+    Ok(ExitCode::SUCCESS)
 }
 
-// NEW: Native function definitions
-type NativeFunction = fn(&str, &mut Environment, &FuncDefs, Vec<Value>) -> Result<Value, String>;
+/// Calls one job in a fresh interpreter and reports the outcome. Like a test, a job fails if it
+/// raises an error or returns false.
+fn run_job(snapshot: &PreparedRuntime, function: &str) -> Result<bool, Fatal> {
+    let mut interpreter = Interpreter::from_snapshot(snapshot);
+    let started = Instant::now();
+    let result = interpreter.call_function(function, Vec::new());
+    let elapsed = started.elapsed();
+    let error = match result {
+        Ok(Value::Boolean(false)) => "returned false".to_string(),
+        Ok(_) => {
+            info!("Job {} succeeded in {:.2?}", function, elapsed);
+            emit(format_args!("ok     {} ({:.2?})", function, elapsed))?;
+            return Ok(true);
+        }
+        Err(e) => e,
+    };
+    warn!("Job {} failed after {:.2?}: {}", function, elapsed, error);
+    emit(format_args!("FAILED {}: {}", function, error))?;
+    Ok(false)
+}
 
-fn get_native_function(name: &str) -> Option<NativeFunction> {
-    match name {
-        // Only 'length' is kept as a built-in helper for arrays
-        "length" => Some(native_length),
-        // All other array manipulation logic (slicing, mutability) is handled by Expr::Slice and Expr::Infix.
-        _ => None,
+/// Net count of open brackets in `source`, ignoring string literals and comments, used to keep
+/// reading lines while a block or call is unfinished.
+fn open_delimiters(source: &str) -> i32 {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut in_comment = false;
+    for ch in source.chars() {
+        match ch {
+            '\n' => in_comment = false,
+            _ if in_comment => {}
+            '"' => in_string = !in_string,
+            _ if in_string => {}
+            ';' => in_comment = true,
+            '[' | '{' | '(' => depth += 1,
+            ']' | '}' | ')' => depth -= 1,
+            _ => {}
+        }
     }
+    depth
 }
 
-// --- Array Helper Functions ---
-
-fn native_length(fn_name: &str, _env: &mut Environment, _func_defs: &FuncDefs, mut args: Vec<Value>) -> Result<Value, String> {
-    if args.len() != 1 {
-        return Err(format!("'{}' expects 1 argument (array), found {}", fn_name, args.len()));
-    }
-    match args.remove(0) {
-        Value::Array(a) => Ok(Value::Integer(BigInt::from(a.len()))),
-        v => Err(format!("Argument to '{}' must be an Array, found {:?}", fn_name, v)),
+/// Reads one entry at the prompt: a line, or more while brackets are still open. None at the end
+/// of input.
+fn read_entry() -> Result<Option<String>, Fatal> {
+    let mut source = String::new();
+    loop {
+        print!("{}", if source.is_empty() { "> " } else { "... " });
+        io::stdout().flush().map_err(io_error("Failed to write to stdout"))?;
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line).map_err(io_error("Failed to read input"))? == 0 {
+            return Ok(None);
+        }
+        source.push_str(&line);
+        if open_delimiters(&source) <= 0 {
+            return Ok(Some(source));
+        }
     }
 }
 
-
-fn execute_function(fn_name: &str, arg_exprs: &[Expr], caller_env: &mut Environment, func_defs: &FuncDefs) -> Result<Value, String> {
-    debug!("Executing function '{}', args: {:?}", fn_name, arg_exprs);
-    
-    // Evaluate arguments first
-    let evaluated_args: Vec<Value> = arg_exprs
-        .iter()
-        .map(|e| {
-            let result = eval(e, caller_env, func_defs);
-            //debug!("Evaluated arg {:?} -> {:?}", e, result);
-            result
-        })
-        .collect::<Result<Vec<Value>, String>>()?;
-    
-    // 1. Check for Native Functions
-    if let Some(native_func) = get_native_function(fn_name) {
-        // All native functions are executed directly now
-        native_func(fn_name, caller_env, func_defs, evaluated_args)
-    } 
-    // 2. Check for User-Defined Functions
-    else if let Some((params, body_statements)) = func_defs.get(fn_name) {
-        if params.len() != evaluated_args.len() {
-            return Err(format!(
-                "Function '{}' expects {} arguments, but received {}",
-                fn_name, params.len(), evaluated_args.len()
-            ));
-        }
-        
-        let mut local_env = Environment::new();
-        for (param_name, arg_value) in params.iter().zip(evaluated_args.into_iter()) {
-            local_env.insert(param_name.clone(), arg_value);
+/// Runs the statements of an entry, echoing expression values but not assignments. Output
+/// captured by the interpreter is shown as it is printed and returned. An error is reported and
+/// skips the rest of the entry; a top-level 'return' ends it with its value.
+fn run_entry(interpreter: &mut Interpreter, source: &str) -> Result<(Vec<String>, Option<Value>), Fatal> {
+    let statements = match Parser::new(source).parse() {
+        Ok(statements) => statements,
+        Err(e) => {
+            eprintln!("{}", Fatal::Parse(e));
+            return Ok((Vec::new(), None));
         }
-        //debug!("Local env for '{}': {:?}", fn_name, local_env);
-
-        let mut last_value = Value::Void;
-
-        // CHANGE: Loop through the pre-parsed statements directly
-        for (i, stmt) in body_statements.iter().enumerate() {
-            match run_statement_in_function(stmt, &mut local_env, func_defs) {
-                Ok(flow) => {
-                    match flow {
-                        FunctionControlFlow::Return(val) => {
-                            // Explicit return
-                            //debug!("Explicit return triggered from block with value: {:?}", val);
-                            return Ok(val);
-                        }
-                        FunctionControlFlow::Continue(val) => {
-                            last_value = val;
-                        }
-                        FunctionControlFlow::Print(output) => {
-                            // Write output directly to stdout for immediate display
-                            writeln!(io::stdout(), "{}", output).map_err(|e| format!("Failed to write to stdout: {}", e))?;
-                            io::stdout().flush().map_err(|e| format!("Failed to flush stdout: {}", e))?;
-                            // Also log to runlog
-                            let mut log_file = OpenOptions::new().create(true).append(true).open("runlog").map_err(|e| format!("Failed to open runlog: {}", e))?;
-                            writeln!(log_file, "Block Output (Stmt {}): {}", i + 1, output).map_err(|e| format!("Failed to write to runlog: {}", e))?;
-                            log_file.flush().map_err(|e| format!("Failed to flush runlog: {}", e))?;
-                        }
-                    }
-                }
-                Err(e) => {
-                    return Err(format!("Function '{}' Execution Error (Stmt {}): {}", fn_name, i + 1, e));
-                }
+    };
+    let mut printed = Vec::new();
+    for stmt in &statements {
+        let echo = !matches!(stmt, Statement::Expr(Expr::Infix(_, '=', _)));
+        let result = interpreter.run_statement(stmt);
+        for line in interpreter.take_output() {
+            emit(&line)?;
+            printed.push(line);
+        }
+        match result {
+            Ok(ScriptFlow::Continue(_, Some(value))) if echo && value != Value::Void => emit(value)?,
+            Ok(ScriptFlow::Continue(..)) => {}
+            Ok(ScriptFlow::Return(value)) => return Ok((printed, Some(value))),
+            Err(e) => {
+                eprintln!("Runtime Error: {}", e);
+                break;
             }
         }
-        
-        // Implicit return of the last expression value or Void
-        Ok(last_value)
-    } 
-    // 3. Undefined Function
-    else {
-        Err(format!("Function '{}' is not defined", fn_name))
     }
+    Ok((printed, None))
 }
 
-// The rest of the `run_statement_in_function`, `run_statement`, and `main` functions
-// remain largely the same, except for incorporating the function call logic into the interpreter.
-
-fn run_statement_in_function(stmt: &Statement, env: &mut Environment, func_defs: &FuncDefs) -> Result<FunctionControlFlow, String> {
-    debug!("Running statement in function: {:?}", stmt);
-    match stmt {
-        Statement::Expr(expr) => {
-            let result = eval(expr, env, func_defs)?;
-            Ok(FunctionControlFlow::Continue(result))
-        }
-        Statement::Print(opt_format_string, expressions) => {
-            let results: Vec<Value> = expressions
-                .iter()
-                .map(|e| eval(e, env, func_defs))
-                .collect::<Result<Vec<Value>, String>>()?;
-
-            let output = if let Some(format_string) = opt_format_string {
-                let mut output = format_string.clone();
-                let placeholder = "{}";
-                let mut current_pos = 0;
-                
-                for result in results.iter() {
-                    let result_str = match result {
-                        Value::Integer(n) => format!("{}", n),
-                        Value::Float(n) => format!("{}", n),
-                        Value::String(s) => s.clone(), 
-                        Value::Boolean(b) => format!("{}", if *b { "true" } else { "false" }), 
-                        Value::Void => String::from("void"),
-                        Value::Array(v) => format!("{}", Value::Array(v.clone())), // Use Array's display for formatting
-                    };
-                    if let Some(start) = output[current_pos..].find(placeholder) {
-                        let full_start = current_pos + start;
-                        let full_end = full_start + placeholder.len();
-                        output.replace_range(full_start..full_end, &result_str);
-                        current_pos = full_start + result_str.len();
-                    } else {
-                        return Err(format!("Not enough placeholders ({}) in format string: \"{}\"", placeholder, format_string));
-                    }
-                }
-                output
-            } else {
-                if results.len() != 1 {
-                    return Err("Simple print (without format string) expects exactly one argument".to_string());
-                }
-                match &results[0] {
-                    // MODIFIED: Explicitly format Boolean without quotes in simple print
-                    Value::Boolean(b) => format!("{}", if *b { "true" } else { "false" }), 
-                    Value::String(s) => s.clone(), 
-                    v => format!("{}", v),         
-                }
-            };
-            
-            Ok(FunctionControlFlow::Print(output))
-        }
-        // CHANGE: Uses Vec<Statement> for bodies
-        Statement::If(condition_expr, if_statements, else_opt_statements) => {
-            let condition_val = eval(condition_expr, env, func_defs)?;
-
-            let execute_if = match condition_val {
-                Value::Boolean(b) => b,
-                _ => return Err(format!("'if' condition must evaluate to a Boolean, found {:?}", condition_val)),
-            };
-
-            let body_to_execute = if execute_if {
-                Some(if_statements)
-            } else if let Some(else_statements) = else_opt_statements {
-                Some(else_statements)
-            } else {
-                return Ok(FunctionControlFlow::Continue(Value::Void)); 
-            };
-            
-            let mut last_value = Value::Void;
-            
-            // Loop through the statements in the block
-            if let Some(statements) = body_to_execute {
-                for stmt in statements.iter() {
-                    match run_statement_in_function(stmt, env, func_defs) {
-                        Ok(flow) => {
-                            match flow {
-                                FunctionControlFlow::Return(val) => {
-                                    // Propagate return flow up the call stack
-                                    return Ok(FunctionControlFlow::Return(val)); 
-                                }
-                                FunctionControlFlow::Continue(val) => {
-                                    last_value = val;
-                                }
-                                FunctionControlFlow::Print(output) => {
-                                    writeln!(io::stdout(), "{}", output).map_err(|e| format!("Failed to write to stdout: {}", e))?;
-                                    io::stdout().flush().map_err(|e| format!("Failed to flush stdout: {}", e))?;
-                                    let mut log_file = OpenOptions::new()
-                                        .create(true)
-                                        .append(true)
-                                        .open("runlog")
-                                        .map_err(|e| format!("Failed to open runlog: {}", e))?;
-                                    writeln!(log_file, "Block Output: {}", output)
-                                        .map_err(|e| format!("Failed to write to runlog: {}", e))?;
-                                    log_file.flush().map_err(|e| format!("Failed to flush runlog: {}", e))?;
-                                }
-                            }
-                        }
-                        Err(e) => return Err(e),
-                    }
-                }
-            }
-            
-            Ok(FunctionControlFlow::Continue(last_value))
-        }
-        Statement::Def(name, ..) => {
-            Err(format!("Function definition '{}' is only allowed at the top level", name))
-        }
-        Statement::Return(opt_expr) => {
-            let return_val = if let Some(expr) = opt_expr {
-                eval(expr, env, func_defs)?
-            } else {
-                Value::Void
-            };
-            Ok(FunctionControlFlow::Return(return_val))
+fn repl() -> Result<ExitCode, Fatal> {
+    let mut options = Options::default();
+    let cwd = std::env::current_dir().map_err(io_error("Failed to read the current directory"))?;
+    ProjectConfig::find(&cwd)?.apply_to(&mut options);
+    let mut interpreter = Interpreter::new(options);
+    while let Some(source) = read_entry()? {
+        if let (_, Some(value)) = run_entry(&mut interpreter, &source)? {
+            return Ok(exit_code_for(&value));
         }
     }
+    Ok(ExitCode::SUCCESS)
 }
 
-fn run_statement(stmt: &Statement, env: &mut Environment, func_defs: &mut FuncDefs) -> Result<String, String> {
-    debug!("Running statement: {:?}", stmt);
-    match stmt {
-        Statement::Expr(expr) => {
-            let result = eval(expr, env, func_defs)?;
-            match result {
-                Value::Void => Ok(String::new()),
-                _ => Ok(format!("{}", result)),
+const LEARN_COMMANDS: &str = "Commands: :solution shows an answer, :skip goes to the next lesson, :quit stops.";
+
+/// Runs the lessons from `start` (1-based) like the REPL. After every entry the lesson checks
+/// what the learner has done so far; once it passes, the next lesson begins.
+fn learn(start: usize) -> Result<ExitCode, Fatal> {
+    emit(format_args!("Welcome to Astra! Type code at the prompt to solve each task.\n{}", LEARN_COMMANDS))?;
+    for (number, lesson) in LESSONS.iter().enumerate().skip(start - 1).map(|(i, lesson)| (i + 1, lesson)) {
+        emit(format_args!("\n--- Lesson {} of {}: {} ---\n{}\n\nTask: {}", number, LESSONS.len(), lesson.title, lesson.explanation, lesson.task))?;
+        let mut interpreter = match lesson.start() {
+            Ok(interpreter) => interpreter,
+            Err(e) => {
+                eprintln!("Lesson {}: setup failed: {}", number, e);
+                return Err(Fatal::Reported);
             }
-        }
-        Statement::Print(opt_format_string, expressions) => {
-            let results: Vec<Value> = expressions
-                .iter()
-                .map(|e| eval(e, env, func_defs))
-                .collect::<Result<Vec<Value>, String>>()?;
-            
-            let output = if let Some(format_string) = opt_format_string {
-                let mut output = format_string.clone();
-                let placeholder = "{}";
-                let mut current_pos = 0;
-                
-                for result in results.iter() {
-                    let result_str = match result {
-                        Value::Integer(n) => format!("{}", n),
-                        Value::Float(n) => format!("{}", n),
-                        Value::String(s) => s.clone(), 
-                        Value::Boolean(b) => format!("{}", if *b { "true" } else { "false" }), 
-                        Value::Void => String::from("void"),
-                        Value::Array(v) => format!("{}", Value::Array(v.clone())), 
-                    };
-                    if let Some(start) = output[current_pos..].find(placeholder) {
-                        let full_start = current_pos + start;
-                        let full_end = full_start + placeholder.len();
-                        output.replace_range(full_start..full_end, &result_str);
-                        current_pos = full_start + result_str.len();
-                    } else {
-                        return Err(format!("Not enough placeholders ({}) in format string: \"{}\"", placeholder, format_string));
-                    }
-                }
-                output
-            } else {
-                if results.len() != 1 {
-                    return Err("Simple print (without format string) expects exactly one argument".to_string());
+        };
+        loop {
+            let Some(source) = read_entry()? else {
+                return Ok(ExitCode::SUCCESS);
+            };
+            match source.trim() {
+                "" => continue,
+                ":quit" => return Ok(ExitCode::SUCCESS),
+                ":skip" => break,
+                ":solution" => {
+                    emit(lesson.solution)?;
+                    continue;
                 }
-                match &results[0] {
-                    // MODIFIED: Explicitly format Boolean without quotes in simple print
-                    Value::Boolean(b) => format!("{}", if *b { "true" } else { "false" }), 
-                    Value::String(s) => s.clone(), 
-                    v => format!("{}", v),         
+                command if command.starts_with(':') => {
+                    emit(LEARN_COMMANDS)?;
+                    continue;
                 }
-            };
-            
-            writeln!(io::stdout(), "{}", output).map_err(|e| format!("Failed to write to stdout: {}", e))?;
-            io::stdout().flush().map_err(|e| format!("Failed to flush stdout: {}", e))?;
-            let mut log_file = OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open("runlog")
-                .map_err(|e| format!("Failed to open runlog file: {}", e))?;
-            writeln!(log_file, "Output: {}", output)
-                .expect("Failed to write to runlog");
-            log_file.flush().expect("Failed to flush runlog");
-            Ok(output)
-        }
-        // CHANGE: Store Vec<Statement> directly in FuncDefs
-        Statement::Def(name, params, body_statements) => {
-            func_defs.insert(name.clone(), (params.clone(), body_statements.clone()));
-            Ok(String::new())
-        }
-        Statement::Return(_) => {
-            Ok(String::new())
-        }
-        // CHANGE: Execute pre-parsed Vec<Statement>
-        Statement::If(condition_expr, if_statements, else_opt_statements) => {
-            let condition_val = eval(condition_expr, env, func_defs)?;
-
-            let execute_if = match condition_val {
-                Value::Boolean(b) => b,
-                _ => return Err(format!("'if' condition must evaluate to a Boolean, found {:?}", condition_val)),
-            };
-
-            let body_to_execute = if execute_if {
-                Some(if_statements)
-            } else if let Some(else_statements) = else_opt_statements {
-                Some(else_statements)
-            } else {
-                return Ok(String::new()); 
-            };
-            
-            // Loop through the statements in the block
-            if let Some(statements) = body_to_execute {
-                for stmt in statements.iter() {
-                    match run_statement(stmt, env, func_defs) {
-                        Ok(_) => continue,
-                        Err(e) => return Err(e),
-                    }
+                _ => {}
+            }
+            let (printed, _) = run_entry(&mut interpreter, &source)?;
+            match lesson.check(&interpreter, &printed) {
+                Ok(()) => {
+                    emit("Correct!")?;
+                    break;
                 }
+                Err(e) => emit(format_args!("Not yet: {}", e))?,
             }
-            
-            Ok(String::new())
         }
     }
+    emit("\nThat was the last lesson. Keep experimenting with 'astra repl'; 'astra builtins' lists the builtin functions.")?;
+    Ok(ExitCode::SUCCESS)
 }
 
-fn main() {
-    let debug_file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open("runlog")
-        .expect("Failed to open runlog file for debug logging");
-    let debug_writer = BufWriter::new(debug_file);
-    env_logger::Builder::new()
-        .filter_level(LevelFilter::Debug)
-        .target(env_logger::Target::Pipe(Box::new(debug_writer)))
-        .init();
-
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: {} <filename>", args[0]);
-        eprintln!("To test, create a file (e.g., 'test.txt') and run: cargo run -- test.txt");
-        return;
+fn run_script(args: RunArgs) -> Result<ExitCode, Fatal> {
+    let mut options = Options {
+        implicit_return: !args.no_implicit_return,
+        contracts: args.contracts,
+        max_output_bytes: args.max_output_bytes,
+        truncate_output: args.truncate_output,
+        pure_cache_size: args.pure_cache,
+        max_steps: args.max_steps,
+        loop_fusion: !args.no_loop_fusion,
+        int_division: IntDivision::default(),
+        ..Options::default()
+    };
+    args.permissions.apply_to(&mut options);
+    // clap requires the filename for 'run' and the shorthand form
+    let Some(path) = args.filename else {
+        return Err(Fatal::Reported);
+    };
+    let source = read_script(&path)?;
+    ProjectConfig::for_script(&path)?.apply_to(&mut options);
+    if let Some(mode) = args.int_div {
+        options.int_division = mode;
     }
-    let filename = &args[1];
-    let file_content = match fs::read_to_string(filename) {
-        Ok(content) => content,
-        Err(e) => {
-            eprintln!("Error reading file {}: {}", filename, e);
-            return;
+    if let Some(signature) = &args.verify {
+        verify_script(&source, signature, args.public_key.as_deref())?;
+    }
+    let mut log = RunLog;
+    log.line(format_args!("--- Starting script execution from {} ---", path.display()))?;
+    let (statements, directives) = parse_source(&source)?;
+    debug!("Parsed statements: {:?}", statements);
+    directives.apply_to(&mut options);
+    let mut interpreter = Interpreter::new(options);
+    if !interpreter.options().implicit_return {
+        for warning in implicit_return_warnings(&statements, &directives) {
+            eprintln!("Warning: {}", warning);
+            log.line(format_args!("Warning: {}", warning))?;
         }
-    };
-    let mut parser = Parser::new(&file_content);
-    let mut env = HashMap::new();
-    let mut func_defs: FuncDefs = HashMap::new();
-    let mut log_file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open("runlog")
-        .expect("Failed to open runlog file");
-    writeln!(log_file, "--- Starting script execution from {} ---", filename)
-        .expect("Failed to write to runlog");
-    log_file.flush().expect("Failed to flush runlog");
-    match parser.parse() {
-        Ok(statements) => {
-            debug!("Parsed statements: {:?}", statements);
-            for (i, stmt) in statements.into_iter().enumerate() {
-                writeln!(log_file, "\nExecuting Statement {}\n-----------------------", i + 1)
-                    .expect("Failed to write to runlog");
-                log_file.flush().expect("Failed to flush runlog");
-                match run_statement(&stmt, &mut env, &mut func_defs) {
-                    Ok(output) => {
-                        if !output.is_empty() {
-                            writeln!(log_file, "Result: {}", output)
-                                .expect("Failed to write to runlog");
-                            log_file.flush().expect("Failed to flush runlog");
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("Runtime Error (Statement {}): {}", i + 1, e);
-                        writeln!(log_file, "Runtime Error (Statement {}): {}", i + 1, e)
-                            .expect("Failed to write error to runlog");
-                        break;
-                    }
-                }
+    }
+    let mut last_value = Value::Void;
+    let mut returned = false;
+    for (i, stmt) in statements.iter().enumerate() {
+        log.line(format_args!("\nExecuting Statement {}\n-----------------------", i + 1))?;
+        match interpreter.run_statement(stmt).map_err(|e| Fatal::Runtime(i + 1, e))? {
+            ScriptFlow::Continue(output, value) => {
+                if !output.is_empty() {
+                    log.line(format_args!("Result: {}", interpreter.redact(&output)))?;
+                }
+                if let Some(value) = value {
+                    last_value = value;
+                }
+            }
+            ScriptFlow::Return(value) => {
+                log.line(format_args!("Script returned: {}", interpreter.redact(&value.to_string())))?;
+                last_value = value;
+                returned = true;
+                break;
             }
         }
-        Err(e) => {
-            eprintln!("Parsing Error: {}", e);
-            writeln!(log_file, "Parsing Error: {}", e)
-                .expect("Failed to write error to runlog");
+    }
+    let jobs = interpreter.scheduled_jobs();
+    if !jobs.is_empty() {
+        eprintln!("Note: {} job(s) registered with 'every' only run under 'astra schedule {}'", jobs.len(), path.display());
+    }
+    let report = interpreter.timing_report();
+    if !report.is_empty() {
+        eprintln!("Timing report:");
+        log.line("Timing report:")?;
+        for line in report {
+            eprintln!("  {}", line);
+            log.line(format_args!("  {}", line))?;
         }
     }
-}
\ No newline at end of file
+    if args.print_last && last_value != Value::Void {
+        emit(print_repr(&last_value))?;
+    }
+    // Only an explicit top-level 'return' chooses the exit code
+    Ok(if returned { exit_code_for(&last_value) } else { ExitCode::SUCCESS })
+}
@@ -0,0 +1,59 @@
+//! XML and HTML builtins, compiled with the "markup" feature. There is no map type, so an XML
+//! element becomes the array [name, attributes, children], where attributes are [name, value]
+//! pairs and children are elements or Strings of text.
+
+use scraper::{Html, Selector};
+
+use crate::{Environment, Runtime, Value};
+
+fn text(s: &str) -> Value {
+    Value::String(s.to_string())
+}
+
+/// Elements by local name (without a namespace prefix). Comments and processing instructions
+/// are left out, and so is text that is only whitespace, such as indentation between elements.
+fn element(node: roxmltree::Node) -> Value {
+    let attributes = node.attributes().map(|attr| Value::Array(vec![text(attr.name()), text(attr.value())])).collect();
+    let children = node
+        .children()
+        .filter_map(|child| match child.node_type() {
+            roxmltree::NodeType::Element => Some(element(child)),
+            roxmltree::NodeType::Text => child.text().filter(|s| !s.trim().is_empty()).map(text),
+            _ => None,
+        })
+        .collect();
+    Value::Array(vec![text(node.tag_name().name()), Value::Array(attributes), Value::Array(children)])
+}
+
+/// The document's root element as [name, attributes, children].
+pub(crate) fn native_xml_parse(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    let source = match args.as_slice() {
+        [Value::String(source)] => source,
+        [other] => return Err(message!("Argument to '{}' must be a String of XML, found {:?}", fn_name, other)),
+        _ => return Err(message!("'{}' expects 1 argument (text), found {}", fn_name, args.len())),
+    };
+    let document = roxmltree::Document::parse(source).map_err(|e| message!("'{}' could not parse the XML: {}", fn_name, e))?;
+    Ok(element(document.root_element()))
+}
+
+/// Elements matching a CSS selector, in document order: their text (trimmed), or the value of
+/// the given attribute for the matches that have it. HTML is parsed as browsers do, so broken
+/// markup never fails.
+pub(crate) fn native_html_select(fn_name: &str, _env: &mut Environment, _runtime: &Runtime, args: Vec<Value>) -> Result<Value, String> {
+    let (source, selector, attribute) = match args.as_slice() {
+        [Value::String(source), Value::String(selector)] => (source, selector, None),
+        [Value::String(source), Value::String(selector), Value::String(attribute)] => (source, selector, Some(attribute.as_str())),
+        [..] if (2..=3).contains(&args.len()) => {
+            return Err(message!("'{}' expects String arguments (html, selector, attribute), found {:?}", fn_name, args));
+        }
+        _ => return Err(message!("'{}' expects 2 or 3 arguments (html, selector, attribute), found {}", fn_name, args.len())),
+    };
+    let selector = Selector::parse(selector).map_err(|e| message!("Invalid CSS selector '{}' for '{}': {}", selector, fn_name, e))?;
+    let document = Html::parse_document(source);
+    let matches = document.select(&selector);
+    let values = match attribute {
+        Some(attribute) => matches.filter_map(|el| el.value().attr(attribute)).map(text).collect(),
+        None => matches.map(|el| text(el.text().collect::<String>().trim())).collect(),
+    };
+    Ok(Value::Array(values))
+}
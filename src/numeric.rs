@@ -0,0 +1,283 @@
+//! The numeric tower: how Integer and Float values combine. Operators and builtins that take two
+//! numbers go through `coerce_pair` (arithmetic) or `compare` (ordering and `==`), so a new
+//! numeric type only needs its promotion rules added here.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{FromPrimitive, One, Signed, ToPrimitive, Zero};
+
+use crate::Value;
+
+/// Two numbers brought to a common representation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NumericPair {
+    // Both operands were Integers; arithmetic stays exact
+    Integers(BigInt, BigInt),
+    // At least one operand was a Float; the other is converted
+    Floats(f64, f64),
+}
+
+/// How '/' and '%' treat two Integers (--int-div, or 'int-div' in astra.toml). Floats are
+/// unaffected: their '/' is exact and their '%' takes the dividend's sign.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IntDivision {
+    // The quotient rounds toward zero and the remainder takes the dividend's sign: -7 / 2 == -3, -7 % 2 == -1
+    #[default]
+    Trunc,
+    // The quotient rounds down and the remainder takes the divisor's sign: -7 / 2 == -4, -7 % 2 == 1
+    Floor,
+    // '/' gives the exact Float quotient (-7 / 2 == -3.5); '%' and divmod round down as with Floor
+    Promote,
+}
+
+impl FromStr for IntDivision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "trunc" => Ok(IntDivision::Trunc),
+            "floor" => Ok(IntDivision::Floor),
+            "promote" => Ok(IntDivision::Promote),
+            _ => Err(message!("Unknown integer division mode '{}' (expected trunc, floor or promote)", s)),
+        }
+    }
+}
+
+impl fmt::Display for IntDivision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            IntDivision::Trunc => "trunc",
+            IntDivision::Floor => "floor",
+            IntDivision::Promote => "promote",
+        })
+    }
+}
+
+/// Promotes two numbers for arithmetic: Integer with Integer stays Integer, anything involving
+/// a Float becomes Float. Fails for non-numbers and for Integers too large for an f64.
+pub fn coerce_pair(a: Value, b: Value) -> Result<NumericPair, String> {
+    match (a, b) {
+        (Value::Integer(a), Value::Integer(b)) => Ok(NumericPair::Integers(a, b)),
+        (Value::Float(a), Value::Float(b)) => Ok(NumericPair::Floats(a, b)),
+        (Value::Integer(a), Value::Float(b)) => Ok(NumericPair::Floats(to_float(&a)?, b)),
+        (Value::Float(a), Value::Integer(b)) => Ok(NumericPair::Floats(a, to_float(&b)?)),
+        (a, b) => Err(message!("Expected two numbers, found {:?} and {:?}", a, b)),
+    }
+}
+
+/// Converts an Integer for mixed arithmetic.
+pub fn to_float(i: &BigInt) -> Result<f64, String> {
+    i.to_f64().ok_or_else(|| message!("BigInt too large for float conversion: {}", i))
+}
+
+/// Applies an arithmetic operator (+, -, *, /, %, ^) to a promoted pair.
+pub fn arithmetic(op: char, pair: NumericPair, division: IntDivision) -> Result<Value, String> {
+    match pair {
+        NumericPair::Integers(l, r) => integer_arithmetic(op, &l, &r, division),
+        NumericPair::Floats(l, r) => match op {
+            '+' => Ok(Value::Float(l + r)),
+            '-' => Ok(Value::Float(l - r)),
+            '*' => Ok(Value::Float(l * r)),
+            '%' if r.abs() < f64::EPSILON => Err(message!("Modulo by zero in float operation")),
+            '%' => Ok(Value::Float(l % r)),
+            '/' if r.abs() < f64::EPSILON => Err(message!("Division by zero in float operation")),
+            '/' => Ok(Value::Float(l / r)),
+            '^' => Ok(Value::Float(l.powf(r))),
+            _ => Err(message!("Unknown numeric infix operator: {}", op)),
+        },
+    }
+}
+
+/// Like `arithmetic`, for operands that stay where they are stored (e.g., variables): Integer pairs
+/// are computed from references, so a large operand is never copied. Floats are cheap to copy.
+pub fn arithmetic_ref(op: char, a: &Value, b: &Value, division: IntDivision) -> Result<Value, String> {
+    match (a, b) {
+        (Value::Integer(l), Value::Integer(r)) => integer_arithmetic(op, l, r, division),
+        _ => arithmetic(op, coerce_pair(a.clone(), b.clone())?, division),
+    }
+}
+
+/// Updates an Integer in place (`x += y`, `x -= y`, `x *= y`), reusing its allocation where
+/// num-bigint can. Returns false, leaving `target` unchanged, for any other operator or types.
+pub fn update_in_place(op: char, target: &mut Value, operand: &BigInt) -> bool {
+    let Value::Integer(value) = target else { return false };
+    match op {
+        '+' => *value += operand,
+        '-' => *value -= operand,
+        '*' => *value *= operand,
+        _ => return false,
+    }
+    true
+}
+
+fn integer_arithmetic(op: char, l: &BigInt, r: &BigInt, division: IntDivision) -> Result<Value, String> {
+    let result = match op {
+        '+' => l + r,
+        '-' => l - r,
+        '*' => l * r,
+        '%' if r.is_zero() => return Err(message!("Modulo by zero")),
+        '%' if division == IntDivision::Trunc => l % r,
+        '%' => l.mod_floor(r),
+        '/' if r.is_zero() => return Err(message!("Division by zero")),
+        '/' => match division {
+            IntDivision::Trunc => l / r,
+            IntDivision::Floor => l.div_floor(r),
+            IntDivision::Promote => return Ok(Value::Float(to_float(l)? / to_float(r)?)),
+        },
+        '^' if r.is_zero() => BigInt::one(),
+        '^' if r.is_positive() => {
+            let exp = r.to_u32().ok_or_else(|| message!("Integer exponentiation only supports positive exponents up to u32 max"))?;
+            l.pow(exp)
+        }
+        '^' => return Err(message!("Integer exponentiation only supports positive exponents up to u32 max")),
+        _ => return Err(message!("Unknown numeric infix operator: {}", op)),
+    };
+    Ok(Value::Integer(result))
+}
+
+/// Quotient and remainder of a division, with `quotient * b + remainder == a`. The quotient is a
+/// whole number, rounded toward zero for Trunc and down otherwise; with a Float operand both are Floats.
+pub fn divmod(pair: NumericPair, division: IntDivision) -> Result<(Value, Value), String> {
+    let floor = division != IntDivision::Trunc;
+    match pair {
+        NumericPair::Integers(_, r) if r.is_zero() => Err(message!("Division by zero")),
+        NumericPair::Integers(l, r) => {
+            let (q, m) = if floor { l.div_mod_floor(&r) } else { l.div_rem(&r) };
+            Ok((Value::Integer(q), Value::Integer(m)))
+        }
+        NumericPair::Floats(_, r) if r.abs() < f64::EPSILON => Err(message!("Division by zero in float operation")),
+        NumericPair::Floats(l, r) => {
+            let mut m = l % r;
+            if floor && m != 0.0 && (m < 0.0) != (r < 0.0) {
+                m += r;
+            }
+            // Exact up to rounding, since l - m is a whole multiple of r
+            Ok((Value::Float(((l - m) / r).round()), Value::Float(m)))
+        }
+    }
+}
+
+/// Orders two numbers exactly, without rounding large Integers through f64.
+/// Returns None if either value is not a number, Some(None) if a NaN makes them unordered.
+pub fn compare(a: &Value, b: &Value) -> Option<Option<Ordering>> {
+    match (a, b) {
+        (Value::Integer(a), Value::Integer(b)) => Some(Some(a.cmp(b))),
+        (Value::Float(a), Value::Float(b)) => Some(a.partial_cmp(b)),
+        (Value::Integer(i), Value::Float(f)) => Some(compare_int_float(i, *f)),
+        (Value::Float(f), Value::Integer(i)) => Some(compare_int_float(i, *f).map(Ordering::reverse)),
+        _ => None,
+    }
+}
+
+/// Exact Integer/Float ordering. Converting the BigInt to f64 would round above 2^53,
+/// so the float is compared against the integer through its floor instead.
+fn compare_int_float(i: &BigInt, f: f64) -> Option<Ordering> {
+    if f.is_nan() {
+        return None;
+    }
+    if f.is_infinite() {
+        return Some(if f > 0.0 { Ordering::Less } else { Ordering::Greater });
+    }
+    let floor = BigInt::from_f64(f.floor())?;
+    Some(match i.cmp(&floor) {
+        Ordering::Equal if f.fract() == 0.0 => Ordering::Equal,
+        Ordering::Equal | Ordering::Less => Ordering::Less,
+        Ordering::Greater => Ordering::Greater,
+    })
+}
+
+/// How a value between two whole numbers is rounded: the seven modes of IEEE 754 / Python's
+/// decimal module. "Up" and "down" are away from and toward zero; "ceiling" and "floor" are
+/// toward positive and negative infinity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundingMode {
+    HalfEven,
+    HalfUp,
+    HalfDown,
+    Up,
+    Down,
+    Ceiling,
+    Floor,
+}
+
+impl FromStr for RoundingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "half_even" => Ok(RoundingMode::HalfEven),
+            "half_up" => Ok(RoundingMode::HalfUp),
+            "half_down" => Ok(RoundingMode::HalfDown),
+            "up" => Ok(RoundingMode::Up),
+            "down" => Ok(RoundingMode::Down),
+            "ceiling" => Ok(RoundingMode::Ceiling),
+            "floor" => Ok(RoundingMode::Floor),
+            _ => Err(message!(
+                "Unknown rounding mode '{}' (expected half_even, half_up, half_down, up, down, ceiling or floor)",
+                s
+            )),
+        }
+    }
+}
+
+/// A number as an exact fraction, numerator over a positive denominator. Every rounding builtin
+/// works on this form, so a number type only needs a conversion here to support all of them.
+pub fn to_fraction(value: &Value) -> Result<(BigInt, BigInt), String> {
+    match value {
+        Value::Integer(i) => Ok((i.clone(), BigInt::one())),
+        Value::Float(f) if !f.is_finite() => Err(message!("Cannot round {}", f)),
+        // A finite f64 is exactly mantissa * 2^exponent
+        Value::Float(f) => {
+            let bits = f.to_bits();
+            let biased = ((bits >> 52) & 0x7ff) as i64;
+            let fraction = bits & ((1 << 52) - 1);
+            let (mantissa, exponent) = if biased == 0 { (fraction, -1074) } else { (fraction | (1 << 52), biased - 1075) };
+            let mantissa = if f.is_sign_negative() { -BigInt::from(mantissa) } else { BigInt::from(mantissa) };
+            if exponent >= 0 {
+                Ok((mantissa << exponent as usize, BigInt::one()))
+            } else {
+                Ok((mantissa, BigInt::one() << (-exponent) as usize))
+            }
+        }
+        v => Err(message!("Expected a number, found {:?}", v)),
+    }
+}
+
+/// Rounds `num / den` to a whole number. `den` must not be zero.
+pub fn round_fraction(num: &BigInt, den: &BigInt, mode: RoundingMode) -> BigInt {
+    let (floor, rem) = num.div_mod_floor(den);
+    if rem.is_zero() {
+        return floor;
+    }
+    // The exact value lies strictly between floor and floor + 1
+    let negative = floor.is_negative();
+    let up = match mode {
+        RoundingMode::Floor => false,
+        RoundingMode::Ceiling => true,
+        RoundingMode::Down => negative,
+        RoundingMode::Up => !negative,
+        RoundingMode::HalfEven | RoundingMode::HalfUp | RoundingMode::HalfDown => match (rem.abs() * 2u32).cmp(&den.abs()) {
+            Ordering::Less => false,
+            Ordering::Greater => true,
+            Ordering::Equal => match mode {
+                RoundingMode::HalfEven => floor.is_odd(),
+                RoundingMode::HalfUp => !negative,
+                _ => negative,
+            },
+        },
+    };
+    if up { floor + 1 } else { floor }
+}
+
+/// Renders `units / 10^decimals` with exactly `decimals` digits after the point.
+pub fn fixed_point(units: &BigInt, decimals: usize) -> String {
+    let digits = units.abs().to_string();
+    let digits = format!("{:0>width$}", digits, width = decimals + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals);
+    let sign = if units.is_negative() { "-" } else { "" };
+    if decimals == 0 { format!("{}{}", sign, whole) } else { format!("{}{}.{}", sign, whole, fraction) }
+}
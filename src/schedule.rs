@@ -0,0 +1,127 @@
+//! Timing for 'astra schedule': a script registers jobs with `every("5m", "fn_name")`, and a
+//! `Schedule` decides which job runs next. Jobs are due as soon as the scheduler starts, then
+//! once per interval; a job that keeps failing waits longer between attempts.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// Consecutive failures double the wait, up to this long (or the interval, if that is longer)
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+/// Longest interval `parse_interval` accepts; far longer ones would overflow `Instant`.
+pub const MAX_INTERVAL: Duration = Duration::from_secs(366 * 24 * 60 * 60);
+
+/// A zero-argument function and how often to call it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduledJob {
+    pub function: String,
+    pub interval: Duration,
+}
+
+/// Parses an interval such as "30s", "5m", "1h30m" or "1d": whole numbers with a unit of s
+/// (seconds), m (minutes), h (hours) or d (days). The total must be at least one second and at
+/// most 366 days.
+pub fn parse_interval(text: &str) -> Result<Duration, String> {
+    let invalid = |reason: String| message!("Invalid interval \"{}\": {} (use e.g. \"30s\", \"5m\" or \"1h30m\")", text, reason);
+    let mut total = Duration::ZERO;
+    let mut rest = text.trim();
+    if rest.is_empty() {
+        return Err(invalid(message!("it is empty")));
+    }
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        if digits == 0 {
+            return Err(invalid(message!("expected a number")));
+        }
+        let count: u64 = rest[..digits].parse().map_err(|_| invalid(message!("the number is too large")))?;
+        let mut unit_chars = rest[digits..].chars();
+        let seconds = match unit_chars.next() {
+            Some('s') => 1,
+            Some('m') => 60,
+            Some('h') => 60 * 60,
+            Some('d') => 24 * 60 * 60,
+            Some(other) => return Err(invalid(message!("unknown unit '{}'", other))),
+            None => return Err(invalid(message!("a number needs a unit"))),
+        };
+        let part = count.checked_mul(seconds).ok_or_else(|| invalid(message!("the number is too large")))?;
+        total = total.saturating_add(Duration::from_secs(part));
+        rest = unit_chars.as_str();
+    }
+    if total.is_zero() {
+        return Err(invalid(message!("it must be at least one second")));
+    }
+    if total > MAX_INTERVAL {
+        return Err(invalid(message!("it must be at most {} days", MAX_INTERVAL.as_secs() / (24 * 60 * 60))));
+    }
+    Ok(total)
+}
+
+/// Writes a whole number of seconds in the units `parse_interval` reads: 5400s is "1h30m".
+pub struct Interval(pub Duration);
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut seconds = self.0.as_secs();
+        if seconds == 0 {
+            return write!(f, "0s");
+        }
+        for (unit, size) in [("d", 24 * 60 * 60), ("h", 60 * 60), ("m", 60), ("s", 1)] {
+            if seconds >= size {
+                write!(f, "{}{}", seconds / size, unit)?;
+                seconds %= size;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The wait after `failures` consecutive failed runs: the interval, doubled for every failure,
+/// but never more than an hour unless the interval itself is longer.
+pub fn backoff(interval: Duration, failures: u32) -> Duration {
+    let doubled = interval.saturating_mul(1 << failures.min(16));
+    doubled.min(MAX_BACKOFF.max(interval))
+}
+
+struct Entry {
+    job: ScheduledJob,
+    next_run: Instant,
+    failures: u32,
+}
+
+/// The registered jobs and when each one runs next.
+pub struct Schedule {
+    entries: Vec<Entry>,
+}
+
+impl Schedule {
+    /// Every job is due at `start`.
+    pub fn new(jobs: Vec<ScheduledJob>, start: Instant) -> Schedule {
+        Schedule { entries: jobs.into_iter().map(|job| Entry { job, next_run: start, failures: 0 }).collect() }
+    }
+
+    pub fn job(&self, index: usize) -> &ScheduledJob {
+        &self.entries[index].job
+    }
+
+    /// Consecutive failures of the job up to now.
+    pub fn failures(&self, index: usize) -> u32 {
+        self.entries[index].failures
+    }
+
+    /// The job that is due first and when; of jobs due at the same time, the one registered first.
+    pub fn next_due(&self) -> Option<(usize, Instant)> {
+        self.entries.iter().enumerate().min_by_key(|(_, entry)| entry.next_run).map(|(i, entry)| (i, entry.next_run))
+    }
+
+    /// Records the outcome of a run that began at `started` and returns when the job runs next.
+    /// Waits are measured from the start of a run, so a slow job keeps its rhythm; a run that
+    /// takes longer than the wait is followed by the next one right away.
+    pub fn record(&mut self, index: usize, succeeded: bool, started: Instant) -> Instant {
+        let entry = &mut self.entries[index];
+        entry.failures = if succeeded { 0 } else { entry.failures.saturating_add(1) };
+        // Jobs built by hand may have any interval; past what Instant can hold, wait the longest parse_interval allows
+        let wait = backoff(entry.job.interval, entry.failures);
+        entry.next_run = started.checked_add(wait).unwrap_or(started + MAX_INTERVAL);
+        entry.next_run
+    }
+}
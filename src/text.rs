@@ -0,0 +1,163 @@
+//! Unicode-aware text transforms behind the string builtins. Letters, digits and case come from
+//! the Unicode tables of `char`; normalization and combining marks from `unicode_normalization`.
+//! Edit distance is measured in characters, and also ranks the "did you mean" hints of errors.
+
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Characters that belong to a word. Combining marks count, so a decomposed "é" stays one word.
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || is_combining_mark(c)
+}
+
+/// Splits text into words for identifier-style case conversion: runs of letters and digits, also
+/// broken where a lowercase letter or digit is followed by an uppercase one and before the last
+/// capital of an acronym: "parseHTTPResponse2" gives "parse", "HTTP" and "Response2".
+fn words(text: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, &(offset, c)) in chars.iter().enumerate() {
+        if !is_word_char(c) {
+            if let Some(start) = start.take() {
+                words.push(&text[start..offset]);
+            }
+            continue;
+        }
+        if let Some(word_start) = start
+            && c.is_uppercase()
+        {
+            let previous = chars[i - 1].1;
+            let acronym_ends = chars.get(i + 1).is_some_and(|&(_, next)| next.is_lowercase());
+            if !previous.is_uppercase() || acronym_ends {
+                words.push(&text[word_start..offset]);
+                start = Some(offset);
+            }
+        }
+        start.get_or_insert(offset);
+    }
+    if let Some(start) = start {
+        words.push(&text[start..]);
+    }
+    words
+}
+
+/// The word with its first character uppercased and the rest lowercased.
+fn capitalized(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect(),
+        None => String::new(),
+    }
+}
+
+/// Uppercases the first letter of every word and lowercases the rest, keeping spacing and
+/// punctuation: "the CAT's hat" becomes "The Cat's Hat". An apostrophe between letters does
+/// not start a new word.
+pub fn title_case(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_word = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if is_word_char(c) {
+            if in_word {
+                out.extend(c.to_lowercase());
+            } else {
+                out.extend(c.to_uppercase());
+            }
+            in_word = true;
+        } else {
+            out.push(c);
+            let apostrophe = matches!(c, '\'' | '\u{2019}');
+            in_word = in_word && apostrophe && chars.peek().is_some_and(|&next| next.is_alphabetic());
+        }
+    }
+    out
+}
+
+/// Lowercase words joined by '_': "parseHTTPResponse" becomes "parse_http_response".
+pub fn snake_case(text: &str) -> String {
+    words(text).iter().map(|word| word.to_lowercase()).collect::<Vec<_>>().join("_")
+}
+
+/// Words joined without separators, each capitalized except the first, which is lowercase:
+/// "user_ID field" becomes "userIdField".
+pub fn camel_case(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for (i, word) in words(text).into_iter().enumerate() {
+        if i == 0 {
+            out.push_str(&word.to_lowercase());
+        } else {
+            out.push_str(&capitalized(word));
+        }
+    }
+    out
+}
+
+/// Canonical composition (NFC): "e" followed by a combining acute accent becomes "é".
+pub fn normalize_nfc(text: &str) -> String {
+    text.nfc().collect()
+}
+
+/// Canonical decomposition (NFD): "é" becomes "e" followed by a combining acute accent.
+pub fn normalize_nfd(text: &str) -> String {
+    text.nfd().collect()
+}
+
+/// Removes the combining marks of the decomposed text, then composes what is left, so text
+/// that only decomposes without marks (such as Hangul syllables) comes back unchanged.
+/// Letters that are distinct rather than accented, like "ø" or "ł", are kept.
+pub fn strip_accents(text: &str) -> String {
+    text.nfd().filter(|&c| !is_combining_mark(c)).nfc().collect()
+}
+
+/// Levenshtein distance in characters: the fewest single-character insertions, deletions and
+/// substitutions that turn `a` into `b`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // Distances from the prefix of `a` read so far to every prefix of `b`
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Edit distance scaled to a score from 0.0 (nothing in common) to 1.0 (equal): one minus the
+/// distance divided by the length of the longer string.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let longer = a.chars().count().max(b.chars().count());
+    if longer == 0 {
+        return 1.0;
+    }
+    1.0 - edit_distance(a, b) as f64 / longer as f64
+}
+
+/// The candidate with the smallest edit distance to `query`, and that distance. Ties go to
+/// the earliest candidate.
+pub fn closest<'a>(query: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<(&'a str, usize)> {
+    candidates
+        .into_iter()
+        .map(|candidate| (candidate, edit_distance(query, candidate)))
+        .min_by_key(|&(_, distance)| distance)
+}
+
+/// The name to suggest in a "did you mean" hint for the unknown `name`: the closest candidate,
+/// if it is at most a third of the name's length away (at least one edit). Ties go to the name
+/// that sorts first, so the hint doesn't depend on the order of the candidates.
+pub fn suggestion<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|&candidate| candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= limit)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
@@ -0,0 +1,112 @@
+//! gzip and zip builtins (the "archives" feature). Run with `cargo test --features archives`.
+#![cfg(feature = "archives")]
+
+mod common;
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use astra::{Interpreter, Options, Value};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use zip::write::SimpleFileOptions;
+
+use common::{eval_with as eval, TempDir};
+
+fn with_files() -> Options {
+    Options { allow_read: true, allow_write: true, ..Options::default() }
+}
+
+fn write_zip(path: &Path, entries: &[(&str, &str)]) {
+    let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+    for (name, contents) in entries {
+        zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+        zip.write_all(contents.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+#[test]
+fn gzip_round_trips_strings_and_bytes() {
+    let source = "packed = gzip_compress(\"log line\\n\")\nresult = [packed[0], packed[1], gzip_decompress(packed, \"text\")]\nresult\n";
+    let text = Value::String("log line\n".to_string());
+    assert_eq!(eval(source, Options::default()), Ok(Value::Array(vec![Value::Integer(0x1f.into()), Value::Integer(0x8b.into()), text])));
+
+    let bytes = Value::Array(vec![Value::Integer(0.into()), Value::Integer(255.into())]);
+    assert_eq!(eval("gzip_decompress(gzip_compress([0, 255]))", Options::default()), Ok(bytes));
+    assert!(eval("gzip_decompress(gzip_compress([255]), \"text\")", Options::default()).unwrap_err().contains("not UTF-8 text"));
+    assert!(eval("gzip_decompress([1, 2, 3])", Options::default()).unwrap_err().contains("could not decompress"));
+}
+
+#[test]
+fn gzip_decompress_stops_at_the_byte_limit() {
+    // A megabyte of zeros compresses to about a kilobyte
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&vec![0; 1 << 20]).unwrap();
+    let bomb = Value::Array(encoder.finish().unwrap().into_iter().map(|byte| Value::Integer(byte.into())).collect());
+    let decompress = |args: Vec<Value>| Interpreter::new(Options::default()).call_function("gzip_decompress", args);
+    let bytes = || Value::String("bytes".to_string());
+
+    let error = decompress(vec![bomb.clone(), bytes(), Value::Integer(1000.into())]).unwrap_err();
+    assert!(error.contains("more than the limit of 1000 bytes"), "{}", error);
+    let exact = decompress(vec![bomb.clone(), bytes(), Value::Integer((1 << 20).into())]).unwrap();
+    assert!(matches!(exact, Value::Array(items) if items.len() == 1 << 20));
+    let error = decompress(vec![bomb.clone(), bytes(), Value::Integer(((1 << 20) - 1).into())]).unwrap_err();
+    assert!(error.contains("more than the limit"), "{}", error);
+    // The default limit is far above this
+    assert!(decompress(vec![bomb.clone()]).is_ok());
+    assert!(decompress(vec![bomb, bytes(), Value::Integer((-1).into())]).unwrap_err().contains("must be a non-negative Integer"));
+}
+
+#[test]
+fn zip_files_are_listed_and_extracted_below_the_destination() {
+    let dir = TempDir::new("archives");
+    let archive = dir.join("logs.zip");
+    write_zip(&archive, &[("app.log", "started\n"), ("old/app.log", "stopped\n")]);
+
+    let source = format!("zip_list(\"{}\")", archive.display());
+    let names = Value::Array(vec![Value::String("app.log".to_string()), Value::String("old/app.log".to_string())]);
+    assert_eq!(eval(&source, with_files()), Ok(names));
+
+    let out = dir.join("out");
+    let source = format!("length(zip_extract(\"{}\", \"{}\"))", archive.display(), out.display());
+    assert_eq!(eval(&source, with_files()), Ok(Value::Integer(2.into())));
+    assert_eq!(fs::read_to_string(out.join("old/app.log")).unwrap(), "stopped\n");
+
+    // Reading and writing files each need the host's permission
+    let error = eval(&source, Options { allow_read: true, ..Options::default() }).unwrap_err();
+    assert!(error.contains("'zip_extract' writes to the file system"), "{}", error);
+    assert!(eval(&format!("zip_list(\"{}\")", archive.display()), Options::default()).unwrap_err().contains("--allow-read"));
+
+    // Entries before the bad name aren't written either
+    let evil = dir.join("evil.zip");
+    write_zip(&evil, &[("first.txt", "x"), ("../escaped.txt", "x")]);
+    let error = eval(&format!("zip_extract(\"{}\", \"{}\")", evil.display(), out.display()), with_files()).unwrap_err();
+    assert!(error.contains("the path leaves the destination directory"), "{}", error);
+    assert!(!dir.join("escaped.txt").exists());
+    assert!(!out.join("first.txt").exists());
+}
+
+#[test]
+fn zip_extract_refuses_archives_larger_than_the_limit() {
+    let dir = TempDir::new("archives_limit");
+    // A megabyte of zeros compresses to about a kilobyte
+    let bomb = dir.join("bomb.zip");
+    let zeros = "\0".repeat(1 << 20);
+    write_zip(&bomb, &[("small.txt", "x"), ("zeros.bin", &zeros)]);
+    assert!(fs::metadata(&bomb).unwrap().len() < 10_000);
+
+    let out = dir.join("out");
+    let extract = |limit: &str| eval(&format!("zip_extract(\"{}\", \"{}\"{})", bomb.display(), out.display(), limit), with_files());
+    let error = extract(", 100000").unwrap_err();
+    assert!(error.contains("its files take 1048577 bytes, more than the limit of 100000"), "{}", error);
+    assert!(!out.exists());
+
+    assert_eq!(extract(", 1048577").map(|files| matches!(files, Value::Array(files) if files.len() == 2)), Ok(true));
+    assert_eq!(fs::metadata(out.join("zeros.bin")).unwrap().len(), 1 << 20);
+    // The default limit is far above this archive
+    assert!(extract("").is_ok());
+    assert!(extract(", -1").unwrap_err().contains("must be a non-negative Integer"));
+    assert!(extract(", \"big\"").unwrap_err().contains("must be an Integer"));
+}
@@ -0,0 +1,23 @@
+//! The 'true' and 'false' literals, usable wherever an expression is.
+
+mod common;
+
+use astra::Value;
+
+use common::eval;
+
+#[test]
+fn literals_can_be_assigned_returned_and_tested() {
+    assert_eq!(eval("x = true\nx"), Ok(Value::Boolean(true)));
+    assert_eq!(eval("fn no() [\n    return false\n]\nno()"), Ok(Value::Boolean(false)));
+    assert_eq!(eval("result = 0\nif (true) [\n    result = 1\n]\nresult"), Ok(Value::Integer(1.into())));
+    assert_eq!(eval("result = 0\nif (false) [\n    result = 1\n] else [\n    result = 2\n]\nresult"), Ok(Value::Integer(2.into())));
+    assert_eq!(eval("!false and true"), Ok(Value::Boolean(true)));
+    assert_eq!(eval("[true, false == false]"), Ok(Value::Array(vec![Value::Boolean(true), Value::Boolean(true)])));
+}
+
+#[test]
+fn literals_are_keywords_not_variables() {
+    assert!(eval("true = 1").is_err());
+    assert_eq!(eval("truth = false\ntruth"), Ok(Value::Boolean(false)));
+}
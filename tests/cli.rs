@@ -0,0 +1,102 @@
+//! Command-line parsing: global flags work on either side of a subcommand, while the flags of
+//! 'astra <file>' only apply to running a script. Also the 'completions' and 'builtins' commands.
+
+mod common;
+
+use std::process::Output;
+
+use astra::builtins;
+
+use common::TempDir;
+
+fn astra(args: &[&str]) -> Output {
+    let dir = TempDir::new("cli");
+    dir.write("hello.as", "print(\"hello\")\n");
+    common::astra(&dir, args)
+}
+
+#[test]
+fn global_flags_are_accepted_before_or_after_the_subcommand() {
+    for args in [
+        &["--log", "off", "check", "hello.as"][..],
+        &["check", "hello.as", "--log", "off"],
+        &["--max-log-bytes", "4096", "--log", "warn", "check", "hello.as"],
+    ] {
+        let output = astra(args);
+        assert!(output.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "hello.as: OK\n");
+    }
+    for args in [&["--log", "off", "hello.as"][..], &["--log", "off", "run", "--contracts", "hello.as"], &["hello.as"]] {
+        let output = astra(args);
+        assert!(output.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "hello\n");
+    }
+}
+
+#[test]
+fn run_flags_before_another_subcommand_are_rejected() {
+    let output = astra(&["--contracts", "check", "hello.as"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("'--contracts' cannot be used before the 'check' subcommand"));
+}
+
+#[test]
+fn fmt_reports_the_comments_it_drops() {
+    let dir = TempDir::new("cli_fmt");
+    dir.write("commented.as", "; header\n; astra: max-steps 10\nx = 1 ; trailing\nprint(x)\n");
+    dir.write("plain.as", "x=1\n");
+    let fmt = |file: &str| common::astra(&dir, &["fmt", file]);
+
+    let output = fmt("commented.as");
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "; astra: max-steps 10\nx = 1\nprint(x)\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr, "commented.as:1: comment not carried over: ; header\ncommented.as:3: comment not carried over: ; trailing\n");
+
+    let output = fmt("plain.as");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "x = 1\n");
+}
+
+#[test]
+fn builtins_json_lists_every_registered_builtin() {
+    let output = astra(&["builtins", "--json"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let listed: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let listed = listed.as_array().unwrap();
+    let registry: Vec<_> = builtins().iter().collect();
+    assert_eq!(listed.len(), registry.len());
+    for (entry, builtin) in listed.iter().zip(registry) {
+        assert_eq!(
+            entry,
+            &serde_json::json!({
+                "name": builtin.name,
+                "signature": builtin.signature,
+                "category": builtin.category,
+                "description": builtin.description,
+                "pure": builtin.pure,
+            })
+        );
+    }
+
+    let output = astra(&["builtins"]);
+    let plain = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(plain.lines().count(), listed.len());
+    assert!(plain.lines().any(|line| line.starts_with("take ") && line.ends_with("Lazy sequence of the first n items.")), "{}", plain);
+}
+
+#[test]
+fn completions_cover_the_subcommands_and_flags() {
+    for shell in ["bash", "zsh", "fish", "elvish", "powershell"] {
+        let output = astra(&["completions", shell]);
+        assert!(output.status.success(), "{}: {}", shell, String::from_utf8_lossy(&output.stderr));
+        let script = String::from_utf8_lossy(&output.stdout);
+        for word in ["astra", "builtins", "completions", "fmt", "contracts", "no-loop-fusion"] {
+            assert!(script.contains(word), "{} completions lack {:?}", shell, word);
+        }
+    }
+
+    let output = astra(&["completions", "tcsh"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid value 'tcsh'"));
+}
@@ -0,0 +1,45 @@
+//! unique and group_by, which compare items by value rather than by how they print, and calls
+//! through variables holding function references.
+
+mod common;
+
+use astra::Value;
+
+use common::{eval, int, text};
+
+#[test]
+fn unique_keeps_the_first_of_each_strictly_equal_value() {
+    assert_eq!(
+        eval("unique([1, 1.0, \"1\", 1, [1, 2], [1, 2], [1.0, 2], 0.0, -0.0])"),
+        Ok(Value::Array(vec![
+            int(1),
+            Value::Float(1.0),
+            text("1"),
+            Value::Array(vec![int(1), int(2)]),
+            Value::Array(vec![Value::Float(1.0), int(2)]),
+            Value::Float(0.0),
+        ]))
+    );
+}
+
+#[test]
+fn group_by_groups_on_key_values_in_order_of_first_appearance() {
+    let source = "fn key(x) [\n    return x[0]\n]\ngroup_by(key, [[1, \"a\"], [1.0, \"b\"], [\"1\", \"c\"], [1, \"d\"]])\n";
+    let item = |key: Value, tag: &str| Value::Array(vec![key, text(tag)]);
+    let group = |key: Value, items: Vec<Value>| Value::Array(vec![key, Value::Array(items)]);
+    assert_eq!(
+        eval(source),
+        Ok(Value::Array(vec![
+            group(int(1), vec![item(int(1), "a"), item(int(1), "d")]),
+            group(Value::Float(1.0), vec![item(Value::Float(1.0), "b")]),
+            group(text("1"), vec![item(text("1"), "c")]),
+        ]))
+    );
+}
+
+#[test]
+fn calls_through_function_references_stop_at_self_references_and_cycles() {
+    assert_eq!(eval("fn g() [ return 1 ]\ng = g\nf = g\ng = f\ng() + f()"), Ok(int(2)));
+    let error = eval("fn x() [ return 1 ]\nfn y() [ return 2 ]\nfn swap(x, y) [ return x() ]\nswap(y, x)").unwrap_err();
+    assert!(error.contains("'x' refers to a function through more than 16 variables"), "{}", error);
+}
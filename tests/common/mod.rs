@@ -0,0 +1,80 @@
+//! Helpers shared by the integration tests, included in each with `mod common;`. Not every test
+//! file uses every helper.
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use astra::{Interpreter, Options, Value};
+use num_bigint::BigInt;
+
+pub fn eval(source: &str) -> Result<Value, String> {
+    eval_with(source, Options::default())
+}
+
+pub fn eval_with(source: &str, options: Options) -> Result<Value, String> {
+    Interpreter::new(options).run_source(source)
+}
+
+pub fn int(n: i64) -> Value {
+    Value::Integer(BigInt::from(n))
+}
+
+pub fn ints(values: &[i64]) -> Value {
+    Value::Array(values.iter().map(|n| int(*n)).collect())
+}
+
+pub fn text(s: &str) -> Value {
+    Value::String(s.to_string())
+}
+
+/// Printing appends to ./runlog, even while output is captured, so tests that print in-process
+/// run from the system temp directory rather than the repository.
+pub fn leave_the_repository() {
+    env::set_current_dir(env::temp_dir()).unwrap();
+}
+
+/// A fresh directory under the system temp directory, removed again when dropped. The name
+/// includes the process id and a counter, so concurrent test runs never share one.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let count = COUNT.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("astra_{}_test_{}_{}", name, process::id(), count));
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    /// Writes a file into the directory, creating its parent directories, and returns its path.
+    pub fn write(&self, file: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.0.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Runs the astra binary inside `dir`, so its runlog lands there rather than in the repository.
+pub fn astra(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_astra")).args(args).current_dir(dir).output().unwrap()
+}
@@ -0,0 +1,59 @@
+//! Function contracts: 'requires' and 'ensures' clauses are checked on every call with
+//! --contracts (Options::contracts) and ignored without it.
+
+mod common;
+
+use astra::{Interpreter, Options, Value};
+
+use common::{astra, int, TempDir};
+
+const HALVE: &str = "fn halve(n) requires (n >= 0) requires (n % 2 == 0) ensures (result * 2 == n) [\n    return n / 2\n]\n";
+
+const BROKEN: &str = "fn broken(n) ensures (result > n) [\n    return n - 1\n]\n";
+
+fn eval(source: &str, contracts: bool) -> Result<Value, String> {
+    Interpreter::new(Options { contracts, ..Options::default() }).run_source(source)
+}
+
+#[test]
+fn contracts_are_checked_when_enabled() {
+    assert_eq!(eval(&format!("{}halve(8)", HALVE), true), Ok(int(4)));
+
+    let error = eval(&format!("{}halve(-2)", HALVE), true).unwrap_err();
+    assert!(error.contains("Precondition failed for 'halve': requires (n >= 0)"), "{}", error);
+    // Every clause is checked, not just the first
+    let error = eval(&format!("{}halve(3)", HALVE), true).unwrap_err();
+    assert!(error.contains("Precondition failed for 'halve': requires (n % 2 == 0)"), "{}", error);
+
+    let error = eval(&format!("{}broken(5)", BROKEN), true).unwrap_err();
+    assert!(error.contains("Postcondition failed for 'broken': ensures (result > n)"), "{}", error);
+}
+
+#[test]
+fn contracts_are_ignored_when_disabled() {
+    assert_eq!(eval(&format!("{}halve(-2)", HALVE), false), Ok(int(-1)));
+    assert_eq!(eval(&format!("{}broken(5)", BROKEN), false), Ok(int(4)));
+    // Not even evaluated, so a non-Boolean condition goes unnoticed
+    assert_eq!(eval("fn f(n) requires (n + 1) [\n    return n\n]\nf(2)", false), Ok(int(2)));
+}
+
+#[test]
+fn contract_conditions_must_be_booleans() {
+    let error = eval("fn f(n) requires (n + 1) [\n    return n\n]\nf(2)", true).unwrap_err();
+    assert!(error.contains("Contract condition 'n + 1' of 'f' must be a Boolean, found Integer(3)"), "{}", error);
+}
+
+#[test]
+fn the_contracts_flag_switches_checking_on_from_the_command_line() {
+    let dir = TempDir::new("contracts");
+    dir.write("halve.as", format!("{}print(halve(-2))\n", HALVE));
+    let run = |args: &[&str]| astra(&dir, args);
+
+    let output = run(&["--log", "off", "halve.as"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "-1\n");
+
+    let output = run(&["--log", "off", "--contracts", "halve.as"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Precondition failed for 'halve'"));
+}
@@ -0,0 +1,40 @@
+//! inspect and hexdump print their reports; run them through the binary to see the output.
+
+mod common;
+
+use common::{astra, TempDir};
+
+fn run(name: &str, script: &str) -> String {
+    let dir = TempDir::new("debugging");
+    let path = dir.write(name, script);
+    let output = astra(&dir, &[path.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn inspect_describes_structure_and_returns_its_argument() {
+    let script = "fn sq(x) [ x * x ]\nx = inspect([2^100, 1.5, \"héllo\", [true, sq], range(3)])\nprint(length(x))\n";
+    let expected = "\
+Array, 5 items
+  [0] Integer, 101 bits: 1267650600228229401496703205376
+  [1] Float: 1.5
+  [2] String, 5 chars, 6 bytes: \"héllo\"
+  [3] Array, 2 items
+    [0] Boolean: true
+    [1] Function: sq(x), user-defined
+  [4] Sequence (lazy): range(0, 3)
+5
+";
+    assert_eq!(run("inspect.as", script), expected);
+}
+
+#[test]
+fn hexdump_prints_offset_hex_and_ascii_columns() {
+    let expected = "\
+00000000  68 65 6c 6c 6f 2c 20 77  6f 72 6c 64 0a 09 74 68  |hello, world..th|
+00000010  69 73                                             |is|
+00000000  00 ff 41                                          |..A|
+";
+    assert_eq!(run("hexdump.as", "hexdump(\"hello, world\\n\\tthis\")\nhexdump([0, 255, 65])\n"), expected);
+}
@@ -0,0 +1,60 @@
+//! '@memoize' and '@timed', which wrap every call, and user decorators, which are called once
+//! with the function and may replace it.
+
+mod common;
+
+use astra::{Interpreter, Options, Value};
+
+use common::leave_the_repository;
+
+fn interpreter(source: &str) -> Interpreter {
+    leave_the_repository();
+    let mut interpreter = Interpreter::new(Options::default());
+    interpreter.capture_output();
+    interpreter.run_source(source).unwrap();
+    interpreter
+}
+
+#[test]
+fn memoized_functions_run_once_per_distinct_arguments() {
+    let mut interpreter = interpreter("@memoize\nfn double(x) [\n    print(\"computing {}\", x)\n    return x + x\n]");
+    let results = interpreter.run_source("[double(2), double(2), double(2.0), double([1]), double([1]), double(2)]").unwrap();
+    let int = |n: i64| Value::Integer(n.into());
+    assert_eq!(results, Value::Array(vec![int(4), int(4), Value::Float(4.0), Value::Array(vec![int(1), int(1)]), Value::Array(vec![int(1), int(1)]), int(4)]));
+    // 2 and 2.0 are different arguments, though they print alike
+    assert_eq!(interpreter.take_output(), ["computing 2", "computing 2", "computing [1]"]);
+}
+
+#[test]
+fn the_memo_cache_is_bounded() {
+    let mut interpreter = interpreter("@memoize\nfn id(x) [\n    print(\"computing {}\", x)\n    return x\n]");
+    interpreter.run_source("for (i in range(10001)) [\n    id(i)\n]").unwrap();
+    assert_eq!(interpreter.take_output().len(), 10001);
+    // The oldest result made room for the newest; the newest is still cached
+    interpreter.run_source("id(10000)\nid(0)").unwrap();
+    assert_eq!(interpreter.take_output(), ["computing 0"]);
+}
+
+#[test]
+fn timed_functions_are_in_the_timing_report() {
+    let mut interpreter = interpreter("@timed\nfn work(n) [ return sum(range(n)) ]");
+    assert_eq!(interpreter.run_source("work(10) + work(20)"), Ok(Value::Integer(235.into())));
+    let report = interpreter.timing_report();
+    assert_eq!(report.len(), 1, "{:?}", report);
+    assert!(report[0].starts_with("work: 2 run(s), total "), "{:?}", report);
+}
+
+#[test]
+fn user_decorators_run_once_and_may_replace_the_function() {
+    let source = "fn announce(f) [\n    print(\"defined {}\", f)\n    return f\n]\nfn shout() [ return \"HI\" ]\nfn replace(f) [ return shout ]\n\
+                  @announce\nfn greet() [ return \"hi\" ]\n@replace\nfn wave() [ return \"bye\" ]";
+    let mut interpreter = interpreter(source);
+    assert_eq!(interpreter.take_output().len(), 1);
+    let results = interpreter.run_source("[greet(), greet(), wave()]").unwrap();
+    let text = |s: &str| Value::String(s.to_string());
+    assert_eq!(results, Value::Array(vec![text("hi"), text("hi"), text("HI")]));
+    assert_eq!(interpreter.take_output(), Vec::<String>::new());
+
+    let error = Interpreter::new(Options::default()).run_source("@missing\nfn f() [ return 1 ]").unwrap_err();
+    assert!(error.contains("missing"), "{}", error);
+}
@@ -0,0 +1,81 @@
+//! '; astra:' comment directives: parsed into a per-file table that the linter and the limits consult.
+
+mod common;
+
+use astra::{implicit_return_warnings, Directives, Interpreter, Options, Parser};
+
+use common::{astra, TempDir};
+
+fn directives(source: &str) -> Result<Directives, String> {
+    let mut parser = Parser::new(source);
+    parser.parse()?;
+    parser.directives()
+}
+
+#[test]
+fn directive_comments_are_collected() {
+    let source = "; astra: allow(implicit-return)\nx = 1 ; astra: max-steps=1e6, max-output-bytes = 4096\n; plain comment: astra\n";
+    assert_eq!(
+        directives(source),
+        Ok(Directives {
+            allow: vec!["implicit-return".to_string()],
+            max_steps: Some(1_000_000),
+            max_output_bytes: Some(4096),
+            warnings: Vec::new(),
+        })
+    );
+    assert_eq!(directives("x = 1\n"), Ok(Directives::default()));
+
+    let errors = [
+        ("; astra: max-steps=1.5", "Invalid directive on line 1: 'max-steps' expects a whole number, found '1.5'"),
+        ("; astra: speed=11", "Invalid directive on line 1: unknown setting 'speed' (known settings: max-steps, max-output-bytes)"),
+        ("; astra: fast", "Invalid directive on line 1: expected 'allow(...)' or 'name=value', found 'fast'"),
+    ];
+    for (source, expected) in errors {
+        assert_eq!(directives(source), Err(expected.to_string()), "{}", source);
+    }
+}
+
+#[test]
+fn limits_apply_unless_the_options_set_them() {
+    let source = "; astra: max-steps=50\ntotal = 0\nfor (i in range(100)) [ total += i ]\ntotal";
+    let directives = directives(source).unwrap();
+
+    let mut options = Options::default();
+    directives.apply_to(&mut options);
+    assert_eq!(options.max_steps, Some(50));
+    let error = Interpreter::new(options).run_source(source).unwrap_err();
+    assert!(error.contains("Step limit of 50 statements exceeded"), "{}", error);
+
+    let mut options = Options { max_steps: Some(1000), ..Options::default() };
+    directives.apply_to(&mut options);
+    assert_eq!(Interpreter::new(options).run_source(source).map(|v| v.to_string()), Ok("4950".to_string()));
+}
+
+#[test]
+fn allow_silences_the_linter() {
+    let source = "fn f(x) [ x ]\n";
+    let statements = Parser::new(source).parse().unwrap();
+    assert_eq!(implicit_return_warnings(&statements, &Directives::default()).len(), 1);
+    let allowed = directives(&format!("; astra: allow(implicit-return)\n{}", source)).unwrap();
+    assert!(implicit_return_warnings(&statements, &allowed).is_empty());
+}
+
+#[test]
+fn unknown_lints_are_warnings_not_errors() {
+    let parsed = directives("x = 1\n; astra: allow(unused, implicit-return), max-steps=10").unwrap();
+    assert_eq!(parsed.allow, ["implicit-return"]);
+    assert_eq!(parsed.max_steps, Some(10));
+    assert_eq!(parsed.warnings, ["unknown lint 'unused' in the directive on line 2 (known lints: implicit-return)"]);
+    assert!(directives("; astra: allow(implicit-return)").unwrap().warnings.is_empty());
+
+    let dir = TempDir::new("directives");
+    dir.write("unused.as", "; astra: allow(unused)\nprint(\"ran\")\n");
+    let output = astra(&dir, &["--log", "off", "unused.as"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ran\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Warning: unknown lint 'unused' in the directive on line 1 (known lints: implicit-return)\n"
+    );
+}
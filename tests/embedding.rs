@@ -0,0 +1,176 @@
+//! Host-facing interpreter API: reloading definitions, cancelling evaluations, per-call bindings,
+//! compiled expressions, secret masking, and running scripts the same way as the binary.
+
+use std::collections::HashMap;
+mod common;
+
+use std::fs;
+use std::thread;
+use std::time::Duration;
+
+use astra::{Interpreter, Options, PreparedRuntime, Value};
+
+use common::{astra, leave_the_repository, TempDir};
+
+#[test]
+fn redefinition_keeps_variables_and_is_all_or_nothing() {
+    let mut interpreter = Interpreter::new(Options::default());
+    interpreter.run_source("speed = 3\nfn tick(x) [ return x + 3 ]").unwrap();
+    assert_eq!(interpreter.call_function("tick", vec![Value::Integer(1.into())]), Ok(Value::Integer(4.into())));
+
+    let redefined = interpreter.redefine_from_source("speed = 100\nfn tick(x) [ return x * 3 ]");
+    assert_eq!(redefined, Ok(vec!["tick".to_string()]));
+    assert_eq!(interpreter.call_function("tick", vec![Value::Integer(2.into())]), Ok(Value::Integer(6.into())));
+    // The assignment in the reloaded source is ignored
+    assert_eq!(interpreter.run_source("speed"), Ok(Value::Integer(3.into())));
+
+    // An unknown decorator on the second definition leaves the first one unchanged too
+    let failed = interpreter.redefine_from_source("fn tick(x) [ return 0 ]\n@missing\nfn other() [ return 1 ]");
+    assert!(failed.is_err());
+    assert_eq!(interpreter.call_function("tick", vec![Value::Integer(2.into())]), Ok(Value::Integer(6.into())));
+    assert!(interpreter.call_function("other", vec![]).is_err());
+}
+
+#[test]
+fn cancel_handle_stops_endless_loops() {
+    let mut interpreter = Interpreter::new(Options::default());
+    let handle = interpreter.cancel_handle();
+    // The first loop runs on the fused integer path, the second on the general one
+    for source in ["x = 0\nfor (i in range(10^15)) [ x = x + i ]", "for (i in range(10^15)) [ s = \"busy\" ]"] {
+        let canceller = handle.clone();
+        let timer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            canceller.cancel();
+        });
+        let result = interpreter.run_source(source);
+        timer.join().unwrap();
+        assert!(result.is_err_and(|e| e.ends_with("Cancelled")));
+        handle.reset();
+    }
+    assert_eq!(interpreter.run_source("1 + 1"), Ok(Value::Integer(2.into())));
+}
+
+#[test]
+fn call_bindings_are_read_only_and_scoped_to_the_call() {
+    let mut interpreter = Interpreter::new(Options::default());
+    interpreter
+        .run_source("fn greet(greeting) [ return greeting + \", \" + user ]\nfn rename() [ user = \"mallory\" ]\nfn shadow(user) [ return user ]")
+        .unwrap();
+    let bindings = HashMap::from([("user".to_string(), Value::String("alice".to_string()))]);
+
+    let greeting = interpreter.call_with_env("greet", vec![Value::String("hi".to_string())], bindings.clone());
+    assert_eq!(greeting, Ok(Value::String("hi, alice".to_string())));
+    assert!(interpreter.call_with_env("rename", vec![], bindings.clone()).is_err_and(|e| e.contains("read-only")));
+    let shadowed = interpreter.call_with_env("shadow", vec![Value::Integer(1.into())], bindings);
+    assert_eq!(shadowed, Ok(Value::Integer(1.into())));
+
+    assert!(interpreter.call_function("greet", vec![Value::String("hi".to_string())]).is_err());
+    assert!(interpreter.run_source("user").is_err());
+}
+
+#[test]
+fn memoized_functions_see_the_bindings_of_each_call() {
+    let mut interpreter = Interpreter::new(Options::default());
+    interpreter.run_source("@memoize\nfn who() [ return user ]").unwrap();
+    for user in ["alice", "bob", "alice"] {
+        let bindings = HashMap::from([("user".to_string(), Value::String(user.to_string()))]);
+        assert_eq!(interpreter.call_with_env("who", vec![], bindings), Ok(Value::String(user.to_string())));
+    }
+}
+
+#[test]
+fn compiled_expressions_are_shared_between_threads() {
+    let mut interpreter = Interpreter::new(Options::default());
+    interpreter.run_source("fn tax(amount) [ return amount / 10 ]").unwrap();
+    let compiled = interpreter.compile_expr("price * qty + tax(price)").unwrap();
+    assert!(interpreter.compile_expr("a = 1\nb").is_err());
+
+    thread::scope(|scope| {
+        for qty in 1..4 {
+            let compiled = &compiled;
+            scope.spawn(move || {
+                let row = HashMap::from([
+                    ("price".to_string(), Value::Integer(20.into())),
+                    ("qty".to_string(), Value::Integer(qty.into())),
+                ]);
+                assert_eq!(compiled.eval(&row), Ok(Value::Integer((20 * qty + 2).into())));
+            });
+        }
+    });
+}
+
+#[test]
+fn secrets_are_masked_in_errors() {
+    let mut interpreter = Interpreter::new(Options::default());
+    interpreter.mark_secret(&Value::String("hunter2".to_string())).unwrap();
+    interpreter.run_source("fn check(password) [ return length(\"user:\" + password) ]").unwrap();
+
+    let error = interpreter.call_function("check", vec![Value::String("hunter2".to_string())]).unwrap_err();
+    assert!(error.contains("user:***") && !error.contains("hunter2"), "{}", error);
+    let error = interpreter.run_source("key = mark_secret(\"k-12345\")\nlength(key)").unwrap_err();
+    assert!(error.contains("***") && !error.contains("k-12345"), "{}", error);
+    assert_eq!(interpreter.redact("key k-12345, password hunter2"), "key ***, password ***");
+}
+
+#[test]
+fn hexdumps_of_secrets_are_masked_in_the_runlog() {
+    let dir = TempDir::new("embedding_hexdump");
+    dir.write("dump.as", "tok = mark_secret(\"hunter2secret\")\nhexdump(\"Bearer \" + tok)\nhexdump(\"plain\")\n");
+    let output = astra(&dir, &["--log", "off", "dump.as"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let runlog = fs::read_to_string(dir.join("runlog")).unwrap();
+    assert!(runlog.contains("Block Output: ***\n"), "{}", runlog);
+    assert!(!runlog.contains("68 75 6e 74") && !runlog.contains("hunter2"), "{}", runlog);
+    assert!(runlog.contains("70 6c 61 69 6e"), "dumps without secrets are logged: {}", runlog);
+}
+
+#[test]
+fn values_computed_from_secrets_are_masked() {
+    let mut interpreter = Interpreter::new(Options::default());
+    interpreter.run_source("tok = mark_secret(\"abcdefgh\")\ndistance = edit_distance(tok, \"abc\")").unwrap();
+    let error = interpreter.run_source("name = title_case(tok)\nname + 1").unwrap_err();
+    assert!(error.contains("***") && !error.contains("Abcdefgh"), "{}", error);
+    assert_eq!(interpreter.redact("Abcdefgh"), "***");
+    // Short results, like the distance, don't mask unrelated text
+    assert_eq!(interpreter.redact("distance 5"), "distance 5");
+}
+
+#[test]
+fn short_secrets_are_rejected() {
+    let mut interpreter = Interpreter::new(Options::default());
+    let error = interpreter.run_source("x = mark_secret(1)").unwrap_err();
+    assert!(error.contains("at least 6 characters"), "{}", error);
+    assert!(interpreter.mark_secret(&Value::String("pw".to_string())).is_err());
+    assert!(interpreter.mark_secret(&Value::Array(vec![Value::String("hunter2".to_string()), Value::String("x".to_string())])).is_err());
+    assert_eq!(interpreter.redact("1 x pw"), "1 x pw");
+}
+
+#[test]
+fn binary_and_library_run_scripts_the_same_way() {
+    let scripts = [
+        ("ok.as", "fn sq(x) [\n    return x * x\n]\nprint(\"{} {}\", sq(12), 2^70)\nfor (i in range(3)) [\n    print(i * 1.5)\n]\n"),
+        ("fails.as", "print(\"before\")\nx = 1 / 0\nprint(\"after\")\n"),
+    ];
+    let dir = TempDir::new("embedding");
+    // Printing appends to the runlog in the current directory, for the binary and the library alike
+    leave_the_repository();
+    let snapshot = PreparedRuntime::new("", Options::default()).unwrap();
+    for (name, source) in scripts {
+        dir.write(name, source);
+        let output = astra(&dir, &["run", name]);
+
+        let mut interpreter = Interpreter::from_snapshot(&snapshot);
+        interpreter.capture_output();
+        let result = interpreter.run_source(source);
+        let printed: String = interpreter.take_output().iter().map(|line| format!("{}\n", line)).collect();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), printed, "{}", name);
+        match result {
+            Ok(_) => assert!(output.status.success(), "{}: {}", name, String::from_utf8_lossy(&output.stderr)),
+            Err(e) => {
+                assert_eq!(output.status.code(), Some(1), "{}", name);
+                assert!(String::from_utf8_lossy(&output.stderr).contains(&e), "{}: expected {:?} on stderr", name, e);
+            }
+        }
+    }
+}
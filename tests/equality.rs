@@ -0,0 +1,56 @@
+//! The equality matrix: '==' / '!=' coerce between Integer and Float only, '===' / '!==' never
+//! coerce, and equals / strict_equals agree with the operators. Every row is checked both ways.
+
+mod common;
+
+use std::collections::HashMap;
+
+use astra::{Interpreter, Options, Value};
+use num_bigint::BigInt;
+
+use common::int;
+
+/// The results of a == b, a != b, a === b, a !== b, equals(a, b) and strict_equals(a, b).
+fn compare(interpreter: &Interpreter, a: &Value, b: &Value) -> Vec<Value> {
+    let bindings = HashMap::from([("a".to_string(), a.clone()), ("b".to_string(), b.clone())]);
+    ["a == b", "a != b", "a === b", "a !== b", "equals(a, b)", "strict_equals(a, b)"]
+        .iter()
+        .map(|source| interpreter.compile_expr(source).unwrap().eval(&bindings).unwrap())
+        .collect()
+}
+
+#[test]
+fn equality_matrix_is_symmetric_and_only_coerces_numbers() {
+    let interpreter = Interpreter::new(Options::default());
+    let text = |s: &str| Value::String(s.to_string());
+    let values = [
+        int(1),
+        Value::Float(1.0),
+        Value::Float(1.5),
+        text("1"),
+        Value::Boolean(true),
+        Value::Array(vec![int(1)]),
+        Value::Array(vec![Value::Float(1.0)]),
+        Value::Void,
+    ];
+    // (i, j): equal with '==' / strict equal with '===', for the pairs that are equal at all
+    let loose = [(0, 1), (5, 6)];
+    for (i, a) in values.iter().enumerate() {
+        for (j, b) in values.iter().enumerate() {
+            let equal = i == j || loose.contains(&(i, j)) || loose.contains(&(j, i));
+            let strict = i == j;
+            let expected: Vec<Value> = [equal, !equal, strict, !strict, equal, strict].into_iter().map(Value::Boolean).collect();
+            assert_eq!(compare(&interpreter, a, b), expected, "{:?} vs {:?}", a, b);
+        }
+    }
+}
+
+#[test]
+fn integer_float_equality_is_exact() {
+    let interpreter = Interpreter::new(Options::default());
+    // 2^53 + 1 rounds to 2^53 as an f64 but is a different number
+    let big = Value::Integer(BigInt::from(2u64.pow(53)) + 1);
+    assert_eq!(compare(&interpreter, &big, &Value::Float(2f64.powi(53)))[0], Value::Boolean(false));
+    let nan = Value::Float(f64::NAN);
+    assert_eq!(compare(&interpreter, &nan, &nan)[..4], [false, true, false, true].map(Value::Boolean));
+}
@@ -0,0 +1,50 @@
+//! Print format strings: '{}' takes the next argument, '{expr}' evaluates in the current scope,
+//! '{{' and '}}' are literal braces, and errors point inside the format string.
+
+mod common;
+
+use astra::{Interpreter, Options, Parser, ScriptFlow};
+
+use common::leave_the_repository;
+
+/// Runs `source` and returns what its last print statement wrote.
+fn printed(source: &str) -> Result<String, String> {
+    leave_the_repository();
+    let mut interpreter = Interpreter::new(Options::default());
+    let mut output = String::new();
+    for statement in Parser::new(source).parse()? {
+        if let ScriptFlow::Continue(text, None) = interpreter.run_statement(&statement)? {
+            output = text;
+        }
+    }
+    Ok(output)
+}
+
+#[test]
+fn placeholders_evaluate_expressions() {
+    let source = "x = 41\nitems = [\"a\", \"b\"]\nprint(\"{x + 1} items: {items[0]}, {}, {length(items)}\", 7)";
+    assert_eq!(printed(source), Ok("42 items: a, 7, 2".to_string()));
+    assert_eq!(printed("print(\"{{x}} {\\\"}\\\"} }}\")"), Ok("{x} } }".to_string()));
+    // Placeholders see the function's scope, not the globals
+    assert!(printed("fn f(n) [ print(\"{n * n}\") ]\nf(3)").is_ok());
+    assert!(printed("n = 2\nfn f() [ print(\"{n}\") ]\nf()").is_err());
+    // Positional placeholders without an argument are printed as written
+    assert_eq!(printed("print(\"{} {}\", 1)"), Ok("1 {}".to_string()));
+}
+
+#[test]
+fn errors_point_inside_the_format_string() {
+    let cases = [
+        ("print(\"total {x + } here\")", "Format string error at character 12 in placeholder '{x + }': Bad token in prefix: Eof (Expected expression start or operator)"),
+        ("print(\"{x y}\")", "Format string error at character 4 in placeholder '{x y}': Unexpected Ident(\"y\") after the expression"),
+        ("print(\"a } b\")", "Format string error at character 3: unmatched '}' (write '}}' for a literal brace)"),
+        ("print(\"a {b\")", "Format string error at character 3: unclosed '{' (write '{{' for a literal brace)"),
+    ];
+    for (source, expected) in cases {
+        assert_eq!(Parser::new(source).parse(), Err(expected.to_string()), "{}", source);
+    }
+    assert_eq!(
+        printed("print(\"total: {y}\")"),
+        Err("In placeholder '{y}' at character 8 of the format string: Cannot evaluate uninitialized variable: y".to_string())
+    );
+}
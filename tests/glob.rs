@@ -0,0 +1,39 @@
+//! glob lists files and needs --allow-read; glob_match only compares a pattern with a path.
+
+mod common;
+
+use astra::{Interpreter, Options, Value};
+
+use common::{astra, TempDir};
+
+#[test]
+fn glob_lists_matching_paths_only_when_reading_is_allowed() {
+    let dir = TempDir::new("glob");
+    for file in ["logs/x.txt", "logs/a/y.txt", "logs/b/z.log", "logs/.hidden.txt"] {
+        dir.write(file, "");
+    }
+    dir.write("list.as", "print(glob(\"logs/**/*.txt\"))\nprint(glob(\"logs/*\"))\n");
+
+    // Relative patterns resolve in the directory the script runs in
+    let run = |args: &[&str]| astra(&dir, args);
+    let output = run(&["run", "list.as", "--allow-read"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "[logs/a/y.txt, logs/x.txt]\n[logs/a, logs/b, logs/x.txt]\n");
+
+    let output = run(&["list.as"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("'glob' reads the file system, which this script is not allowed to do (run with --allow-read)"), "{}", stderr);
+}
+
+#[test]
+fn glob_match_compares_without_touching_the_file_system() {
+    let matches = |pattern: &str, path: &str| {
+        let source = format!("glob_match(\"{}\", \"{}\")", pattern, path);
+        Interpreter::new(Options::default()).run_source(&source)
+    };
+    assert_eq!(matches("logs/**/*.txt", "logs/2024/01/app.txt"), Ok(Value::Boolean(true)));
+    assert_eq!(matches("logs/*.txt", "logs/2024/app.txt"), Ok(Value::Boolean(false)));
+    assert_eq!(matches("*.txt", ".hidden.txt"), Ok(Value::Boolean(false)));
+    assert_eq!(matches("data_[0-9]?.csv", "data_42.csv"), Ok(Value::Boolean(true)));
+    assert!(matches("[bad", "x").unwrap_err().contains("Invalid pattern '[bad' for 'glob_match'"));
+}
@@ -0,0 +1,79 @@
+//! 'help()' and 'help("name")', and the builtin registry they are generated from: every builtin
+//! has a signature, a category and a description.
+
+mod common;
+
+use astra::{Interpreter, Options, builtins};
+
+use common::leave_the_repository;
+
+fn help_output(source: &str) -> Result<Vec<String>, String> {
+    leave_the_repository();
+    let mut interpreter = Interpreter::new(Options::default());
+    interpreter.capture_output();
+    interpreter.run_source(source)?;
+    Ok(interpreter.take_output())
+}
+
+#[test]
+fn help_with_a_name_shows_the_signature_and_description() {
+    assert_eq!(help_output("help(\"take\")"), Ok(vec!["take(sequence, n)\n  Lazy sequence of the first n items.".to_string()]));
+    // A function reference works as well as its name
+    assert_eq!(help_output("help(length)"), Ok(vec!["length(array)\n  Number of elements in an array.".to_string()]));
+    assert_eq!(
+        help_output("fn mine(a, b) [\n    return a\n]\nhelp(mine)"),
+        Ok(vec!["mine(a, b)\n  User-defined function.".to_string()])
+    );
+}
+
+#[test]
+fn help_rejects_unknown_names_and_non_names() {
+    let error = help_output("help(\"nope\")").unwrap_err();
+    assert!(error.contains("No help available for 'nope': it is not a builtin or defined function"), "{}", error);
+    let error = help_output("help(5)").unwrap_err();
+    assert!(error.contains("Argument to 'help' must be a function name, found Integer(5)"), "{}", error);
+    let error = help_output("help(\"take\", \"drop\")").unwrap_err();
+    assert!(error.contains("'help' expects 0 or 1 arguments, found 2"), "{}", error);
+}
+
+#[test]
+fn help_without_arguments_lists_every_builtin_by_category() {
+    let output = help_output("help()").unwrap();
+    assert_eq!(output.len(), 1);
+    let mut lines = output[0].lines();
+    assert_eq!(lines.next(), Some("Builtin functions by category (help(\"name\") for details):"));
+    let listed: Vec<&str> = lines.collect();
+
+    let registry = builtins();
+    assert_eq!(listed.len(), registry.categories().len());
+    for (line, category) in listed.iter().zip(registry.categories()) {
+        let names = line.strip_prefix(&format!("  {}: ", category)).unwrap_or_else(|| panic!("{:?} is not {}", line, category));
+        let expected: Vec<&str> = registry.iter().filter(|b| b.category == category).map(|b| b.name).collect();
+        assert_eq!(names.split(", ").collect::<Vec<_>>(), expected);
+    }
+    assert!(listed.contains(&"  sequences: range, take, drop, step, enumerate, map, sum"), "{:?}", listed);
+}
+
+#[test]
+fn every_builtin_is_documented() {
+    let registry = builtins();
+    assert!(registry.iter().count() > 0);
+    for builtin in registry.iter() {
+        assert_eq!(registry.get(builtin.name).map(|b| b.name), Some(builtin.name));
+        for alternative in builtin.signature.split(" / ") {
+            assert!(alternative.starts_with(&format!("{}(", builtin.name)) && alternative.ends_with(')'), "{}: {}", builtin.name, builtin.signature);
+        }
+        assert!(registry.categories().contains(&builtin.category), "{}", builtin.name);
+        assert!(!builtin.description.is_empty() && builtin.description.ends_with('.'), "{}: {:?}", builtin.name, builtin.description);
+    }
+    assert!(registry.get("nope").is_none());
+}
+
+#[test]
+fn only_side_effect_free_builtins_are_pure() {
+    let registry = builtins();
+    assert!(registry.get("edit_distance").is_some_and(|b| b.pure));
+    for name in ["help", "mark_secret", "hexdump", "every", "assert", "map"] {
+        assert!(registry.get(name).is_some_and(|b| !b.pure), "{}", name);
+    }
+}
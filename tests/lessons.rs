@@ -0,0 +1,40 @@
+//! The lessons of 'astra learn', and the output capture and assert they are checked with.
+
+mod common;
+
+use astra::lessons::LESSONS;
+use astra::{Interpreter, Options, Value};
+
+use common::leave_the_repository;
+
+#[test]
+fn every_lesson_accepts_its_solution_and_rejects_doing_nothing() {
+    leave_the_repository();
+    for lesson in LESSONS {
+        let mut interpreter = lesson.start().unwrap();
+        assert!(lesson.check(&interpreter, &[]).is_err(), "lesson '{}' passes without an attempt", lesson.title);
+        interpreter.run_source(lesson.solution).unwrap();
+        let output = interpreter.take_output();
+        assert_eq!(lesson.check(&interpreter, &output), Ok(()), "solution of lesson '{}'", lesson.title);
+    }
+}
+
+#[test]
+fn captured_output_is_held_for_the_host() {
+    leave_the_repository();
+    let mut interpreter = Interpreter::new(Options::default());
+    assert_eq!(interpreter.take_output(), Vec::<String>::new());
+    interpreter.capture_output();
+    interpreter.run_source("print(\"a\")\nfor (x in [1, 2]) [\n    print(\"{} squared is {}\", x, x * x)\n]\n").unwrap();
+    assert_eq!(interpreter.take_output(), ["a", "1 squared is 1", "2 squared is 4"]);
+    assert_eq!(interpreter.take_output(), Vec::<String>::new());
+}
+
+#[test]
+fn assert_fails_with_its_message_unless_the_condition_holds() {
+    let run = |source: &str| Interpreter::new(Options::default()).run_source(source);
+    assert_eq!(run("assert(1 < 2, \"math works\")"), Ok(Value::Void));
+    assert!(run("assert(1 > 2)").unwrap_err().ends_with("Assertion failed"));
+    assert!(run("x = 3\nassert(x == 4, \"x should be 4\")").unwrap_err().ends_with("Assertion failed: x should be 4"));
+    assert!(run("assert(1)").unwrap_err().contains("must be a Boolean"));
+}
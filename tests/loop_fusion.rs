@@ -0,0 +1,52 @@
+//! The fused integer 'for' loop gives the same results and errors as the general path, including
+//! where it hands the rest of the loop back to the general path.
+
+use astra::{IntDivision, Interpreter, Options, Value};
+use num_bigint::BigInt;
+
+/// Runs `source` with and without loop fusion and checks that both agree.
+fn run_both(source: &str, options: Options) -> Result<Value, String> {
+    let fused = Interpreter::new(options.clone()).run_source(source);
+    let general = Interpreter::new(Options { loop_fusion: false, ..options }).run_source(source);
+    assert_eq!(fused, general, "{}", source);
+    fused
+}
+
+#[test]
+fn loops_overflowing_i64_resume_on_the_general_path() {
+    let source = "total = 9223372036854775000\nfor (i in range(0, 100)) [\n    total += i\n    total = total * 1\n]\ntotal";
+    let expected = BigInt::from(9223372036854775000i64) + BigInt::from(99 * 100 / 2);
+    assert_eq!(run_both(source, Options::default()), Ok(Value::Integer(expected)));
+
+    let source = "x = 1\nfor (i in range(0, 80)) [\n    x = x * 2\n]\nx";
+    assert_eq!(run_both(source, Options::default()), Ok(Value::Integer(BigInt::from(2).pow(80))));
+}
+
+#[test]
+fn remainder_by_zero_fails_the_same_way() {
+    let source = "total = 0\nfor (i in range(0, 5)) [\n    total += 10 % (3 - i)\n]\ntotal";
+    let error = run_both(source, Options::default()).unwrap_err();
+    assert!(error.contains("zero"), "{}", error);
+
+    // The iterations before the failing one still ran: 10 % 3 + 10 % 2 + 10 % 1
+    let source = "total = 0\nfor (i in range(0, 5)) [\n    total += 10 % (3 - i)\n]";
+    for loop_fusion in [true, false] {
+        let mut interpreter = Interpreter::new(Options { loop_fusion, ..Options::default() });
+        assert!(interpreter.run_source(source).is_err());
+        assert_eq!(interpreter.run_source("total"), Ok(Value::Integer(1.into())));
+    }
+}
+
+#[test]
+fn floor_division_rounds_down_on_both_paths() {
+    // The '%' loop alone would qualify for fusion, which only knows truncating remainders
+    let source = "q = 0\nr = 0\nfor (i in range(-7, 8)) [\n    q += i / 2\n]\nfor (i in range(-7, 8)) [\n    r += i % 3\n]\n[q, r]";
+    let (mut q, mut r) = (0i64, 0i64);
+    for i in -7i64..8 {
+        q += i.div_euclid(2);
+        r += i.rem_euclid(3);
+    }
+    let expected = Value::Array(vec![Value::Integer(q.into()), Value::Integer(r.into())]);
+    assert_eq!(run_both(source, Options { int_division: IntDivision::Floor, ..Options::default() }), Ok(expected));
+    assert_ne!(run_both(source, Options::default()), run_both(source, Options { int_division: IntDivision::Floor, ..Options::default() }));
+}
@@ -0,0 +1,61 @@
+//! Macros are expanded while parsing: each use is replaced by the macro's quoted template, with
+//! the arguments' syntax in place of '$param' and the template's own variables renamed.
+
+mod common;
+
+use astra::{format_program, BlockStyle, Parser, Value};
+
+use common::{eval, int};
+
+const UNLESS: &str = "macro unless(cond, body) [ quote [ if (not $cond) [ $body ] ] ]\n";
+
+#[test]
+fn macro_uses_run_their_template_with_the_arguments_in_place() {
+    // The last argument can be given as a block after the list
+    let source = format!("{}total = 0\nfor (x in [3, 12, 7]) [\n    unless (x > 10) [ total += x ]\n]\ntotal\n", UNLESS);
+    assert_eq!(eval(&source), Ok(int(10)));
+
+    // 'return' in an expansion returns from the function using the macro
+    let source = format!("{}fn sign(n) [\n    unless (n >= 0) [ return -1 ]\n    return 1\n]\nresult = [sign(0 - 4), sign(4)]\nresult\n", UNLESS);
+    assert_eq!(eval(&source), Ok(Value::Array(vec![int(-1), int(1)])));
+
+    // A template holding one expression can be used inside expressions; arguments keep their grouping
+    let source = "macro square(x) [ quote [ $x * $x ] ]\nsquare(1 + 2) + 1\n";
+    assert_eq!(eval(source), Ok(int(10)));
+}
+
+#[test]
+fn variables_introduced_by_a_template_do_not_clash_with_the_callers() {
+    let source = "macro swap(a, b) [\n    quote [\n        tmp = $a\n        $a = $b\n        $b = tmp\n    ]\n]\n\
+                  tmp = \"mine\"\nx = 1\ny = 2\nswap(x, y)\nswap(tmp, x)\nresult = [x, y, tmp]\nresult\n";
+    assert_eq!(eval(source), Ok(Value::Array(vec![Value::String("mine".to_string()), int(1), int(2)])));
+
+    // Loop variables are renamed too, unless the caller names them
+    let source = "macro twice(body) [ quote [ for (i in range(2)) [ $body ] ] ]\n\
+                  macro count(var, n, body) [ quote [ for ($var in range($n)) [ $body ] ] ]\n\
+                  i = 10\nn = 0\ntwice() [ n += i ]\ncount(k, 3) [ n += k ]\nresult = [n, i, k]\nresult\n";
+    assert_eq!(eval(source), Ok(Value::Array(vec![int(23), int(10), int(2)])));
+}
+
+#[test]
+fn formatting_keeps_macro_definitions_and_uses_as_written() {
+    let source = format!("{}n = 0\nunless(n > 0) [ n = 1 ]\n", UNLESS);
+    let statements = Parser::new(&source).parse().unwrap();
+    let formatted = format_program(&statements, BlockStyle::Brackets);
+    assert_eq!(
+        formatted,
+        "macro unless(cond, body) [\n    quote [\n        if (!$cond) [\n            $body\n        ]\n    ]\n]\nn = 0\nunless(n > 0) [\n    n = 1\n]\n"
+    );
+    assert_eq!(Parser::new(&formatted).parse(), Ok(statements));
+}
+
+#[test]
+fn misused_placeholders_and_arguments_are_reported() {
+    let error = |source: &str| Parser::new(source).parse().unwrap_err();
+    assert!(error("print($x)\n").contains("'$x' is only allowed in a macro template"));
+    assert!(error("macro m(a) [ quote [ $b ] ]\n").contains("'$b' does not name a parameter"));
+    assert!(error("macro m(a) [ a + 1 ]\n").contains("must be a single quoted template"));
+    assert!(error(&format!("{}unless(true)\n", UNLESS)).contains("Macro 'unless' expects 2 arguments (cond, body), found 1"));
+    assert!(error(&format!("{}x = unless(a, b)\n", UNLESS)).contains("can only be used as a statement"));
+    assert!(error("macro m(a, b) [ quote [ $b + 1 ] ]\ny = m(1) [ print(2) ]\n").contains("'$b' can only stand alone as a statement"));
+}
@@ -0,0 +1,105 @@
+//! The send_mail builtin (the "mail" feature), against a minimal SMTP server on localhost.
+//! Run with `cargo test --features mail`.
+#![cfg(feature = "mail")]
+
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::thread;
+
+use astra::{Options, Value};
+
+use common::eval_with as eval;
+
+fn with_net() -> Options {
+    Options { allow_net: true, ..Options::default() }
+}
+
+/// Accepts one SMTP session and returns every line the client sent.
+fn smtp_server() -> (u16, thread::JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let handle = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        let mut lines = Vec::new();
+        let mut in_data = false;
+        writer.write_all(b"220 localhost ready\r\n").unwrap();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap() == 0 {
+                break;
+            }
+            let line = line.trim_end().to_string();
+            let reply: &[u8] = if in_data {
+                in_data = line != ".";
+                if in_data { b"" } else { b"250 queued\r\n" }
+            } else if line.starts_with("DATA") {
+                in_data = true;
+                b"354 go ahead\r\n"
+            } else if line.starts_with("QUIT") {
+                writer.write_all(b"221 bye\r\n").unwrap();
+                lines.push(line);
+                break;
+            } else {
+                b"250 ok\r\n"
+            };
+            writer.write_all(reply).unwrap();
+            lines.push(line);
+        }
+        lines
+    });
+    (port, handle)
+}
+
+#[test]
+fn send_mail_delivers_a_plain_text_message() {
+    let (port, server) = smtp_server();
+    let source = format!(
+        "smtp = [[\"host\", \"127.0.0.1\"], [\"port\", {}], [\"security\", \"none\"], [\"from\", \"Builds <ci@example.com>\"]]\n\
+         send_mail(smtp, [\"ops@example.com\", \"dev@example.com\"], \"Nightly build\", \"All 42 jobs passed.\")\n",
+        port
+    );
+    assert_eq!(eval(&source, with_net()), Ok(Value::Void));
+    let lines = server.join().unwrap();
+    assert!(lines.contains(&"MAIL FROM:<ci@example.com>".to_string()), "{:?}", lines);
+    assert!(lines.contains(&"RCPT TO:<ops@example.com>".to_string()), "{:?}", lines);
+    assert!(lines.contains(&"RCPT TO:<dev@example.com>".to_string()), "{:?}", lines);
+    assert!(lines.contains(&"Subject: Nightly build".to_string()), "{:?}", lines);
+    assert!(lines.contains(&"All 42 jobs passed.".to_string()), "{:?}", lines);
+}
+
+#[test]
+fn send_mail_needs_allow_net_and_valid_settings() {
+    let call = |config: &str| format!("send_mail([{}], \"ops@example.com\", \"s\", \"b\")", config);
+    let valid = "[\"host\", \"localhost\"], [\"from\", \"ci@example.com\"]";
+    assert!(eval(&call(valid), Options::default()).unwrap_err().contains("run with --allow-net"));
+
+    let error = |config: &str| eval(&call(config), with_net()).unwrap_err();
+    assert!(error("[\"from\", \"ci@example.com\"]").contains("needs the \"host\""));
+    assert!(error(&format!("{}, [\"usrname\", \"ci\"]", valid)).contains("did you mean \"username\"?"));
+    assert!(error(&format!("{}, [\"port\", 70000]", valid)).contains("between 0 and 65535"));
+    assert!(error(&format!("{}, [\"username\", \"ci\"]", valid)).contains("both \"username\" and \"password\""));
+    assert!(error("[\"host\", \"localhost\"], [\"from\", \"not an address\"]").contains("cannot send to or from 'not an address'"));
+}
+
+#[test]
+fn send_mail_errors_do_not_show_the_settings() {
+    let smtp = "smtp = [[\"host\", \"localhost\"], [\"from\", \"ci@example.com\"], [\"username\", \"ci\"], [\"password\", \"hunter2-secret\"]]\n";
+    let error = |call: &str| {
+        let error = eval(&format!("{}{}", smtp, call), with_net()).unwrap_err();
+        assert!(!error.contains("hunter2"), "{}", error);
+        error
+    };
+    assert!(error("send_mail(smtp, \"ops@example.com\", 1, \"b\")").contains("expects a String subject as its third argument, found an Integer"));
+    assert!(error("send_mail(smtp, \"ops@example.com\", \"s\", [1])").contains("expects a String body as its fourth argument, found an Array"));
+    assert!(error("send_mail(\"smtp\", \"ops@example.com\", \"s\", smtp)").contains("as its first argument, found a String"));
+
+    let error = |config: &str| eval(&format!("send_mail([{}], \"ops@example.com\", \"s\", \"b\")", config), with_net()).unwrap_err();
+    let message = error("[\"password\", \"hunter2-secret\", \"extra\"]");
+    assert!(message.contains("must be [key, value] pairs, found an Array of 3 items") && !message.contains("hunter2"), "{}", message);
+    let message = error("[\"password\", 20240601]");
+    assert!(message.contains("setting \"password\" must be a String, found an Integer") && !message.contains("20240601"), "{}", message);
+}
@@ -0,0 +1,34 @@
+//! XML and HTML builtins (the "markup" feature). Run with `cargo test --features markup`.
+#![cfg(feature = "markup")]
+
+mod common;
+
+use astra::Value;
+
+use common::{eval, text};
+
+fn strings(items: &[&str]) -> Result<Value, String> {
+    Ok(Value::Array(items.iter().map(|s| text(s)).collect()))
+}
+
+#[test]
+fn xml_parse_builds_name_attributes_children_nodes() {
+    let source = "doc = xml_parse(\"<feed xmlns:a='urn:a'>\n  <!-- skipped -->\n  <a:entry id='1' lang='en'>Hi <b>there</b></a:entry>\n</feed>\")\ndoc\n";
+    let entry = Value::Array(vec![
+        text("entry"),
+        Value::Array(vec![Value::Array(vec![text("id"), text("1")]), Value::Array(vec![text("lang"), text("en")])]),
+        Value::Array(vec![text("Hi "), Value::Array(vec![text("b"), Value::Array(vec![]), Value::Array(vec![text("there")])])]),
+    ]);
+    assert_eq!(eval(source), Ok(Value::Array(vec![text("feed"), Value::Array(vec![]), Value::Array(vec![entry])])));
+    assert!(eval("xml_parse(\"<a><b></a>\")").unwrap_err().contains("could not parse the XML"));
+}
+
+#[test]
+fn html_select_returns_text_or_attributes_of_matches() {
+    let page = "page = \"<ul id='nav'><li><a href='/'>Home</a></li><li><a href='/docs' class='x'> Docs </a></li><li>Plain</ul>\"\n";
+    assert_eq!(eval(&format!("{}html_select(page, \"#nav li\")", page)), strings(&["Home", "Docs", "Plain"]));
+    assert_eq!(eval(&format!("{}html_select(page, \"a\", \"href\")", page)), strings(&["/", "/docs"]));
+    assert_eq!(eval(&format!("{}html_select(page, \"li a\", \"class\")", page)), strings(&["x"]));
+    assert_eq!(eval(&format!("{}html_select(page, \"table\")", page)), strings(&[]));
+    assert!(eval(&format!("{}html_select(page, \"li[\")", page)).unwrap_err().contains("Invalid CSS selector"));
+}
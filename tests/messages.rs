@@ -0,0 +1,43 @@
+//! Message catalogs: error text is looked up by its English template and refilled with the same arguments.
+
+use std::sync::Arc;
+
+use astra::{Catalog, Interpreter, Options};
+
+#[test]
+fn errors_use_the_installed_catalog() {
+    let catalog = Catalog::new()
+        .with("Cannot evaluate uninitialized variable: {}", "Variable {0} nicht initialisiert")
+        .with("Runtime Error (Statement {}): {}", "Laufzeitfehler (Anweisung {0}): {1}");
+    let mut interpreter = Interpreter::new(Options::default());
+    interpreter.set_messages(Arc::new(catalog));
+    assert_eq!(
+        interpreter.run_source("x = 1\nmissing"),
+        Err("Laufzeitfehler (Anweisung 2): Variable missing nicht initialisiert".to_string())
+    );
+
+    let untranslated = Interpreter::new(Options::default()).run_source("missing");
+    assert_eq!(untranslated, Err("Runtime Error (Statement 1): Cannot evaluate uninitialized variable: missing".to_string()));
+}
+
+#[test]
+fn arguments_containing_the_template_text_are_kept_whole() {
+    let catalog = Catalog::new()
+        .with("Runtime Error (Statement {}): {}", "Laufzeitfehler (Anweisung {0}): {1}")
+        .with("Invalid interval \"{}\": {} (use e.g. \"30s\", \"5m\" or \"1h30m\")", "Ungültiges Intervall \"{0}\": {1}")
+        .with("unknown unit '{}'", "unbekannte Einheit '{0}'")
+        .with("Argument to '{}' must be an Array, found {:?}", "'{0}' braucht ein Array, nicht {1}");
+    let mut interpreter = Interpreter::new(Options::default());
+    interpreter.set_messages(Arc::new(catalog));
+    // The interval itself contains '": ', the text that follows the first placeholder, and a '{1}'
+    let result = interpreter.run_source("fn job() [ return 1 ]\nevery(\"5q\\\": (use {1}\", \"job\")");
+    assert_eq!(
+        result,
+        Err("Laufzeitfehler (Anweisung 2): Ungültiges Intervall \"5q\": (use {1}\": unbekannte Einheit 'q'".to_string())
+    );
+    // Debug-formatted arguments are passed on as the English message shows them
+    assert_eq!(
+        interpreter.run_source("unique(\"must be an Array, found\")"),
+        Err("Laufzeitfehler (Anweisung 1): 'unique' braucht ein Array, nicht String(\"must be an Array, found\")".to_string())
+    );
+}
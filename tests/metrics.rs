@@ -0,0 +1,34 @@
+//! Interpreter counters exposed to embedding hosts.
+
+use astra::{Interpreter, Options, Value};
+
+#[test]
+fn counters_accumulate_across_calls() {
+    let mut interpreter = Interpreter::new(Options::default());
+    interpreter.run_source("fn twice(x) [ return x * 2 ]\nnames = [\"a\", \"b\"]\ntwice(3)").unwrap();
+    assert!(interpreter.run_source("missing").is_err());
+    assert!(interpreter.run_source("fn (").is_err());
+    assert_eq!(interpreter.call_function("length", vec![Value::Array(vec![])]), Ok(Value::Integer(0.into())));
+
+    let metrics = interpreter.metrics();
+    // 3 top-level statements + the return inside 'twice' + the failing 'missing'
+    assert_eq!(metrics.statements_executed, 5);
+    assert_eq!(metrics.functions_called, 2);
+    assert_eq!(metrics.errors, 2);
+    // "a", "b", the array literal and the value of the assignment; arguments passed in by the host don't count
+    assert_eq!(metrics.allocations_estimate, 4);
+}
+
+#[test]
+fn fused_range_loops_count_every_body_statement() {
+    let source = "total = 0\nfor (i in range(0, 1000)) [\n    total = total + i\n    total = total % 7919\n]\ntotal";
+    let run = |options: Options| {
+        let mut interpreter = Interpreter::new(options);
+        let result = interpreter.run_source(source).unwrap();
+        (result, interpreter.metrics().statements_executed)
+    };
+    let fused = run(Options::default());
+    let general = run(Options { loop_fusion: false, ..Options::default() });
+    assert_eq!(fused, general);
+    assert_eq!(fused.1, 3 + 2 * 1000);
+}
@@ -0,0 +1,86 @@
+//! Legacy syntax read by Parser::parse_legacy and rewritten by 'astra migrate'.
+
+mod common;
+
+use std::fs;
+
+use astra::{format_program, BlockStyle, Interpreter, Options, Parser, Value};
+
+use common::{astra, TempDir};
+
+fn migrate(source: &str) -> (String, Vec<String>) {
+    let mut parser = Parser::new(source);
+    let statements = parser.parse_legacy();
+    assert_eq!(parser.errors(), &[], "{}", source);
+    (format_program(&statements, BlockStyle::Brackets), parser.migrations().iter().map(|(_, note)| note.clone()).collect())
+}
+
+#[test]
+fn legacy_scripts_are_rewritten_in_current_syntax() {
+    let legacy = "def inside(x, lo, hi) \"return (lo < x) and (x < hi)\"\nsame = false == (true and false)\n";
+    let (migrated, notes) = migrate(legacy);
+    assert_eq!(migrated, "fn inside(x, lo, hi) [\n    return lo < x and x < hi\n]\nsame = false == (true and false)\n");
+    assert_eq!(notes, ["'def' is now 'fn'", "The string body of 'inside' is now a block"]);
+    let result = Interpreter::new(Options::default()).run_source(&format!("{}result = [inside(5, 1, 10), same]\nresult", migrated));
+    assert_eq!(result, Ok(Value::Array(vec![Value::Boolean(true), Value::Boolean(true)])));
+
+    assert!(Parser::new("def f() [ 1 ]").parse().unwrap_err().contains("deprecated"), "only parse_legacy accepts 'def'");
+}
+
+#[test]
+fn comparisons_next_to_and_or_keep_their_meaning_and_are_flagged() {
+    let (migrated, notes) = migrate("def inside(x) \"return 0 < x and x < 9\"\nsame = false == true or false\n");
+    assert_eq!(migrated, "fn inside(x) [\n    return 0 < x and x < 9\n]\nsame = false == true or false\n");
+    let check = |op: &str| {
+        format!("check: comparisons next to '{}' are read as in '(a < b) {} c'; scripts from before comparisons bound tighter than 'and' / 'or' may have meant otherwise", op, op)
+    };
+    assert_eq!(notes, ["'def' is now 'fn'".to_string(), "The string body of 'inside' is now a block".to_string(), check("and"), check("or")]);
+    let result = Interpreter::new(Options::default()).run_source(&format!("{}result = [inside(5), inside(10), same]\nresult", migrated));
+    assert_eq!(result, Ok(Value::Array(vec![Value::Boolean(true), Value::Boolean(false), Value::Boolean(false)])));
+
+    // Parenthesized comparisons aren't ambiguous
+    assert_eq!(migrate("def f(x) \"return (0 < x) and (x < 9)\"").1.len(), 2);
+}
+
+#[test]
+fn migrate_writes_the_file_and_reports_what_it_left_alone() {
+    let dir = TempDir::new("migrate");
+    dir.write("old.as", "; astra: max-steps 1000\ndef twice(x) \"return x * 2\"\ndef broken(x) \"return (x\"\nprint(twice(4))\n");
+    let migrate_file = || astra(&dir, &["migrate", "old.as", "--write"]);
+    let output = migrate_file();
+    assert!(!output.status.success(), "a statement was not translated");
+    let migrated = fs::read_to_string(dir.join("old.as")).unwrap();
+    assert_eq!(migrated, "; astra: max-steps 1000\nfn twice(x) [\n    return x * 2\n]\ndef broken(x) \"return (x\"\nprint(twice(4))\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("old.as:2: 'def' is now 'fn'\n"), "{}", stderr);
+    assert!(stderr.contains("old.as:3: not translated, kept as it was: The string body of 'broken' could not be translated"), "{}", stderr);
+
+    // Once migrated, a script is valid as it is and left alone
+    dir.write("old.as", "def inside(x) \"return 0 < x and x < 9\"\n");
+    for _ in 0..2 {
+        assert!(migrate_file().status.success());
+    }
+    assert_eq!(fs::read_to_string(dir.join("old.as")).unwrap(), "fn inside(x) [\n    return 0 < x and x < 9\n]\n");
+    assert!(String::from_utf8_lossy(&migrate_file().stderr).contains("already valid in the current syntax"));
+}
+
+#[test]
+fn migrate_does_not_write_scripts_whose_comments_would_be_lost() {
+    let dir = TempDir::new("migrate_comments");
+    let legacy = "; old helpers\ndef twice(x) \"return x * 2\" ; doubles\nprint(twice(4))\n";
+    dir.write("old.as", legacy);
+    let migrate_file = |args: &[&str]| astra(&dir, &[&["migrate"], args].concat());
+
+    let output = migrate_file(&["old.as", "--write"]);
+    assert!(!output.status.success());
+    assert_eq!(fs::read_to_string(dir.join("old.as")).unwrap(), legacy);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("old.as:1: comment not carried over: ; old helpers\n"), "{}", stderr);
+    assert!(stderr.contains("old.as:2: comment not carried over: ; doubles\n"), "{}", stderr);
+    assert!(stderr.contains("old.as: not written, since its comments would be lost"), "{}", stderr);
+
+    // Without --write the translation is printed for copying the comments over by hand
+    let output = migrate_file(&["old.as"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "fn twice(x) [\n    return x * 2\n]\nprint(twice(4))\n");
+}
@@ -0,0 +1,59 @@
+//! The nesting limit: deep or long syntax fails to parse with a clean error instead of
+//! overflowing the stack, and everything up to the limit parses and runs.
+
+use std::thread;
+
+use astra::{Interpreter, Options, Parser, Value};
+
+const LIMIT_ERROR: &str = "nested more than 256 levels deep";
+
+/// Runs `test` with the stack of a main thread; a debug build needs more than the 2 MiB of a test
+/// thread to evaluate 256 levels.
+fn on_main_thread_stack(test: impl FnOnce() + Send + 'static) {
+    thread::Builder::new().stack_size(8 << 20).spawn(test).unwrap().join().unwrap();
+}
+
+fn parse(source: &str) -> Result<(), String> {
+    Parser::new(source).parse().map(drop)
+}
+
+fn chain(terms: usize) -> String {
+    vec!["1"; terms].join(" + ")
+}
+
+#[test]
+fn each_operator_of_a_flat_chain_counts_as_a_level() {
+    on_main_thread_stack(|| {
+        let longest = chain(256);
+        assert_eq!(Interpreter::new(Options::default()).run_source(&longest), Ok(Value::Integer(256.into())));
+        assert!(parse(&chain(257)).unwrap_err().contains(LIMIT_ERROR));
+        // The assignment is a level of its own
+        assert!(parse(&format!("x = {}", chain(255))).is_ok());
+        assert!(parse(&format!("x = {}", chain(256))).unwrap_err().contains(LIMIT_ERROR));
+        // Splitting the chain across statements has no limit
+        let steps: String = (0..4).map(|_| format!("total = total + {}\n", chain(200))).collect();
+        assert_eq!(Interpreter::new(Options::default()).run_source(&format!("total = 0\n{}total", steps)), Ok(Value::Integer(800.into())));
+    });
+}
+
+#[test]
+fn parentheses_brackets_and_blocks_count_one_level_each() {
+    on_main_thread_stack(|| {
+        let parens = |n: usize| format!("{}1{}", "(".repeat(n), ")".repeat(n));
+        let arrays = |n: usize| format!("{}1{}", "[".repeat(n), "]".repeat(n));
+        let blocks = |n: usize| format!("{}x = 1\n{}", "if (true) [\n".repeat(n), "]\n".repeat(n));
+        assert_eq!(Interpreter::new(Options::default()).run_source(&parens(255)), Ok(Value::Integer(1.into())));
+        assert!(parse(&parens(256)).unwrap_err().contains(LIMIT_ERROR));
+        assert!(parse(&arrays(255)).is_ok());
+        assert!(parse(&arrays(256)).unwrap_err().contains(LIMIT_ERROR));
+        assert!(Interpreter::new(Options::default()).run_source(&blocks(254)).is_ok());
+        assert!(parse(&blocks(255)).unwrap_err().contains(LIMIT_ERROR));
+    });
+}
+
+#[test]
+fn adversarial_nesting_fails_cleanly() {
+    let deep = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+    assert!(parse(&deep).unwrap_err().contains(LIMIT_ERROR));
+    assert!(parse(&chain(100_000)).unwrap_err().contains(LIMIT_ERROR));
+}
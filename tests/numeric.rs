@@ -0,0 +1,173 @@
+//! The numeric tower: every combination of Integer, Float and non-number operands, so operators
+//! and builtins agree on promotion.
+
+mod common;
+
+use std::cmp::Ordering;
+
+use astra::numeric::{arithmetic, coerce_pair, compare, round_fraction, NumericPair};
+use astra::{IntDivision, Interpreter, Options, RoundingMode, Value};
+use num_bigint::BigInt;
+
+use common::{eval, int};
+
+#[test]
+fn coerce_pair_promotes_to_float_only_when_a_float_is_involved() {
+    assert_eq!(coerce_pair(int(2), int(3)), Ok(NumericPair::Integers(BigInt::from(2), BigInt::from(3))));
+    assert_eq!(coerce_pair(int(2), Value::Float(0.5)), Ok(NumericPair::Floats(2.0, 0.5)));
+    assert_eq!(coerce_pair(Value::Float(0.5), int(2)), Ok(NumericPair::Floats(0.5, 2.0)));
+    assert_eq!(coerce_pair(Value::Float(0.5), Value::Float(1.5)), Ok(NumericPair::Floats(0.5, 1.5)));
+
+    let others = [Value::String("1".into()), Value::Boolean(true), Value::Array(vec![int(1)]), Value::Void];
+    for other in others {
+        for number in [int(1), Value::Float(1.0)] {
+            assert!(coerce_pair(number.clone(), other.clone()).is_err(), "{:?} with {:?}", number, other);
+            assert!(coerce_pair(other.clone(), number).is_err());
+        }
+    }
+}
+
+#[test]
+fn arithmetic_matches_for_every_operator_and_pair() {
+    let cases: &[(char, Value, Value, Result<Value, &str>)] = &[
+        ('+', int(7), int(2), Ok(int(9))),
+        ('-', int(7), int(2), Ok(int(5))),
+        ('*', int(7), int(2), Ok(int(14))),
+        ('/', int(7), int(2), Ok(int(3))),
+        ('%', int(7), int(2), Ok(int(1))),
+        ('^', int(7), int(2), Ok(int(49))),
+        ('^', int(7), int(0), Ok(int(1))),
+        ('^', int(7), int(-1), Err("Integer exponentiation only supports positive exponents up to u32 max")),
+        ('/', int(7), int(0), Err("Division by zero")),
+        ('%', int(7), int(0), Err("Modulo by zero")),
+        ('+', int(7), Value::Float(0.5), Ok(Value::Float(7.5))),
+        ('-', Value::Float(0.5), int(7), Ok(Value::Float(-6.5))),
+        ('*', Value::Float(1.5), Value::Float(2.0), Ok(Value::Float(3.0))),
+        ('/', int(7), Value::Float(2.0), Ok(Value::Float(3.5))),
+        ('%', Value::Float(7.5), int(2), Ok(Value::Float(1.5))),
+        ('^', int(2), Value::Float(0.5), Ok(Value::Float(2f64.sqrt()))),
+        ('/', Value::Float(1.0), int(0), Err("Division by zero in float operation")),
+        ('%', int(1), Value::Float(0.0), Err("Modulo by zero in float operation")),
+    ];
+    for (op, l, r, expected) in cases {
+        let actual = arithmetic(*op, coerce_pair(l.clone(), r.clone()).unwrap(), IntDivision::Trunc);
+        assert_eq!(actual, expected.clone().map_err(str::to_string), "{:?} {} {:?}", l, op, r);
+        // The operator in a script goes through the same path
+        let source = format!("a = {:?}\nb = {:?}\na {} b\n", l, r, op);
+        let source = source.replace("Integer(", "").replace("Float(", "").replace(')', "");
+        let expected = expected.clone().map_err(|e| format!("Runtime Error (Statement 3): {}", e));
+        assert_eq!(eval(&source), expected, "{}", source);
+    }
+}
+
+#[test]
+fn compare_is_exact_across_integers_and_floats() {
+    let big = Value::Integer(BigInt::from(2u64.pow(53)) + 1);
+    let cases = [
+        (int(1), int(2), Some(Some(Ordering::Less))),
+        (int(2), Value::Float(2.0), Some(Some(Ordering::Equal))),
+        (Value::Float(2.5), int(2), Some(Some(Ordering::Greater))),
+        (int(-3), Value::Float(-2.5), Some(Some(Ordering::Less))),
+        // 2^53 + 1 rounds to 2^53 as an f64, but still compares greater
+        (big.clone(), Value::Float(2f64.powi(53)), Some(Some(Ordering::Greater))),
+        (big, Value::Float(f64::INFINITY), Some(Some(Ordering::Less))),
+        (int(0), Value::Float(f64::NEG_INFINITY), Some(Some(Ordering::Greater))),
+        (int(0), Value::Float(f64::NAN), Some(None)),
+        (Value::Float(f64::NAN), Value::Float(f64::NAN), Some(None)),
+        (int(1), Value::String("1".into()), None),
+        (Value::Boolean(true), Value::Float(1.0), None),
+    ];
+    for (a, b, expected) in cases {
+        assert_eq!(compare(&a, &b), expected, "{:?} vs {:?}", a, b);
+        assert_eq!(compare(&b, &a), expected.map(|o| o.map(Ordering::reverse)), "{:?} vs {:?}", b, a);
+    }
+}
+
+#[test]
+fn comparison_operators_order_floats_by_both_operands() {
+    // '>=' on two Floats once compared the right operand with itself
+    for (source, expected) in [("2.5 >= 3.5", false), ("3.5 >= 2.5", true), ("2.5 >= 2.5", true), ("2.5 <= 3.5", true)] {
+        assert_eq!(eval(source), Ok(Value::Boolean(expected)), "{}", source);
+    }
+}
+
+#[test]
+fn builtins_promote_like_operators() {
+    assert_eq!(eval("sum([1, 2, 3])"), Ok(int(6)));
+    assert_eq!(eval("sum([1, 2.5, 3])"), Ok(Value::Float(6.5)));
+    assert_eq!(eval("sum([1, 2.5, 3]) == 1 + 2.5 + 3"), Ok(Value::Boolean(true)));
+    assert_eq!(eval("equals(2, 2.0)"), Ok(Value::Boolean(true)));
+    assert_eq!(eval("strict_equals(2, 2.0)"), Ok(Value::Boolean(false)));
+    assert!(eval("sum([1, \"2\"])").is_err());
+}
+
+#[test]
+fn in_place_updates_keep_copies_and_self_references_intact() {
+    assert_eq!(eval("x = 3\ny = x\nx = x * x\nx *= x\nresult = [x, y]\nresult"), Ok(Value::Array(vec![int(81), int(3)])));
+    assert_eq!(eval("x = 10\nx -= 2.5\nx"), Ok(Value::Float(7.5)));
+    assert_eq!(eval("x = 1\nfor (i in range(1, 21)) [ x = x * i ]\nx"), Ok(Value::Integer("2432902008176640000".parse().unwrap())));
+}
+
+#[test]
+fn int_division_modes_round_quotient_remainder_and_divmod_consistently() {
+    let source = "a = 0 - 7\nresult = [a / 2, a % 2, divmod(a, 2), divmod(7, 0 - 2), divmod(7.5, 0 - 2)]\nresult\n";
+    let cases = [
+        (IntDivision::Trunc, "[-3, -1, [-3, -1], [-3, 1], [-3, 1.5]]"),
+        (IntDivision::Floor, "[-4, 1, [-4, 1], [-4, -1], [-4, -0.5]]"),
+        (IntDivision::Promote, "[-3.5, 1, [-4, 1], [-4, -1], [-4, -0.5]]"),
+    ];
+    for (division, expected) in cases {
+        let value = Interpreter::new(Options { int_division: division, ..Options::default() }).run_source(source).unwrap();
+        assert_eq!(value.to_string(), expected, "{}", division);
+    }
+    assert_eq!(eval("divmod(1, 0)"), Err("Runtime Error (Statement 1): Division by zero".to_string()));
+    assert_eq!("floor".parse(), Ok(IntDivision::Floor));
+    assert!("round".parse::<IntDivision>().is_err());
+}
+
+#[test]
+fn rounding_modes_match_the_decimal_module() {
+    use RoundingMode::*;
+    // Each row rounds n / 2 for n = 5, 3, -3, -5 (2.5, 1.5, -1.5, -2.5)
+    let cases = [
+        (HalfEven, [2, 2, -2, -2]),
+        (HalfUp, [3, 2, -2, -3]),
+        (HalfDown, [2, 1, -1, -2]),
+        (Up, [3, 2, -2, -3]),
+        (Down, [2, 1, -1, -2]),
+        (Ceiling, [3, 2, -1, -2]),
+        (Floor, [2, 1, -2, -3]),
+    ];
+    for (mode, expected) in cases {
+        let actual = [5, 3, -3, -5].map(|n| round_fraction(&BigInt::from(n), &BigInt::from(2), mode));
+        assert_eq!(actual, expected.map(BigInt::from), "{:?}", mode);
+    }
+    // Away from a tie, the half modes all round to the nearest
+    assert_eq!(round_fraction(&BigInt::from(-7), &BigInt::from(3), HalfDown), BigInt::from(-2));
+}
+
+#[test]
+fn rounding_builtins_work_on_the_exact_value_and_keep_or_convert_types() {
+    let cases = [
+        // The nearest f64 to 2.675 is just below it
+        ("round(2.675, 2)", "2.67"),
+        ("round(1250, 0 - 2)", "1200"),
+        ("round(1250, 0 - 2, \"half_up\")", "1300"),
+        ("round(7)", "7"),
+        ("trunc(0.0 - 2.7)", "-2"),
+        ("trunc(2.1, \"ceiling\")", "3"),
+        ("floor_div(0 - 7, 2)", "-4"),
+        ("ceil_div(0 - 7, 2)", "-3"),
+        ("floor_div(7.5, 0.5)", "15"),
+        ("to_fixed(2.675, 2, \"half_up\")", "\"2.67\""),
+        ("to_fixed(0.0 - 1.005, 2, \"up\")", "\"-1.01\""),
+        ("to_fixed(3, 3)", "\"3.000\""),
+    ];
+    for (call, expected) in cases {
+        assert_eq!(eval(&format!("result = {}\nresult\n", call)).map(|v| v.to_string()), Ok(expected.to_string()), "{}", call);
+    }
+    assert_eq!(eval("result = round(2.5)\nresult\n"), Ok(Value::Float(2.0)));
+    assert_eq!(eval("result = trunc(2.5)\nresult\n"), Ok(int(2)));
+    assert!(eval("round(1.5, 0, \"sideways\")").unwrap_err().contains("Unknown rounding mode 'sideways'"));
+    assert!(eval("ceil_div(1, 0.0)").unwrap_err().contains("Division by zero"));
+}
@@ -0,0 +1,75 @@
+//! Lenient parsing: broken regions become Error nodes covering the skipped source, and the rest
+//! of the file still parses.
+
+use astra::{format_program_with_source, BlockStyle, Expr, Interpreter, Options, Parser, Span, Statement};
+
+fn parse(source: &str) -> (Vec<Statement>, Vec<(Span, String)>) {
+    let mut parser = Parser::new(source);
+    let statements = parser.parse_lenient();
+    (statements, parser.errors().to_vec())
+}
+
+fn text(source: &str, span: Span) -> &str {
+    &source[span.start..span.end]
+}
+
+#[test]
+fn broken_statements_become_error_nodes() {
+    let source = "x = 1\ny = (2 +\nprint(x)\nfn f(a) [\n    a ++\n    return a\n]\n";
+    let (statements, errors) = parse(source);
+
+    assert_eq!(statements.len(), 4);
+    assert_eq!(statements[0], Parser::new("x = 1").parse().unwrap()[0]);
+    let Statement::Error(span) = statements[1] else { panic!("expected an error node, got {:?}", statements[1]) };
+    assert_eq!(text(source, span), "y = (2 +");
+    assert!(matches!(statements[2], Statement::Print(..)));
+    // The error inside the body is contained there; the definition itself survives
+    let Statement::Def(name, _, body, ..) = &statements[3] else { panic!("expected a definition") };
+    assert_eq!(name, "f");
+    let Statement::Error(inner) = body[0] else { panic!("expected an error node in the body") };
+    assert_eq!(text(source, inner), "a ++");
+    assert_eq!(body[1], Statement::Return(Some(Expr::Var("a".to_string()))));
+
+    assert_eq!(errors.iter().map(|(span, _)| *span).collect::<Vec<_>>(), vec![span, inner]);
+    // A valid file parses exactly as with parse()
+    let valid = "x = [1, 2]\nprint(\"{}\", f(x))\n";
+    assert_eq!(parse(valid), (Parser::new(valid).parse().unwrap(), Vec::new()));
+}
+
+#[test]
+fn bad_list_elements_become_error_expressions() {
+    let source = "z = f(1, , [1, 2 3], g(4))";
+    let (statements, errors) = parse(source);
+    let [Statement::Expr(Expr::Infix(_, '=', call))] = statements.as_slice() else { panic!("{:?}", statements) };
+    let Expr::Call(_, args) = &**call else { panic!("{:?}", call) };
+    assert_eq!(args.len(), 4);
+    assert!(matches!(args[1], Expr::Error(span) if text(source, span).is_empty()));
+    let Expr::Array(elements) = &args[2] else { panic!("{:?}", args[2]) };
+    assert!(matches!(elements[1], Expr::Error(span) if text(source, span) == "2 3"));
+    assert!(matches!(args[3], Expr::Call(..)));
+    assert_eq!(errors.len(), 2);
+}
+
+#[test]
+fn spans_are_byte_offsets_into_the_original_source() {
+    // A byte order mark, CRLF line endings and multi-byte characters before the error
+    let source = "\u{feff}s = \"héllo\"\r\n) oops\r\nt = 2\r\n";
+    let (statements, errors) = parse(source);
+    assert_eq!(statements.len(), 3);
+    assert_eq!(text(source, errors[0].0), ") oops");
+}
+
+#[test]
+fn broken_regions_are_formatted_verbatim_and_fail_when_run() {
+    let source = "x   =  1\nfn f(a) [\n  a ++\n]\nprint(x ,  y)) )\n";
+    let (statements, _) = parse(source);
+    assert_eq!(
+        format_program_with_source(&statements, BlockStyle::Braces, source),
+        "x = 1\nfn f(a) {\n    a ++\n}\nprint(x ,  y)) )\n"
+    );
+
+    let mut interpreter = Interpreter::new(Options::default());
+    assert!(interpreter.run_statement(&statements[0]).is_ok());
+    let Err(error) = interpreter.run_statement(&statements[2]) else { panic!("running an error node succeeded") };
+    assert!(error.contains("failed to parse"), "{}", error);
+}
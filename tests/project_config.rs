@@ -0,0 +1,29 @@
+//! astra.toml is read from the script's directory or an ancestor; command-line flags override it.
+
+mod common;
+
+use common::{astra as run, TempDir};
+
+#[test]
+fn int_div_comes_from_the_nearest_astra_toml_unless_given_on_the_command_line() {
+    let dir = TempDir::new("project_config");
+    dir.write("astra.toml", "int-div = \"floor\"\n");
+    dir.write("scripts/div.as", "a = 0 - 7\nprint(\"{} {}\", a / 2, a % 2)\n");
+
+    let output = run(&dir, &["scripts/div.as"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "-4 1\n");
+    let output = run(&dir, &["run", "scripts/div.as", "--int-div", "promote"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "-3.5 1\n");
+}
+
+#[test]
+fn unknown_settings_in_astra_toml_are_errors() {
+    let dir = TempDir::new("project_config_invalid");
+    dir.write("astra.toml", "int_div = \"floor\"\n");
+    dir.write("script.as", "print(1)\n");
+
+    let output = run(&dir, &["script.as"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("Configuration Error: ") && stderr.contains("unknown setting 'int_div'"), "{}", stderr);
+}
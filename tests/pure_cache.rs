@@ -0,0 +1,58 @@
+//! The --pure-cache of builtin results: repeated calls are served from it, calls with other
+//! arguments are not, and the oldest entries make room once it is full.
+
+use astra::{Interpreter, Options, Value};
+
+fn cached(capacity: usize) -> Interpreter {
+    Interpreter::new(Options { pure_cache_size: Some(capacity), ..Options::default() })
+}
+
+#[test]
+fn repeated_calls_are_served_from_the_cache() {
+    let mut interpreter = cached(8);
+    let first = interpreter.run_source("sum(range(1, 1000))").unwrap();
+    let second = interpreter.run_source("sum(range(1, 1000))").unwrap();
+    assert_eq!((first, second), (Value::Integer(499500.into()), Value::Integer(499500.into())));
+    assert_eq!(interpreter.metrics().pure_cache_hits, 1);
+
+    // Without the option nothing is cached
+    let mut interpreter = Interpreter::new(Options::default());
+    interpreter.run_source("sum(range(1, 1000))\nsum(range(1, 1000))").unwrap();
+    assert_eq!(interpreter.metrics().pure_cache_hits, 0);
+}
+
+#[test]
+fn calls_with_other_arguments_miss() {
+    let mut interpreter = cached(8);
+    let results = [
+        ("sum(range(1, 1000))", Value::Integer(499500.into())),
+        ("sum(range(1, 1001))", Value::Integer(500500.into())),
+        ("sum(step(range(1, 1000), 2))", Value::Integer(250000.into())),
+        ("sum([1, 2])", Value::Integer(3.into())),
+        ("sum([1.0, 2.0])", Value::Float(3.0)),
+        ("edit_distance(\"kitten\", \"sitting\")", Value::Integer(3.into())),
+        ("edit_distance(\"sitting\", \"kitten\")", Value::Integer(3.into())),
+    ];
+    for (source, expected) in results {
+        assert_eq!(interpreter.run_source(source), Ok(expected), "{}", source);
+    }
+    assert_eq!(interpreter.metrics().pure_cache_hits, 0);
+}
+
+#[test]
+fn cheap_builtins_are_not_cached() {
+    let mut interpreter = cached(8);
+    interpreter.run_source("items = [1, 2, 3]\nlength(items)\nlength(items)\nround(2.5)\nround(2.5)").unwrap();
+    assert_eq!(interpreter.metrics().pure_cache_hits, 0);
+}
+
+#[test]
+fn the_oldest_entry_is_evicted_when_full() {
+    let mut interpreter = cached(2);
+    interpreter.run_source("sum([1])\nsum([2])\nsum([3])").unwrap();
+    // [1] made room for [3]; [2] and [3] are still cached
+    interpreter.run_source("sum([3])\nsum([2])").unwrap();
+    assert_eq!(interpreter.metrics().pure_cache_hits, 2);
+    assert_eq!(interpreter.run_source("sum([1])"), Ok(Value::Integer(1.into())));
+    assert_eq!(interpreter.metrics().pure_cache_hits, 2);
+}
@@ -0,0 +1,36 @@
+//! quote [ ... ] turns syntax into a value that scripts can take apart and run later.
+
+mod common;
+
+use astra::Value;
+
+use common::{eval, text};
+
+#[test]
+fn quoted_syntax_is_inspected_by_kind_and_children() {
+    let source = "q = quote [ x + 1 ]\nresult = [ast_kind(q), ast_children(q)[1], ast_kind(ast_children(q)[0])]\nresult\n";
+    assert_eq!(eval(source), Ok(Value::Array(vec![text("Infix"), text("+"), text("Var")])));
+
+    let source = "q = quote [\n    if (n > 0) [ print(n) ]\n]\nparts = ast_children(q)\nresult = [ast_kind(q), ast_kind(parts[1]), parts[2]]\nresult\n";
+    assert_eq!(eval(source), Ok(Value::Array(vec![text("If"), text("Block"), Value::Void])));
+
+    // Several statements stay a block; its children are the statements
+    let source = "q = quote { a = 1\nb = 2 }\nresult = [ast_kind(q), length(ast_children(q))]\nresult\n";
+    assert_eq!(eval(source), Ok(Value::Array(vec![text("Block"), Value::Integer(2.into())])));
+}
+
+#[test]
+fn eval_ast_runs_quoted_syntax_with_only_the_given_bindings() {
+    let source = "q = quote [\n    total = 0\n    for (i in range(n)) [ total += i ]\n    total\n]\nn = 100\neval_ast(q, [[\"n\", 5]])\n";
+    assert_eq!(eval(source), Ok(Value::Integer(10.into())));
+
+    let source = "x = 1\neval_ast(quote [ x + 1 ])\n";
+    assert!(eval(source).unwrap_err().contains("uninitialized variable: x"));
+    let source = "eval_ast(quote [ x ], [[\"x\"]])\n";
+    assert!(eval(source).unwrap_err().contains("bindings must be [name, value] pairs"));
+}
+
+#[test]
+fn quoted_syntax_displays_as_formatted_source() {
+    assert_eq!(eval("quote [ f(a,b) * 2 ]").map(|v| v.to_string()), Ok("<ast f(a, b) * 2>".to_string()));
+}
@@ -0,0 +1,177 @@
+//! Property tests for the formatter: formatting a syntax tree and parsing the result must give the
+//! same tree back, and formatted source must evaluate exactly like the source it came from.
+
+use std::sync::Arc;
+
+use astra::{format_program, BlockStyle, Contract, Expr, Interpreter, Options, Parser, Statement};
+use proptest::prelude::*;
+use proptest::sample::select;
+
+const VARS: &[&str] = &["a", "b", "c", "xs"];
+const FUNCS: &[&str] = &["f", "g", "length", "sum"];
+const ARITHMETIC: &[char] = &['+', '-', '*', '/', '%', '^'];
+// '^' is left out when evaluating: nested powers of random operands can take arbitrarily long
+const ARITHMETIC_WITHOUT_POW: &[char] = &['+', '-', '*', '/', '%'];
+const COMPARISONS: &[&str] = &["==", "!=", "<", ">", "<=", ">=", "===", "!=="];
+
+fn var() -> impl Strategy<Value = String> {
+    select(VARS).prop_map(str::to_string)
+}
+
+fn leaf() -> impl Strategy<Value = Expr> {
+    prop_oneof![
+        "[0-9]{1,3}".prop_map(Expr::Num),
+        "[0-9]{1,2}\\.[0-9]{1,2}".prop_map(Expr::Num),
+        // Printable ASCII (including quotes and backslashes) plus the escapes the lexer understands
+        "[ -~\n\t]{0,6}".prop_map(|s| Expr::Str(Arc::from(s.as_str()))),
+        any::<bool>().prop_map(Expr::Bool),
+        var().prop_map(Expr::Var),
+    ]
+}
+
+/// Expressions the parser can produce, excluding assignment.
+fn expr_with(arithmetic: &'static [char]) -> impl Strategy<Value = Expr> {
+    leaf().prop_recursive(4, 32, 4, move |inner| {
+        let arg = prop_oneof![3 => inner.clone(), 1 => inner.clone().prop_map(|e| Expr::Spread(Box::new(e)))];
+        prop_oneof![
+            (select(vec!['-', '+', '!']), inner.clone()).prop_map(|(op, e)| Expr::Prefix(op, Box::new(e))),
+            (inner.clone(), select(arithmetic), inner.clone())
+                .prop_map(|(l, op, r)| Expr::Infix(Box::new(l), op, Box::new(r))),
+            (inner.clone(), select(COMPARISONS), inner.clone())
+                .prop_map(|(l, op, r)| Expr::Cmp(Box::new(l), op.to_string(), Box::new(r))),
+            (inner.clone(), select(vec!["and", "or"]), inner.clone())
+                .prop_map(|(l, op, r)| Expr::Logic(Box::new(l), op.to_string(), Box::new(r))),
+            prop::collection::vec(inner.clone(), 0..4).prop_map(Expr::Array),
+            (inner.clone(), inner.clone()).prop_map(|(a, i)| Expr::Slice(Box::new(a), Some(Box::new(i)), None)),
+            (inner.clone(), prop::option::of(inner.clone()), inner.clone())
+                .prop_map(|(a, s, e)| Expr::Slice(Box::new(a), s.map(Box::new), Some(Box::new(e)))),
+            (select(FUNCS), prop::collection::vec(arg, 0..3)).prop_map(|(f, args)| Expr::Call(f.to_string(), args)),
+        ]
+    })
+}
+
+fn expr() -> impl Strategy<Value = Expr> {
+    expr_with(ARITHMETIC)
+}
+
+/// Print format strings: text, '{}' and '{expr}' placeholders, and escaped braces.
+fn format_string() -> impl Strategy<Value = String> {
+    let piece = prop_oneof![
+        "[a-z ]{1,3}",
+        Just("{}".to_string()),
+        Just("{{".to_string()),
+        Just("}}".to_string()),
+        var().prop_map(|v| format!("{{{} + 1}}", v)),
+    ];
+    prop::collection::vec(piece, 0..4).prop_map(|pieces| pieces.concat())
+}
+
+/// Statements that start with an identifier or keyword. Scripts have no statement terminator,
+/// so an expression statement starting with '(', '[' or a sign would continue the previous one.
+fn simple_statement() -> impl Strategy<Value = Statement> {
+    prop_oneof![
+        (var(), expr()).prop_map(|(v, e)| Statement::Expr(Expr::Infix(Box::new(Expr::Var(v)), '=', Box::new(e)))),
+        (select(FUNCS), prop::collection::vec(expr(), 0..3))
+            .prop_map(|(f, args)| Statement::Expr(Expr::Call(f.to_string(), args))),
+        (format_string(), prop::collection::vec(expr(), 0..3)).prop_map(|(fmt, args)| Statement::Print(Some(fmt), args)),
+        prop::option::of(expr()).prop_map(|e| Statement::Print(None, e.into_iter().collect())),
+    ]
+}
+
+/// A block body; 'return' only appears last, since a bare 'return' takes the next line as its value.
+fn body(statement: BoxedStrategy<Statement>) -> impl Strategy<Value = Vec<Statement>> {
+    (prop::collection::vec(statement, 0..3), prop::option::of(prop::option::of(expr()))).prop_map(|(mut stmts, ret)| {
+        if let Some(value) = ret {
+            stmts.push(Statement::Return(value));
+        }
+        stmts
+    })
+}
+
+fn statement() -> BoxedStrategy<Statement> {
+    simple_statement()
+        .prop_recursive(3, 24, 4, |inner| {
+            let inner = inner.boxed();
+            prop_oneof![
+                (expr(), body(inner.clone()), prop::option::of(body(inner.clone())))
+                    .prop_map(|(cond, then, otherwise)| Statement::If(cond, then, otherwise)),
+                (var(), expr(), body(inner.clone())).prop_map(|(v, iterable, b)| Statement::For(v, iterable, b)),
+                (prop::option::of("[a-z ]{0,8}"), body(inner.clone())).prop_map(|(label, b)| Statement::Timed(label, b)),
+                (var(), body(inner)).prop_map(|(v, b)| {
+                    Statement::Expr(Expr::Infix(Box::new(Expr::Var(v)), '=', Box::new(Expr::Quote(b))))
+                }),
+            ]
+        })
+        .boxed()
+}
+
+fn definition() -> impl Strategy<Value = Statement> {
+    (
+        select(FUNCS),
+        prop::collection::vec(var(), 0..3),
+        body(statement()),
+        prop::collection::vec(select(vec!["memoize", "timed", "register"]).prop_map(str::to_string), 0..2),
+        prop::collection::vec(expr(), 0..2),
+        prop::collection::vec(expr(), 0..2),
+    )
+        .prop_map(|(name, params, body, decorators, requires, ensures)| {
+            Statement::Def(name.to_string(), params, body, decorators, Contract { requires, ensures })
+        })
+}
+
+fn program() -> impl Strategy<Value = Vec<Statement>> {
+    prop::collection::vec(prop_oneof![3 => statement(), 1 => definition()], 0..5)
+}
+
+/// Renders an expression with every operation parenthesized, independently of the formatter.
+fn parenthesized(expr: &Expr) -> String {
+    let list = |items: &[Expr]| items.iter().map(parenthesized).collect::<Vec<_>>().join(", ");
+    match expr {
+        Expr::Var(id) => id.clone(),
+        Expr::Num(n) => n.clone(),
+        Expr::Str(s) => format!("{:?}", s.as_ref()),
+        Expr::Bool(b) => b.to_string(),
+        Expr::Prefix(op, e) => format!("({}{})", op, parenthesized(e)),
+        Expr::Infix(l, op, r) => format!("({} {} {})", parenthesized(l), op, parenthesized(r)),
+        Expr::Cmp(l, op, r) | Expr::Logic(l, op, r) => format!("({} {} {})", parenthesized(l), op, parenthesized(r)),
+        Expr::Array(items) => format!("[{}]", list(items)),
+        Expr::Slice(a, start, end) => {
+            let start = start.as_deref().map(parenthesized).unwrap_or_default();
+            match end {
+                Some(end) => format!("({})[{}:{}]", parenthesized(a), start, parenthesized(end)),
+                None => format!("({})[{}]", parenthesized(a), start),
+            }
+        }
+        Expr::Call(name, args) => format!("{}({})", name, list(args)),
+        Expr::Spread(e) => format!("...{}", parenthesized(e)),
+        Expr::Error(_) => unreachable!("the strategies only build valid expressions"),
+        Expr::Quote(_) => unreachable!("quoted blocks are only generated as statements"),
+        Expr::Expand(_) => unreachable!("macro uses need a macro definition, which the strategies don't generate"),
+    }
+}
+
+const PRELUDE: &str = "a = 7\nb = 2.5\nc = \"text\"\nxs = [1, 2, 3]\nfn f(x) [ x ]\nfn g(x, y) [ [y, x] ]\n";
+
+fn evaluate(source: &str) -> String {
+    format!("{:?}", Interpreter::new(Options::default()).run_source(source))
+}
+
+proptest! {
+    #[test]
+    fn parse_of_formatted_program_is_identity(program in program()) {
+        for style in [BlockStyle::Brackets, BlockStyle::Braces] {
+            let source = format_program(&program, style);
+            let reparsed = Parser::new(&source).parse();
+            prop_assert_eq!(reparsed, Ok(program.clone()), "formatted source:\n{}", source);
+        }
+    }
+
+    #[test]
+    fn formatted_expression_evaluates_like_the_original(expr in expr_with(ARITHMETIC_WITHOUT_POW)) {
+        let original = format!("{}result = {}\nresult\n", PRELUDE, parenthesized(&expr));
+        let statements = Parser::new(&original).parse();
+        prop_assert!(statements.is_ok(), "original source does not parse:\n{}", original);
+        let formatted = format_program(&statements.unwrap(), BlockStyle::Brackets);
+        prop_assert_eq!(evaluate(&original), evaluate(&formatted), "formatted source:\n{}", formatted);
+    }
+}
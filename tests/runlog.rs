@@ -0,0 +1,71 @@
+//! The runlog and the output limits of 'astra run': fatal errors are recorded whatever --log
+//! says, --max-output-bytes stops or truncates the output, and --max-log-bytes rotates the runlog.
+
+mod common;
+
+use std::fs;
+use std::process::Output;
+
+use common::{astra, TempDir};
+
+/// Runs `script` in a fresh directory, returning the directory (holding the runlog) and the output.
+fn run(name: &str, script: &str, args: &[&str]) -> (TempDir, Output) {
+    let dir = TempDir::new(&format!("runlog_{}", name));
+    dir.write("script.as", script);
+    let output = astra(&dir, &[args, &["script.as"]].concat());
+    (dir, output)
+}
+
+#[test]
+fn runtime_errors_reach_stderr_and_the_runlog_with_logging_off() {
+    let (dir, output) = run("log_off", "print(\"before\")\nx = 1 / 0\n", &["--log", "off"]);
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "before\n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "Runtime Error (Statement 2): Division by zero\n");
+    let runlog = fs::read_to_string(dir.join("runlog")).unwrap();
+    assert!(runlog.ends_with("Runtime Error (Statement 2): Division by zero\n"), "{}", runlog);
+    assert!(!runlog.contains("ERROR"), "{}", runlog);
+
+    // With logging on the error is recorded once, not again by the logger
+    let (dir, _) = run("log_on", "x = 1 / 0\n", &[]);
+    let runlog = fs::read_to_string(dir.join("runlog")).unwrap();
+    assert_eq!(runlog.matches("Division by zero").count(), 1, "{}", runlog);
+}
+
+#[test]
+fn output_past_the_limit_fails_the_run() {
+    let (dir, output) = run("output_limit", "for (i in range(10)) [\n    print(\"line {}\", i)\n]\n", &["--max-output-bytes", "20"]);
+    assert!(!output.status.success());
+    // "line 0\n" is 7 bytes: the fourth line would make 28
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "line 0\nline 1\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Output limit of 20 bytes exceeded (--max-output-bytes)"));
+    let runlog = fs::read_to_string(dir.join("runlog")).unwrap();
+    assert!(runlog.contains("Output limit of 20 bytes exceeded"), "{}", runlog);
+}
+
+#[test]
+fn truncated_output_ends_with_a_marker() {
+    let script = "for (i in range(10)) [\n    print(\"line {}\", i)\n]\nprint(\"done\")\n";
+    let (_, output) = run("truncate", script, &["--max-output-bytes", "20", "--truncate-output"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "line 0\nline 1\n[output truncated: limit of 20 bytes reached]\n");
+
+    // Output that fits is left alone
+    let (_, output) = run("truncate_fits", "print(\"short\")\n", &["--max-output-bytes", "20", "--truncate-output"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "short\n");
+}
+
+#[test]
+fn the_runlog_rotates_past_its_size_limit() {
+    let script = "for (i in range(200)) [\n    print(\"line {}\", i)\n]\n";
+    let (dir, output) = run("rotation", script, &["--log", "off", "--max-log-bytes", "500"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    for name in ["runlog", "runlog.1", "runlog.2", "runlog.3"] {
+        let size = fs::metadata(dir.join(name)).unwrap().len();
+        assert!(size > 0 && size <= 500, "{} has {} bytes", name, size);
+    }
+    // Only three older generations are kept, and the newest lines are in the current runlog
+    assert!(!dir.join("runlog.4").exists());
+    assert!(fs::read_to_string(dir.join("runlog")).unwrap().contains("line 199"));
+    assert!(!fs::read_to_string(dir.join("runlog.3")).unwrap().contains("line 0\n"));
+}
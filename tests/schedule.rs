@@ -0,0 +1,84 @@
+//! Job registration with 'every', schedule timing and backoff, and 'astra schedule --once'.
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use astra::schedule::{parse_interval, Interval, Schedule, MAX_INTERVAL};
+use astra::{Interpreter, Options, ScheduledJob};
+
+use common::{astra, TempDir};
+
+fn minutes(n: u64) -> Duration {
+    Duration::from_secs(60 * n)
+}
+
+#[test]
+fn intervals_parse_and_print_in_the_same_units() {
+    assert_eq!(parse_interval("30s"), Ok(Duration::from_secs(30)));
+    assert_eq!(parse_interval("1h30m"), Ok(minutes(90)));
+    assert_eq!(parse_interval("1d"), Ok(minutes(24 * 60)));
+    assert_eq!(Interval(minutes(90)).to_string(), "1h30m");
+    assert_eq!(Interval(Duration::from_secs(90061)).to_string(), "1d1h1m1s");
+    for (bad, reason) in [("", "empty"), ("5", "needs a unit"), ("5x", "unknown unit 'x'"), ("m", "expected a number"), ("0s", "at least one second")] {
+        assert!(parse_interval(bad).unwrap_err().contains(reason), "{:?}", bad);
+    }
+}
+
+#[test]
+fn intervals_are_limited_to_366_days() {
+    assert_eq!(parse_interval("366d"), Ok(MAX_INTERVAL));
+    assert_eq!(parse_interval("365d23h59m60s"), Ok(MAX_INTERVAL));
+    for too_long in ["366d1s", "367d", "200000000000000d"] {
+        assert!(parse_interval(too_long).unwrap_err().contains("at most 366 days"), "{:?}", too_long);
+    }
+
+    // Jobs built by hand can have longer intervals; their next run is capped instead of overflowing
+    let start = Instant::now();
+    let job = ScheduledJob { function: "sync".to_string(), interval: Duration::MAX };
+    let mut schedule = Schedule::new(vec![job], start);
+    assert_eq!(schedule.record(0, true, start), start + MAX_INTERVAL);
+    assert_eq!(schedule.record(0, false, start), start + MAX_INTERVAL);
+}
+
+#[test]
+fn failing_jobs_back_off_and_recover() {
+    let start = Instant::now();
+    let jobs = vec![
+        ScheduledJob { function: "sync".to_string(), interval: minutes(5) },
+        ScheduledJob { function: "report".to_string(), interval: minutes(60 * 24) },
+    ];
+    let mut schedule = Schedule::new(jobs, start);
+    // Both are due right away; the first registered goes first
+    assert_eq!(schedule.next_due(), Some((0, start)));
+    assert_eq!(schedule.record(0, true, start), start + minutes(5));
+    assert_eq!(schedule.next_due(), Some((1, start)));
+    assert_eq!(schedule.record(1, false, start), start + minutes(60 * 24), "a daily job never waits longer than a day");
+
+    let waits: Vec<Duration> = (0..6).map(|_| schedule.record(0, false, start) - start).collect();
+    assert_eq!(waits, [minutes(10), minutes(20), minutes(40), minutes(60), minutes(60), minutes(60)]);
+    assert_eq!(schedule.failures(0), 6);
+    assert_eq!(schedule.record(0, true, start), start + minutes(5));
+    assert_eq!(schedule.failures(0), 0);
+}
+
+#[test]
+fn every_registers_jobs_and_schedule_once_runs_each() {
+    let mut interpreter = Interpreter::new(Options::default());
+    let source = "fn sync() [ 1 ]\nfn add(x) [ x ]\nevery(\"5m\", \"sync\")\n";
+    interpreter.run_source(source).unwrap();
+    assert_eq!(interpreter.scheduled_jobs(), [ScheduledJob { function: "sync".to_string(), interval: minutes(5) }]);
+    let mut error = |source: &str| interpreter.run_source(source).unwrap_err();
+    assert!(error("every(\"5m\", \"add\")").contains("without parameters"));
+    assert!(error("every(\"5m\", \"later\")").contains("defined earlier"));
+    assert!(error("every(\"soon\", \"sync\")").contains("Invalid interval"));
+
+    let dir = TempDir::new("schedule");
+    let script = "fn tick() [\n    counter = [1]\n    counter += [2]\n    print(counter)\n]\nfn broken() [ return 1 / 0 ]\nevery(\"1s\", \"tick\")\nevery(\"1h\", \"broken\")\n";
+    dir.write("jobs.as", script);
+    let output = astra(&dir, &["schedule", "jobs.as", "--once"]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("[1, 2]\nok     tick ("), "{}", stdout);
+    assert!(stdout.ends_with("FAILED broken: Function 'broken' Execution Error (Stmt 1): Division by zero\n"), "{}", stdout);
+}
@@ -0,0 +1,80 @@
+//! Lazy sequences: range, take, drop, step and enumerate with BigInt bounds, counts past the
+//! end, negative and zero steps, and sum over ranges far too long to materialize.
+
+mod common;
+
+use astra::Value;
+use num_bigint::BigInt;
+
+use common::{eval, int, ints};
+
+/// Collects the items of the sequence expression into an array by looping over it.
+fn items(sequence: &str) -> Result<Value, String> {
+    eval(&format!("xs = []\nfor (x in {}) [\n    xs = xs + [x]\n]\nxs\n", sequence))
+}
+
+#[test]
+fn ranges_accept_bounds_beyond_i64() {
+    let big = BigInt::from(10).pow(30);
+    assert_eq!(
+        items("take(range(10^30, 10^30 + 10), 3)"),
+        Ok(Value::Array(vec![
+            Value::Integer(big.clone()),
+            Value::Integer(&big + 1),
+            Value::Integer(&big + 2),
+        ]))
+    );
+    assert_eq!(items("drop(range(10^30, 10^30 + 3), 2)"), Ok(Value::Array(vec![Value::Integer(&big + 2)])));
+    assert_eq!(items("range(-10^30, -10^30 + 2)"), Ok(Value::Array(vec![Value::Integer(-&big), Value::Integer(1 - &big)])));
+}
+
+#[test]
+fn take_and_drop_past_the_end_stop_at_the_end() {
+    assert_eq!(items("take(range(5), 10)"), Ok(ints(&[0, 1, 2, 3, 4])));
+    assert_eq!(items("drop(range(5), 10)"), Ok(ints(&[])));
+    assert_eq!(items("drop(range(5), 5)"), Ok(ints(&[])));
+    assert_eq!(items("take(drop(range(5), 3), 10)"), Ok(ints(&[3, 4])));
+    assert_eq!(items("take(range(5), 0)"), Ok(ints(&[])));
+    assert_eq!(eval("sum(drop(range(5), 10))"), Ok(int(0)));
+
+    let error = eval("take(range(5), -1)").unwrap_err();
+    assert!(error.contains("'take' count must not be negative, found -1"), "{}", error);
+    let error = eval("drop(range(5), -1)").unwrap_err();
+    assert!(error.contains("'drop' count must not be negative"), "{}", error);
+}
+
+#[test]
+fn negative_steps_count_down_and_zero_steps_are_rejected() {
+    assert_eq!(items("range(5, 0, -2)"), Ok(ints(&[5, 3, 1])));
+    assert_eq!(items("range(0, 5, -1)"), Ok(ints(&[])));
+    assert_eq!(eval("sum(range(5, 0, -2))"), Ok(int(9)));
+
+    let error = eval("range(1, 5, 0)").unwrap_err();
+    assert!(error.contains("'range' step must not be zero"), "{}", error);
+    let error = eval("step(range(10), 0)").unwrap_err();
+    assert!(error.contains("'step' step must be positive"), "{}", error);
+    let error = eval("step(range(10), -2)").unwrap_err();
+    assert!(error.contains("'step' count must not be negative, found -2"), "{}", error);
+}
+
+#[test]
+fn enumerate_over_a_step_counts_the_items_it_yields() {
+    assert_eq!(
+        items("enumerate(step(range(10), 4))"),
+        Ok(Value::Array(vec![ints(&[0, 0]), ints(&[1, 4]), ints(&[2, 8])]))
+    );
+    assert_eq!(
+        items("enumerate(step(range(10, 0, -1), 3))"),
+        Ok(Value::Array(vec![ints(&[0, 10]), ints(&[1, 7]), ints(&[2, 4]), ints(&[3, 1])]))
+    );
+}
+
+#[test]
+fn sum_over_a_huge_range_does_not_walk_it() {
+    // Far too many items to iterate, let alone collect; only the closed form can finish
+    let n = BigInt::from(10).pow(30);
+    let expected = &n * (&n - 1) / 2;
+    assert_eq!(eval("sum(range(10^30))"), Ok(Value::Integer(expected)));
+    assert_eq!(eval("sum(range(-10^30, 10^30))"), Ok(Value::Integer(-BigInt::from(10).pow(30))));
+    assert_eq!(eval("sum(take(range(10^30), 4))"), Ok(int(6)));
+}
@@ -0,0 +1,68 @@
+//! 'astra sign' and 'astra run --verify': the signature covers the script's exact text, only
+//! line endings and the byte order mark may change.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::Output;
+
+use common::{astra, TempDir};
+
+const SCRIPT: &str = "greeting = \"hi  there\"\nprint(greeting)\n";
+
+/// A fresh directory holding script.as signed with key (public key in key.pub).
+fn signed_script(name: &str) -> TempDir {
+    let dir = TempDir::new(&format!("signing_{}", name));
+    dir.write("script.as", SCRIPT);
+    let output = astra(&dir, &["sign", "script.as", "--key", "key"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    dir
+}
+
+fn run_verified(dir: &Path, public_key: &str) -> Output {
+    astra(dir, &["--verify", "script.as.sig", "--public-key", public_key, "script.as"])
+}
+
+#[test]
+fn signed_scripts_verify() {
+    let dir = signed_script("round_trip");
+    let output = run_verified(&dir, "key.pub");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hi  there\n");
+
+    // Converted line endings and an added byte order mark keep the signature valid
+    let converted = format!("\u{FEFF}{}", SCRIPT.replace('\n', "\r\n"));
+    fs::write(dir.join("script.as"), converted).unwrap();
+    let output = run_verified(&dir, "key.pub");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn tampered_scripts_are_rejected() {
+    let dir = signed_script("tampered");
+    for tampered in [
+        SCRIPT.replace("print", "print(\"pwned\")\nprint"),
+        SCRIPT.replace("hi  there", "hi there"),
+        SCRIPT.replace("there\"", "there \""),
+        SCRIPT.replace("(greeting)\n", "(greeting)  \n"),
+        format!("{}\n", SCRIPT),
+    ] {
+        fs::write(dir.join("script.as"), &tampered).unwrap();
+        let output = run_verified(&dir, "key.pub");
+        assert!(!output.status.success(), "{:?} verified", tampered);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("does not match the script"), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    }
+}
+
+#[test]
+fn signatures_from_another_key_are_rejected() {
+    let dir = signed_script("wrong_key");
+    let output = astra(&dir, &["sign", "script.as", "--key", "other", "--output", "other.sig"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = run_verified(&dir, "other.pub");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("made with another key"), "{}", String::from_utf8_lossy(&output.stderr));
+}
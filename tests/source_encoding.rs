@@ -0,0 +1,59 @@
+//! Source files from Windows editors: a byte order mark, CRLF line endings, and bytes that are
+//! not UTF-8 at all.
+
+mod common;
+
+use astra::{decode_source, Directives, Parser, Value};
+
+use common::{astra, eval, TempDir};
+
+#[test]
+fn byte_order_mark_is_dropped() {
+    assert_eq!(decode_source(b"\xEF\xBB\xBFx = 1\n".to_vec()), Ok("x = 1\n".to_string()));
+    assert_eq!(decode_source(b"x = 1\n".to_vec()), Ok("x = 1\n".to_string()));
+    // Lexer::new accepts a mark left in a &str too
+    assert_eq!(eval("\u{feff}x = 41\nx + 1"), Ok(Value::Integer(42.into())));
+}
+
+#[test]
+fn crlf_reads_as_newline_in_strings_and_comments() {
+    assert_eq!(eval("s = \"one\r\ntwo\"\r\ns"), Ok(Value::String("one\ntwo".to_string())));
+    assert_eq!(eval("x = 1 ; a comment\r\nx = x + 1\r\n; another\r\nx"), Ok(Value::Integer(2.into())));
+    // A lone '\r' is not a line ending and is kept
+    assert_eq!(eval("\"a\rb\""), Ok(Value::String("a\rb".to_string())));
+
+    let mut parser = Parser::new("; astra: max-steps=100\r\nx = 1\r\n");
+    parser.parse().unwrap();
+    assert_eq!(parser.directives(), Ok(Directives { max_steps: Some(100), ..Directives::default() }));
+}
+
+#[test]
+fn crlf_keeps_error_positions_in_file_bytes() {
+    let source = "x = 1\r\ny = (\r\n";
+    let mut parser = Parser::new(source);
+    parser.parse_lenient();
+    let (span, _) = &parser.errors()[0];
+    assert_eq!(span.start, source.find('y').unwrap());
+}
+
+#[test]
+fn invalid_utf8_is_reported_with_the_byte_and_its_offset() {
+    assert_eq!(
+        decode_source(b"print(\"caf\xE9\")\n".to_vec()),
+        Err("source is not valid UTF-8: unexpected byte 0xE9 at offset 10".to_string())
+    );
+    // The offset counts the byte order mark, as the file does
+    assert_eq!(
+        decode_source(b"\xEF\xBB\xBFok\xFF".to_vec()),
+        Err("source is not valid UTF-8: unexpected byte 0xFF at offset 5".to_string())
+    );
+
+    let dir = TempDir::new("source_encoding");
+    dir.write("latin1.as", b"print(\"caf\xE9\")\n");
+    let output = astra(&dir, &["run", "latin1.as"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Encoding Error: latin1.as: source is not valid UTF-8: unexpected byte 0xE9 at offset 10\n"
+    );
+}
@@ -0,0 +1,37 @@
+//! Spread arguments: f(...xs) mixed with ordinary arguments, spreading lazy sequences, and the
+//! errors for spreading a non-sequence or spreading too many arguments.
+
+mod common;
+
+use common::{eval, ints};
+
+const FOUR: &str = "fn f(a, b, c, d) [\n    return [a, b, c, d]\n]\n";
+
+#[test]
+fn spread_arrays_mix_with_ordinary_arguments() {
+    assert_eq!(eval(&format!("{}f(0, ...[1, 2], 3)", FOUR)), Ok(ints(&[0, 1, 2, 3])));
+    assert_eq!(eval(&format!("{}f(...[0, 1], ...[2, 3])", FOUR)), Ok(ints(&[0, 1, 2, 3])));
+    assert_eq!(eval(&format!("{}xs = [1, 2, 3]\nf(...xs, 4)", FOUR)), Ok(ints(&[1, 2, 3, 4])));
+    assert_eq!(eval(&format!("{}f(0, ...[], 1, ...[2, 3])", FOUR)), Ok(ints(&[0, 1, 2, 3])));
+}
+
+#[test]
+fn spread_lazy_sequences_yield_their_items() {
+    assert_eq!(eval(&format!("{}f(...range(4))", FOUR)), Ok(ints(&[0, 1, 2, 3])));
+    assert_eq!(eval(&format!("{}f(...take(range(10), 2), ...[8, 9])", FOUR)), Ok(ints(&[0, 1, 8, 9])));
+    assert_eq!(eval(&format!("{}f(9, ...step(range(10, 0, -1), 4))", FOUR)), Ok(ints(&[9, 10, 6, 2])));
+}
+
+#[test]
+fn spreading_a_non_sequence_is_an_error() {
+    let error = eval(&format!("{}f(...5)", FOUR)).unwrap_err();
+    assert!(error.contains("Spread argument to 'f' must be an Array or sequence, found Integer(5)"), "{}", error);
+    let error = eval(&format!("{}f(1, ...\"abc\", 2)", FOUR)).unwrap_err();
+    assert!(error.contains("Spread argument to 'f' must be an Array or sequence"), "{}", error);
+}
+
+#[test]
+fn spread_arguments_are_counted_against_the_parameters() {
+    let error = eval(&format!("{}f(...range(5))", FOUR)).unwrap_err();
+    assert!(error.contains("Function 'f' expects 4 arguments, but received 5"), "{}", error);
+}
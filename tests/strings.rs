@@ -0,0 +1,57 @@
+//! Case conversion, Unicode normalization and fuzzy matching builtins.
+
+use astra::{Interpreter, Options, Value};
+
+fn call(builtin: &str, arg: &str) -> Result<Value, String> {
+    // The arguments hold no quotes or backslashes, so they can be written between quotes as is
+    let source = format!("{}(\"{}\")\n", builtin, arg);
+    Interpreter::new(Options::default()).run_source(&source)
+}
+
+fn text(s: &str) -> Result<Value, String> {
+    Ok(Value::String(s.to_string()))
+}
+
+#[test]
+fn case_conversion_splits_words_at_separators_and_case_changes() {
+    assert_eq!(call("title_case", "the CAT's hat-trick  again"), text("The Cat's Hat-Trick  Again"));
+    assert_eq!(call("snake_case", "parseHTTPResponse2 fooBar"), text("parse_http_response2_foo_bar"));
+    assert_eq!(call("snake_case", "  Hello World--again "), text("hello_world_again"));
+    assert_eq!(call("camel_case", "user_ID field"), text("userIdField"));
+    assert_eq!(call("camel_case", "XMLHttpRequest"), text("xmlHttpRequest"));
+    // Case mappings and word boundaries come from the Unicode tables
+    assert_eq!(call("snake_case", "ÉcoleNormale Supérieure"), text("école_normale_supérieure"));
+    assert_eq!(call("title_case", "ǉubljana straße"), text("Ǉubljana Straße"));
+}
+
+#[test]
+fn normalization_composes_decomposes_and_strips_combining_marks() {
+    assert_eq!(call("normalize_nfd", "é"), text("e\u{301}"));
+    assert_eq!(call("normalize_nfc", "e\u{301}"), text("é"));
+    // Marks are removed from both forms; text without marks (Hangul) is recomposed unchanged
+    assert_eq!(call("strip_accents", "Crème Brûle\u{301}e, Ångström, 한국어, øl"), text("Creme Brulee, Angstrom, 한국어, øl"));
+
+    let error = Interpreter::new(Options::default()).run_source("snake_case(42)\n").unwrap_err();
+    assert!(error.contains("Argument to 'snake_case' must be a String"), "{}", error);
+}
+
+#[test]
+fn edit_distance_ranks_fuzzy_matches_and_did_you_mean_hints() {
+    let eval = |source: &str| Interpreter::new(Options::default()).run_source(source);
+    let result = eval("result = [edit_distance(\"kitten\", \"sitting\"), edit_distance(\"café\", \"cafe\"), edit_distance(\"\", \"ab\")]\nresult\n");
+    assert_eq!(result, Ok(Value::Array(vec![Value::Integer(3.into()), Value::Integer(1.into()), Value::Integer(2.into())])));
+    assert_eq!(eval("similarity(\"abcd\", \"abcx\")"), Ok(Value::Float(0.75)));
+    assert_eq!(eval("similarity(\"\", \"\")"), Ok(Value::Float(1.0)));
+    assert_eq!(eval("fuzzy_best(\"colour\", [\"cooler\", \"color\", \"collar\"])"), text("color"));
+    assert_eq!(eval("fuzzy_best(\"x\", [])"), Ok(Value::Void));
+
+    // Unknown names get the closest known one as a hint, if it is close enough
+    let error = eval("total = 1\ntotl + 1\n").unwrap_err();
+    assert!(error.ends_with("uninitialized variable: totl (did you mean 'total'?)"), "{}", error);
+    let error = eval("fn compute(x) [ x ]\ncompte(1)\n").unwrap_err();
+    assert!(error.ends_with("Function 'compte' is not defined (did you mean 'compute'?)"), "{}", error);
+    let error = eval("lenght([1])").unwrap_err();
+    assert!(error.ends_with("(did you mean 'length'?)"), "{}", error);
+    let error = eval("zzzzqq(1)").unwrap_err();
+    assert!(error.ends_with("Function 'zzzzqq' is not defined"), "{}", error);
+}
@@ -0,0 +1,80 @@
+//! 'timed("label") [ ... ]' blocks: the automatic label for blocks without one, early returns
+//! inside a timed block, and the per-label timing report printed after a run.
+
+mod common;
+
+use std::fs;
+
+use astra::{Interpreter, Options};
+
+use common::{astra, int, TempDir};
+
+fn report(source: &str) -> Vec<String> {
+    let mut interpreter = Interpreter::new(Options::default());
+    interpreter.run_source(source).unwrap();
+    interpreter.timing_report()
+}
+
+/// The report lines without their durations, which vary from run to run.
+fn labels(report: &[String]) -> Vec<&str> {
+    report.iter().map(|line| line.split(", total ").next().unwrap()).collect()
+}
+
+#[test]
+fn timed_blocks_run_their_body_and_report_under_their_label() {
+    let mut interpreter = Interpreter::new(Options::default());
+    let value = interpreter.run_source("timed(\"setup\") [\n    x = sum(range(10))\n]\nx").unwrap();
+    assert_eq!(value, int(45));
+    assert_eq!(labels(&interpreter.timing_report()), ["setup: 1 run(s)"]);
+}
+
+#[test]
+fn runs_of_the_same_label_are_added_up_in_first_seen_order() {
+    let source = "for (i in range(3)) [\n    timed(\"loop\") [\n        x = i\n    ]\n]\ntimed(\"once\") [\n    y = 1\n]\ntimed(\"loop\") [\n    z = 2\n]";
+    let report = report(source);
+    assert_eq!(labels(&report), ["loop: 4 run(s)", "once: 1 run(s)"]);
+    assert!(report[0].split(", total ").nth(1).is_some_and(|total| !total.is_empty()), "{:?}", report);
+}
+
+#[test]
+fn unlabelled_blocks_are_named_after_their_first_statement() {
+    let source = "timed [\n    total = sum(range(100))\n    other = 1\n]\ntimed [\n    very_long_variable_name = \"a string long enough to be cut short\"\n]\ntimed [\n]";
+    assert_eq!(
+        labels(&report(source)),
+        [
+            "total = sum(range(100)): 1 run(s)",
+            "very_long_variable_name = \"a string long...: 1 run(s)",
+            "empty block: 1 run(s)",
+        ]
+    );
+}
+
+#[test]
+fn a_return_inside_a_timed_block_still_records_it() {
+    let source = "fn first() [\n    timed(\"search\") [\n        return 7\n    ]\n    return 0\n]\nfirst()";
+    let mut interpreter = Interpreter::new(Options::default());
+    assert_eq!(interpreter.run_source(source), Ok(int(7)));
+    assert_eq!(labels(&interpreter.timing_report()), ["search: 1 run(s)"]);
+}
+
+#[test]
+fn the_timing_report_goes_to_stderr_and_the_runlog() {
+    let dir = TempDir::new("timed");
+    dir.write("timed.as", "timed(\"work\") [\n    print(sum(range(5)))\n]\n");
+    let output = astra(&dir, &["timed.as"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "10\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Timing report:\n  work: 1 run(s), total "), "{}", stderr);
+    let runlog = fs::read_to_string(dir.join("runlog")).unwrap();
+    assert!(runlog.contains("  work: 1 run(s), total "), "{}", runlog);
+}
+
+#[test]
+fn runs_without_timed_blocks_have_no_report() {
+    assert!(report("x = 1").is_empty());
+    let dir = TempDir::new("timed_plain");
+    dir.write("plain.as", "print(1)\n");
+    let output = astra(&dir, &["--log", "off", "plain.as"]);
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("Timing report"));
+}
@@ -0,0 +1,39 @@
+//! A trailing comma after the last parameter or argument, which the formatter leaves out.
+
+mod common;
+
+use astra::{format_program, BlockStyle, Interpreter, Options, Parser, Value};
+
+use common::leave_the_repository;
+
+fn formatted(source: &str) -> String {
+    format_program(&Parser::new(source).parse().unwrap_or_else(|e| panic!("{}: {}", source, e)), BlockStyle::Brackets)
+}
+
+#[test]
+fn trailing_commas_parse_and_are_formatted_away() {
+    assert_eq!(formatted("fn f(a, b,) [ return a - b ]"), "fn f(a, b) [\n    return a - b\n]\n");
+    assert_eq!(formatted("f(1, 2,)"), "f(1, 2)\n");
+    assert_eq!(formatted("f(\n    1,\n    2,\n)"), "f(1, 2)\n");
+    assert_eq!(formatted("print(1,)"), "print(1)\n");
+    assert_eq!(formatted("print(length(xs),)"), "print(length(xs))\n");
+    assert_eq!(formatted("print(\"{} {}\", 1, 2,)"), "print(\"{} {}\", 1, 2)\n");
+}
+
+#[test]
+fn trailing_commas_run_like_the_plain_form() {
+    leave_the_repository();
+    let mut interpreter = Interpreter::new(Options::default());
+    interpreter.capture_output();
+    let result = interpreter.run_source("fn f(a, b,) [ return a - b ]\nxs = [1, 2, 3]\nprint(length(xs),)\nprint(\"{}\", f(5, 2,),)\nf(7, 1,)");
+    assert_eq!(result, Ok(Value::Integer(6.into())));
+    assert_eq!(interpreter.take_output(), ["3", "3"]);
+}
+
+#[test]
+fn a_single_comma_is_all_that_is_tolerated() {
+    for source in ["print(1, 2)", "print(1,,)", "f(1,,)", "fn f(a,,) [ 1 ]", "f(,)"] {
+        assert!(Parser::new(source).parse().is_err(), "{}", source);
+    }
+    assert!(Parser::new("print(1, 2)").parse().unwrap_err().contains("only a single expression is allowed"));
+}